@@ -0,0 +1,133 @@
+// 再接続ポリシー検証用のローカル WebSocket サーバー
+//
+// 使い方:
+//   cargo run --example flaky_ws_server            (ws://127.0.0.1:9001 で待ち受け)
+//   KRAKEN_WS_URL=ws://127.0.0.1:9001 cargo run    (Krakenコレクターをこのサーバーに向ける)
+//
+// 標準入力のコマンドで障害を再現する:
+//   drop    全接続を Close フレームなしで切断 (Network)
+//   close   全接続に Close フレームを送って切断 (ServerClosed)
+//   429     以降のハンドシェイクに HTTP 429 を返す (もう一度で解除)
+//   503     以降のハンドシェイクに HTTP 503 を返す (もう一度で解除)
+use futures_util::{SinkExt, StreamExt};
+use log::{info, warn};
+use serde_json::json;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+const ADDR: &str = "127.0.0.1:9001";
+
+#[derive(Debug, Clone, Copy)]
+enum Command {
+    Drop,
+    Close,
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let listener = TcpListener::bind(ADDR).await.expect("failed to bind");
+    info!("Listening on ws://{}", ADDR);
+
+    // 0 = 通常、それ以外はハンドシェイクに返すHTTPステータス
+    let reject_status = Arc::new(AtomicU16::new(0));
+    let (cmd_tx, _) = broadcast::channel::<Command>(16);
+
+    // 標準入力のコマンド処理
+    let tx = cmd_tx.clone();
+    let status = reject_status.clone();
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            match line.trim() {
+                "drop" => {
+                    let _ = tx.send(Command::Drop);
+                }
+                "close" => {
+                    let _ = tx.send(Command::Close);
+                }
+                code @ ("429" | "503") => {
+                    let code: u16 = code.parse().unwrap();
+                    let prev = status.load(Ordering::Relaxed);
+                    let next = if prev == code { 0 } else { code };
+                    status.store(next, Ordering::Relaxed);
+                    info!("Handshake rejection: {}", if next == 0 { "off".to_string() } else { next.to_string() });
+                }
+                other => warn!("Unknown command: {}", other),
+            }
+        }
+    });
+
+    while let Ok((stream, peer)) = listener.accept().await {
+        let status = reject_status.load(Ordering::Relaxed);
+        let mut cmd_rx = cmd_tx.subscribe();
+
+        tokio::spawn(async move {
+            // ErrorResponse の型は tungstenite 側で決まっている
+            #[allow(clippy::result_large_err)]
+            let callback = |_req: &Request, resp: Response| -> Result<Response, ErrorResponse> {
+                if status == 0 {
+                    return Ok(resp);
+                }
+                let mut err = ErrorResponse::new(Some("rejected by flaky_ws_server".to_string()));
+                *err.status_mut() = StatusCode::from_u16(status).unwrap();
+                Err(err)
+            };
+
+            let ws = match tokio_tungstenite::accept_hdr_async(stream, callback).await {
+                Ok(ws) => ws,
+                Err(e) => {
+                    warn!("{} handshake rejected: {}", peer, e);
+                    return;
+                }
+            };
+            info!("{} connected", peer);
+            let (mut write, mut read) = ws.split();
+            let mut ticker = tokio::time::interval(Duration::from_secs(1));
+
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {
+                        // Kraken の ticker 形式
+                        let frame = json!([0, { "c": ["150.000", "1"] }, "ticker", "USD/JPY"]);
+                        if write.send(Message::Text(frame.to_string())).await.is_err() {
+                            break;
+                        }
+                    }
+                    cmd = cmd_rx.recv() => {
+                        match cmd {
+                            Ok(Command::Drop) => {
+                                info!("{} dropped", peer);
+                                break;
+                            }
+                            Ok(Command::Close) => {
+                                info!("{} closing", peer);
+                                let _ = write.send(Message::Close(None)).await;
+                                break;
+                            }
+                            Err(_) => break,
+                        }
+                    }
+                    msg = read.next() => {
+                        match msg {
+                            Some(Ok(Message::Text(text))) => info!("{} -> {}", peer, text),
+                            Some(Ok(_)) => {}
+                            _ => {
+                                info!("{} disconnected", peer);
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,185 @@
+// Bitbank の代わりに使うローカル Socket.IO (Engine.IO v4) サーバー
+//
+// 使い方:
+//   cargo run --example socketio_server             (ws://127.0.0.1:9002 で待ち受け)
+//   BITBANK_WS_URL=ws://127.0.0.1:9002 cargo run    (Bitbankコレクターをこのサーバーに向ける)
+//
+// 接続ごとに Open パケット (pingInterval 2秒 / pingTimeout 1秒) を送り、
+// 40 で名前空間に接続、join-room で参加したルームに Bitbank 形式のデータを毎秒配信する。
+//
+// 標準入力のコマンドで異常系を再現する:
+//   silence   Ping もデータも送らなくなる (クライアントは約3秒で切断するはず)
+//   reject    以降の名前空間接続に 44 (接続エラー) を返す (もう一度で解除)
+//   close     全接続に Engine.IO Close (1) を送る
+use futures_util::{SinkExt, StreamExt};
+use log::{info, warn};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+const ADDR: &str = "127.0.0.1:9002";
+const PING_INTERVAL_MS: u64 = 2_000;
+const PING_TIMEOUT_MS: u64 = 1_000;
+
+#[derive(Debug, Clone, Copy)]
+enum Command {
+    Silence,
+    Close,
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let listener = TcpListener::bind(ADDR).await.expect("failed to bind");
+    info!("Listening on ws://{}", ADDR);
+
+    let reject = Arc::new(AtomicBool::new(false));
+    let sid = Arc::new(AtomicU64::new(1));
+    let (cmd_tx, _) = broadcast::channel::<Command>(16);
+
+    // 標準入力のコマンド処理
+    let tx = cmd_tx.clone();
+    let reject_flag = reject.clone();
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            match line.trim() {
+                "silence" => {
+                    let _ = tx.send(Command::Silence);
+                }
+                "close" => {
+                    let _ = tx.send(Command::Close);
+                }
+                "reject" => {
+                    let next = !reject_flag.load(Ordering::Relaxed);
+                    reject_flag.store(next, Ordering::Relaxed);
+                    info!("Namespace rejection: {}", if next { "on" } else { "off" });
+                }
+                other => warn!("Unknown command: {}", other),
+            }
+        }
+    });
+
+    while let Ok((stream, peer)) = listener.accept().await {
+        let reject = reject.load(Ordering::Relaxed);
+        let sid = format!("standin-{}", sid.fetch_add(1, Ordering::Relaxed));
+        let mut cmd_rx = cmd_tx.subscribe();
+
+        tokio::spawn(async move {
+            let ws = match tokio_tungstenite::accept_async(stream).await {
+                Ok(ws) => ws,
+                Err(e) => {
+                    warn!("{} handshake failed: {}", peer, e);
+                    return;
+                }
+            };
+            info!("{} connected ({})", peer, sid);
+            let (mut write, mut read) = ws.split();
+
+            let open = json!({
+                "sid": sid,
+                "upgrades": [],
+                "pingInterval": PING_INTERVAL_MS,
+                "pingTimeout": PING_TIMEOUT_MS,
+                "maxPayload": 1_000_000,
+            });
+            if write.send(Message::Text(format!("0{}", open))).await.is_err() {
+                return;
+            }
+
+            let mut rooms: Vec<String> = Vec::new();
+            let mut silent = false;
+            let mut ping = tokio::time::interval(Duration::from_millis(PING_INTERVAL_MS));
+            let mut data = tokio::time::interval(Duration::from_secs(1));
+
+            loop {
+                tokio::select! {
+                    _ = ping.tick(), if !silent => {
+                        if write.send(Message::Text("2".to_string())).await.is_err() {
+                            break;
+                        }
+                    }
+                    _ = data.tick(), if !silent => {
+                        for room in &rooms {
+                            let Some(frame) = sample_frame(room) else { continue };
+                            if write.send(Message::Text(frame)).await.is_err() {
+                                break;
+                            }
+                        }
+                    }
+                    cmd = cmd_rx.recv() => {
+                        match cmd {
+                            Ok(Command::Silence) => {
+                                info!("{} going silent", peer);
+                                silent = true;
+                            }
+                            Ok(Command::Close) => {
+                                info!("{} sending engine.io close", peer);
+                                let _ = write.send(Message::Text("1".to_string())).await;
+                                break;
+                            }
+                            Err(_) => break,
+                        }
+                    }
+                    msg = read.next() => {
+                        let text = match msg {
+                            Some(Ok(Message::Text(text))) => text,
+                            Some(Ok(_)) => continue,
+                            _ => {
+                                info!("{} disconnected", peer);
+                                break;
+                            }
+                        };
+                        info!("{} -> {}", peer, text);
+                        if text == "40" {
+                            let reply = if reject {
+                                format!("44{}", json!({ "message": "Not authorized (stand-in)" }))
+                            } else {
+                                format!("40{}", json!({ "sid": format!("{}-ns", sid) }))
+                            };
+                            if write.send(Message::Text(reply)).await.is_err() {
+                                break;
+                            }
+                        } else if let Some(event) = text.strip_prefix("42")
+                            && let Ok(Value::Array(args)) = serde_json::from_str::<Value>(event)
+                            && args.first().and_then(Value::as_str) == Some("join-room")
+                            && let Some(room) = args.get(1).and_then(Value::as_str)
+                        {
+                            rooms.push(room.to_string());
+                        }
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// ルームごとのメッセージ (Bitbank の配信形式に合わせた固定値)
+fn sample_frame(room: &str) -> Option<String> {
+    let now_ms = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+    let data = if room.starts_with("ticker_") {
+        json!({ "sell": "15000100", "buy": "14999900", "last": "15000000", "vol": "123.4567", "timestamp": now_ms })
+    } else if room.starts_with("depth_whole_") {
+        json!({
+            "asks": [["15000100", "0.05"], ["15000500", "0.20"], ["15001000", "1.00"]],
+            "bids": [["14999900", "0.08"], ["14999500", "0.30"], ["14999000", "1.20"]],
+            "asks_over": "10.0", "bids_under": "12.0",
+            "timestamp": now_ms,
+            "sequenceId": (now_ms / 1000).to_string(),
+        })
+    } else if room.starts_with("transactions_") {
+        json!({ "transactions": [
+            { "transaction_id": now_ms, "side": "buy", "price": "15000050", "amount": "0.01", "executed_at": now_ms },
+        ]})
+    } else {
+        return None;
+    };
+    let message = json!(["message", { "room_name": room, "message": { "data": data } }]);
+    Some(format!("42{}", message))
+}
@@ -231,14 +231,14 @@ fn handle_message(text: &str, store: &MarketStore) {
         }
     };
 
-    if let Some(channel) = v.get("channel").and_then(|c| c.as_str()) {
-        if let Some(data) = v.get("data") {
-            match channel {
-                "l2Book" => process_l2_book(data, store),
-                "activeAssetCtx" => process_asset_ctx(data, store),
-                _ => {
-                    debug!("Unhandled channel: {}", channel);
-                }
+    if let Some(channel) = v.get("channel").and_then(|c| c.as_str())
+        && let Some(data) = v.get("data")
+    {
+        match channel {
+            "l2Book" => process_l2_book(data, store),
+            "activeAssetCtx" => process_asset_ctx(data, store),
+            _ => {
+                debug!("Unhandled channel: {}", channel);
             }
         }
     }
@@ -294,15 +294,13 @@ fn process_asset_ctx(data: &Value, store: &MarketStore) {
         }
     };
 
-    if let Some(ctx) = data.get("ctx") {
-        if let Some(funding_val) = ctx.get("funding") {
-            if let Some(funding_rate) = parse_decimal(funding_val) {
-                store.update_funding_rate(coin, funding_rate);
-                
-                debug!("{} | Funding Rate: {}", coin, funding_rate);
-            } else {
-                warn!("Could not parse funding rate for {}", coin);
-            }
+    if let Some(funding_val) = data.get("ctx").and_then(|ctx| ctx.get("funding")) {
+        if let Some(funding_rate) = parse_decimal(funding_val) {
+            store.update_funding_rate(coin, funding_rate);
+            
+            debug!("{} | Funding Rate: {}", coin, funding_rate);
+        } else {
+            warn!("Could not parse funding rate for {}", coin);
         }
     }
 }
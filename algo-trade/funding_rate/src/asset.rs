@@ -0,0 +1,198 @@
+use crate::store::Exchange;
+use crate::strategy::{symbol_for, InstrumentType};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Mutex, OnceLock};
+
+/// 取引対象の通貨 (例: "BTC")。
+/// 設定ファイルから読み込んだシンボル名を指す軽量なハンドルで、Copy で持ち回せる
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Asset(&'static str);
+
+impl Asset {
+    /// シンボル名から Asset を得る。同じ名前は同じ文字列を共有する
+    pub fn new(symbol: &str) -> Self {
+        static INTERNED: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+        let mut interned = INTERNED.get_or_init(|| Mutex::new(HashSet::new())).lock().unwrap();
+        if let Some(&s) = interned.get(symbol) {
+            return Asset(s);
+        }
+        // 通貨の種類は設定ファイルの数だけなので、プロセス終了まで保持する
+        let s: &'static str = Box::leak(symbol.to_string().into_boxed_str());
+        interned.insert(s);
+        Asset(s)
+    }
+
+    pub fn as_symbol(&self) -> &'static str {
+        self.0
+    }
+}
+
+impl fmt::Debug for Asset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl fmt::Display for Asset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl Serialize for Asset {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Asset {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let symbol = String::deserialize(deserializer)?;
+        Ok(Asset::new(&symbol))
+    }
+}
+
+/// 1通貨分の設定 (設定ファイルの [[assets]])。
+/// 取引所ごとのシンボル名を持ち、None の取引所では扱わない
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AssetConfig {
+    pub symbol: Asset,
+    /// Hyperliquid の Perp (例: "BTC")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hyperliquid_perp: Option<String>,
+    /// Hyperliquid の Spot ID (例: "@142")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hyperliquid_spot: Option<String>,
+    /// GMOコイン 現物 (例: "BTC")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gmo_spot: Option<String>,
+    /// GMOコイン レバレッジ (例: "BTC_JPY")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gmo_leverage: Option<String>,
+    /// bitbank の通貨ペア (例: "btc_jpy")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitbank: Option<String>,
+}
+
+impl AssetConfig {
+    /// 全取引所に上場している通貨の既定の設定
+    fn listed_everywhere(symbol: &str, hyperliquid_spot: &str) -> Self {
+        Self {
+            symbol: Asset::new(symbol),
+            hyperliquid_perp: Some(symbol.to_string()),
+            hyperliquid_spot: Some(hyperliquid_spot.to_string()),
+            gmo_spot: Some(symbol.to_string()),
+            gmo_leverage: Some(format!("{}_JPY", symbol)),
+            bitbank: Some(format!("{}_jpy", symbol.to_lowercase())),
+        }
+    }
+
+    /// 既定の取引対象
+    pub fn defaults() -> Vec<Self> {
+        vec![
+            Self::listed_everywhere("BTC", "@142"),
+            Self::listed_everywhere("ETH", "@151"),
+            Self::listed_everywhere("SOL", "@156"),
+            // HYPE は国内取引所に上場していない
+            Self {
+                symbol: Asset::new("HYPE"),
+                hyperliquid_perp: Some("HYPE".to_string()),
+                hyperliquid_spot: Some("@107".to_string()),
+                gmo_spot: None,
+                gmo_leverage: None,
+                bitbank: None,
+            },
+        ]
+    }
+
+    /// (取引所, 取引所側のシンボル名, MarketStore のキー) の一覧
+    fn listings(&self) -> Vec<(Exchange, &str, String)> {
+        let asset = &self.symbol;
+        let mut listings = Vec::new();
+        if let Some(s) = &self.hyperliquid_perp {
+            listings.push((Exchange::Hyperliquid, s.as_str(), symbol_for(Exchange::Hyperliquid, asset, InstrumentType::Perp)));
+        }
+        if let Some(s) = &self.hyperliquid_spot {
+            listings.push((Exchange::Hyperliquid, s.as_str(), symbol_for(Exchange::Hyperliquid, asset, InstrumentType::Spot)));
+        }
+        if let Some(s) = &self.gmo_spot {
+            listings.push((Exchange::Gmo, s.as_str(), symbol_for(Exchange::Gmo, asset, InstrumentType::Spot)));
+        }
+        if let Some(s) = &self.gmo_leverage {
+            listings.push((Exchange::Gmo, s.as_str(), symbol_for(Exchange::Gmo, asset, InstrumentType::Margin)));
+        }
+        if let Some(s) = &self.bitbank {
+            listings.push((Exchange::Bitbank, s.as_str(), symbol_for(Exchange::Bitbank, asset, InstrumentType::Spot)));
+        }
+        listings
+    }
+}
+
+/// 取引所側のシンボル名から引いた通貨と MarketStore のキー
+#[derive(Debug, Clone)]
+pub struct Listing {
+    pub asset: Asset,
+    pub store_key: String,
+}
+
+/// 設定ファイルから作る通貨の一覧と、取引所ごとのシンボル名の対応表。
+/// コレクターは購読するシンボルと受信データの振り分けに、戦略は対象通貨の列挙に使う
+#[derive(Debug, Clone, Default)]
+pub struct AssetRegistry {
+    assets: Vec<AssetConfig>,
+    by_venue_symbol: HashMap<(Exchange, String), Listing>,
+}
+
+impl AssetRegistry {
+    pub fn new(assets: Vec<AssetConfig>) -> Self {
+        let mut by_venue_symbol = HashMap::new();
+        for config in &assets {
+            for (exchange, venue_symbol, store_key) in config.listings() {
+                by_venue_symbol.insert(
+                    (exchange, venue_symbol.to_string()),
+                    Listing { asset: config.symbol, store_key },
+                );
+            }
+        }
+        Self { assets, by_venue_symbol }
+    }
+
+    /// 登録順の通貨一覧
+    pub fn assets(&self) -> impl Iterator<Item = Asset> + '_ {
+        self.assets.iter().map(|c| c.symbol)
+    }
+
+    pub fn configs(&self) -> &[AssetConfig] {
+        &self.assets
+    }
+
+    pub fn get(&self, asset: Asset) -> Option<&AssetConfig> {
+        self.assets.iter().find(|c| c.symbol == asset)
+    }
+
+    /// 取引所側のシンボル名から通貨を引く
+    pub fn lookup(&self, exchange: Exchange, venue_symbol: &str) -> Option<&Listing> {
+        self.by_venue_symbol.get(&(exchange, venue_symbol.to_string()))
+    }
+
+    /// 取引所側のシンボル名 (購読に使う)
+    pub fn venue_symbol(&self, asset: Asset, exchange: Exchange, instrument: InstrumentType) -> Option<&str> {
+        let config = self.get(asset)?;
+        let symbol = match (exchange, instrument) {
+            (Exchange::Hyperliquid, InstrumentType::Perp) => &config.hyperliquid_perp,
+            (Exchange::Hyperliquid, InstrumentType::Spot) => &config.hyperliquid_spot,
+            (Exchange::Gmo, InstrumentType::Spot) => &config.gmo_spot,
+            (Exchange::Gmo, InstrumentType::Margin) => &config.gmo_leverage,
+            (Exchange::Bitbank, InstrumentType::Spot) => &config.bitbank,
+            _ => &None,
+        };
+        symbol.as_deref()
+    }
+
+    pub fn is_listed(&self, asset: Asset, exchange: Exchange, instrument: InstrumentType) -> bool {
+        self.venue_symbol(asset, exchange, instrument).is_some()
+    }
+}
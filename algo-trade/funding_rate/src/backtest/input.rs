@@ -0,0 +1,138 @@
+use crate::history::{FundingRecord, HistoryDb};
+use crate::recorder::{RecordedFrame, RecordingIndex};
+use crate::replay::recorded_frames;
+use crate::store::Exchange;
+use log::warn;
+use rust_decimal::Decimal;
+use serde::de::IntoDeserializer;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::str::FromStr;
+
+/// CSV の列 (1行目はこのヘッダー)。funding_rate は Perp 以外は空でよい
+pub const CSV_HEADER: &str = "timestamp_ms,exchange,symbol,bid,ask,funding_rate";
+
+/// バックテストの入力の1件
+#[derive(Debug, Clone)]
+pub enum Tick {
+    /// recorder が記録した生フレーム (コレクターで処理する)
+    Frame(RecordedFrame),
+    /// CSV・履歴から読んだ気配 (そのまま MarketStore に反映する)
+    Quote(QuoteRow),
+    /// 履歴から読んだFR
+    Funding(FundingRecord),
+}
+
+impl Tick {
+    /// UNIX µs
+    pub fn ts_us(&self) -> u64 {
+        match self {
+            Tick::Frame(frame) => frame.ts_us,
+            Tick::Quote(quote) => quote.ts_us,
+            Tick::Funding(funding) => funding.ts_ms * 1_000,
+        }
+    }
+}
+
+/// CSV の1行。symbol は MarketStore のキー (例: "BTC", "BTC_SPOT", "USD_JPY")
+#[derive(Debug, Clone)]
+pub struct QuoteRow {
+    pub ts_us: u64,
+    pub exchange: Exchange,
+    pub symbol: String,
+    pub bid: Decimal,
+    pub ask: Decimal,
+    pub funding_rate: Option<Decimal>,
+}
+
+impl QuoteRow {
+    fn parse(line: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        if fields.len() < 5 || fields.len() > 6 {
+            return Err(format!("expected 5 or 6 columns ({}), got {}", CSV_HEADER, fields.len()));
+        }
+        let decimal = |name: &str, s: &str| Decimal::from_str(s).map_err(|e| format!("{}: {}", name, e));
+        let timestamp_ms: u64 = fields[0].parse().map_err(|e| format!("timestamp_ms: {}", e))?;
+        let exchange = Exchange::deserialize(fields[1].into_deserializer())
+            .map_err(|e: serde::de::value::Error| format!("exchange: {}", e))?;
+        Ok(Self {
+            ts_us: timestamp_ms * 1_000,
+            exchange,
+            symbol: fields[2].to_string(),
+            bid: decimal("bid", fields[3])?,
+            ask: decimal("ask", fields[4])?,
+            funding_rate: match fields.get(5) {
+                Some(s) if !s.is_empty() => Some(decimal("funding_rate", s)?),
+                _ => None,
+            },
+        })
+    }
+}
+
+/// 期間 [from_us, to_us] の入力を時刻順に読む。
+/// path がディレクトリなら recorder の記録、拡張子が .sqlite / .db なら履歴 (history)、
+/// それ以外は CSV (時刻順に並んでいること) として扱う
+pub fn load_ticks(path: &Path, from_us: u64, to_us: u64) -> io::Result<Box<dyn Iterator<Item = Tick>>> {
+    if path.is_dir() {
+        let index = RecordingIndex::load(path)?;
+        return Ok(Box::new(recorded_frames(&index, from_us, to_us).map(Tick::Frame)));
+    }
+    if path.extension().is_some_and(|ext| ext == "sqlite" || ext == "db") {
+        return history_ticks(path, from_us / 1_000, to_us / 1_000).map_err(io::Error::other);
+    }
+
+    let mut lines = BufReader::new(File::open(path)?).lines().enumerate();
+    match lines.next() {
+        // funding_rate の列は省略できる
+        Some((_, Ok(header))) if header.trim() == CSV_HEADER || Some(header.trim()) == CSV_HEADER.strip_suffix(",funding_rate") => {}
+        Some((_, Ok(header))) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: unexpected header '{}' (expected {})", path.display(), header, CSV_HEADER),
+            ));
+        }
+        Some((_, Err(e))) => return Err(e),
+        None => {}
+    }
+    let display = path.display().to_string();
+    Ok(Box::new(
+        lines
+            .map_while(move |(i, line)| match line {
+                Ok(line) => Some((i, line)),
+                Err(e) => {
+                    warn!("[Backtest] {}: stopping at line {}: {}", display, i + 1, e);
+                    None
+                }
+            })
+            .filter(|(_, line)| !line.trim().is_empty())
+            .filter_map(|(i, line)| match QuoteRow::parse(&line) {
+                Ok(quote) => Some(quote),
+                Err(e) => {
+                    warn!("[Backtest] Skipping CSV line {}: {}", i + 1, e);
+                    None
+                }
+            })
+            .filter(move |quote| quote.ts_us >= from_us && quote.ts_us <= to_us)
+            .map(Tick::Quote),
+    ))
+}
+
+/// 履歴の気配とFRを時刻順に並べる (同じ時刻なら気配が先)
+fn history_ticks(path: &Path, from_ms: u64, to_ms: u64) -> rusqlite::Result<Box<dyn Iterator<Item = Tick>>> {
+    let db = HistoryDb::open(path)?;
+    let quotes = db.quotes_between(from_ms, to_ms)?.into_iter().map(|q| {
+        Tick::Quote(QuoteRow {
+            ts_us: q.ts_ms * 1_000,
+            exchange: q.exchange,
+            symbol: q.symbol,
+            bid: q.bid,
+            ask: q.ask,
+            funding_rate: None,
+        })
+    });
+    let mut ticks: Vec<Tick> = quotes.chain(db.funding_between(from_ms, to_ms)?.into_iter().map(Tick::Funding)).collect();
+    ticks.sort_by_key(Tick::ts_us);
+    Ok(Box::new(ticks.into_iter()))
+}
@@ -0,0 +1,500 @@
+// バックテスト
+//
+// 記録した生フレーム (recorder) または CSV の気配を仮想時計で MarketStore に流し、ライブと同じ手順
+// (rank_arbitrage と OpportunityTracker による SpreadArbitrage) で裁定を判定する。判定のパラメータは
+// [backtest.sweep] の組み合わせごとに並行して回し、同じデータで比べられるようにする。
+// 執行は検出から latency_ms 後の板で約定させ、手数料とスリッページを差し引いて成績を集計する。
+pub mod input;
+pub mod report;
+
+pub use input::*;
+pub use report::*;
+
+use crate::asset::AssetRegistry;
+use crate::clock::VirtualClock;
+use crate::collector::Collector;
+use crate::config::Config;
+use crate::executor::{fill_price, order_quantity, ExecutorConfig, Side};
+use crate::fx::FxOracle;
+use crate::replay::FeedReplayer;
+use crate::store::MarketStore;
+use crate::strategy::{
+    ArbitrageOpportunity, ArbitrageParams, Asset, AssetIndex, ClosedOpportunity, FeeSchedule, FxRates, MarketSnapshot, OpportunityKey,
+    PendingEvaluations, Positions, Signal, SpreadArbitrage, Strategy,
+};
+use log::{debug, info};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::TryRecvError;
+
+/// バックテストの設定 (設定ファイルの [backtest])。
+/// 手数料は [exchanges.*] の taker_fee と同じものを使う
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BacktestConfig {
+    /// 機会の検出から約定までの遅延 (ms)。約定価格はこの時点の板で決める
+    pub latency_ms: u64,
+    /// 約定価格に上乗せする不利なスリッページ (小数。0.0002 = 0.02%)
+    pub slippage: Decimal,
+    /// 結果 (summary.csv と equity_*.csv) の出力先
+    pub output_dir: String,
+    pub sweep: SweepConfig,
+}
+
+impl Default for BacktestConfig {
+    fn default() -> Self {
+        Self {
+            latency_ms: 200,
+            slippage: Decimal::from_str("0.0002").unwrap(),
+            output_dir: "backtest".to_string(),
+            sweep: SweepConfig::default(),
+        }
+    }
+}
+
+/// 比べる判定パラメータの値 (設定ファイルの [backtest.sweep])。
+/// 空の項目は [strategies.arbitrage] の値だけを使い、すべての組み合わせを試す
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SweepConfig {
+    /// ArbitrageParams::min_profit_pct の候補 (%)
+    pub min_profit_pct: Vec<Decimal>,
+    /// ArbitrageParams::slippage (板が無い場合に見込むスリッページ) の候補
+    pub slippage: Vec<Decimal>,
+}
+
+impl SweepConfig {
+    /// base の min_profit_pct と slippage を候補で置き換えた組み合わせ
+    pub fn variants(&self, base: &ArbitrageParams) -> Vec<ArbitrageParams> {
+        let or_base = |values: &[Decimal], base: Decimal| if values.is_empty() { vec![base] } else { values.to_vec() };
+        let mut variants = Vec::new();
+        for min_profit_pct in or_base(&self.min_profit_pct, base.min_profit_pct) {
+            for slippage in or_base(&self.slippage, base.slippage) {
+                variants.push(ArbitrageParams { min_profit_pct, slippage, ..base.clone() });
+            }
+        }
+        variants
+    }
+}
+
+/// 検出済みで約定待ちのシグナル
+#[derive(Debug)]
+struct PendingOrder {
+    fill_at_us: u64,
+    opportunity: ArbitrageOpportunity,
+}
+
+/// パラメータの組1つ分の判定と成績
+struct Variant {
+    params: ArbitrageParams,
+    strategy: SpreadArbitrage,
+    orders: VecDeque<PendingOrder>,
+    total: TradeStats,
+    assets: BTreeMap<Asset, TradeStats>,
+    routes: BTreeMap<String, TradeStats>,
+    equity_curve: Vec<(u64, Decimal)>,
+}
+
+impl Variant {
+    fn new(params: ArbitrageParams) -> Self {
+        Self {
+            strategy: SpreadArbitrage::new(params.clone()),
+            params,
+            orders: VecDeque::new(),
+            total: TradeStats::default(),
+            assets: BTreeMap::new(),
+            routes: BTreeMap::new(),
+            equity_curve: Vec::new(),
+        }
+    }
+
+    /// 全体・通貨・経路の成績に同じ更新をする
+    fn update(&mut self, key: &OpportunityKey, f: impl Fn(&mut TradeStats)) {
+        f(&mut self.total);
+        f(self.assets.entry(key.asset).or_default());
+        f(self.routes.entry(key.to_string()).or_default());
+    }
+
+    fn record_closed(&mut self, closed: &ClosedOpportunity) {
+        self.update(&closed.key, |s| {
+            s.opportunities += 1;
+            if closed.confirmed {
+                s.confirmed += 1;
+                s.time_in_market_ms += closed.lifetime_ms();
+            }
+        });
+    }
+
+    fn into_report(self) -> VariantReport {
+        VariantReport {
+            min_profit_pct: self.params.min_profit_pct,
+            slippage: self.params.slippage,
+            total: self.total,
+            assets: self.assets,
+            routes: self.routes,
+            equity_curve: self.equity_curve,
+        }
+    }
+}
+
+/// 入力を時刻順に流して全パラメータの組の判定と執行を模擬する
+pub struct Backtester<'a> {
+    store: MarketStore,
+    config: &'a Config,
+    registry: &'a AssetRegistry,
+    settings: &'a BacktestConfig,
+    replayer: FeedReplayer,
+    fees: FeeSchedule,
+    fx_oracle: FxOracle,
+    index: AssetIndex,
+    variants: Vec<Variant>,
+    /// 最後に取れた為替レート (終了時に開いている機会を閉じるのに使う)
+    last_fx: Option<FxRates>,
+    ticks: u64,
+    first_us: Option<u64>,
+    last_us: u64,
+}
+
+impl<'a> Backtester<'a> {
+    /// collectors は記録したフレームの処理に使う (CSV だけなら空でよい)
+    pub fn new(config: &'a Config, registry: &'a AssetRegistry, collectors: Vec<Box<dyn Collector>>) -> Self {
+        let clock = Arc::new(VirtualClock::default());
+        let store = MarketStore::new().with_clock(clock.clone());
+        for (exchange, venue) in config.exchanges.iter() {
+            store.set_max_age(exchange, venue.max_quote_age());
+            store.set_max_funding_age(exchange, venue.max_funding_age());
+        }
+        let settings = &config.backtest;
+        let fx_oracle = FxOracle::new(&config.fx, |e| config.exchanges.get(e).enabled);
+        let variants: Vec<Variant> = settings.sweep.variants(&config.strategies.arbitrage).into_iter().map(Variant::new).collect();
+        info!(
+            "[Backtest] {} parameter set(s), latency {}ms, execution slippage {}",
+            variants.len(),
+            settings.latency_ms,
+            settings.slippage
+        );
+        Self {
+            replayer: FeedReplayer::new(store.clone(), clock, collectors),
+            store,
+            config,
+            registry,
+            settings,
+            fees: config.fee_schedule(),
+            index: AssetIndex::new(registry, fx_oracle.sources().map(|s| (s.exchange, s.symbol.clone()))),
+            fx_oracle,
+            variants,
+            last_fx: None,
+            ticks: 0,
+            first_us: None,
+            last_us: 0,
+        }
+    }
+
+    /// 入力を最後まで流す。評価のスケジュール (ウォームアップ・debounce・一定間隔の見直し) はライブと同じ
+    pub fn run(mut self, ticks: impl Iterator<Item = Tick>) -> BacktestReport {
+        let intervals = &self.config.intervals;
+        let us = |ms: u64| ms * 1_000;
+        let mut updates = self.store.subscribe();
+        let mut pending = PendingEvaluations::default();
+        let mut warmup_end = u64::MAX;
+        let mut next_full_evaluation = u64::MAX;
+        let mut next_evaluation: Option<u64> = None;
+
+        for tick in ticks {
+            let ts_us = tick.ts_us();
+            if self.first_us.is_none() {
+                self.first_us = Some(ts_us);
+                warmup_end = ts_us + us(intervals.warmup_ms);
+                next_full_evaluation = warmup_end;
+            }
+
+            // このティックより前に予定されている約定と評価を済ませる
+            loop {
+                let due = self
+                    .next_fill_us()
+                    .min(next_evaluation.unwrap_or(u64::MAX))
+                    .min(next_full_evaluation);
+                if due > ts_us {
+                    break;
+                }
+                self.replayer.clock().advance(due);
+                self.fill_due(due);
+                if next_evaluation.is_some_and(|t| t <= due) {
+                    next_evaluation = None;
+                }
+                if due >= next_full_evaluation {
+                    pending.mark_all(self.registry);
+                    next_full_evaluation = due + us(intervals.strategy_loop_ms);
+                }
+                if !pending.is_empty() && next_evaluation.is_none() && !self.evaluate(&mut pending, due) {
+                    next_evaluation = Some(due + us(intervals.fx_retry_ms));
+                }
+            }
+
+            self.replayer.clock().advance(ts_us);
+            self.apply(&tick);
+            loop {
+                match updates.try_recv() {
+                    Ok(_) | Err(TryRecvError::Lagged(_)) if ts_us < warmup_end => {}
+                    Ok(update) => pending.mark_update(&update, &self.index, self.registry),
+                    Err(TryRecvError::Lagged(_)) => pending.mark_all(self.registry),
+                    Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+                }
+            }
+            if !pending.is_empty() && next_evaluation.is_none() {
+                next_evaluation = Some(ts_us + us(intervals.debounce_ms));
+            }
+        }
+
+        // 残りの評価と約定を済ませ、開いている機会を閉じる
+        if let Some(due) = next_evaluation
+            && !pending.is_empty()
+        {
+            self.replayer.clock().advance(due);
+            self.evaluate(&mut pending, due);
+        }
+        while self.next_fill_us() != u64::MAX {
+            let due = self.next_fill_us();
+            self.replayer.clock().advance(due);
+            self.fill_due(due);
+        }
+        self.close_all();
+
+        BacktestReport {
+            start_ms: self.first_us.unwrap_or(0) / 1_000,
+            end_ms: self.last_us / 1_000,
+            ticks: self.ticks,
+            variants: self.variants.into_iter().map(Variant::into_report).collect(),
+        }
+    }
+
+    fn apply(&mut self, tick: &Tick) {
+        self.ticks += 1;
+        self.last_us = self.last_us.max(tick.ts_us());
+        match tick {
+            Tick::Frame(frame) => self.replayer.apply(frame),
+            Tick::Quote(quote) => {
+                self.store.update_market_data(quote.exchange, &quote.symbol, quote.bid, quote.ask, (quote.bid + quote.ask) / Decimal::from(2), None);
+                if let Some(funding_rate) = quote.funding_rate {
+                    self.store.update_funding_rate(quote.exchange, &quote.symbol, funding_rate);
+                }
+            }
+            Tick::Funding(funding) => self.store.update_funding_rate(funding.exchange, &funding.symbol, funding.rate),
+        }
+    }
+
+    /// 評価待ちの通貨を全パラメータの組で評価し、シグナルを約定待ちに積む。為替レートが取れなければ false
+    fn evaluate(&mut self, pending: &mut PendingEvaluations, now_us: u64) -> bool {
+        let usd_jpy = match self.fx_oracle.quote(&self.store) {
+            Ok(quote) if quote.diverged => {
+                debug!("[Backtest] USD_JPY sources diverge by {:.4}%. Skipping cycle.", quote.divergence_pct);
+                return false;
+            }
+            Ok(quote) => quote,
+            Err(e) => {
+                debug!("[Backtest] USD_JPY unavailable: {}. Skipping cycle.", e);
+                return false;
+            }
+        };
+        let (fx_rates, _) = self.fx_oracle.rates(&self.store, &usd_jpy);
+
+        for asset in self.registry.assets() {
+            if pending.take(asset).is_none() {
+                continue;
+            }
+            let snapshot = MarketSnapshot::collect(
+                &self.store,
+                &self.config.exchanges,
+                self.registry,
+                &self.fees,
+                asset,
+                &fx_rates,
+                self.store.now_ms(),
+            );
+            for variant in &mut self.variants {
+                for signal in variant.strategy.evaluate(&snapshot, &Positions::default()) {
+                    match signal {
+                        // 候補のうち最良の経路だけを執行する
+                        Signal::Arbitrage(candidates) => {
+                            if let Some(opportunity) = candidates.into_iter().next() {
+                                let key = OpportunityKey::of(&opportunity);
+                                variant.orders.push_back(PendingOrder { fill_at_us: now_us + self.settings.latency_ms * 1_000, opportunity });
+                                debug!("[Backtest] {} signal at {}us", key, now_us);
+                            }
+                        }
+                        Signal::OpportunityClosed(closed) => variant.record_closed(&closed),
+                        Signal::Target(_) => {}
+                    }
+                }
+            }
+        }
+        self.last_fx = Some(fx_rates);
+        true
+    }
+
+    fn next_fill_us(&self) -> u64 {
+        self.variants
+            .iter()
+            .filter_map(|v| v.orders.front().map(|o| o.fill_at_us))
+            .min()
+            .unwrap_or(u64::MAX)
+    }
+
+    /// 約定予定時刻が now_us 以前のシグナルを、その時点の板で約定させる
+    fn fill_due(&mut self, now_us: u64) {
+        for variant in &mut self.variants {
+            while variant.orders.front().is_some_and(|o| o.fill_at_us <= now_us) {
+                let order = variant.orders.pop_front().unwrap();
+                let key = OpportunityKey::of(&order.opportunity);
+                match simulate_fill(&self.store, &self.fees, self.settings.slippage, &variant.params, &order.opportunity) {
+                    Some((theoretical, captured)) => {
+                        variant.update(&key, |s| s.record_trade(theoretical, captured));
+                        variant.equity_curve.push((now_us / 1_000, variant.total.captured_profit_jpy));
+                    }
+                    None => variant.update(&key, |s| s.missed += 1),
+                }
+            }
+        }
+    }
+
+    /// 終了時に開いている機会を閉じて、持続時間を集計に含める
+    fn close_all(&mut self) {
+        let Some(fx) = self.last_fx.take() else {
+            return;
+        };
+        let now_ms = self.last_us / 1_000;
+        for asset in self.registry.assets() {
+            let snapshot = MarketSnapshot { asset, markets: Vec::new(), fx: fx.clone(), now_ms };
+            for variant in &mut self.variants {
+                for signal in variant.strategy.evaluate(&snapshot, &Positions::default()) {
+                    if let Signal::OpportunityClosed(closed) = signal {
+                        variant.record_closed(&closed);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 現在の板で両足を約定させ、(推定利益, 実現損益) を返す。どちらかの足の気配が無ければ None。
+/// 約定価格には slippage を不利な方向に上乗せし、FRとレバレッジ手数料は検出時の見込みを数量で按分して含める
+fn simulate_fill(
+    store: &MarketStore,
+    fees: &FeeSchedule,
+    slippage: Decimal,
+    params: &ArbitrageParams,
+    opp: &ArbitrageOpportunity,
+) -> Option<(Decimal, Decimal)> {
+    let executor_config = ExecutorConfig { max_notional_jpy: params.max_notional_jpy, ..ExecutorConfig::default() };
+    let quantity = order_quantity(&executor_config, opp);
+    if quantity <= Decimal::ZERO {
+        return None;
+    }
+    let long_price = fill_price(store, opp.long_exchange, opp.asset, opp.long_instrument, Side::Buy, quantity).ok()? * (Decimal::ONE + slippage);
+    let short_price = fill_price(store, opp.short_exchange, opp.asset, opp.short_instrument, Side::Sell, quantity).ok()? * (Decimal::ONE - slippage);
+
+    let long_cost_jpy = quantity * long_price * (Decimal::ONE + fees.taker(opp.long_exchange, opp.long_instrument)) * opp.long_fx_rate;
+    let short_revenue_jpy = quantity * short_price * (Decimal::ONE - fees.taker(opp.short_exchange, opp.short_instrument)) * opp.short_fx_rate;
+    let carry_jpy = (opp.fr_impact_jpy - opp.rollover_cost_jpy) * quantity / opp.quantity;
+    Some((opp.estimated_profit_for(quantity), short_revenue_jpy - long_cost_jpy + carry_jpy))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::Exchange;
+
+    fn decimals(values: &[&str]) -> Vec<Decimal> {
+        values.iter().map(|v| Decimal::from_str(v).unwrap()).collect()
+    }
+
+    #[test]
+    fn empty_sweep_uses_base_params() {
+        let base = ArbitrageParams::default();
+        let variants = SweepConfig::default().variants(&base);
+        assert_eq!(variants.len(), 1);
+        assert_eq!((variants[0].min_profit_pct, variants[0].slippage), (base.min_profit_pct, base.slippage));
+
+        // config.example.toml は既定値なので、スイープも1通り
+        let example = Config::from_toml(include_str!("../../config.example.toml")).unwrap();
+        assert_eq!(example.backtest.sweep.variants(&base).len(), 1);
+    }
+
+    #[test]
+    fn sweep_tries_every_combination() {
+        let base = ArbitrageParams { max_notional_jpy: Decimal::from(5_000), ..ArbitrageParams::default() };
+        let sweep = SweepConfig { min_profit_pct: decimals(&["0.05", "0.1", "0.2"]), slippage: decimals(&["0.0001", "0.0005"]) };
+        let combinations: Vec<(String, String)> = sweep
+            .variants(&base)
+            .iter()
+            .map(|p| (p.min_profit_pct.to_string(), p.slippage.to_string()))
+            .collect();
+        let expected = [("0.05", "0.0001"), ("0.05", "0.0005"), ("0.1", "0.0001"), ("0.1", "0.0005"), ("0.2", "0.0001"), ("0.2", "0.0005")];
+        assert_eq!(combinations, expected.map(|(a, b)| (a.to_string(), b.to_string())));
+        // 候補の無い項目は base のまま
+        let sweep = SweepConfig { slippage: decimals(&["0.0005"]), ..SweepConfig::default() };
+        let variants = sweep.variants(&base);
+        assert_eq!(variants.len(), 1);
+        assert_eq!(variants[0].min_profit_pct, base.min_profit_pct);
+        assert_eq!(variants[0].max_notional_jpy, base.max_notional_jpy);
+    }
+
+    /// Bitbank 現物 ¥10,000 / GMO 現物 ¥10,100 の気配と手数料なしのスケジュール
+    fn market() -> (MarketStore, FeeSchedule) {
+        let store = MarketStore::new();
+        let price = Decimal::from(10_000);
+        store.update_market_data(Exchange::Bitbank, "BTC", price, price, price, None);
+        let price = Decimal::from(10_100);
+        store.update_market_data(Exchange::Gmo, "BTC_SPOT", price, price, price, None);
+        let mut fees = FeeSchedule::default();
+        fees.set_taker(Exchange::Bitbank, Decimal::ZERO);
+        fees.set_taker(Exchange::Gmo, Decimal::ZERO);
+        (store, fees)
+    }
+
+    fn opportunity() -> ArbitrageOpportunity {
+        ArbitrageOpportunity::sample(
+            Asset::new("BTC"),
+            (Exchange::Bitbank, Decimal::from(10_000)),
+            (Exchange::Gmo, Decimal::from(10_100)),
+            Decimal::ONE,
+        )
+    }
+
+    #[test]
+    fn simulate_fill_applies_slippage_and_fees() {
+        let (store, mut fees) = market();
+        let params = ArbitrageParams::default();
+        let opp = opportunity();
+
+        let fill = simulate_fill(&store, &fees, Decimal::ZERO, &params, &opp);
+        assert_eq!(fill, Some((Decimal::from(100), Decimal::from(100))));
+
+        // 買いは 10,010、売りは 10,089.9 で約定する
+        let fill = simulate_fill(&store, &fees, Decimal::from_str("0.001").unwrap(), &params, &opp);
+        assert_eq!(fill, Some((Decimal::from(100), Decimal::from_str("79.9").unwrap())));
+
+        fees.set_taker(Exchange::Gmo, Decimal::from_str("0.001").unwrap());
+        let (_, realized) = simulate_fill(&store, &fees, Decimal::ZERO, &params, &opp).unwrap();
+        assert_eq!(realized, Decimal::from_str("89.9").unwrap());
+    }
+
+    #[test]
+    fn simulate_fill_is_capped_by_max_notional() {
+        let (store, fees) = market();
+        let params = ArbitrageParams { max_notional_jpy: Decimal::from(5_000), ..ArbitrageParams::default() };
+        let fill = simulate_fill(&store, &fees, Decimal::ZERO, &params, &opportunity());
+        assert_eq!(fill, Some((Decimal::from(50), Decimal::from(50))));
+    }
+
+    #[test]
+    fn simulate_fill_without_quote_is_none() {
+        let store = MarketStore::new();
+        let price = Decimal::from(10_000);
+        store.update_market_data(Exchange::Bitbank, "BTC", price, price, price, None);
+        assert_eq!(simulate_fill(&store, &FeeSchedule::default(), Decimal::ZERO, &ArbitrageParams::default(), &opportunity()), None);
+    }
+}
@@ -0,0 +1,167 @@
+use crate::strategy::Asset;
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// 通貨・経路ごとの成績。損益はすべて JPY
+#[derive(Debug, Clone, Default)]
+pub struct TradeStats {
+    /// 観測した裁定機会 (持続の条件を満たさなかったものも含む)
+    pub opportunities: u64,
+    /// 持続の条件を満たしてシグナルを出した機会
+    pub confirmed: u64,
+    pub trades: u64,
+    /// 約定させられなかったシグナル (執行時点で気配が無い・板が薄い)
+    pub missed: u64,
+    /// 実現損益がプラスだった約定
+    pub wins: u64,
+    /// 検出時の推定利益 (約定した数量分)
+    pub theoretical_profit_jpy: Decimal,
+    /// 執行遅延・スリッページ・手数料込みの実現損益
+    pub captured_profit_jpy: Decimal,
+    /// 持続の条件を満たした機会が開いていた時間の合計
+    pub time_in_market_ms: u64,
+    equity_jpy: Decimal,
+    peak_equity_jpy: Decimal,
+    pub max_drawdown_jpy: Decimal,
+}
+
+impl TradeStats {
+    pub fn record_trade(&mut self, theoretical_jpy: Decimal, captured_jpy: Decimal) {
+        self.trades += 1;
+        if captured_jpy > Decimal::ZERO {
+            self.wins += 1;
+        }
+        self.theoretical_profit_jpy += theoretical_jpy;
+        self.captured_profit_jpy += captured_jpy;
+        self.equity_jpy += captured_jpy;
+        self.peak_equity_jpy = self.peak_equity_jpy.max(self.equity_jpy);
+        self.max_drawdown_jpy = self.max_drawdown_jpy.max(self.peak_equity_jpy - self.equity_jpy);
+    }
+
+    /// 約定のうち実現損益がプラスだった割合
+    pub fn hit_rate(&self) -> Option<Decimal> {
+        (self.trades > 0).then(|| Decimal::from(self.wins) / Decimal::from(self.trades))
+    }
+
+    /// 推定利益のうち実際に取れた割合 (推定がゼロ以下なら None)
+    pub fn capture_ratio(&self) -> Option<Decimal> {
+        (self.theoretical_profit_jpy > Decimal::ZERO).then(|| self.captured_profit_jpy / self.theoretical_profit_jpy)
+    }
+
+    /// 期間に対する time_in_market_ms の割合
+    pub fn time_in_market(&self, span_ms: u64) -> Option<Decimal> {
+        (span_ms > 0).then(|| Decimal::from(self.time_in_market_ms) / Decimal::from(span_ms))
+    }
+}
+
+fn percent(ratio: Option<Decimal>) -> String {
+    ratio.map(|r| format!("{:.1}%", r * Decimal::from(100))).unwrap_or_else(|| "-".to_string())
+}
+
+impl fmt::Display for TradeStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "機会 {} (持続 {}) 約定 {} (未約定 {}) 勝率 {} 実現 ¥{:.2} / 推定 ¥{:.2} (捕捉率 {}) 最大DD ¥{:.2}",
+            self.opportunities,
+            self.confirmed,
+            self.trades,
+            self.missed,
+            percent(self.hit_rate()),
+            self.captured_profit_jpy,
+            self.theoretical_profit_jpy,
+            percent(self.capture_ratio()),
+            self.max_drawdown_jpy,
+        )
+    }
+}
+
+/// パラメータの組1つ分の結果
+#[derive(Debug, Clone)]
+pub struct VariantReport {
+    pub min_profit_pct: Decimal,
+    pub slippage: Decimal,
+    pub total: TradeStats,
+    pub assets: BTreeMap<Asset, TradeStats>,
+    /// キーは "BTC Bitbank Spot -> Gmo Margin" の形
+    pub routes: BTreeMap<String, TradeStats>,
+    /// 約定ごとの累計実現損益 (UNIX ms, JPY)
+    pub equity_curve: Vec<(u64, Decimal)>,
+}
+
+impl VariantReport {
+    pub fn label(&self) -> String {
+        format!("min_profit_pct={} slippage={}", self.min_profit_pct, self.slippage)
+    }
+}
+
+/// バックテスト全体の結果
+#[derive(Debug, Clone)]
+pub struct BacktestReport {
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub ticks: u64,
+    pub variants: Vec<VariantReport>,
+}
+
+impl BacktestReport {
+    pub fn span_ms(&self) -> u64 {
+        self.end_ms.saturating_sub(self.start_ms)
+    }
+
+    /// dir に summary.csv (パラメータの組 × 全体・通貨・経路ごとの成績) と、
+    /// パラメータの組ごとの資産曲線 equity_<n>.csv を書き出す (n は summary.csv の variant)
+    pub fn write_to(&self, dir: impl AsRef<Path>) -> io::Result<()> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let span_ms = self.span_ms();
+
+        let mut summary = BufWriter::new(File::create(dir.join("summary.csv"))?);
+        writeln!(
+            summary,
+            "variant,min_profit_pct,slippage,scope,key,opportunities,confirmed,trades,missed,wins,hit_rate,\
+             theoretical_profit_jpy,captured_profit_jpy,capture_ratio,time_in_market,max_drawdown_jpy"
+        )?;
+        let ratio = |r: Option<Decimal>| r.map(|r| r.round_dp(6).to_string()).unwrap_or_default();
+        for (n, variant) in self.variants.iter().enumerate() {
+            let rows = std::iter::once(("total", String::new(), &variant.total))
+                .chain(variant.assets.iter().map(|(asset, stats)| ("asset", asset.to_string(), stats)))
+                .chain(variant.routes.iter().map(|(route, stats)| ("route", route.clone(), stats)));
+            for (scope, key, s) in rows {
+                writeln!(
+                    summary,
+                    "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                    n,
+                    variant.min_profit_pct,
+                    variant.slippage,
+                    scope,
+                    key,
+                    s.opportunities,
+                    s.confirmed,
+                    s.trades,
+                    s.missed,
+                    s.wins,
+                    ratio(s.hit_rate()),
+                    s.theoretical_profit_jpy.round_dp(2),
+                    s.captured_profit_jpy.round_dp(2),
+                    ratio(s.capture_ratio()),
+                    ratio(s.time_in_market(span_ms)),
+                    s.max_drawdown_jpy.round_dp(2),
+                )?;
+            }
+
+            let mut equity = BufWriter::new(File::create(dir.join(format!("equity_{}.csv", n)))?);
+            writeln!(equity, "timestamp_ms,equity_jpy")?;
+            writeln!(equity, "{},0", self.start_ms)?;
+            for (timestamp_ms, equity_jpy) in &variant.equity_curve {
+                writeln!(equity, "{},{}", timestamp_ms, equity_jpy.round_dp(2))?;
+            }
+            equity.flush()?;
+        }
+        summary.flush()
+    }
+}
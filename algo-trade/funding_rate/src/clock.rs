@@ -0,0 +1,51 @@
+// 時計
+//
+// 気配の鮮度・FRの支払い予定・機会の持続時間・取引ジャーナルの時刻は、MarketStore に持たせた時計で測る。
+// ライブは実時間 (SystemClock)、リプレイとバックテストは記録したフレームの時刻で進める仮想時計 (VirtualClock) を使う。
+// 時計は MarketStore ごとに持つので、同じプロセスで別の期間を何度でもリプレイできる。
+use crate::store::current_timestamp_us;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub trait Clock: Send + Sync + fmt::Debug {
+    /// 現在時刻 (UNIX µs)
+    fn now_us(&self) -> u64;
+
+    /// 現在時刻 (UNIX ms)
+    fn now_ms(&self) -> u64 {
+        self.now_us() / 1_000
+    }
+}
+
+/// 実時間
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_us(&self) -> u64 {
+        current_timestamp_us()
+    }
+}
+
+/// リプレイ・バックテスト用の仮想時計。advance で進めた時刻を返す
+#[derive(Debug, Default)]
+pub struct VirtualClock {
+    now_us: AtomicU64,
+}
+
+impl VirtualClock {
+    pub fn new(start_us: u64) -> Self {
+        Self { now_us: AtomicU64::new(start_us) }
+    }
+
+    /// now_us まで進める。時刻は戻さない (記録の順序が前後しても単調に進む)
+    pub fn advance(&self, now_us: u64) {
+        self.now_us.fetch_max(now_us, Ordering::Relaxed);
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now_us(&self) -> u64 {
+        self.now_us.load(Ordering::Relaxed)
+    }
+}
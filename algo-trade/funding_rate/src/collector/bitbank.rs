@@ -1,164 +1,408 @@
-use crate::store::{MarketStore, Exchange};
-use futures_util::{SinkExt, StreamExt};
-use log::{error, info, debug};
+use super::socketio::{ClientEvent, SocketIoClient};
+use super::{Collector, CollectorError};
+use crate::asset::AssetRegistry;
+use crate::store::{BookSide, DiffOutcome, Exchange, MarketStore, PriceLevel};
+use log::{info, debug, warn};
 use rust_decimal::Decimal;
 use serde::Deserialize;
 use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
 use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
-use tokio::time::sleep;
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
 
 // Bitbank Socket.IO Endpoint
 // EIO=4 (Engine.IO v4), transport=websocket
 const WS_URL: &str = "wss://stream.bitbank.cc/socket.io/?EIO=4&transport=websocket";
 
+/// ルーム名の接頭辞 (ルーム名 = 接頭辞 + 通貨ペア)
+const ROOM_PREFIXES: [&str; 4] = ["ticker_", "depth_whole_", "depth_diff_", "transactions_"];
+
+/// depth_whole を待つ間に通貨ごとに溜める depth_diff の上限 (超えたら古いものから捨てる)
+const MAX_PENDING_DIFFS: usize = 1_000;
+
 #[derive(Deserialize, Debug)]
 struct BitbankTickerData {
     sell: String,
     buy: String,
     last: String,
-    // timestamp: u64,
+    timestamp: Option<u64>,
 }
 
-pub async fn start_collection(store: MarketStore) {
-    loop {
-        info!("[Bitbank] Connecting to WebSocket (Socket.IO)...");
+/// 板の差分 (depth_diff) 1件
+#[derive(Debug, Clone)]
+struct DepthDiff {
+    levels: Vec<(BookSide, PriceLevel)>,
+    sequence: Option<u64>,
+    exchange_ts_ms: Option<u64>,
+}
 
-        match connect_async(WS_URL).await {
-            Ok((ws_stream, _)) => {
-                info!("[Bitbank] WebSocket connected");
-                if let Err(e) = handle_socket_io(ws_stream, &store).await {
-                    error!("[Bitbank] Connection lost: {}", e);
-                }
-            }
-            Err(e) => {
-                error!("[Bitbank] Connect failed: {}", e);
-            }
-        }
+pub struct BitbankCollector {
+    rooms: Vec<String>,
+    registry: Arc<AssetRegistry>,
+    client: SocketIoClient,
+    /// スナップショット (depth_whole) 受信前に届いた差分。キーはストアのシンボル
+    pending_diffs: HashMap<String, VecDeque<DepthDiff>>,
+}
 
-        sleep(Duration::from_secs(5)).await;
+impl BitbankCollector {
+    pub fn new(registry: Arc<AssetRegistry>) -> Self {
+        // 購読するルーム: 設定された通貨ペアごとに
+        // ticker / 板 (全量スナップショット + 差分) / 約定履歴
+        let rooms = registry
+            .configs()
+            .iter()
+            .filter_map(|c| c.bitbank.as_deref())
+            .flat_map(|pair| ROOM_PREFIXES.iter().map(move |prefix| format!("{}{}", prefix, pair)))
+            .collect();
+        Self {
+            rooms,
+            registry,
+            client: SocketIoClient::new("/"),
+            pending_diffs: HashMap::new(),
+        }
     }
 }
 
-async fn handle_socket_io(
-    stream: tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>,
-    store: &MarketStore
-) -> Result<(), Box<dyn std::error::Error>> {
-    let (mut write, mut read) = stream.split();
-
-    // Socket.IO Handshake Flow:
-    // 1. Receive Open packet (0{...})
-    // 2. Send Connect packet (40)
-    // 3. Receive Connect packet (40{...})
-    // 4. Send Subscribe (42["join-room", ...])
-    // 5. Loop (Handle 2=Ping -> Send 3=Pong, Handle 42=Message)
-
-    // 購読するルーム
-    let rooms = vec![
-        "ticker_btc_jpy",
-        // "ticker_eth_jpy", // 必要なら追加
-    ];
-
-    while let Some(msg) = read.next().await {
-        let msg = msg?;
-        match msg {
-            Message::Text(text) => {
-                // Engine.IO packet types:
-                // '0': Open
-                // '2': Ping
-                // '3': Pong
-                // '4': Message (Socket.IO types inside)
-                
-                if text.starts_with('0') {
-                    // Open packet: {"sid":"...", "pingInterval":25000, ...}
-                    debug!("[Bitbank] Received Open packet: {}", text);
-                    
-                    // Namespaceへの接続 (40)
-                    write.send(Message::Text("40".to_string())).await?;
-                    info!("[Bitbank] Sent Namespace Connect (40)");
-
-                } else if text.starts_with("40") {
-                    // Connected to Namespace
-                    debug!("[Bitbank] Namespace connected: {}", text);
-
-                    // ルームへのJoin (42["join-room", "room_name"])
-                    for room in &rooms {
-                        let join_payload = format!("42[\"join-room\",\"{}\"]", room);
-                        write.send(Message::Text(join_payload)).await?;
-                        info!("[Bitbank] Joined room: {}", room);
-                    }
+impl Collector for BitbankCollector {
+    fn name(&self) -> &'static str {
+        "Bitbank"
+    }
 
-                } else if text.starts_with('2') {
-                    // Ping受信 -> Pong (3) を返す
-                    // debug!("[Bitbank] Ping received");
-                    write.send(Message::Text("3".to_string())).await?;
-                    // debug!("[Bitbank] Pong sent");
-
-                } else if text.starts_with("42") {
-                    // Event Message: 42["message", {...}]
-                    // 最初の2文字 "42" をスキップしてJSON配列としてパース
-                    let json_str = &text[2..];
-                    if let Ok(val) = serde_json::from_str::<Value>(json_str) {
-                        // 配列形式: ["message", { "room_name": "...", "message": { "data": ... } }]
-                        if let Some(event_name) = val.get(0).and_then(|v| v.as_str()) {
-                            if event_name == "message" {
-                                if let Some(payload) = val.get(1) {
-                                    process_data(payload, store);
-                                }
-                            }
-                        }
+    fn exchange(&self) -> Exchange {
+        Exchange::Bitbank
+    }
+
+    fn url(&self) -> String {
+        WS_URL.to_string()
+    }
+
+    // Socket.IO ではハンドシェイク後にルームへ参加するため、接続直後には何も送らない
+    fn subscriptions(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    // Socket.IO Handshake Flow (SocketIoClient が処理する):
+    // 1. Receive Open packet (0{...}) -> Send Connect packet (40)
+    // 2. Receive Connect packet (40{...}) -> Send Subscribe (42["join-room", ...])
+    // 3. Loop (Handle 2=Ping -> Send 3=Pong, Handle 42=Message)
+    fn handle_text(&mut self, text: &str, store: &MarketStore) -> Result<Vec<String>, CollectorError> {
+        for event in self.client.handle_frame(text)? {
+            match event {
+                ClientEvent::Connected { namespace } => {
+                    debug!("[Bitbank] Namespace connected: {}", namespace);
+                    for room in &self.rooms {
+                        info!("[Bitbank] Joining room: {}", room);
+                        self.client.emit("join-room", vec![Value::String(room.clone())]);
                     }
                 }
+                // 配列形式: ["message", { "room_name": "...", "message": { "data": ... } }]
+                ClientEvent::Event { name, args, .. } if name == "message" => {
+                    if let Some(payload) = args.first() {
+                        self.process_data(payload, store);
+                    }
+                }
+                other => debug!("[Bitbank] Ignoring socket.io event: {:?}", other),
             }
-            Message::Close(_) => return Ok(()),
-            _ => {}
         }
+        Ok(self.client.take_outgoing())
+    }
+
+    fn on_connect(&mut self) {
+        self.client.reset();
+        self.pending_diffs.clear();
     }
 
-    Ok(())
+    // 何も受信しないまま pingInterval + pingTimeout が過ぎたら切断する。
+    // データだけが流れて Ping が途絶えた場合は SocketIoClient が handle_text でエラーを返す
+    fn read_timeout(&self) -> Option<Duration> {
+        self.client.read_timeout()
+    }
 }
 
-fn process_data(payload: &Value, store: &MarketStore) {
-    // payload structure:
-    // {
-    //   "room_name": "ticker_btc_jpy",
-    //   "message": {
-    //     "data": { "sell": "...", "buy": "...", "last": "...", ... }
-    //   }
-    // }
-
-    let room_name = payload["room_name"].as_str().unwrap_or("");
-    
-    // 通貨ペアの判定
-    let symbol = if room_name.contains("btc_jpy") {
-        "BTC"
-    } else {
-        return; // 未知のペア
-    };
-
-    if let Some(data) = payload.get("message").and_then(|m| m.get("data")) {
-        // Tickerデータのパース
-        // Bitbankは文字列で数値を返してくる
-        if let (Some(sell_str), Some(buy_str), Some(last_str)) = (
-            data["sell"].as_str(),
-            data["buy"].as_str(),
-            data["last"].as_str(),
-        ) {
-            if let (Ok(ask), Ok(bid), Ok(last)) = (
-                Decimal::from_str(sell_str),
-                Decimal::from_str(buy_str),
-                Decimal::from_str(last_str),
-            ) {
-                store.update_market_data(
-                    Exchange::Bitbank,
-                    symbol,
-                    bid,
-                    ask,
-                    last
-                );
-                // info!("[Bitbank WS] Updated {}: {}", symbol, last);
+impl BitbankCollector {
+    fn process_data(&mut self, payload: &Value, store: &MarketStore) {
+        // payload structure:
+        // {
+        //   "room_name": "ticker_btc_jpy",
+        //   "message": {
+        //     "data": { "sell": "...", "buy": "...", "last": "...", ... }
+        //   }
+        // }
+
+        let room_name = payload["room_name"].as_str().unwrap_or("");
+
+        // ルーム名 (例: depth_diff_btc_jpy) を種類と通貨ペアに分け、ペアから通貨を引く
+        let Some((kind, pair)) = ROOM_PREFIXES
+            .into_iter()
+            .find_map(|prefix| room_name.strip_prefix(prefix).map(|pair| (prefix, pair)))
+        else {
+            return;
+        };
+        let registry = self.registry.clone();
+        let Some(listing) = registry.lookup(Exchange::Bitbank, pair) else {
+            return; // 未知のペア
+        };
+        let symbol = listing.store_key.as_str();
+
+        let Some(data) = payload.get("message").and_then(|m| m.get("data")) else {
+            return;
+        };
+
+        match kind {
+            "ticker_" => process_ticker(data, symbol, store),
+            "depth_whole_" => self.process_depth_whole(data, symbol, store),
+            "depth_diff_" => self.process_depth_diff(data, symbol, store),
+            _ => process_transactions(data, symbol, store),
+        }
+    }
+
+    // depth_whole: { "asks": [["price", "size"], ...], "bids": [...], "timestamp": 1234, "sequenceId": "5678" }
+    // 反映後、受信前に溜めた差分のうち通番がスナップショットより新しいものを順に適用する
+    fn process_depth_whole(&mut self, data: &Value, symbol: &str, store: &MarketStore) {
+        let bids = parse_levels(&data["bids"]);
+        let asks = parse_levels(&data["asks"]);
+        if bids.is_empty() || asks.is_empty() {
+            return;
+        }
+        store.update_order_book(
+            Exchange::Bitbank,
+            symbol,
+            &bids,
+            &asks,
+            parse_sequence(&data["sequenceId"]),
+            data["timestamp"].as_u64(),
+        );
+
+        let Some(pending) = self.pending_diffs.remove(symbol) else {
+            return;
+        };
+        let mut pending: Vec<DepthDiff> = pending.into();
+        pending.sort_by_key(|d| d.sequence);
+        for diff in pending {
+            // 途中で通番が飛んだら、残りは次の depth_whole まで溜め直す
+            if apply_depth_diff(&diff, symbol, store) == DiffOutcome::NoSnapshot {
+                self.buffer_diff(symbol, diff);
             }
         }
     }
-}
\ No newline at end of file
+
+    // depth_diff: { "a": [["price", "size"], ...], "b": [...], "t": 1234, "s": "5679" }
+    // 数量 "0" はそのレベルの削除
+    fn process_depth_diff(&mut self, data: &Value, symbol: &str, store: &MarketStore) {
+        let levels = parse_levels(&data["b"])
+            .into_iter()
+            .map(|l| (BookSide::Bid, l))
+            .chain(parse_levels(&data["a"]).into_iter().map(|l| (BookSide::Ask, l)))
+            .collect();
+        let diff = DepthDiff { levels, sequence: parse_sequence(&data["s"]), exchange_ts_ms: data["t"].as_u64() };
+
+        // スナップショット受信前 (通番の欠落で板を破棄した後も含む) は depth_whole まで溜めておく
+        if apply_depth_diff(&diff, symbol, store) == DiffOutcome::NoSnapshot {
+            self.buffer_diff(symbol, diff);
+        }
+    }
+
+    fn buffer_diff(&mut self, symbol: &str, diff: DepthDiff) {
+        let pending = self.pending_diffs.entry(symbol.to_string()).or_default();
+        if pending.len() >= MAX_PENDING_DIFFS {
+            pending.pop_front();
+        }
+        pending.push_back(diff);
+    }
+}
+
+fn process_ticker(data: &Value, symbol: &str, store: &MarketStore) {
+    // Tickerデータのパース
+    // Bitbankは文字列で数値を返してくる
+    if let Ok(ticker) = BitbankTickerData::deserialize(data)
+        && let (Ok(ask), Ok(bid), Ok(last)) = (
+            Decimal::from_str(&ticker.sell),
+            Decimal::from_str(&ticker.buy),
+            Decimal::from_str(&ticker.last),
+        )
+    {
+        store.update_market_data(
+            Exchange::Bitbank,
+            symbol,
+            bid,
+            ask,
+            last,
+            ticker.timestamp
+        );
+    }
+}
+
+fn apply_depth_diff(diff: &DepthDiff, symbol: &str, store: &MarketStore) -> DiffOutcome {
+    let outcome = store.apply_order_book_diff(Exchange::Bitbank, symbol, &diff.levels, diff.sequence, diff.exchange_ts_ms);
+    match outcome {
+        DiffOutcome::Applied | DiffOutcome::NoSnapshot => {}
+        DiffOutcome::Gap { expected, received } => {
+            warn!("[Bitbank] Depth diff gap for {} (expected seq {}, got {}); waiting for depth_whole", symbol, expected, received);
+        }
+        DiffOutcome::Stale => debug!("[Bitbank] Skipped stale depth diff for {} (seq {:?})", symbol, diff.sequence),
+    }
+    outcome
+}
+
+// transactions: { "transactions": [{ "transaction_id": 1, "side": "buy", "price": "...", "amount": "...", "executed_at": 1234 }, ...] }
+// 約定時刻が最も新しいものを最終約定とする
+fn process_transactions(data: &Value, symbol: &str, store: &MarketStore) {
+    if let Some(price) = data["transactions"]
+        .as_array()
+        .and_then(|txs| txs.iter().max_by_key(|t| t["executed_at"].as_u64().unwrap_or(0)))
+        .and_then(|t| t["price"].as_str())
+        .and_then(|p| Decimal::from_str(p).ok())
+    {
+        store.update_last_trade(Exchange::Bitbank, symbol, price);
+    }
+}
+
+fn parse_levels(side: &Value) -> Vec<PriceLevel> {
+    side.as_array()
+        .map(|levels| {
+            levels
+                .iter()
+                .filter_map(|l| {
+                    Some(PriceLevel {
+                        price: Decimal::from_str(l.get(0)?.as_str()?).ok()?,
+                        size: Decimal::from_str(l.get(1)?.as_str()?).ok()?,
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// sequenceId は文字列で届く
+fn parse_sequence(v: &Value) -> Option<u64> {
+    v.as_str().and_then(|s| s.parse().ok()).or_else(|| v.as_u64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asset::AssetConfig;
+
+    const OPEN: &str = r#"0{"sid":"lv_VI97HAXpY6yYWAAAC","upgrades":[],"pingInterval":25000,"pingTimeout":60000,"maxPayload":1000000}"#;
+
+    fn message(room: &str, data: &str) -> String {
+        format!(r#"42["message",{{"room_name":"{}","message":{{"data":{}}}}}]"#, room, data)
+    }
+
+    /// ハンドシェイクを済ませたコレクター
+    fn connected() -> (BitbankCollector, MarketStore) {
+        let registry = Arc::new(AssetRegistry::new(AssetConfig::defaults()));
+        let mut collector = BitbankCollector::new(registry);
+        let store = MarketStore::new();
+        collector.on_connect();
+        assert_eq!(collector.handle_text(OPEN, &store).unwrap(), vec!["40".to_string()]);
+        let joins = collector.handle_text(r#"40{"sid":"x"}"#, &store).unwrap();
+        assert!(joins.contains(&r#"42["join-room","depth_diff_btc_jpy"]"#.to_string()));
+        assert_eq!(joins.len(), 12);
+        (collector, store)
+    }
+
+    fn best(store: &MarketStore) -> (Decimal, Decimal) {
+        let book = store.get_order_book(Exchange::Bitbank, "BTC").unwrap();
+        (book.best_bid().unwrap().price, book.best_ask().unwrap().price)
+    }
+
+    const WHOLE: &str = r#"{"asks":[["10100","1"],["10200","2"]],"bids":[["10000","2"],["9900","3"]],"timestamp":1700000000000,"sequenceId":"100"}"#;
+
+    fn diff(seq: u64, bid: &str) -> String {
+        message("depth_diff_btc_jpy", &format!(r#"{{"a":[],"b":[["{}","1"]],"t":1700000000000,"s":"{}"}}"#, bid, seq))
+    }
+
+    #[test]
+    fn depth_diff_is_gated_by_snapshot_sequence() {
+        let (mut collector, store) = connected();
+
+        // スナップショット前の差分は溜めておき、スナップショット以前の通番のものは適用しない
+        collector.handle_text(&diff(99, "10050"), &store).unwrap();
+        assert!(store.get_order_book(Exchange::Bitbank, "BTC").is_none());
+
+        collector.handle_text(&message("depth_whole_btc_jpy", WHOLE), &store).unwrap();
+        assert_eq!(best(&store), (Decimal::from(10_000), Decimal::from(10_100)));
+
+        // スナップショット以前の sequence の差分は適用しない
+        collector.handle_text(&diff(100, "10050"), &store).unwrap();
+        assert_eq!(best(&store), (Decimal::from(10_000), Decimal::from(10_100)));
+
+        collector.handle_text(&diff(101, "10050"), &store).unwrap();
+        assert_eq!(best(&store), (Decimal::from(10_050), Decimal::from(10_100)));
+
+        // 数量 0 はレベルの削除
+        let remove = message("depth_diff_btc_jpy", r#"{"a":[["10100","0"]],"b":[],"t":1700000000001,"s":"102"}"#);
+        collector.handle_text(&remove, &store).unwrap();
+        assert_eq!(best(&store), (Decimal::from(10_050), Decimal::from(10_200)));
+    }
+
+    #[test]
+    fn diffs_before_the_snapshot_are_applied_after_it() {
+        let (mut collector, store) = connected();
+        // 到着順が入れ替わっても通番順に適用する
+        for (seq, bid) in [(102, "10060"), (100, "9000"), (101, "10050")] {
+            collector.handle_text(&diff(seq, bid), &store).unwrap();
+        }
+        assert!(store.get_order_book(Exchange::Bitbank, "BTC").is_none());
+
+        collector.handle_text(&message("depth_whole_btc_jpy", WHOLE), &store).unwrap();
+        let book = store.get_order_book(Exchange::Bitbank, "BTC").unwrap();
+        assert_eq!(book.sequence, Some(102));
+        assert_eq!(best(&store), (Decimal::from(10_060), Decimal::from(10_100)));
+        assert!(!book.bids.contains_key(&Decimal::from(9_000)));
+
+        // 溜めた差分は一度だけ適用する
+        collector.handle_text(&message("depth_whole_btc_jpy", WHOLE), &store).unwrap();
+        assert_eq!(best(&store), (Decimal::from(10_000), Decimal::from(10_100)));
+    }
+
+    #[test]
+    fn gap_waits_for_the_next_snapshot() {
+        let (mut collector, store) = connected();
+        collector.handle_text(&message("depth_whole_btc_jpy", WHOLE), &store).unwrap();
+        collector.handle_text(&diff(101, "10050"), &store).unwrap();
+
+        // 102 が欠けたので板を破棄し、以降の差分は次の depth_whole まで溜める
+        collector.handle_text(&diff(103, "10060"), &store).unwrap();
+        assert!(store.get_order_book(Exchange::Bitbank, "BTC").is_none());
+        collector.handle_text(&diff(104, "10070"), &store).unwrap();
+        assert!(store.get_order_book(Exchange::Bitbank, "BTC").is_none());
+
+        let whole = WHOLE.replace(r#""sequenceId":"100""#, r#""sequenceId":"103""#);
+        collector.handle_text(&message("depth_whole_btc_jpy", &whole), &store).unwrap();
+        assert_eq!(best(&store), (Decimal::from(10_070), Decimal::from(10_100)));
+    }
+
+    #[test]
+    fn reconnect_discards_buffered_diffs() {
+        let (mut collector, store) = connected();
+        collector.handle_text(&diff(101, "10050"), &store).unwrap();
+        collector.on_connect();
+        collector.handle_text(&message("depth_whole_btc_jpy", WHOLE), &store).unwrap();
+        assert_eq!(best(&store), (Decimal::from(10_000), Decimal::from(10_100)));
+    }
+
+    #[test]
+    fn transactions_update_last_trade() {
+        let (mut collector, store) = connected();
+        let data = r#"{"transactions":[{"transaction_id":2,"side":"buy","price":"10120","amount":"0.1","executed_at":1700000000002},{"transaction_id":1,"side":"sell","price":"10080","amount":"0.1","executed_at":1700000000001}]}"#;
+        collector.handle_text(&message("transactions_btc_jpy", data), &store).unwrap();
+        assert_eq!(store.get_market_data(Exchange::Bitbank, "BTC").unwrap().last_price, Decimal::from(10_120));
+    }
+
+    #[test]
+    fn unknown_rooms_are_ignored() {
+        let (mut collector, store) = connected();
+        let data = r#"{"sell":"1","buy":"1","last":"1","timestamp":1}"#;
+        collector.handle_text(&message("ticker_xyz_jpy", data), &store).unwrap();
+        assert!(store.get_market_data(Exchange::Bitbank, "XYZ").is_none());
+    }
+
+    #[test]
+    fn server_ping_is_answered() {
+        let (mut collector, store) = connected();
+        assert_eq!(collector.handle_text("2", &store).unwrap(), vec!["3".to_string()]);
+    }
+}
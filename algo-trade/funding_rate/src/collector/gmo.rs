@@ -1,95 +1,251 @@
-use crate::store::{MarketStore, Exchange};
-use futures_util::{SinkExt, StreamExt};
-use log::{error, info, warn};
+use super::{Collector, CollectorError};
+use crate::asset::AssetRegistry;
+use crate::store::{MarketStore, Exchange, PriceLevel};
+use crate::strategy::InstrumentType;
 use rust_decimal::Decimal;
-use serde_json::json;
+use serde_json::{json, Value};
 use std::str::FromStr;
-use std::time::Duration;
-use tokio::time::sleep;
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
+use std::sync::Arc;
 
 const GMO_WS_URL: &str = "wss://api.coin.z.com/ws/public/v1";
 
-pub async fn start_collection(symbols: Vec<String>, store: MarketStore) {
-    loop {
-        info!("[GMO] Connecting to WebSocket...");
+pub struct GmoCollector {
+    registry: Arc<AssetRegistry>,
+}
+
+impl GmoCollector {
+    pub fn new(registry: Arc<AssetRegistry>) -> Self {
+        Self { registry }
+    }
+}
+
+impl Collector for GmoCollector {
+    fn name(&self) -> &'static str {
+        "GMO"
+    }
 
-        match connect_async(GMO_WS_URL).await {
-            Ok((ws_stream, _)) => {
-                info!("[GMO] WebSocket connected");
-                if let Err(e) = handle_socket(ws_stream, &symbols, &store).await {
-                    error!("[GMO] Connection error: {}", e);
+    fn exchange(&self) -> Exchange {
+        Exchange::Gmo
+    }
+
+    fn url(&self) -> String {
+        GMO_WS_URL.to_string()
+    }
+
+    fn subscriptions(&self) -> Vec<String> {
+        // 1. 為替の購読
+        let mut subs = vec![json!({
+            "command": "subscribe", "channel": "ticker", "symbol": "USD_JPY"
+        }).to_string()];
+
+        // 2. 仮想通貨（現物・レバレッジ両方）の購読 (ticker + 板)
+        for asset in self.registry.assets() {
+            // 現物 (例: BTC), レバレッジ (例: BTC_JPY)
+            let symbols = [InstrumentType::Spot, InstrumentType::Margin]
+                .into_iter()
+                .filter_map(|instrument| self.registry.venue_symbol(asset, Exchange::Gmo, instrument));
+            for symbol in symbols {
+                for channel in ["ticker", "orderbooks"] {
+                    subs.push(json!({
+                        "command": "subscribe", "channel": channel, "symbol": symbol
+                    }).to_string());
                 }
             }
-            Err(e) => {
-                error!("[GMO] Connection failed: {}", e);
-            }
         }
-        
-        warn!("[GMO] Reconnecting in 5 seconds...");
-        sleep(Duration::from_secs(5)).await;
+        subs
+    }
+
+    fn handle_text(&mut self, text: &str, store: &MarketStore) -> Result<Vec<String>, CollectorError> {
+        let v: Value = serde_json::from_str(text)?;
+        match v["channel"].as_str() {
+            Some("ticker") => process_ticker(&v, store, &self.registry),
+            Some("orderbooks") => process_orderbooks(&v, store, &self.registry),
+            _ => {}
+        }
+        Ok(Vec::new())
     }
 }
 
-async fn handle_socket(
-    stream: tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>,
-    symbols: &[String],
-    store: &MarketStore
-) -> Result<(), Box<dyn std::error::Error>> {
-    let (mut write, mut read) = stream.split();
-
-    // 1. 為替の購読
-    write.send(Message::Text(json!({
-        "command": "subscribe", "channel": "ticker", "symbol": "USD_JPY"
-    }).to_string())).await?;
-
-    // 2. 仮想通貨（現物・レバレッジ両方）の購読
-    for sym in symbols {
-        // 現物 (例: BTC)
-        write.send(Message::Text(json!({
-            "command": "subscribe", "channel": "ticker", "symbol": sym
-        }).to_string())).await?;
-        
-        // レバレッジ (例: BTC_JPY)
-        write.send(Message::Text(json!({
-            "command": "subscribe", "channel": "ticker", "symbol": format!("{}_JPY", sym)
-        }).to_string())).await?;
+/// ticker チャンネルのメッセージを MarketStore に反映
+pub fn process_ticker(v: &Value, store: &MarketStore, registry: &AssetRegistry) {
+    let Some(store_key) = v["symbol"].as_str().and_then(|s| store_key(s, registry)) else {
+        return;
+    };
+    let bid_str = v["bid"].as_str().unwrap_or("0");
+    let ask_str = v["ask"].as_str().unwrap_or("0");
+
+    if let (Ok(bid), Ok(ask)) = (Decimal::from_str(bid_str), Decimal::from_str(ask_str)) {
+        let mid = (bid + ask) / Decimal::from(2);
+
+        store.update_market_data(
+            Exchange::Gmo,
+            &store_key,
+            bid,
+            ask,
+            mid,
+            v["timestamp"].as_str().and_then(parse_timestamp_ms)
+        );
     }
+}
 
-    while let Some(msg) = read.next().await {
-        let msg = msg?;
-        if let Message::Text(text) = msg {
-            let v: serde_json::Value = serde_json::from_str(&text)?;
-            
-            if v["channel"] == "ticker" {
-                let symbol_raw = v["symbol"].as_str().unwrap_or("");
-                let bid_str = v["bid"].as_str().unwrap_or("0");
-                let ask_str = v["ask"].as_str().unwrap_or("0");
-                
-                if let (Ok(bid), Ok(ask)) = (Decimal::from_str(bid_str), Decimal::from_str(ask_str)) {
-                    let mid = (bid + ask) / Decimal::from(2);
-                    
-                    // --- 命名規則のマッピングロジック ---
-                    let (store_key, is_spot) = if symbol_raw == "USD_JPY" {
-                        ("USD_JPY".to_string(), false)
-                    } else if symbol_raw.contains("_JPY") {
-                        // レバレッジ (BTC_JPY -> BTC)
-                        (symbol_raw.replace("_JPY", ""), false)
-                    } else {
-                        // 現物 (BTC -> BTC_SPOT)
-                        (format!("{}_SPOT", symbol_raw), true)
-                    };
-
-                    store.update_market_data(
-                        Exchange::Gmo,
-                        &store_key,
-                        bid,
-                        ask,
-                        mid
-                    );
-                }
+/// orderbooks チャンネル (板のスナップショット) を MarketStore に反映
+pub fn process_orderbooks(v: &Value, store: &MarketStore, registry: &AssetRegistry) {
+    let Some(store_key) = v["symbol"].as_str().and_then(|s| store_key(s, registry)) else {
+        return;
+    };
+    // 各レベルは {"price": "...", "size": "..."}
+    let parse_side = |side: &Value| -> Vec<PriceLevel> {
+        side.as_array()
+            .map(|levels| {
+                levels
+                    .iter()
+                    .filter_map(|l| {
+                        Some(PriceLevel {
+                            price: Decimal::from_str(l["price"].as_str()?).ok()?,
+                            size: Decimal::from_str(l["size"].as_str()?).ok()?,
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let bids = parse_side(&v["bids"]);
+    let asks = parse_side(&v["asks"]);
+    if bids.is_empty() || asks.is_empty() {
+        return;
+    }
+    store.update_order_book(
+        Exchange::Gmo,
+        &store_key,
+        &bids,
+        &asks,
+        None,
+        v["timestamp"].as_str().and_then(parse_timestamp_ms),
+    );
+}
+
+/// GMOのシンボル名から MarketStore のキーを決める。
+/// 為替はそのまま、仮想通貨は対応表から (レバレッジ BTC_JPY -> BTC、現物 BTC -> BTC_SPOT)
+fn store_key(symbol_raw: &str, registry: &AssetRegistry) -> Option<String> {
+    if symbol_raw == "USD_JPY" {
+        return Some("USD_JPY".to_string());
+    }
+    registry.lookup(Exchange::Gmo, symbol_raw).map(|l| l.store_key.clone())
+}
+
+/// GMOのタイムスタンプ ("2018-03-30T12:34:56.789Z", RFC 3339) を UNIX ms に変換。
+/// 時差は Z か ±HH:MM。桁数・範囲が合わないもの (空の小数部など) は None
+pub fn parse_timestamp_ms(s: &str) -> Option<u64> {
+    let (date, rest) = s.split_once('T')?;
+    let (time, offset_min) = match rest.strip_suffix('Z') {
+        Some(time) => (time, 0),
+        None => {
+            let (time, offset) = rest.split_at(rest.rfind(['+', '-'])?);
+            let (hours, minutes) = offset[1..].split_once(':')?;
+            let (hours, minutes) = (digits(hours, 2)?, digits(minutes, 2)?);
+            if hours > 23 || minutes > 59 {
+                return None;
             }
+            let sign = if offset.starts_with('-') { -1 } else { 1 };
+            (time, sign * (hours * 60 + minutes))
+        }
+    };
+
+    let date_parts: Vec<&str> = date.split('-').collect();
+    let [year, month, day] = date_parts[..] else {
+        return None;
+    };
+    let (year, month, day) = (digits(year, 4)?, digits(month, 2)?, digits(day, 2)?);
+    if !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) {
+        return None;
+    }
+
+    let (hms, frac) = match time.split_once('.') {
+        Some((hms, frac)) => (hms, Some(frac)),
+        None => (time, None),
+    };
+    let time_parts: Vec<&str> = hms.split(':').collect();
+    let [hour, minute, second] = time_parts[..] else {
+        return None;
+    };
+    let (hour, minute, second) = (digits(hour, 2)?, digits(minute, 2)?, digits(second, 2)?);
+    if hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    // 小数部はミリ秒の3桁に揃える (4桁目以降は切り捨て)
+    let millis = match frac {
+        Some(frac) if !frac.is_empty() && frac.bytes().all(|b| b.is_ascii_digit()) => {
+            format!("{:0<3}", &frac[..frac.len().min(3)]).parse().ok()?
+        }
+        Some(_) => return None,
+        None => 0,
+    };
+
+    // 1970-01-01 からの日数 (proleptic Gregorian)
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+
+    let ms = (days * 86_400 + hour * 3_600 + minute * 60 + second - offset_min * 60) * 1_000 + millis;
+    u64::try_from(ms).ok()
+}
+
+/// width 桁の数字だけからなる文字列を数値にする
+fn digits(s: &str, width: usize) -> Option<i64> {
+    if s.len() != width || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_utc_and_offset_timestamps() {
+        // 2023-11-14T22:13:20Z = 1,700,000,000,000 ms
+        assert_eq!(parse_timestamp_ms("2023-11-14T22:13:20.123Z"), Some(1_700_000_000_123));
+        assert_eq!(parse_timestamp_ms("2023-11-14T22:13:20Z"), Some(1_700_000_000_000));
+        assert_eq!(parse_timestamp_ms("2023-11-14T22:13:20.1Z"), Some(1_700_000_000_100));
+        assert_eq!(parse_timestamp_ms("2023-11-14T22:13:20.123456Z"), Some(1_700_000_000_123));
+        assert_eq!(parse_timestamp_ms("2023-11-15T07:13:20.123+09:00"), Some(1_700_000_000_123));
+        assert_eq!(parse_timestamp_ms("2023-11-14T17:13:20-05:00"), Some(1_700_000_000_000));
+        assert_eq!(parse_timestamp_ms("2024-02-29T00:00:00Z"), Some(1_709_164_800_000));
+    }
+
+    #[test]
+    fn rejects_malformed_timestamps() {
+        for s in [
+            "2023-11-14T22:13:20.Z",
+            "2023-11-14T22:13:20.12aZ",
+            "2023-11-14T22:13:20",
+            "2023-11-14 22:13:20Z",
+            "2023-11-14T22:13:20+0900",
+            "2023-11-14T22:13:20+24:00",
+            "2023-11-14T22:13Z",
+            "23-11-14T22:13:20Z",
+            "2023-13-01T00:00:00Z",
+            "2023-02-29T00:00:00Z",
+            "2023-11-14T24:00:00Z",
+            "2023-11-14T22:60:00Z",
+            "+2023-11-14T22:13:20Z",
+            "",
+        ] {
+            assert_eq!(parse_timestamp_ms(s), None, "{}", s);
         }
     }
-    Ok(())
-}
\ No newline at end of file
+}
@@ -1,88 +1,73 @@
+use super::{Collector, CollectorError};
 use crate::store::{MarketStore, Exchange};
-use futures_util::{SinkExt, StreamExt};
-use log::{error, info, warn};
+use log::info;
 use rust_decimal::Decimal;
 use serde_json::{json, Value};
 use std::str::FromStr;
-use std::time::Duration;
-use tokio::time::sleep;
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
 
 const KRAKEN_WS_URL: &str = "wss://ws.kraken.com";
 
-pub async fn start_collection(store: MarketStore) {
-    loop {
-        info!("[Kraken] Connecting to WebSocket...");
+/// 為替・ステーブルコインのレート (Ticker) を購読する
+pub struct KrakenCollector {
+    /// MarketStore 上のシンボル名 (例: "USD_JPY", "USDC_USD")
+    symbols: Vec<String>,
+}
 
-        match connect_async(KRAKEN_WS_URL).await {
-            Ok((ws_stream, _)) => {
-                info!("[Kraken] WebSocket connected");
-                if let Err(e) = handle_socket(ws_stream, &store).await {
-                    error!("[Kraken] Connection error: {}", e);
-                }
-            }
-            Err(e) => {
-                error!("[Kraken] Connection failed: {}", e);
-            }
-        }
-        
-        warn!("[Kraken] Reconnecting in 5 seconds...");
-        sleep(Duration::from_secs(5)).await;
+impl KrakenCollector {
+    pub fn new(symbols: Vec<String>) -> Self {
+        Self { symbols }
     }
 }
 
-async fn handle_socket(
-    stream: tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>,
-    store: &MarketStore
-) -> Result<(), Box<dyn std::error::Error>> {
-    let (mut write, mut read) = stream.split();
+/// MarketStore のシンボル名を Kraken のペア名に変換 ("USD_JPY" -> "USD/JPY")
+fn pair_name(symbol: &str) -> String {
+    symbol.replace('_', "/")
+}
 
-    // 購読メッセージ送信 (USD/JPYのTicker)
-    let subscribe_msg = json!({
-        "event": "subscribe",
-        "pair": ["USD/JPY"],
-        "subscription": {
-            "name": "ticker"
-        }
-    });
+impl Collector for KrakenCollector {
+    fn name(&self) -> &'static str {
+        "Kraken"
+    }
 
-    write.send(Message::Text(subscribe_msg.to_string())).await?;
-    info!("[Kraken] Subscribed to USD/JPY ticker");
+    fn exchange(&self) -> Exchange {
+        Exchange::Kraken
+    }
 
-    while let Some(msg) = read.next().await {
-        let msg = msg?;
-        match msg {
-            Message::Text(text) => {
-                // Krakenのメッセージ処理
-                // 配列形式: [ChannelID, {Data}, ChannelName, Pair]
-                // イベント形式: {"event": ...}
-                
-                let v: Value = serde_json::from_str(&text)?;
-                
-                if v.is_array() {
-                    // データメッセージ
-                    if let Some(data) = v.get(1) {
-                        process_ticker_data(data, store);
-                    }
-                } else if v.is_object() {
-                    // イベントメッセージ (heartbeat, systemStatus等)
-                    if let Some(event) = v.get("event").and_then(|e| e.as_str()) {
-                        if event == "heartbeat" {
-                            // 何もしなくてOK
-                        } else if event == "systemStatus" {
-                            // info!("[Kraken] System Status: {:?}", v);
-                        }
-                    }
-                }
+    fn url(&self) -> String {
+        KRAKEN_WS_URL.to_string()
+    }
+
+    fn subscriptions(&self) -> Vec<String> {
+        // 購読メッセージ送信 (USD/JPY・USDC/USD 等のTicker)
+        let pairs: Vec<String> = self.symbols.iter().map(|s| pair_name(s)).collect();
+        let subscribe_msg = json!({
+            "event": "subscribe",
+            "pair": pairs,
+            "subscription": {
+                "name": "ticker"
+            }
+        });
+        info!("[Kraken] Subscribing to {} ticker", pairs.join(", "));
+        vec![subscribe_msg.to_string()]
+    }
+
+    fn handle_text(&mut self, text: &str, store: &MarketStore) -> Result<Vec<String>, CollectorError> {
+        // Krakenのメッセージ処理
+        // 配列形式: [ChannelID, {Data}, ChannelName, Pair]
+        // イベント形式: {"event": ...} (heartbeat, systemStatus等。処理不要)
+        let v: Value = serde_json::from_str(text)?;
+
+        if v.is_array() {
+            // データメッセージ (ペア名 "USD/JPY" -> シンボル名 "USD_JPY")
+            if let (Some(data), Some(pair)) = (v.get(1), v.get(3).and_then(|p| p.as_str())) {
+                process_ticker_data(data, &pair.replace('/', "_"), store);
             }
-            Message::Close(_) => return Ok(()),
-            _ => {}
         }
+        Ok(Vec::new())
     }
-    Ok(())
 }
 
-fn process_ticker_data(data: &Value, store: &MarketStore) {
+pub fn process_ticker_data(data: &Value, symbol: &str, store: &MarketStore) {
     // Tickerフォーマット:
     // {
     //   "a": ["Ask Price", "Whole Lot Vol", "Lot Vol"],
@@ -91,21 +76,19 @@ fn process_ticker_data(data: &Value, store: &MarketStore) {
     //   ...
     // }
 
-    // Last Price (c[0]) を取得
-    if let Some(last_arr) = data.get("c").and_then(|c| c.as_array()) {
-        if let Some(price_str) = last_arr.get(0).and_then(|p| p.as_str()) {
-            if let Ok(price) = Decimal::from_str(price_str) {
-                // Bid/Askも取れるが、為替レートとしてはLastかMidで十分
-                // ここでは便宜上すべてLast Priceを入れておく
-                store.update_market_data(
-                    Exchange::Kraken,
-                    "USD_JPY",
-                    price, // bid
-                    price, // ask
-                    price  // last
-                );
-                // debug!("[Kraken] Updated USD/JPY: {}", price);
-            }
-        }
+    let first = |key: &str| -> Option<Decimal> {
+        Decimal::from_str(data.get(key)?.as_array()?.first()?.as_str()?).ok()
+    };
+    if let (Some(bid), Some(ask)) = (first("b"), first("a")) {
+        // Last がない場合は仲値で代用
+        let last = first("c").unwrap_or((bid + ask) / Decimal::from(2));
+        store.update_market_data(
+            Exchange::Kraken,
+            symbol,
+            bid,
+            ask,
+            last,
+            None // Kraken v1 の ticker には時刻が含まれない
+        );
     }
-}
\ No newline at end of file
+}
@@ -1,4 +1,41 @@
 pub mod hyperliquid;
 pub mod bitbank;
 pub mod kraken;
-pub mod gmo;
\ No newline at end of file
+pub mod gmo;
+pub mod reconnect;
+pub mod socketio;
+pub mod supervisor;
+
+pub use reconnect::*;
+pub use supervisor::*;
+
+use crate::store::{Exchange, MarketStore};
+use std::time::Duration;
+
+pub type CollectorError = Box<dyn std::error::Error + Send + Sync>;
+
+/// 取引所ごとのデータ収集器。
+/// 接続・再接続・死活監視は Supervisor が受け持ち、実装側は購読内容とメッセージ処理だけを書く
+pub trait Collector: Send + 'static {
+    /// ログや死活監視に使う名前 (例: "GMO")
+    fn name(&self) -> &'static str;
+
+    fn exchange(&self) -> Exchange;
+
+    /// 接続先 WebSocket URL
+    fn url(&self) -> String;
+
+    /// 接続直後に送る購読メッセージ
+    fn subscriptions(&self) -> Vec<String>;
+
+    /// 受信したテキストフレームを処理し、返信すべきフレームを返す
+    fn handle_text(&mut self, text: &str, store: &MarketStore) -> Result<Vec<String>, CollectorError>;
+
+    /// 新しい接続の確立時に呼ばれる (接続単位の状態のリセット用)
+    fn on_connect(&mut self) {}
+
+    /// この時間何も受信しなければ接続が死んでいるとみなして切断する (None なら監視しない)
+    fn read_timeout(&self) -> Option<Duration> {
+        None
+    }
+}
@@ -0,0 +1,279 @@
+use rand::Rng;
+use std::fmt;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite;
+
+/// 切断・接続失敗の原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureKind {
+    /// 名前解決に失敗
+    Dns,
+    /// TLSハンドシェイクの失敗
+    Tls,
+    /// HTTP 429 (接続数・購読数の制限)
+    RateLimited,
+    /// HTTP 5xx 等、取引所側の障害
+    ServerError,
+    /// サーバーから Close フレームを受けて正常に切断
+    ServerClosed,
+    /// TCPレベルの切断・タイムアウト
+    Network,
+    /// 想定外のメッセージ・パース失敗など
+    Protocol,
+}
+
+impl FailureKind {
+    /// WebSocket のエラーから原因を判定する
+    pub fn classify(err: &tungstenite::Error) -> Self {
+        use tungstenite::Error;
+        use tungstenite::error::ProtocolError;
+        match err {
+            Error::Tls(_) => FailureKind::Tls,
+            Error::Http(resp) if resp.status().as_u16() == 429 => FailureKind::RateLimited,
+            Error::Http(resp) if resp.status().is_server_error() => FailureKind::ServerError,
+            Error::Http(_) | Error::HttpFormat(_) | Error::Url(_) => FailureKind::Protocol,
+            Error::ConnectionClosed | Error::AlreadyClosed => FailureKind::ServerClosed,
+            Error::Io(e) if is_dns_error(e) => FailureKind::Dns,
+            Error::Io(_) => FailureKind::Network,
+            Error::Protocol(ProtocolError::ResetWithoutClosingHandshake) => FailureKind::Network,
+            _ => FailureKind::Protocol,
+        }
+    }
+}
+
+impl fmt::Display for FailureKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// 名前解決のエラーは io::ErrorKind で区別できないためメッセージで判定する
+fn is_dns_error(e: &std::io::Error) -> bool {
+    let msg = e.to_string();
+    msg.contains("failed to lookup address") || msg.contains("Name or service not known") || msg.contains("nodename nor servname")
+}
+
+/// 再接続ポリシー
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    /// 初回の待機時間
+    pub initial_delay: Duration,
+    /// 待機時間の上限
+    pub max_delay: Duration,
+    /// 失敗ごとの待機時間の倍率
+    pub multiplier: f64,
+    /// 待機時間に掛ける揺らぎの幅 (0.2 = ±20%)
+    pub jitter: f64,
+    /// この時間以上接続が続いたら失敗回数をリセットする
+    pub stable_after: Duration,
+    /// HTTP 429 を受けたときの最小待機時間
+    pub rate_limit_delay: Duration,
+    /// 連続失敗がこの回数に達したらサーキットブレーカーを開く
+    pub breaker_threshold: u32,
+    /// ブレーカーが開いている間の待機時間
+    pub breaker_cooldown: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            multiplier: 2.0,
+            jitter: 0.2,
+            stable_after: Duration::from_secs(30),
+            rate_limit_delay: Duration::from_secs(30),
+            breaker_threshold: 5,
+            breaker_cooldown: Duration::from_secs(120),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    /// 通常運転
+    Closed,
+    /// 連続失敗により停止中。cooldown 後に1回だけ試行する
+    Open,
+    /// Open からの試行中
+    HalfOpen,
+}
+
+/// 1つのコレクターの再接続状態 (指数バックオフ + サーキットブレーカー)
+#[derive(Debug)]
+pub struct Backoff {
+    policy: ReconnectPolicy,
+    consecutive_failures: u32,
+    state: BreakerState,
+}
+
+impl Backoff {
+    pub fn new(policy: ReconnectPolicy) -> Self {
+        Self {
+            policy,
+            consecutive_failures: 0,
+            state: BreakerState::Closed,
+        }
+    }
+
+    pub fn state(&self) -> BreakerState {
+        self.state
+    }
+
+    pub fn consecutive_failures(&self) -> u32 {
+        self.consecutive_failures
+    }
+
+    /// 接続が確立した
+    pub fn on_connected(&mut self) {
+        if self.state == BreakerState::Open {
+            self.state = BreakerState::HalfOpen;
+        }
+    }
+
+    /// 切断 (または接続失敗) を記録し、次の接続までの待機時間を返す。
+    /// connected_for は接続できていた時間 (接続自体に失敗した場合は None)
+    pub fn on_disconnect(&mut self, kind: FailureKind, connected_for: Option<Duration>) -> Duration {
+        let stable = connected_for.is_some_and(|d| d >= self.policy.stable_after);
+        if stable {
+            // 十分に安定していた接続の切断は新しい障害として数え直す
+            self.consecutive_failures = 0;
+            self.state = BreakerState::Closed;
+        }
+
+        // 安定した接続がサーバー都合で閉じられた場合はすぐに繋ぎ直す
+        if stable && kind == FailureKind::ServerClosed {
+            return self.with_jitter(self.policy.initial_delay);
+        }
+
+        self.consecutive_failures += 1;
+        if self.consecutive_failures >= self.policy.breaker_threshold {
+            self.state = BreakerState::Open;
+            return self.with_jitter(self.policy.breaker_cooldown);
+        }
+
+        let exponent = self.consecutive_failures.saturating_sub(1).min(31) as i32;
+        let base = self.policy.initial_delay.as_secs_f64() * self.policy.multiplier.powi(exponent);
+        let mut delay = Duration::from_secs_f64(base.min(self.policy.max_delay.as_secs_f64()));
+        if kind == FailureKind::RateLimited {
+            delay = delay.max(self.policy.rate_limit_delay);
+        }
+        self.with_jitter(delay)
+    }
+
+    fn with_jitter(&self, delay: Duration) -> Duration {
+        if self.policy.jitter <= 0.0 {
+            return delay;
+        }
+        let factor = rand::rng().random_range((1.0 - self.policy.jitter)..=(1.0 + self.policy.jitter));
+        Duration::from_secs_f64(delay.as_secs_f64() * factor.max(0.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_tungstenite::tungstenite::error::ProtocolError;
+    use tokio_tungstenite::tungstenite::http::Response;
+
+    /// 揺らぎなし、1s 始まりで 2 倍ずつ 8s まで、5 回でブレーカーが開くポリシー
+    fn policy() -> ReconnectPolicy {
+        ReconnectPolicy {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(8),
+            multiplier: 2.0,
+            jitter: 0.0,
+            stable_after: Duration::from_secs(30),
+            rate_limit_delay: Duration::from_secs(30),
+            breaker_threshold: 5,
+            breaker_cooldown: Duration::from_secs(120),
+        }
+    }
+
+    #[test]
+    fn delay_grows_exponentially_up_to_max() {
+        let mut backoff = Backoff::new(ReconnectPolicy { breaker_threshold: 10, ..policy() });
+        let delays: Vec<u64> =
+            (0..6).map(|_| backoff.on_disconnect(FailureKind::Network, None).as_secs()).collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 8, 8]);
+        assert_eq!(backoff.consecutive_failures(), 6);
+    }
+
+    #[test]
+    fn jitter_stays_within_bounds() {
+        let policy = ReconnectPolicy { jitter: 0.2, ..policy() };
+        for _ in 0..200 {
+            let delay = Backoff::new(policy.clone()).on_disconnect(FailureKind::Network, None);
+            assert!((0.8..=1.2).contains(&delay.as_secs_f64()), "{:?}", delay);
+        }
+    }
+
+    #[test]
+    fn rate_limit_waits_at_least_rate_limit_delay() {
+        let mut backoff = Backoff::new(policy());
+        assert_eq!(backoff.on_disconnect(FailureKind::RateLimited, None), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn stable_connection_resets_failures() {
+        let mut backoff = Backoff::new(policy());
+        for _ in 0..3 {
+            backoff.on_disconnect(FailureKind::Network, None);
+        }
+        let stable = Some(Duration::from_secs(30));
+
+        // 安定した接続をサーバーが閉じたら失敗に数えずすぐ繋ぎ直す
+        assert_eq!(backoff.on_disconnect(FailureKind::ServerClosed, stable), Duration::from_secs(1));
+        assert_eq!(backoff.consecutive_failures(), 0);
+
+        // 安定した接続の障害は1回目の失敗として数え直す
+        for _ in 0..3 {
+            backoff.on_disconnect(FailureKind::Network, None);
+        }
+        assert_eq!(backoff.on_disconnect(FailureKind::Network, stable), Duration::from_secs(1));
+        assert_eq!(backoff.consecutive_failures(), 1);
+
+        // 短命な接続の切断はリセットしない
+        assert_eq!(backoff.on_disconnect(FailureKind::ServerClosed, Some(Duration::from_secs(29))), Duration::from_secs(2));
+        assert_eq!(backoff.consecutive_failures(), 2);
+    }
+
+    #[test]
+    fn breaker_opens_half_opens_and_closes() {
+        let mut backoff = Backoff::new(policy());
+        for _ in 0..4 {
+            backoff.on_disconnect(FailureKind::Network, None);
+            assert_eq!(backoff.state(), BreakerState::Closed);
+        }
+        assert_eq!(backoff.on_disconnect(FailureKind::Network, None), Duration::from_secs(120));
+        assert_eq!(backoff.state(), BreakerState::Open);
+
+        // cooldown 後の試行で接続できたら HalfOpen、すぐ切れたら再び Open
+        backoff.on_connected();
+        assert_eq!(backoff.state(), BreakerState::HalfOpen);
+        assert_eq!(backoff.on_disconnect(FailureKind::Network, Some(Duration::from_secs(1))), Duration::from_secs(120));
+        assert_eq!(backoff.state(), BreakerState::Open);
+
+        // 安定して繋がった後なら Closed に戻る
+        backoff.on_connected();
+        assert_eq!(backoff.state(), BreakerState::HalfOpen);
+        backoff.on_disconnect(FailureKind::ServerClosed, Some(Duration::from_secs(60)));
+        assert_eq!(backoff.state(), BreakerState::Closed);
+        assert_eq!(backoff.consecutive_failures(), 0);
+    }
+
+    #[test]
+    fn classify_errors() {
+        let http = |status: u16| tungstenite::Error::Http(Response::builder().status(status).body(None).unwrap());
+        assert_eq!(FailureKind::classify(&http(429)), FailureKind::RateLimited);
+        assert_eq!(FailureKind::classify(&http(503)), FailureKind::ServerError);
+        assert_eq!(FailureKind::classify(&http(404)), FailureKind::Protocol);
+        assert_eq!(FailureKind::classify(&tungstenite::Error::ConnectionClosed), FailureKind::ServerClosed);
+        let reset = tungstenite::Error::Protocol(ProtocolError::ResetWithoutClosingHandshake);
+        assert_eq!(FailureKind::classify(&reset), FailureKind::Network);
+        let refused = std::io::Error::from(std::io::ErrorKind::ConnectionRefused);
+        assert_eq!(FailureKind::classify(&tungstenite::Error::Io(refused)), FailureKind::Network);
+        let dns = std::io::Error::other("failed to lookup address information: Name or service not known");
+        assert_eq!(FailureKind::classify(&tungstenite::Error::Io(dns)), FailureKind::Dns);
+    }
+}
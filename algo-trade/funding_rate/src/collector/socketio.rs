@@ -0,0 +1,541 @@
+// Socket.IO v4 (Engine.IO v4, WebSocket トランスポート) の最小限のクライアント。
+//
+// WebSocket の送受信は Supervisor が行うため、ここではフレームの解釈と返信の生成だけを受け持つ。
+// バイナリイベント (packet type 5/6) は扱わない。
+use log::debug;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Engine.IO の Open パケットで通知される接続パラメータ
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpenInfo {
+    pub sid: String,
+    /// サーバーが Ping を送る間隔
+    pub ping_interval: Duration,
+    /// Ping 間隔を超えてこの時間待っても Ping が来なければ切断とみなす
+    pub ping_timeout: Duration,
+    pub max_payload: Option<u64>,
+}
+
+/// Engine.IO のパケット (先頭1文字が種類)
+#[derive(Debug, Clone, PartialEq)]
+pub enum EnginePacket {
+    /// 0{"sid": ..., "pingInterval": ..., "pingTimeout": ...}
+    Open(OpenInfo),
+    /// 1
+    Close,
+    /// 2 (v4 ではサーバーから送られる)
+    Ping(String),
+    /// 3
+    Pong(String),
+    /// 4 + Socket.IO パケット
+    Message(String),
+    /// 5
+    Upgrade,
+    /// 6
+    Noop,
+}
+
+impl EnginePacket {
+    pub fn decode(text: &str) -> Result<Self, SocketIoError> {
+        let mut chars = text.chars();
+        let kind = chars.next().ok_or_else(|| SocketIoError::Malformed("empty frame".to_string()))?;
+        let body = chars.as_str();
+        match kind {
+            '0' => {
+                let v: Value = serde_json::from_str(body)
+                    .map_err(|e| SocketIoError::Malformed(format!("open packet: {}", e)))?;
+                let ms = |key: &str| {
+                    v[key]
+                        .as_u64()
+                        .map(Duration::from_millis)
+                        .ok_or_else(|| SocketIoError::Malformed(format!("open packet without {}", key)))
+                };
+                Ok(EnginePacket::Open(OpenInfo {
+                    sid: v["sid"].as_str().unwrap_or_default().to_string(),
+                    ping_interval: ms("pingInterval")?,
+                    ping_timeout: ms("pingTimeout")?,
+                    max_payload: v["maxPayload"].as_u64(),
+                }))
+            }
+            '1' => Ok(EnginePacket::Close),
+            '2' => Ok(EnginePacket::Ping(body.to_string())),
+            '3' => Ok(EnginePacket::Pong(body.to_string())),
+            '4' => Ok(EnginePacket::Message(body.to_string())),
+            '5' => Ok(EnginePacket::Upgrade),
+            '6' => Ok(EnginePacket::Noop),
+            other => Err(SocketIoError::Malformed(format!("unknown engine.io packet type {:?}", other))),
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        match self {
+            EnginePacket::Open(info) => format!(
+                "0{}",
+                serde_json::json!({
+                    "sid": info.sid,
+                    "upgrades": [],
+                    "pingInterval": info.ping_interval.as_millis() as u64,
+                    "pingTimeout": info.ping_timeout.as_millis() as u64,
+                    "maxPayload": info.max_payload,
+                })
+            ),
+            EnginePacket::Close => "1".to_string(),
+            EnginePacket::Ping(data) => format!("2{}", data),
+            EnginePacket::Pong(data) => format!("3{}", data),
+            EnginePacket::Message(data) => format!("4{}", data),
+            EnginePacket::Upgrade => "5".to_string(),
+            EnginePacket::Noop => "6".to_string(),
+        }
+    }
+}
+
+/// Socket.IO のパケット (Engine.IO の Message に載る)。
+/// 形式: <種類>[<名前空間>,][<ack ID>][JSON]
+#[derive(Debug, Clone, PartialEq)]
+pub enum SocketPacket {
+    /// 0
+    Connect { namespace: String, data: Option<Value> },
+    /// 1
+    Disconnect { namespace: String },
+    /// 2 ["イベント名", 引数...]
+    Event { namespace: String, id: Option<u64>, data: Value },
+    /// 3 [返り値...]
+    Ack { namespace: String, id: u64, data: Value },
+    /// 4 {"message": ...}
+    ConnectError { namespace: String, data: Value },
+}
+
+impl SocketPacket {
+    pub fn decode(text: &str) -> Result<Self, SocketIoError> {
+        let malformed = |what: &str| SocketIoError::Malformed(format!("{}: {}", what, text));
+
+        let mut chars = text.chars();
+        let kind = chars.next().ok_or_else(|| malformed("empty socket.io packet"))?;
+        let mut rest = chars.as_str();
+
+        if matches!(kind, '5' | '6') {
+            return Err(SocketIoError::Unsupported("binary packets".to_string()));
+        }
+
+        // 名前空間 ("/" 以外のときだけ付く)
+        let mut namespace = "/".to_string();
+        if rest.starts_with('/') {
+            match rest.find(',') {
+                Some(i) => {
+                    namespace = rest[..i].to_string();
+                    rest = &rest[i + 1..];
+                }
+                None => {
+                    namespace = rest.to_string();
+                    rest = "";
+                }
+            }
+        }
+
+        // ack ID (JSON の前の数字列)
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let id = if digits > 0 {
+            Some(rest[..digits].parse::<u64>().map_err(|_| malformed("invalid ack id"))?)
+        } else {
+            None
+        };
+        rest = &rest[digits..];
+
+        let data = if rest.is_empty() {
+            None
+        } else {
+            Some(serde_json::from_str::<Value>(rest).map_err(|_| malformed("invalid JSON payload"))?)
+        };
+
+        match kind {
+            '0' => Ok(SocketPacket::Connect { namespace, data }),
+            '1' => Ok(SocketPacket::Disconnect { namespace }),
+            '2' => match data {
+                Some(data @ Value::Array(_)) => Ok(SocketPacket::Event { namespace, id, data }),
+                _ => Err(malformed("event payload is not an array")),
+            },
+            '3' => Ok(SocketPacket::Ack {
+                namespace,
+                id: id.ok_or_else(|| malformed("ack without id"))?,
+                data: data.unwrap_or(Value::Array(Vec::new())),
+            }),
+            '4' => Ok(SocketPacket::ConnectError { namespace, data: data.unwrap_or(Value::Null) }),
+            other => Err(malformed(&format!("unknown socket.io packet type {:?}", other))),
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        let (kind, namespace, id, data) = match self {
+            SocketPacket::Connect { namespace, data } => ('0', namespace, None, data.clone()),
+            SocketPacket::Disconnect { namespace } => ('1', namespace, None, None),
+            SocketPacket::Event { namespace, id, data } => ('2', namespace, *id, Some(data.clone())),
+            SocketPacket::Ack { namespace, id, data } => ('3', namespace, Some(*id), Some(data.clone())),
+            SocketPacket::ConnectError { namespace, data } => ('4', namespace, None, Some(data.clone())),
+        };
+        let mut out = kind.to_string();
+        if namespace != "/" {
+            out.push_str(namespace);
+            out.push(',');
+        }
+        if let Some(id) = id {
+            out.push_str(&id.to_string());
+        }
+        if let Some(data) = data {
+            out.push_str(&data.to_string());
+        }
+        out
+    }
+
+    /// Engine.IO の Message パケットとして送れる文字列にする (先頭に "4")
+    pub fn to_frame(&self) -> String {
+        EnginePacket::Message(self.encode()).encode()
+    }
+}
+
+#[derive(Debug)]
+pub enum SocketIoError {
+    /// 解釈できないフレーム
+    Malformed(String),
+    /// 対応していない機能 (バイナリなど)
+    Unsupported(String),
+    /// 名前空間への接続を拒否された (パケット 44)
+    ConnectRejected { namespace: String, message: String },
+    /// サーバーから Engine.IO Close / Socket.IO Disconnect を受けた
+    Closed(String),
+    /// pingInterval + pingTimeout を過ぎてもサーバーの Ping が来ない
+    PingTimeout(Duration),
+}
+
+impl fmt::Display for SocketIoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SocketIoError::Malformed(msg) => write!(f, "malformed socket.io frame: {}", msg),
+            SocketIoError::Unsupported(what) => write!(f, "unsupported socket.io feature: {}", what),
+            SocketIoError::ConnectRejected { namespace, message } => {
+                write!(f, "socket.io namespace {} rejected connection: {}", namespace, message)
+            }
+            SocketIoError::Closed(reason) => write!(f, "socket.io session closed: {}", reason),
+            SocketIoError::PingTimeout(elapsed) => {
+                write!(f, "no engine.io ping from server for {:.1}s", elapsed.as_secs_f64())
+            }
+        }
+    }
+}
+
+impl std::error::Error for SocketIoError {}
+
+/// クライアントが上位 (コレクター) に通知する出来事
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientEvent {
+    /// 名前空間への接続が完了した
+    Connected { namespace: String },
+    /// サーバーからのイベント
+    Event { name: String, args: Vec<Value>, ack_id: Option<u64> },
+    /// emit_with_ack に対する返答。event は送ったイベント名
+    Ack { id: u64, event: Option<String>, args: Vec<Value> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionState {
+    /// Open パケット待ち
+    Opening,
+    /// 名前空間への接続待ち
+    Connecting,
+    Connected,
+}
+
+/// 1つの WebSocket 接続上の Socket.IO セッション。
+/// 受信フレームを handle_frame に渡し、take_outgoing で返信を取り出して送る
+#[derive(Debug)]
+pub struct SocketIoClient {
+    namespace: String,
+    state: SessionState,
+    open: Option<OpenInfo>,
+    /// 最後にサーバーの Ping (または Open) を受けた時刻
+    last_ping_at: Option<Instant>,
+    next_ack_id: u64,
+    /// 返答待ちの ack ID -> イベント名
+    pending_acks: HashMap<u64, String>,
+    outgoing: Vec<String>,
+}
+
+impl SocketIoClient {
+    pub fn new(namespace: &str) -> Self {
+        Self {
+            namespace: namespace.to_string(),
+            state: SessionState::Opening,
+            open: None,
+            last_ping_at: None,
+            next_ack_id: 0,
+            pending_acks: HashMap::new(),
+            outgoing: Vec::new(),
+        }
+    }
+
+    /// 新しい接続のためにセッションを初期化する
+    pub fn reset(&mut self) {
+        *self = Self::new(&self.namespace);
+    }
+
+    pub fn state(&self) -> SessionState {
+        self.state
+    }
+
+    pub fn open_info(&self) -> Option<&OpenInfo> {
+        self.open.as_ref()
+    }
+
+    /// この時間何も受信しなければ接続が死んでいるとみなす (pingInterval + pingTimeout)
+    pub fn read_timeout(&self) -> Option<Duration> {
+        self.open.as_ref().map(|o| o.ping_interval + o.ping_timeout)
+    }
+
+    pub fn pending_acks(&self) -> usize {
+        self.pending_acks.len()
+    }
+
+    /// 送信待ちのフレームを取り出す
+    pub fn take_outgoing(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.outgoing)
+    }
+
+    /// イベントを送る
+    pub fn emit(&mut self, event: &str, args: Vec<Value>) {
+        self.push_event(event, args, None);
+    }
+
+    /// 返答 (ack) を求めてイベントを送る。返答は ClientEvent::Ack で通知される
+    pub fn emit_with_ack(&mut self, event: &str, args: Vec<Value>) -> u64 {
+        let id = self.next_ack_id;
+        self.next_ack_id += 1;
+        self.pending_acks.insert(id, event.to_string());
+        self.push_event(event, args, Some(id));
+        id
+    }
+
+    /// サーバーから受けたイベントに返答する
+    pub fn ack(&mut self, id: u64, args: Vec<Value>) {
+        let packet = SocketPacket::Ack { namespace: self.namespace.clone(), id, data: Value::Array(args) };
+        self.outgoing.push(packet.to_frame());
+    }
+
+    fn push_event(&mut self, event: &str, args: Vec<Value>, id: Option<u64>) {
+        let mut data = vec![Value::String(event.to_string())];
+        data.extend(args);
+        let packet = SocketPacket::Event { namespace: self.namespace.clone(), id, data: Value::Array(data) };
+        self.outgoing.push(packet.to_frame());
+    }
+
+    /// 受信フレームを1つ処理する。
+    /// Ping への Pong や名前空間への接続要求は take_outgoing で取り出せるようにしておく
+    pub fn handle_frame(&mut self, text: &str) -> Result<Vec<ClientEvent>, SocketIoError> {
+        self.handle_frame_at(text, Instant::now())
+    }
+
+    /// handle_frame の受信時刻を指定できる版。
+    /// データが流れ続けていても、サーバーの Ping が pingInterval + pingTimeout 以上途絶えたらエラーを返す
+    pub fn handle_frame_at(&mut self, text: &str, now: Instant) -> Result<Vec<ClientEvent>, SocketIoError> {
+        let packet = EnginePacket::decode(text)?;
+        if !matches!(packet, EnginePacket::Open(_) | EnginePacket::Ping(_))
+            && let (Some(limit), Some(last_ping_at)) = (self.read_timeout(), self.last_ping_at)
+        {
+            let elapsed = now.saturating_duration_since(last_ping_at);
+            if elapsed > limit {
+                return Err(SocketIoError::PingTimeout(elapsed));
+            }
+        }
+        match packet {
+            EnginePacket::Open(info) => {
+                debug!("socket.io open: sid={} pingInterval={:?} pingTimeout={:?}", info.sid, info.ping_interval, info.ping_timeout);
+                self.open = Some(info);
+                self.last_ping_at = Some(now);
+                self.state = SessionState::Connecting;
+                let connect = SocketPacket::Connect { namespace: self.namespace.clone(), data: None };
+                self.outgoing.push(connect.to_frame());
+                Ok(Vec::new())
+            }
+            EnginePacket::Ping(data) => {
+                self.last_ping_at = Some(now);
+                self.outgoing.push(EnginePacket::Pong(data).encode());
+                Ok(Vec::new())
+            }
+            EnginePacket::Close => Err(SocketIoError::Closed("engine.io close packet".to_string())),
+            EnginePacket::Pong(_) | EnginePacket::Upgrade | EnginePacket::Noop => Ok(Vec::new()),
+            EnginePacket::Message(payload) => self.handle_socket_packet(SocketPacket::decode(&payload)?),
+        }
+    }
+
+    fn handle_socket_packet(&mut self, packet: SocketPacket) -> Result<Vec<ClientEvent>, SocketIoError> {
+        match packet {
+            SocketPacket::Connect { namespace, .. } => {
+                if namespace == self.namespace {
+                    self.state = SessionState::Connected;
+                }
+                Ok(vec![ClientEvent::Connected { namespace }])
+            }
+            SocketPacket::ConnectError { namespace, data } => {
+                let message = data["message"].as_str().map(str::to_string).unwrap_or_else(|| data.to_string());
+                Err(SocketIoError::ConnectRejected { namespace, message })
+            }
+            SocketPacket::Disconnect { namespace } => {
+                Err(SocketIoError::Closed(format!("server disconnected namespace {}", namespace)))
+            }
+            SocketPacket::Event { id, data, .. } => {
+                let mut args = match data {
+                    Value::Array(args) => args,
+                    other => vec![other],
+                };
+                let Some(name) = args.first().and_then(Value::as_str).map(str::to_string) else {
+                    return Err(SocketIoError::Malformed("event without name".to_string()));
+                };
+                args.remove(0);
+                Ok(vec![ClientEvent::Event { name, args, ack_id: id }])
+            }
+            SocketPacket::Ack { id, data, .. } => {
+                let event = self.pending_acks.remove(&id);
+                if event.is_none() {
+                    debug!("socket.io ack for unknown id {}", id);
+                }
+                let args = match data {
+                    Value::Array(args) => args,
+                    other => vec![other],
+                };
+                Ok(vec![ClientEvent::Ack { id, event, args }])
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const OPEN: &str = r#"0{"sid":"lv_VI97HAXpY6yYWAAAC","upgrades":[],"pingInterval":25000,"pingTimeout":20000,"maxPayload":1000000}"#;
+
+    #[test]
+    fn decode_engine_packets() {
+        let EnginePacket::Open(info) = EnginePacket::decode(OPEN).unwrap() else {
+            panic!("not an open packet");
+        };
+        assert_eq!(info.sid, "lv_VI97HAXpY6yYWAAAC");
+        assert_eq!(info.ping_interval, Duration::from_millis(25_000));
+        assert_eq!(info.ping_timeout, Duration::from_millis(20_000));
+        assert_eq!(info.max_payload, Some(1_000_000));
+        assert_eq!(EnginePacket::decode(&EnginePacket::Open(info.clone()).encode()).unwrap(), EnginePacket::Open(info));
+
+        assert_eq!(EnginePacket::decode("2").unwrap(), EnginePacket::Ping(String::new()));
+        assert_eq!(EnginePacket::decode("2probe").unwrap(), EnginePacket::Ping("probe".to_string()));
+        assert_eq!(EnginePacket::Pong("probe".to_string()).encode(), "3probe");
+        assert_eq!(EnginePacket::decode("40").unwrap(), EnginePacket::Message("0".to_string()));
+        assert!(matches!(EnginePacket::decode(""), Err(SocketIoError::Malformed(_))));
+        assert!(matches!(EnginePacket::decode(r#"0{"sid":"x"}"#), Err(SocketIoError::Malformed(_))));
+        assert!(matches!(EnginePacket::decode("9"), Err(SocketIoError::Malformed(_))));
+    }
+
+    #[test]
+    fn decode_socket_packets() {
+        assert_eq!(
+            SocketPacket::decode(r#"0{"sid":"abc"}"#).unwrap(),
+            SocketPacket::Connect { namespace: "/".to_string(), data: Some(json!({"sid": "abc"})) }
+        );
+        assert_eq!(
+            SocketPacket::decode(r#"4/admin,{"message":"Not authorized"}"#).unwrap(),
+            SocketPacket::ConnectError { namespace: "/admin".to_string(), data: json!({"message": "Not authorized"}) }
+        );
+        assert_eq!(
+            SocketPacket::decode(r#"2/chat,12["hello",1]"#).unwrap(),
+            SocketPacket::Event { namespace: "/chat".to_string(), id: Some(12), data: json!(["hello", 1]) }
+        );
+        assert_eq!(
+            SocketPacket::decode(r#"37["ok"]"#).unwrap(),
+            SocketPacket::Ack { namespace: "/".to_string(), id: 7, data: json!(["ok"]) }
+        );
+        assert_eq!(SocketPacket::decode("1/chat").unwrap(), SocketPacket::Disconnect { namespace: "/chat".to_string() });
+        assert!(matches!(SocketPacket::decode("3[]"), Err(SocketIoError::Malformed(_))));
+        assert!(matches!(SocketPacket::decode(r#"2{"a":1}"#), Err(SocketIoError::Malformed(_))));
+        assert!(matches!(SocketPacket::decode(r#"51-["a",{"_placeholder":true,"num":0}]"#), Err(SocketIoError::Unsupported(_))));
+    }
+
+    #[test]
+    fn encode_socket_packets() {
+        let packets = [
+            (SocketPacket::Connect { namespace: "/".to_string(), data: None }, "0"),
+            (SocketPacket::Event { namespace: "/".to_string(), id: None, data: json!(["join-room", "ticker_btc_jpy"]) }, r#"2["join-room","ticker_btc_jpy"]"#),
+            (SocketPacket::Event { namespace: "/chat".to_string(), id: Some(3), data: json!(["hi"]) }, r#"2/chat,3["hi"]"#),
+            (SocketPacket::Ack { namespace: "/".to_string(), id: 3, data: json!([]) }, "33[]"),
+        ];
+        for (packet, encoded) in packets {
+            assert_eq!(packet.encode(), encoded);
+            assert_eq!(SocketPacket::decode(encoded).unwrap(), packet);
+        }
+        assert_eq!(SocketPacket::Connect { namespace: "/".to_string(), data: None }.to_frame(), "40");
+    }
+
+    #[test]
+    fn handshake_ping_and_acks() {
+        let mut client = SocketIoClient::new("/");
+        assert!(client.handle_frame(OPEN).unwrap().is_empty());
+        assert_eq!(client.state(), SessionState::Connecting);
+        assert_eq!(client.read_timeout(), Some(Duration::from_secs(45)));
+        assert_eq!(client.take_outgoing(), vec!["40".to_string()]);
+
+        assert_eq!(
+            client.handle_frame(r#"40{"sid":"abc"}"#).unwrap(),
+            vec![ClientEvent::Connected { namespace: "/".to_string() }]
+        );
+        assert_eq!(client.state(), SessionState::Connected);
+
+        client.handle_frame("2").unwrap();
+        assert_eq!(client.take_outgoing(), vec!["3".to_string()]);
+
+        let id = client.emit_with_ack("join-room", vec![json!("ticker_btc_jpy")]);
+        assert_eq!(client.take_outgoing(), vec![format!(r#"42{}["join-room","ticker_btc_jpy"]"#, id)]);
+        assert_eq!(client.pending_acks(), 1);
+        assert_eq!(
+            client.handle_frame(&format!(r#"43{}["joined"]"#, id)).unwrap(),
+            vec![ClientEvent::Ack { id, event: Some("join-room".to_string()), args: vec![json!("joined")] }]
+        );
+        assert_eq!(client.pending_acks(), 0);
+
+        // サーバーからの ack 付きイベントに返答する
+        let events = client.handle_frame(r#"425["question",1]"#).unwrap();
+        assert_eq!(events, vec![ClientEvent::Event { name: "question".to_string(), args: vec![json!(1)], ack_id: Some(5) }]);
+        client.ack(5, vec![json!("answer")]);
+        assert_eq!(client.take_outgoing(), vec![r#"435["answer"]"#.to_string()]);
+    }
+
+    #[test]
+    fn connect_error_is_reported() {
+        let mut client = SocketIoClient::new("/");
+        client.handle_frame(OPEN).unwrap();
+        match client.handle_frame(r#"44{"message":"Not authorized"}"#) {
+            Err(SocketIoError::ConnectRejected { namespace, message }) => {
+                assert_eq!(namespace, "/");
+                assert_eq!(message, "Not authorized");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn missing_pings_time_out_even_while_data_flows() {
+        let mut client = SocketIoClient::new("/");
+        let start = Instant::now();
+        client.handle_frame_at(OPEN, start).unwrap();
+        client.handle_frame_at("40", start).unwrap();
+
+        let event = r#"42["message",{}]"#;
+        // pingInterval + pingTimeout (45s) 以内は Ping がなくてもよい
+        client.handle_frame_at(event, start + Duration::from_secs(44)).unwrap();
+        client.handle_frame_at("2", start + Duration::from_secs(45)).unwrap();
+        client.handle_frame_at(event, start + Duration::from_secs(89)).unwrap();
+
+        // 最後の Ping から 45s を超えたらデータが届いていても切断する
+        let err = client.handle_frame_at(event, start + Duration::from_secs(91)).unwrap_err();
+        assert!(matches!(err, SocketIoError::PingTimeout(elapsed) if elapsed == Duration::from_secs(46)), "{}", err);
+    }
+}
@@ -0,0 +1,404 @@
+use super::{Backoff, BreakerState, Collector, CollectorError, FailureKind, ReconnectPolicy};
+use crate::recorder::{FrameDirection, Recorder};
+use crate::store::{current_timestamp_ms, Exchange, MarketStore, VenueStatus};
+use dashmap::DashMap;
+use futures_util::{SinkExt, StreamExt};
+use log::{error, info, warn};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
+use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    Disconnected,
+}
+
+/// コレクターごとの死活状況
+#[derive(Debug, Clone)]
+pub struct CollectorHealth {
+    pub name: &'static str,
+    pub exchange: Exchange,
+    pub state: ConnectionState,
+    /// 状態が変わった時刻 (UNIX ms)
+    pub since_ms: u64,
+    pub connections: u64,
+    pub messages: u64,
+    pub last_message_ms: Option<u64>,
+    pub last_error: Option<String>,
+    pub last_failure: Option<FailureKind>,
+    pub breaker: BreakerState,
+    pub consecutive_failures: u32,
+}
+
+/// コレクターのタスクを起動し、再接続と死活監視を受け持つ
+pub struct Supervisor {
+    store: MarketStore,
+    policy: ReconnectPolicy,
+    health: Arc<DashMap<&'static str, CollectorHealth>>,
+    handles: Vec<(&'static str, JoinHandle<()>)>,
+    recorder: Option<Recorder>,
+}
+
+impl Supervisor {
+    pub fn new(store: MarketStore) -> Self {
+        Self {
+            store,
+            policy: ReconnectPolicy::default(),
+            health: Arc::new(DashMap::new()),
+            handles: Vec::new(),
+            recorder: None,
+        }
+    }
+
+    /// 以降に spawn するコレクターの再接続ポリシーを設定する
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// 以降に spawn するコレクターの送受信フレームを記録する
+    pub fn with_recorder(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// コレクターを専用タスクで起動する
+    pub fn spawn(&mut self, collector: Box<dyn Collector>) {
+        let name = collector.name();
+        self.health.insert(name, CollectorHealth {
+            name,
+            exchange: collector.exchange(),
+            state: ConnectionState::Connecting,
+            since_ms: current_timestamp_ms(),
+            connections: 0,
+            messages: 0,
+            last_message_ms: None,
+            last_error: None,
+            last_failure: None,
+            breaker: BreakerState::Closed,
+            consecutive_failures: 0,
+        });
+
+        let store = self.store.clone();
+        let health = self.health.clone();
+        let backoff = Backoff::new(self.policy.clone());
+        let recorder = self.recorder.clone();
+        let handle = tokio::spawn(async move {
+            run_collector(collector, store, health, backoff, recorder).await;
+        });
+        self.handles.push((name, handle));
+    }
+
+    /// 全コレクターの現在の状況
+    pub fn health(&self) -> Vec<CollectorHealth> {
+        let mut list: Vec<CollectorHealth> = self.health.iter().map(|e| e.value().clone()).collect();
+        list.sort_by_key(|h| h.name);
+        list
+    }
+
+    /// 一定間隔で死活状況をログに出すタスクを起動する
+    pub fn spawn_health_reporter(&mut self, interval: Duration) {
+        let health = self.health.clone();
+        let handle = tokio::spawn(async move {
+            loop {
+                sleep(interval).await;
+                let now = current_timestamp_ms();
+                for entry in health.iter() {
+                    let h = entry.value();
+                    let idle = h.last_message_ms.map(|t| format!("{}ms", now.saturating_sub(t))).unwrap_or_else(|| "-".to_string());
+                    info!(
+                        "[Supervisor] {}: {:?} (breaker: {:?}, connections: {}, messages: {}, idle: {})",
+                        h.name, h.state, h.breaker, h.connections, h.messages, idle
+                    );
+                }
+            }
+        });
+        self.handles.push(("health-reporter", handle));
+    }
+
+    /// 全タスクを停止する
+    pub fn shutdown(self) {
+        for (name, handle) in self.handles {
+            info!("[Supervisor] Stopping {}", name);
+            handle.abort();
+        }
+    }
+}
+
+/// 切断理由
+struct Disconnect {
+    kind: FailureKind,
+    message: String,
+}
+
+impl Disconnect {
+    fn from_ws(e: &tokio_tungstenite::tungstenite::Error) -> Self {
+        Self { kind: FailureKind::classify(e), message: e.to_string() }
+    }
+}
+
+/// 接続先URL。環境変数 `<NAME>_WS_URL` (例: GMO_WS_URL) で上書きできる。
+/// ローカルの検証用サーバーに繋ぐときに使う
+fn resolve_url(collector: &dyn Collector) -> String {
+    let key = format!("{}_WS_URL", collector.name().to_uppercase());
+    std::env::var(key).unwrap_or_else(|_| collector.url())
+}
+
+async fn run_collector(
+    mut collector: Box<dyn Collector>,
+    store: MarketStore,
+    health: Arc<DashMap<&'static str, CollectorHealth>>,
+    mut backoff: Backoff,
+    recorder: Option<Recorder>,
+) {
+    let name = collector.name();
+    let exchange = collector.exchange();
+    let mut connection = 0;
+    loop {
+        set_state(&health, name, ConnectionState::Connecting, None);
+        let url = resolve_url(collector.as_ref());
+        info!("[{}] Connecting to {}...", name, url);
+
+        let (disconnect, connected_for) = match connect_async(url.as_str()).await {
+            Ok((ws_stream, _)) => {
+                info!("[{}] WebSocket connected", name);
+                backoff.on_connected();
+                store.set_venue_status(exchange, VenueStatus::Up);
+                set_state(&health, name, ConnectionState::Connected, None);
+                if let Some(mut h) = health.get_mut(name) {
+                    h.connections += 1;
+                }
+                collector.on_connect();
+                connection += 1;
+                record(recorder.as_ref(), exchange, FrameDirection::Connect, connection, &url);
+
+                let connected_at = Instant::now();
+                let disconnect =
+                    run_connection(ws_stream, collector.as_mut(), &store, &health, recorder.as_ref(), connection).await;
+                (disconnect, Some(connected_at.elapsed()))
+            }
+            Err(e) => (Disconnect::from_ws(&e), None),
+        };
+
+        let delay = backoff.on_disconnect(disconnect.kind, connected_for);
+        match disconnect.kind {
+            FailureKind::ServerClosed => warn!("[{}] Disconnected: {}", name, disconnect.message),
+            kind => error!("[{}] Disconnected ({}): {}", name, kind, disconnect.message),
+        }
+
+        if backoff.state() == BreakerState::Open {
+            error!(
+                "[{}] Circuit breaker open after {} consecutive failures; marking {} as down",
+                name, backoff.consecutive_failures(), exchange
+            );
+            store.set_venue_status(exchange, VenueStatus::Down(format!("{}: {}", disconnect.kind, disconnect.message)));
+        }
+
+        set_state(&health, name, ConnectionState::Disconnected, Some(disconnect.message));
+        if let Some(mut h) = health.get_mut(name) {
+            h.last_failure = Some(disconnect.kind);
+            h.breaker = backoff.state();
+            h.consecutive_failures = backoff.consecutive_failures();
+        }
+
+        warn!("[{}] Reconnecting in {:.1} seconds...", name, delay.as_secs_f64());
+        sleep(delay).await;
+    }
+}
+
+async fn run_connection(
+    ws_stream: tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>,
+    collector: &mut dyn Collector,
+    store: &MarketStore,
+    health: &DashMap<&'static str, CollectorHealth>,
+    recorder: Option<&Recorder>,
+    connection: u64,
+) -> Disconnect {
+    let name = collector.name();
+    let exchange = collector.exchange();
+    let (mut write, mut read) = ws_stream.split();
+
+    for sub in collector.subscriptions() {
+        record(recorder, exchange, FrameDirection::Out, connection, &sub);
+        if let Err(e) = write.send(Message::Text(sub)).await {
+            return Disconnect::from_ws(&e);
+        }
+    }
+
+    loop {
+        // プロトコル側のハートビートが途絶えたら切断する
+        let next = match collector.read_timeout() {
+            Some(limit) => match timeout(limit, read.next()).await {
+                Ok(next) => next,
+                Err(_) => {
+                    return Disconnect {
+                        kind: FailureKind::Network,
+                        message: format!("no message received for {:.1}s", limit.as_secs_f64()),
+                    };
+                }
+            },
+            None => read.next().await,
+        };
+        let Some(msg) = next else {
+            break;
+        };
+        let msg = match msg {
+            Ok(msg) => msg,
+            Err(e) => return Disconnect::from_ws(&e),
+        };
+        match msg {
+            Message::Text(text) => {
+                // 判断までの遅延はメッセージの解析を含めて受信時点から測る
+                let received_at = Instant::now();
+                record(recorder, exchange, FrameDirection::In, connection, &text);
+                if let Some(mut h) = health.get_mut(name) {
+                    h.messages += 1;
+                    h.last_message_ms = Some(current_timestamp_ms());
+                }
+                let replies = match collector.handle_text(&text, &store.receiving_at(received_at)) {
+                    Ok(replies) => replies,
+                    Err(e) => return protocol_error(e),
+                };
+                for reply in replies {
+                    record(recorder, exchange, FrameDirection::Out, connection, &reply);
+                    if let Err(e) = write.send(Message::Text(reply)).await {
+                        return Disconnect::from_ws(&e);
+                    }
+                }
+            }
+            Message::Close(frame) => {
+                return Disconnect {
+                    kind: FailureKind::ServerClosed,
+                    message: format!("server closed connection: {:?}", frame),
+                };
+            }
+            // Ping には tungstenite が自動で Pong を返す
+            _ => {}
+        }
+    }
+    Disconnect {
+        kind: FailureKind::Network,
+        message: "stream ended without close frame".to_string(),
+    }
+}
+
+/// 記録が有効ならフレームを記録する
+fn record(recorder: Option<&Recorder>, exchange: Exchange, dir: FrameDirection, connection: u64, text: &str) {
+    if let Some(recorder) = recorder {
+        recorder.record(exchange, dir, connection, text);
+    }
+}
+
+fn protocol_error(e: CollectorError) -> Disconnect {
+    Disconnect {
+        kind: FailureKind::Protocol,
+        message: e.to_string(),
+    }
+}
+
+fn set_state(health: &DashMap<&'static str, CollectorHealth>, name: &'static str, state: ConnectionState, error: Option<String>) {
+    if let Some(mut h) = health.get_mut(name) {
+        h.state = state;
+        h.since_ms = current_timestamp_ms();
+        if error.is_some() {
+            h.last_error = error;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::accept_async;
+
+    /// 受信したフレーム数を数えるだけのコレクター
+    struct CountingCollector {
+        url: String,
+        received: Arc<AtomicUsize>,
+    }
+
+    impl Collector for CountingCollector {
+        fn name(&self) -> &'static str {
+            "SupervisorTest"
+        }
+
+        fn exchange(&self) -> Exchange {
+            Exchange::Kraken
+        }
+
+        fn url(&self) -> String {
+            self.url.clone()
+        }
+
+        fn subscriptions(&self) -> Vec<String> {
+            vec!["subscribe".to_string()]
+        }
+
+        fn handle_text(&mut self, _text: &str, _store: &MarketStore) -> Result<Vec<String>, CollectorError> {
+            self.received.fetch_add(1, Ordering::SeqCst);
+            Ok(Vec::new())
+        }
+    }
+
+    /// 購読を受けたら1フレーム送って切断するローカルサーバー。
+    /// 奇数回目の接続は Close フレームなしで TCP を切り、偶数回目は Close フレームを送る
+    async fn spawn_flaky_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut accepted = 0;
+            while let Ok((stream, _)) = listener.accept().await {
+                accepted += 1;
+                let Ok(mut ws) = accept_async(stream).await else {
+                    continue;
+                };
+                if let Some(Ok(Message::Text(_))) = ws.next().await {
+                    let _ = ws.send(Message::Text("tick".to_string())).await;
+                }
+                if accepted % 2 == 0 {
+                    let _ = ws.close(None).await;
+                }
+                drop(ws);
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn reconnects_after_server_drops_connection() {
+        let url = spawn_flaky_server().await;
+        let received = Arc::new(AtomicUsize::new(0));
+        let policy = ReconnectPolicy {
+            initial_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(50),
+            breaker_threshold: 100,
+            ..ReconnectPolicy::default()
+        };
+        let mut supervisor = Supervisor::new(MarketStore::new()).with_reconnect_policy(policy);
+        supervisor.spawn(Box::new(CountingCollector { url, received: received.clone() }));
+
+        let health = timeout(Duration::from_secs(10), async {
+            loop {
+                let health = supervisor.health().remove(0);
+                if health.connections >= 3 && health.last_failure.is_some() {
+                    return health;
+                }
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("collector did not reconnect");
+
+        assert!(received.load(Ordering::SeqCst) >= 2);
+        assert!(health.messages >= 2);
+        assert!(matches!(health.last_failure, Some(FailureKind::Network | FailureKind::ServerClosed)));
+        assert_eq!(health.breaker, BreakerState::Closed);
+        supervisor.shutdown();
+    }
+}
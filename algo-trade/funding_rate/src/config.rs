@@ -0,0 +1,831 @@
+use crate::asset::{AssetConfig, AssetRegistry};
+use crate::backtest::BacktestConfig;
+use crate::executor::PaperAccountConfig;
+use crate::fx::{FxConfig, FxMethod, FxSource};
+use crate::export::ExportConfig;
+use crate::history::HistoryConfig;
+use crate::recorder::RecorderConfig;
+use crate::store::Exchange;
+use crate::strategy::registry::StrategiesConfig;
+use crate::strategy::{daily_rollover_fee_for, taker_fee_for, Currency, FeeSchedule, InstrumentType};
+use log::info;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+/// --config を指定しなかった場合に読む設定ファイル
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+/// ボット全体の設定。
+/// ファイルに書かれていない項目は既定値 (Config::default) のまま使う
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// 取引対象の通貨と取引所ごとのシンボル名。
+    /// ファイルに [[assets]] を書いた場合は既定の一覧を置き換える
+    pub assets: Vec<AssetConfig>,
+    /// 戦略ごとの設定と有効・無効
+    pub strategies: StrategiesConfig,
+    pub fx: FxConfig,
+    pub intervals: IntervalConfig,
+    pub exchanges: ExchangesConfig,
+    /// 送受信した生フレームの記録
+    pub recorder: RecorderConfig,
+    /// 気配・FRの履歴の保存
+    pub history: HistoryConfig,
+    /// 気配・FR・裁定機会の Parquet への書き出し
+    pub export: ExportConfig,
+    /// バックテストの執行の模擬と、比べる判定パラメータ
+    pub backtest: BacktestConfig,
+    /// ペーパートレード (--paper・--replay) の初期残高・在庫と取引ジャーナル
+    pub paper: PaperAccountConfig,
+}
+
+/// ループ間隔など (すべて ms)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IntervalConfig {
+    /// 起動後、戦略を回し始めるまでの待機時間
+    pub warmup_ms: u64,
+    /// 気配の更新が無い場合でも、すべての通貨を評価し直す間隔
+    pub strategy_loop_ms: u64,
+    /// 更新を受けてから評価するまで待つ時間。この間に届いた同じ通貨の更新は1回の評価にまとめる
+    pub debounce_ms: u64,
+    /// 為替レートが取れなかったときの再試行間隔
+    pub fx_retry_ms: u64,
+    /// コレクターの稼働状況をログに出す間隔
+    pub health_report_ms: u64,
+    /// 更新から判断までの遅延をログに出す間隔
+    pub latency_report_ms: u64,
+}
+
+impl IntervalConfig {
+    pub fn warmup(&self) -> Duration {
+        Duration::from_millis(self.warmup_ms)
+    }
+
+    pub fn strategy_loop(&self) -> Duration {
+        Duration::from_millis(self.strategy_loop_ms)
+    }
+
+    pub fn debounce(&self) -> Duration {
+        Duration::from_millis(self.debounce_ms)
+    }
+
+    pub fn latency_report(&self) -> Duration {
+        Duration::from_millis(self.latency_report_ms)
+    }
+
+    pub fn fx_retry(&self) -> Duration {
+        Duration::from_millis(self.fx_retry_ms)
+    }
+
+    pub fn health_report(&self) -> Duration {
+        Duration::from_millis(self.health_report_ms)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExchangesConfig {
+    pub hyperliquid: VenueConfig,
+    pub bitbank: VenueConfig,
+    pub gmo: VenueConfig,
+    pub kraken: VenueConfig,
+}
+
+impl ExchangesConfig {
+    pub fn get(&self, exchange: Exchange) -> &VenueConfig {
+        match exchange {
+            Exchange::Hyperliquid => &self.hyperliquid,
+            Exchange::Bitbank => &self.bitbank,
+            Exchange::Gmo => &self.gmo,
+            Exchange::Kraken => &self.kraken,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Exchange, &VenueConfig)> {
+        [Exchange::Hyperliquid, Exchange::Bitbank, Exchange::Gmo, Exchange::Kraken]
+            .into_iter()
+            .map(|e| (e, self.get(e)))
+    }
+}
+
+/// 取引所ごとの設定
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VenueConfig {
+    pub enabled: bool,
+    /// Taker手数料 (小数。0.0005 = 0.05%)
+    pub taker_fee: Decimal,
+    /// 気配の最大許容経過時間
+    pub max_quote_age_ms: u64,
+    /// FRの最大許容経過時間。これより古いFRは損益に見込まない
+    pub max_funding_age_ms: u64,
+    /// レバレッジ取引の手数料 (現物と別体系の取引所のみ)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub leverage: Option<LeverageConfig>,
+}
+
+/// レバレッジ取引の手数料
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LeverageConfig {
+    /// Taker手数料 (小数)
+    pub taker_fee: Decimal,
+    /// 建玉を持ち越すごとにかかる1日あたりの手数料 (想定元本に対する小数。0.0004 = 0.04%)
+    pub daily_rollover_fee: Decimal,
+}
+
+impl VenueConfig {
+    fn new(exchange: Exchange, max_quote_age_ms: u64) -> Self {
+        Self {
+            enabled: true,
+            taker_fee: taker_fee_for(exchange),
+            max_quote_age_ms,
+            max_funding_age_ms: 300_000,
+            leverage: None,
+        }
+    }
+
+    pub fn max_quote_age(&self) -> Duration {
+        Duration::from_millis(self.max_quote_age_ms)
+    }
+
+    pub fn max_funding_age(&self) -> Duration {
+        Duration::from_millis(self.max_funding_age_ms)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            assets: AssetConfig::defaults(),
+            strategies: StrategiesConfig::default(),
+            fx: FxConfig::default(),
+            intervals: IntervalConfig {
+                warmup_ms: 5_000,
+                strategy_loop_ms: 500,
+                debounce_ms: 20,
+                fx_retry_ms: 1_000,
+                health_report_ms: 60_000,
+                latency_report_ms: 60_000,
+            },
+            exchanges: ExchangesConfig {
+                hyperliquid: VenueConfig::new(Exchange::Hyperliquid, 2_000),
+                // 国内取引所とFXは変化時のみ配信されるため長めに取る
+                bitbank: VenueConfig::new(Exchange::Bitbank, 10_000),
+                // GMOコインのレバレッジ取引は取引手数料が無料で、代わりに持ち越し手数料がかかる
+                gmo: VenueConfig {
+                    leverage: Some(LeverageConfig {
+                        taker_fee: Decimal::ZERO,
+                        daily_rollover_fee: daily_rollover_fee_for(Exchange::Gmo),
+                    }),
+                    ..VenueConfig::new(Exchange::Gmo, 10_000)
+                },
+                kraken: VenueConfig::new(Exchange::Kraken, 60_000),
+            },
+            recorder: RecorderConfig::default(),
+            history: HistoryConfig::default(),
+            export: ExportConfig::default(),
+            backtest: BacktestConfig::default(),
+            paper: PaperAccountConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io { path: String, source: std::io::Error },
+    Parse { path: String, message: String },
+    /// 値の検証に失敗した項目 (すべて列挙する)
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, source } => write!(f, "failed to read {}: {}", path, source),
+            ConfigError::Parse { path, message } => write!(f, "failed to parse {}: {}", path, message),
+            ConfigError::Invalid(problems) => {
+                write!(f, "invalid configuration:")?;
+                for p in problems {
+                    write!(f, "\n  - {}", p)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// 設定ファイルを読み込んで検証する。
+    /// path が既定のパスでファイルが存在しない場合は既定値を使う
+    pub fn load(path: &str) -> Result<Self, ConfigError> {
+        if path == DEFAULT_CONFIG_PATH && !Path::new(path).exists() {
+            info!("[Config] {} not found, using defaults", path);
+            let config = Self::default();
+            config.validate()?;
+            return Ok(config);
+        }
+        let text = std::fs::read_to_string(path).map_err(|source| ConfigError::Io { path: path.to_string(), source })?;
+        Self::from_toml(&text).map_err(|e| match e {
+            ConfigError::Parse { message, .. } => ConfigError::Parse { path: path.to_string(), message },
+            other => other,
+        })
+    }
+
+    /// TOML 文字列を既定値に重ねて読み込み、検証する
+    pub fn from_toml(text: &str) -> Result<Self, ConfigError> {
+        let parse_err = |message: String| ConfigError::Parse { path: String::new(), message };
+
+        let overrides: toml::Table = toml::from_str(text).map_err(|e| parse_err(e.to_string()))?;
+        let mut merged = match toml::Value::try_from(Self::default()) {
+            Ok(toml::Value::Table(table)) => table,
+            Ok(_) => unreachable!("Config serializes to a table"),
+            Err(e) => return Err(parse_err(e.to_string())),
+        };
+        // 通貨の一覧は既定値と混ぜずに丸ごと置き換える
+        if overrides.contains_key("assets") {
+            merged.remove("assets");
+        }
+        merge(&mut merged, overrides);
+
+        let config: Self = toml::Value::Table(merged).try_into().map_err(|e: toml::de::Error| parse_err(e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// 値の範囲と組み合わせを検証する
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+
+        if self.assets.is_empty() {
+            problems.push("assets: at least one asset is required".to_string());
+        }
+        let mut seen = HashSet::new();
+        let mut venue_symbols = HashSet::new();
+        // 有効な取引所で取引できる (取引所, 商品) の組。同じ取引所の Perp と現物も別の足として数える
+        let mut trading_legs = HashSet::new();
+        for asset in &self.assets {
+            let symbol = asset.symbol.as_symbol();
+            if symbol.is_empty() || !symbol.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit()) {
+                problems.push(format!("assets: symbol {:?} must be upper-case letters and digits", symbol));
+            }
+            if !seen.insert(asset.symbol) {
+                problems.push(format!("assets: {} is listed more than once", symbol));
+            }
+            let venues = [
+                ("hyperliquid_perp", Exchange::Hyperliquid, InstrumentType::Perp, &asset.hyperliquid_perp),
+                ("hyperliquid_spot", Exchange::Hyperliquid, InstrumentType::Spot, &asset.hyperliquid_spot),
+                ("gmo_spot", Exchange::Gmo, InstrumentType::Spot, &asset.gmo_spot),
+                ("gmo_leverage", Exchange::Gmo, InstrumentType::Margin, &asset.gmo_leverage),
+                ("bitbank", Exchange::Bitbank, InstrumentType::Spot, &asset.bitbank),
+            ];
+            if venues.iter().all(|(_, _, _, s)| s.is_none()) {
+                problems.push(format!("assets.{}: no venue symbol is configured", symbol));
+            }
+            for (field, exchange, instrument, venue_symbol) in venues {
+                let Some(venue_symbol) = venue_symbol else {
+                    continue;
+                };
+                if self.exchanges.get(exchange).enabled {
+                    trading_legs.insert((exchange, instrument));
+                }
+                if venue_symbol.is_empty() {
+                    problems.push(format!("assets.{}.{}: must not be empty", symbol, field));
+                } else if !venue_symbols.insert((exchange, venue_symbol.clone())) {
+                    problems.push(format!("assets.{}.{}: {} is used more than once on {}", symbol, field, venue_symbol, exchange));
+                }
+            }
+        }
+
+        if !self.strategies.arbitrage.enabled && !self.strategies.carry.enabled {
+            problems.push("strategies: at least one strategy must be enabled".to_string());
+        }
+        let s = &self.strategies.arbitrage;
+        if s.min_profit_pct < Decimal::ZERO {
+            problems.push(format!("strategies.arbitrage.min_profit_pct: must be >= 0 (got {})", s.min_profit_pct));
+        }
+        if s.max_notional_jpy <= Decimal::ZERO {
+            problems.push(format!("strategies.arbitrage.max_notional_jpy: must be > 0 (got {})", s.max_notional_jpy));
+        }
+        if s.slippage < Decimal::ZERO || s.slippage >= Decimal::new(1, 2) {
+            problems.push(format!("strategies.arbitrage.slippage: must be in [0, 0.01) (got {})", s.slippage));
+        }
+        // 1年を超える保有は想定しない
+        if s.holding_hours < Decimal::ZERO || s.holding_hours > Decimal::from(24 * 365) {
+            problems.push(format!("strategies.arbitrage.holding_hours: must be in [0, 8760] (got {})", s.holding_hours));
+        }
+
+        let c = &self.strategies.carry;
+        if c.horizon_hours <= Decimal::ZERO || c.horizon_hours > Decimal::from(24 * 365) {
+            problems.push(format!("strategies.carry.horizon_hours: must be in (0, 8760] (got {})", c.horizon_hours));
+        }
+        if c.min_funding_apr_pct <= Decimal::ZERO {
+            problems.push(format!("strategies.carry.min_funding_apr_pct: must be > 0 (got {})", c.min_funding_apr_pct));
+        }
+        // 建てた直後に解消しないよう、解消の閾値は建てる閾値より低くする
+        if c.exit_edge_pct >= c.entry_edge_pct {
+            problems.push(format!(
+                "strategies.carry.exit_edge_pct: must be below strategies.carry.entry_edge_pct ({} >= {})",
+                c.exit_edge_pct, c.entry_edge_pct
+            ));
+        }
+        if c.fx_risk_pct < Decimal::ZERO {
+            problems.push(format!("strategies.carry.fx_risk_pct: must be >= 0 (got {})", c.fx_risk_pct));
+        }
+        if c.max_notional_jpy <= Decimal::ZERO {
+            problems.push(format!("strategies.carry.max_notional_jpy: must be > 0 (got {})", c.max_notional_jpy));
+        }
+        if c.enabled && !self.exchanges.hyperliquid.enabled {
+            problems.push("strategies.carry.enabled: requires exchanges.hyperliquid (perp funding source)".to_string());
+        }
+
+        let fx = &self.fx;
+        if fx.sources.is_empty() {
+            problems.push("fx.sources: at least one source is required".to_string());
+        }
+        for (i, source) in fx.sources.iter().enumerate() {
+            if source.symbol.is_empty() {
+                problems.push(format!("fx.sources[{}].symbol: must not be empty", i));
+            }
+            if source.weight < Decimal::ZERO {
+                problems.push(format!("fx.sources[{}].weight: must be >= 0 (got {})", i, source.weight));
+            }
+        }
+        if fx.method == FxMethod::Weighted && fx.sources.iter().all(|s| s.weight.is_zero()) {
+            problems.push("fx.sources: weighted method needs at least one positive weight".to_string());
+        }
+        if fx.max_divergence_pct <= Decimal::ZERO {
+            problems.push(format!("fx.max_divergence_pct: must be > 0 (got {})", fx.max_divergence_pct));
+        }
+        if fx.min_sources == 0 {
+            problems.push("fx.min_sources: must be >= 1".to_string());
+        }
+        let mut stablecoins = HashSet::new();
+        for stablecoin in &fx.stablecoins {
+            let currency = stablecoin.currency;
+            if !matches!(currency, Currency::USDC | Currency::USDT) {
+                problems.push(format!("fx.stablecoins: {:?} is not a stablecoin (USDC or USDT)", currency));
+            }
+            if !stablecoins.insert(currency) {
+                problems.push(format!("fx.stablecoins: {:?} is listed more than once", currency));
+            }
+            if stablecoin.sources.iter().any(|s| s.symbol.is_empty()) {
+                problems.push(format!("fx.stablecoins.{:?}.sources: symbol must not be empty", currency));
+            }
+            if stablecoin.max_depeg_pct <= Decimal::ZERO {
+                problems.push(format!("fx.stablecoins.{:?}.max_depeg_pct: must be > 0 (got {})", currency, stablecoin.max_depeg_pct));
+            }
+        }
+
+        let i = &self.intervals;
+        for (name, value) in [
+            ("strategy_loop_ms", i.strategy_loop_ms),
+            ("fx_retry_ms", i.fx_retry_ms),
+            ("health_report_ms", i.health_report_ms),
+            ("latency_report_ms", i.latency_report_ms),
+        ] {
+            if value == 0 {
+                problems.push(format!("intervals.{}: must be > 0", name));
+            }
+        }
+        if i.debounce_ms >= i.strategy_loop_ms {
+            problems.push(format!(
+                "intervals.debounce_ms: must be below intervals.strategy_loop_ms ({} >= {})",
+                i.debounce_ms, i.strategy_loop_ms
+            ));
+        }
+
+        for (exchange, venue) in self.exchanges.iter() {
+            let name = exchange.to_string().to_lowercase();
+            // メイカーリベートを考慮して負の値も許す
+            if venue.taker_fee <= Decimal::new(-1, 2) || venue.taker_fee >= Decimal::new(1, 2) {
+                problems.push(format!("exchanges.{}.taker_fee: must be in (-0.01, 0.01) (got {})", name, venue.taker_fee));
+            }
+            if venue.max_quote_age_ms == 0 {
+                problems.push(format!("exchanges.{}.max_quote_age_ms: must be > 0", name));
+            }
+            if venue.max_funding_age_ms == 0 {
+                problems.push(format!("exchanges.{}.max_funding_age_ms: must be > 0", name));
+            }
+            if let Some(leverage) = &venue.leverage {
+                // レバレッジ取引のシンボルを設定できるのは GMO のみ
+                if exchange != Exchange::Gmo {
+                    problems.push(format!("exchanges.{}.leverage: only supported on gmo", name));
+                }
+                if leverage.taker_fee <= Decimal::new(-1, 2) || leverage.taker_fee >= Decimal::new(1, 2) {
+                    problems.push(format!("exchanges.{}.leverage.taker_fee: must be in (-0.01, 0.01) (got {})", name, leverage.taker_fee));
+                }
+                if leverage.daily_rollover_fee < Decimal::ZERO || leverage.daily_rollover_fee >= Decimal::new(1, 2) {
+                    problems.push(format!(
+                        "exchanges.{}.leverage.daily_rollover_fee: must be in [0, 0.01) (got {})",
+                        name, leverage.daily_rollover_fee
+                    ));
+                }
+            }
+        }
+
+        if trading_legs.len() < 2 {
+            problems.push(format!(
+                "exchanges: at least two trading legs (exchange and instrument) must be enabled (got {})",
+                trading_legs.len()
+            ));
+        }
+        // Hyperliquid の USDC 建ての価格を USDC_USD -> USD_JPY と換算するため、
+        // どちらも有効な取引所の参照元が min_sources 以上必要
+        if self.exchanges.hyperliquid.enabled {
+            let enabled = |sources: &[FxSource]| sources.iter().filter(|s| self.exchanges.get(s.exchange).enabled).count();
+            let usd_jpy = enabled(&fx.sources);
+            if usd_jpy < fx.min_sources {
+                problems.push(format!(
+                    "fx.sources: {} source(s) on enabled exchanges, but hyperliquid needs at least fx.min_sources = {} (USD/JPY)",
+                    usd_jpy, fx.min_sources
+                ));
+            }
+            let usdc = fx.stablecoins.iter().find(|s| s.currency == Currency::USDC).map(|s| enabled(&s.sources)).unwrap_or(0);
+            if usdc < fx.min_sources {
+                problems.push(format!(
+                    "fx.stablecoins: {} USDC source(s) on enabled exchanges, but hyperliquid needs at least fx.min_sources = {} (USDC/USD)",
+                    usdc, fx.min_sources
+                ));
+            }
+        }
+
+        let r = &self.recorder;
+        if r.enabled {
+            if r.dir.trim().is_empty() {
+                problems.push("recorder.dir: must not be empty".to_string());
+            }
+            for (name, value) in [
+                ("rotate_mb", r.rotate_mb),
+                ("rotate_secs", r.rotate_secs),
+                ("max_disk_mb", r.max_disk_mb),
+                ("flush_ms", r.flush_ms),
+            ] {
+                if value == 0 {
+                    problems.push(format!("recorder.{}: must be > 0", name));
+                }
+            }
+        }
+
+        let h = &self.history;
+        if h.enabled {
+            if h.path.trim().is_empty() {
+                problems.push("history.path: must not be empty".to_string());
+            }
+            if h.batch_size == 0 {
+                problems.push("history.batch_size: must be > 0".to_string());
+            }
+            if h.flush_ms == 0 {
+                problems.push("history.flush_ms: must be > 0".to_string());
+            }
+        }
+
+        // --export による変換でも使うので enabled に関係なく確認する
+        let x = &self.export;
+        if x.dir.trim().is_empty() {
+            problems.push("export.dir: must not be empty".to_string());
+        }
+        if x.row_group_size == 0 {
+            problems.push("export.row_group_size: must be > 0".to_string());
+        }
+
+        let b = &self.backtest;
+        if b.slippage < Decimal::ZERO || b.slippage >= Decimal::new(1, 2) {
+            problems.push(format!("backtest.slippage: must be in [0, 0.01) (got {})", b.slippage));
+        }
+        if b.output_dir.trim().is_empty() {
+            problems.push("backtest.output_dir: must not be empty".to_string());
+        }
+        if let Some(v) = b.sweep.min_profit_pct.iter().find(|v| **v < Decimal::ZERO) {
+            problems.push(format!("backtest.sweep.min_profit_pct: must be >= 0 (got {})", v));
+        }
+        if let Some(v) = b.sweep.slippage.iter().find(|v| **v < Decimal::ZERO || **v >= Decimal::new(1, 2)) {
+            problems.push(format!("backtest.sweep.slippage: must be in [0, 0.01) (got {})", v));
+        }
+
+        let p = &self.paper;
+        let mut balances = HashSet::new();
+        for balance in &p.balances {
+            if balance.amount < Decimal::ZERO {
+                problems.push(format!("paper.balances: {} {:?} must be >= 0 (got {})", balance.exchange, balance.currency, balance.amount));
+            }
+            if !balances.insert((balance.exchange, balance.currency)) {
+                problems.push(format!("paper.balances: {} {:?} is listed more than once", balance.exchange, balance.currency));
+            }
+        }
+        let mut inventory = HashSet::new();
+        for item in &p.inventory {
+            if item.quantity < Decimal::ZERO {
+                problems.push(format!("paper.inventory: {} {} must be >= 0 (got {})", item.exchange, item.asset, item.quantity));
+            }
+            if !inventory.insert((item.exchange, item.asset)) {
+                problems.push(format!("paper.inventory: {} {} is listed more than once", item.exchange, item.asset));
+            }
+        }
+        if p.leverage <= Decimal::ZERO {
+            problems.push(format!("paper.leverage: must be > 0 (got {})", p.leverage));
+        }
+        if p.journal_path.trim().is_empty() {
+            problems.push("paper.journal_path: must not be empty".to_string());
+        }
+        if p.replay_journal_path.trim().is_empty() {
+            problems.push("paper.replay_journal_path: must not be empty".to_string());
+        }
+
+        if problems.is_empty() { Ok(()) } else { Err(ConfigError::Invalid(problems)) }
+    }
+
+    /// 通貨と取引所ごとのシンボル名の対応表
+    pub fn registry(&self) -> AssetRegistry {
+        AssetRegistry::new(self.assets.clone())
+    }
+
+    pub fn fee_schedule(&self) -> FeeSchedule {
+        let mut fees = FeeSchedule::default();
+        for (exchange, venue) in self.exchanges.iter() {
+            fees.set_taker(exchange, venue.taker_fee);
+            if let Some(leverage) = &venue.leverage {
+                fees.set_margin(exchange, leverage.taker_fee, leverage.daily_rollover_fee);
+            }
+        }
+        fees
+    }
+}
+
+/// overrides の値で base を上書きする (テーブルは再帰的に、それ以外は置き換え)
+fn merge(base: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(table)) => merge(base_table, table),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asset::Asset;
+    use std::str::FromStr;
+
+    /// 検証で見つかった問題の一覧 (検証を通った・読み込めなかった場合は panic)
+    fn problems(text: &str) -> Vec<String> {
+        match Config::from_toml(text) {
+            Err(ConfigError::Invalid(problems)) => problems,
+            Err(e) => panic!("{:?} failed to parse: {}", text, e),
+            Ok(_) => panic!("{:?} passed validation", text),
+        }
+    }
+
+    #[test]
+    fn empty_file_is_the_defaults() {
+        Config::default().validate().unwrap();
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(toml::Value::try_from(&config).unwrap(), toml::Value::try_from(Config::default()).unwrap());
+        let example = Config::from_toml(include_str!("../config.example.toml")).unwrap();
+        assert_eq!(toml::Value::try_from(&example).unwrap(), toml::Value::try_from(Config::default()).unwrap());
+    }
+
+    #[test]
+    fn file_overrides_are_merged_onto_defaults() {
+        let config = Config::from_toml(
+            r#"
+            [strategies.arbitrage]
+            min_profit_pct = "0.2"
+
+            [exchanges.gmo]
+            max_quote_age_ms = 5000
+            "#,
+        )
+        .unwrap();
+        let defaults = Config::default();
+        assert_eq!(config.strategies.arbitrage.min_profit_pct, Decimal::from_str("0.2").unwrap());
+        // 同じテーブルの書かなかった項目は既定値のまま
+        assert_eq!(config.strategies.arbitrage.max_notional_jpy, defaults.strategies.arbitrage.max_notional_jpy);
+        assert_eq!(config.exchanges.gmo.max_quote_age_ms, 5_000);
+        assert_eq!(config.exchanges.gmo.taker_fee, defaults.exchanges.gmo.taker_fee);
+        let leverage = config.exchanges.gmo.leverage.as_ref().unwrap();
+        assert_eq!(leverage.daily_rollover_fee, daily_rollover_fee_for(Exchange::Gmo));
+        assert_eq!(config.assets.len(), defaults.assets.len());
+        assert_eq!(config.fx.sources.len(), defaults.fx.sources.len());
+    }
+
+    #[test]
+    fn assets_replace_the_default_list() {
+        let config = Config::from_toml(
+            r#"
+            [[assets]]
+            symbol = "XRP"
+            gmo_spot = "XRP"
+            bitbank = "xrp_jpy"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.assets.len(), 1);
+        let asset = &config.assets[0];
+        assert_eq!(asset.symbol, Asset::new("XRP"));
+        assert_eq!(asset.bitbank.as_deref(), Some("xrp_jpy"));
+        // 書かなかった取引所では扱わない
+        assert!(asset.hyperliquid_perp.is_none());
+        assert!(asset.gmo_leverage.is_none());
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        let err = Config::from_toml("[strategies.arbitrage]\nmin_profit = \"0.1\"").unwrap_err();
+        assert!(matches!(err, ConfigError::Parse { .. }), "{}", err);
+    }
+
+    #[test]
+    fn perp_and_spot_on_one_venue_are_two_legs() {
+        let config = Config::from_toml(
+            r#"
+            [exchanges.gmo]
+            enabled = false
+
+            [exchanges.bitbank]
+            enabled = false
+            "#,
+        )
+        .unwrap();
+        assert!(config.exchanges.hyperliquid.enabled);
+
+        let problems = problems(
+            r#"
+            [[assets]]
+            symbol = "BTC"
+            hyperliquid_perp = "BTC"
+            gmo_spot = "BTC"
+
+            [exchanges.gmo]
+            enabled = false
+            "#,
+        );
+        assert_eq!(problems, ["exchanges: at least two trading legs (exchange and instrument) must be enabled (got 1)"]);
+    }
+
+    #[test]
+    fn each_problem_is_reported() {
+        let cases = [
+            ("assets = []", "assets: at least one asset is required"),
+            (
+                "[[assets]]\nsymbol = \"btc\"\nbitbank = \"btc_jpy\"",
+                r#"assets: symbol "btc" must be upper-case letters and digits"#,
+            ),
+            (
+                "[[assets]]\nsymbol = \"BTC\"\nbitbank = \"btc_jpy\"\n[[assets]]\nsymbol = \"BTC\"\ngmo_spot = \"BTC\"",
+                "assets: BTC is listed more than once",
+            ),
+            ("[[assets]]\nsymbol = \"XRP\"", "assets.XRP: no venue symbol is configured"),
+            ("[[assets]]\nsymbol = \"BTC\"\nbitbank = \"\"", "assets.BTC.bitbank: must not be empty"),
+            (
+                "[[assets]]\nsymbol = \"BTC\"\nbitbank = \"btc_jpy\"\n[[assets]]\nsymbol = \"ETH\"\nbitbank = \"btc_jpy\"",
+                "assets.ETH.bitbank: btc_jpy is used more than once on Bitbank",
+            ),
+            ("[strategies.arbitrage]\nenabled = false", "strategies: at least one strategy must be enabled"),
+            (
+                "[strategies.arbitrage]\nmin_profit_pct = \"-0.1\"",
+                "strategies.arbitrage.min_profit_pct: must be >= 0 (got -0.1)",
+            ),
+            (
+                "[strategies.arbitrage]\nmax_notional_jpy = \"0\"",
+                "strategies.arbitrage.max_notional_jpy: must be > 0 (got 0)",
+            ),
+            ("[strategies.arbitrage]\nslippage = \"0.01\"", "strategies.arbitrage.slippage: must be in [0, 0.01) (got 0.01)"),
+            (
+                "[strategies.arbitrage]\nholding_hours = \"9000\"",
+                "strategies.arbitrage.holding_hours: must be in [0, 8760] (got 9000)",
+            ),
+            ("[strategies.carry]\nhorizon_hours = \"0\"", "strategies.carry.horizon_hours: must be in (0, 8760] (got 0)"),
+            (
+                "[strategies.carry]\nmin_funding_apr_pct = \"0\"",
+                "strategies.carry.min_funding_apr_pct: must be > 0 (got 0)",
+            ),
+            (
+                "[strategies.carry]\nentry_edge_pct = \"0.1\"\nexit_edge_pct = \"0.2\"",
+                "strategies.carry.exit_edge_pct: must be below strategies.carry.entry_edge_pct (0.2 >= 0.1)",
+            ),
+            ("[strategies.carry]\nfx_risk_pct = \"-1\"", "strategies.carry.fx_risk_pct: must be >= 0 (got -1)"),
+            ("[strategies.carry]\nmax_notional_jpy = \"0\"", "strategies.carry.max_notional_jpy: must be > 0 (got 0)"),
+            (
+                "[strategies.carry]\nenabled = true\n[exchanges.hyperliquid]\nenabled = false",
+                "strategies.carry.enabled: requires exchanges.hyperliquid (perp funding source)",
+            ),
+            ("[fx]\nsources = []", "fx.sources: at least one source is required"),
+            ("[[fx.sources]]\nexchange = \"gmo\"\nsymbol = \"\"", "fx.sources[0].symbol: must not be empty"),
+            (
+                "[[fx.sources]]\nexchange = \"gmo\"\nsymbol = \"USD_JPY\"\nweight = \"-1\"",
+                "fx.sources[0].weight: must be >= 0 (got -1)",
+            ),
+            (
+                "[fx]\nmethod = \"weighted\"\n[[fx.sources]]\nexchange = \"gmo\"\nsymbol = \"USD_JPY\"\nweight = \"0\"",
+                "fx.sources: weighted method needs at least one positive weight",
+            ),
+            ("[fx]\nmax_divergence_pct = \"0\"", "fx.max_divergence_pct: must be > 0 (got 0)"),
+            ("[fx]\nmin_sources = 0", "fx.min_sources: must be >= 1"),
+            (
+                "[[fx.stablecoins]]\ncurrency = \"JPY\"\nsources = []\nmax_depeg_pct = \"0.5\"",
+                "fx.stablecoins: JPY is not a stablecoin (USDC or USDT)",
+            ),
+            (
+                "[[fx.stablecoins]]\ncurrency = \"USDC\"\nsources = []\nmax_depeg_pct = \"0.5\"\n\
+                 [[fx.stablecoins]]\ncurrency = \"USDC\"\nsources = []\nmax_depeg_pct = \"0.5\"",
+                "fx.stablecoins: USDC is listed more than once",
+            ),
+            (
+                "[[fx.stablecoins]]\ncurrency = \"USDC\"\nmax_depeg_pct = \"0.5\"\n\
+                 [[fx.stablecoins.sources]]\nexchange = \"kraken\"\nsymbol = \"\"",
+                "fx.stablecoins.USDC.sources: symbol must not be empty",
+            ),
+            (
+                "[[fx.stablecoins]]\ncurrency = \"USDC\"\nmax_depeg_pct = \"0\"\n\
+                 [[fx.stablecoins.sources]]\nexchange = \"kraken\"\nsymbol = \"USDC_USD\"",
+                "fx.stablecoins.USDC.max_depeg_pct: must be > 0 (got 0)",
+            ),
+            ("[intervals]\nstrategy_loop_ms = 0", "intervals.strategy_loop_ms: must be > 0"),
+            ("[intervals]\nfx_retry_ms = 0", "intervals.fx_retry_ms: must be > 0"),
+            ("[intervals]\nhealth_report_ms = 0", "intervals.health_report_ms: must be > 0"),
+            ("[intervals]\nlatency_report_ms = 0", "intervals.latency_report_ms: must be > 0"),
+            (
+                "[intervals]\ndebounce_ms = 500\nstrategy_loop_ms = 500",
+                "intervals.debounce_ms: must be below intervals.strategy_loop_ms (500 >= 500)",
+            ),
+            ("[exchanges.bitbank]\ntaker_fee = \"0.01\"", "exchanges.bitbank.taker_fee: must be in (-0.01, 0.01) (got 0.01)"),
+            ("[exchanges.bitbank]\nmax_quote_age_ms = 0", "exchanges.bitbank.max_quote_age_ms: must be > 0"),
+            ("[exchanges.hyperliquid]\nmax_funding_age_ms = 0", "exchanges.hyperliquid.max_funding_age_ms: must be > 0"),
+            (
+                "[exchanges.bitbank.leverage]\ntaker_fee = \"0\"\ndaily_rollover_fee = \"0\"",
+                "exchanges.bitbank.leverage: only supported on gmo",
+            ),
+            (
+                "[exchanges.gmo.leverage]\ntaker_fee = \"0.01\"",
+                "exchanges.gmo.leverage.taker_fee: must be in (-0.01, 0.01) (got 0.01)",
+            ),
+            (
+                "[exchanges.gmo.leverage]\ndaily_rollover_fee = \"-0.0001\"",
+                "exchanges.gmo.leverage.daily_rollover_fee: must be in [0, 0.01) (got -0.0001)",
+            ),
+            (
+                "[exchanges.hyperliquid]\nenabled = false\n[exchanges.gmo]\nenabled = false\n[exchanges.bitbank]\nenabled = false",
+                "exchanges: at least two trading legs (exchange and instrument) must be enabled (got 0)",
+            ),
+            (
+                "[exchanges.kraken]\nenabled = false\n[[fx.sources]]\nexchange = \"kraken\"\nsymbol = \"USD_JPY\"",
+                "fx.sources: 0 source(s) on enabled exchanges, but hyperliquid needs at least fx.min_sources = 1 (USD/JPY)",
+            ),
+            (
+                "[fx]\nmin_sources = 2",
+                "fx.stablecoins: 1 USDC source(s) on enabled exchanges, but hyperliquid needs at least fx.min_sources = 2 (USDC/USD)",
+            ),
+            ("[recorder]\nenabled = true\ndir = \" \"", "recorder.dir: must not be empty"),
+            ("[recorder]\nenabled = true\nrotate_mb = 0", "recorder.rotate_mb: must be > 0"),
+            ("[recorder]\nenabled = true\nrotate_secs = 0", "recorder.rotate_secs: must be > 0"),
+            ("[recorder]\nenabled = true\nmax_disk_mb = 0", "recorder.max_disk_mb: must be > 0"),
+            ("[recorder]\nenabled = true\nflush_ms = 0", "recorder.flush_ms: must be > 0"),
+            ("[history]\nenabled = true\npath = \"\"", "history.path: must not be empty"),
+            ("[history]\nenabled = true\nbatch_size = 0", "history.batch_size: must be > 0"),
+            ("[history]\nenabled = true\nflush_ms = 0", "history.flush_ms: must be > 0"),
+            ("[export]\ndir = \"\"", "export.dir: must not be empty"),
+            ("[export]\nrow_group_size = 0", "export.row_group_size: must be > 0"),
+            ("[backtest]\nslippage = \"0.01\"", "backtest.slippage: must be in [0, 0.01) (got 0.01)"),
+            ("[backtest]\noutput_dir = \"\"", "backtest.output_dir: must not be empty"),
+            ("[backtest.sweep]\nmin_profit_pct = [\"0.1\", \"-1\"]", "backtest.sweep.min_profit_pct: must be >= 0 (got -1)"),
+            ("[backtest.sweep]\nslippage = [\"0.01\"]", "backtest.sweep.slippage: must be in [0, 0.01) (got 0.01)"),
+            (
+                "[[paper.balances]]\nexchange = \"gmo\"\ncurrency = \"JPY\"\namount = \"-1\"",
+                "paper.balances: Gmo JPY must be >= 0 (got -1)",
+            ),
+            (
+                "[[paper.balances]]\nexchange = \"gmo\"\ncurrency = \"JPY\"\namount = \"1\"\n\
+                 [[paper.balances]]\nexchange = \"gmo\"\ncurrency = \"JPY\"\namount = \"2\"",
+                "paper.balances: Gmo JPY is listed more than once",
+            ),
+            (
+                "[paper]\ninventory = [{ exchange = \"gmo\", asset = \"BTC\", quantity = \"-1\" }]",
+                "paper.inventory: Gmo BTC must be >= 0 (got -1)",
+            ),
+            (
+                "[paper]\ninventory = [\n{ exchange = \"gmo\", asset = \"BTC\", quantity = \"1\" },\n\
+                 { exchange = \"gmo\", asset = \"BTC\", quantity = \"2\" },\n]",
+                "paper.inventory: Gmo BTC is listed more than once",
+            ),
+            ("[paper]\nleverage = \"0\"", "paper.leverage: must be > 0 (got 0)"),
+            ("[paper]\njournal_path = \"\"", "paper.journal_path: must not be empty"),
+            ("[paper]\nreplay_journal_path = \" \"", "paper.replay_journal_path: must not be empty"),
+        ];
+        for (text, expected) in cases {
+            let problems = problems(text);
+            assert!(problems.iter().any(|p| p == expected), "{:?}: expected {:?} in {:?}", text, expected, problems);
+        }
+    }
+}
@@ -0,0 +1,321 @@
+// 戦略の評価と執行
+//
+// 評価待ちの通貨ごとに MarketSnapshot を作って全戦略を評価し、裁定はその場で executor で執行する。
+// いつ評価するか (更新の待ち方) は呼び出し側が決める。ライブは main の戦略ループ、リプレイは replay が仮想時計で回す
+use crate::asset::AssetRegistry;
+use crate::config::Config;
+use crate::executor::Executor;
+use crate::fx::FxOracle;
+use crate::store::MarketStore;
+use crate::strategy::carry::PositionTarget;
+use crate::strategy::{
+    describe_fx_chain, ArbitrageOpportunity, AssetIndex, ClosedOpportunity, FeeSchedule, LatencyStats, LatencySummary, MarketSnapshot,
+    PendingEvaluations, Positions, Signal, StrategyRegistry,
+};
+use log::{debug, error, info, warn};
+
+/// 執行に回す裁定機会と代替経路の順位を受け取る処理
+type OpportunityHook<'a> = Box<dyn FnMut(&ArbitrageOpportunity, usize) + Send + 'a>;
+
+pub struct StrategyEngine<'a, E: Executor> {
+    store: &'a MarketStore,
+    config: &'a Config,
+    registry: &'a AssetRegistry,
+    fees: FeeSchedule,
+    fx_oracle: FxOracle,
+    strategies: StrategyRegistry,
+    positions: Positions,
+    executor: E,
+    after_execution: Box<dyn Fn(&E) + Send + 'a>,
+    on_opportunity: OpportunityHook<'a>,
+    index: AssetIndex,
+    latency: LatencyStats,
+}
+
+impl<'a, E: Executor> StrategyEngine<'a, E> {
+    pub fn new(store: &'a MarketStore, config: &'a Config, registry: &'a AssetRegistry, executor: E) -> Self {
+        let fx_oracle = FxOracle::new(&config.fx, |e| config.exchanges.get(e).enabled);
+        let strategies = StrategyRegistry::from_config(&config.strategies);
+        info!("[Strategy] Enabled strategies: {:?}", strategies.names());
+        Self {
+            store,
+            config,
+            registry,
+            fees: config.fee_schedule(),
+            index: AssetIndex::new(registry, fx_oracle.sources().map(|s| (s.exchange, s.symbol.clone()))),
+            fx_oracle,
+            strategies,
+            positions: Positions::default(),
+            executor,
+            after_execution: Box::new(|_| {}),
+            on_opportunity: Box::new(|_, _| {}),
+            latency: LatencyStats::default(),
+        }
+    }
+
+    /// 裁定を執行するたびに呼ぶ処理 (ペーパートレードの累計のログなど)
+    pub fn with_after_execution(mut self, after_execution: impl Fn(&E) + Send + 'a) -> Self {
+        self.after_execution = Box::new(after_execution);
+        self
+    }
+
+    /// 執行に回す裁定機会ごとに、執行の前に呼ぶ処理 (書き出しなど)。2つめの引数は代替経路の順位 (0 が最良)
+    pub fn with_on_opportunity(mut self, on_opportunity: impl FnMut(&ArbitrageOpportunity, usize) + Send + 'a) -> Self {
+        self.on_opportunity = Box::new(on_opportunity);
+        self
+    }
+
+    /// 更新通知から評価し直す通貨を引く索引
+    pub fn index(&self) -> &AssetIndex {
+        &self.index
+    }
+
+    pub fn registry(&self) -> &'a AssetRegistry {
+        self.registry
+    }
+
+    pub fn executor(&self) -> &E {
+        &self.executor
+    }
+
+    /// 執行結果から積み上げた建玉 (戦略に渡すもの)
+    pub fn positions(&self) -> &Positions {
+        &self.positions
+    }
+
+    /// 前回からの、フレームの受信から判断までの遅延の集計
+    pub fn take_latency_summary(&mut self) -> Option<LatencySummary> {
+        self.latency.take_summary()
+    }
+
+    /// 評価待ちの通貨をすべて評価し、出たシグナルを処理する。
+    /// 為替レートが取れない場合は何もせずに false を返す (評価待ちはそのまま残る)
+    pub async fn evaluate_pending(&mut self, pending: &mut PendingEvaluations) -> bool {
+        // 為替レートの取得 (USD_JPY、複数の参照元を集計)
+        let usd_jpy = match self.fx_oracle.quote(self.store) {
+            Ok(quote) if quote.diverged => {
+                warn!(
+                    "[FX] USD_JPY sources diverge by {:.4}% (max {}%): {}. Skipping cycle.",
+                    quote.divergence_pct,
+                    self.config.fx.max_divergence_pct,
+                    quote.samples.iter().map(|s| format!("{} {}/{}", s.exchange, s.bid, s.ask)).collect::<Vec<_>>().join(", "),
+                );
+                return false;
+            }
+            Ok(quote) => {
+                debug!("[FX] USD_JPY {}/{} from {} source(s)", quote.rate.bid, quote.rate.ask, quote.samples.len());
+                quote
+            }
+            Err(e) => {
+                warn!("[FX] USD_JPY unavailable: {}. Skipping cycle.", e);
+                return false;
+            }
+        };
+        // ステーブルコインは USDC_USD -> USD_JPY と換算する。取れない通貨建ての市場は対象外
+        let (fx_rates, unavailable) = self.fx_oracle.rates(self.store, &usd_jpy);
+        for (currency, e) in &unavailable {
+            warn!("[FX] {:?} unavailable: {}. Skipping {:?} markets.", currency, e, currency);
+        }
+
+        for asset in self.registry.assets() {
+            let Some(received_at) = pending.take(asset) else {
+                continue;
+            };
+            let snapshot = MarketSnapshot::collect(
+                self.store,
+                &self.config.exchanges,
+                self.registry,
+                &self.fees,
+                asset,
+                &fx_rates,
+                self.store.now_ms(),
+            );
+            let signals = self.strategies.evaluate(&snapshot, &self.positions);
+            if let Some(received_at) = received_at {
+                self.latency.record(received_at.elapsed());
+            }
+            for (name, signal) in signals {
+                match signal {
+                    Signal::Arbitrage(candidates) => {
+                        for (rank, opp) in candidates.iter().enumerate() {
+                            if rank > 0 {
+                                info!("↪️ [代替経路] {:?} #{} {}", asset, rank + 1, opp.route());
+                            }
+                            log_opportunity(name, opp);
+                            (self.on_opportunity)(opp, rank);
+                            if rank == 0 && candidates.len() > 1 {
+                                let alternatives: Vec<String> = candidates[1..]
+                                    .iter()
+                                    .map(|o| format!("{} ¥{:.2}", o.route(), o.estimated_profit_jpy))
+                                    .collect();
+                                info!("  代替経路: {}", alternatives.join(" / "));
+                            }
+                            match self.executor.execute(opp).await {
+                                Ok(report) => {
+                                    let label = if self.executor.is_simulated() { "執行結果 (模擬)" } else { "執行結果" };
+                                    info!("📝 [{}] {:?} 数量: {}", label, report.asset, report.matched_quantity);
+                                    info!("  買い: {} @ {} (手数料 ¥{:.2})", report.long.exchange, report.long.average_price, report.long.fee_jpy);
+                                    info!("  売り: {} @ {} (手数料 ¥{:.2})", report.short.exchange, report.short.average_price, report.short.fee_jpy);
+                                    info!("  実現損益: ¥{:.2} (推定: ¥{:.2})", report.realized_profit_jpy, report.estimated_profit_jpy);
+                                    self.positions.record_fill(report.long.exchange, report.long.instrument, report.asset, report.long.signed_quantity());
+                                    self.positions.record_fill(report.short.exchange, report.short.instrument, report.asset, report.short.signed_quantity());
+                                    break;
+                                }
+                                // 発注前の失敗 (残高・板の厚み不足など) なら次の経路を試す
+                                Err(e) if e.is_pre_trade() && rank + 1 < candidates.len() => {
+                                    warn!("[Executor] {:?} {} not executable: {}. Trying next route.", asset, opp.route(), e);
+                                }
+                                Err(e) => {
+                                    error!("[Executor] {:?} execution failed: {}", asset, e);
+                                    // 片足だけ約定した場合も建玉に反映して、戦略が見る建玉を取引所と揃える
+                                    if let Some(leg) = e.filled_leg() {
+                                        self.positions.record_fill(leg.exchange, leg.instrument, asset, leg.signed_quantity());
+                                    }
+                                    break;
+                                }
+                            }
+                        }
+                        (self.after_execution)(&self.executor);
+                    }
+                    // 目標ポジションは変化したときだけ出力される
+                    Signal::Target(target) => log_position_target(name, &target),
+                    Signal::OpportunityClosed(closed) => log_closed_opportunity(name, &closed),
+                }
+            }
+        }
+        true
+    }
+}
+
+/// 裁定機会の内訳をログに出す
+fn log_opportunity(strategy: &str, opp: &ArbitrageOpportunity) {
+    info!("================================================================================");
+    info!("🚀 [裁定取引機会] {:?} ({})", opp.asset, strategy);
+    info!("================================================================================");
+    info!("📊 取引詳細:");
+    info!("  数量: {:.6} (想定元本 ¥{:.0})", opp.quantity, opp.notional_jpy);
+    info!("  買い: {:?} {:?} @ {} (VWAP, 指値 {}) {:?}", opp.long_exchange, opp.long_instrument, opp.long_price_raw, opp.long_limit_price, opp.long_currency);
+    info!("  売り: {:?} {:?} @ {} (VWAP, 指値 {}) {:?}", opp.short_exchange, opp.short_instrument, opp.short_price_raw, opp.short_limit_price, opp.short_currency);
+    info!("  為替レート: {} JPY/USD", opp.usd_jpy_rate);
+    info!("  換算経路(買い): {} = {} JPY/{:?}", describe_fx_chain(&opp.long_fx_chain), opp.long_fx_rate, opp.long_currency);
+    info!("  換算経路(売り): {} = {} JPY/{:?}", describe_fx_chain(&opp.short_fx_chain), opp.short_fx_rate, opp.short_currency);
+    info!("");
+    info!("💰 損益計算 (数量分の合計):");
+    info!("  平均買値(JPY換算): ¥{:.2}", opp.long_price_jpy);
+    info!("  平均売値(JPY換算): ¥{:.2}", opp.short_price_jpy);
+    info!("  粗利益: ¥{:.2}", opp.base_profit_jpy);
+    info!("");
+    info!("📉 コスト:");
+    info!("  買い手数料: ¥{:.2}", opp.long_fee_jpy);
+    info!("  売り手数料: ¥{:.2}", opp.short_fee_jpy);
+    info!("  スリッページ: ¥{:.2}", opp.slippage_cost_jpy);
+    info!("  FR影響({}時間): ¥{:.2}", opp.holding_hours, opp.fr_impact_jpy);
+    info!("  レバレッジ手数料({}時間): ¥{:.2}", opp.holding_hours, opp.rollover_cost_jpy);
+    info!("  合計コスト: ¥{:.2}", opp.long_fee_jpy + opp.short_fee_jpy + opp.slippage_cost_jpy + opp.rollover_cost_jpy);
+    info!("");
+    info!("✅ 純利益: ¥{:.2} ({:.4}%)", opp.estimated_profit_jpy, opp.estimated_profit_pct);
+    info!("  即時スプレッド: {:.4}% / FRキャリー(年率): {:.2}%", opp.instant_spread_pct, opp.carry_annualized_pct);
+    for point in &opp.profit_curve {
+        debug!(
+            "  限界利益: 数量 {:.6} (¥{:.0}) 1単位 ¥{:.2} 累計 ¥{:.2}",
+            point.quantity, point.notional_jpy, point.marginal_profit_jpy, point.cumulative_profit_jpy
+        );
+    }
+    info!("================================================================================");
+}
+
+/// 消えた裁定機会の生存期間をログに出す。持続せずに執行しなかったものは debug
+fn log_closed_opportunity(strategy: &str, closed: &ClosedOpportunity) {
+    let message = format!(
+        "[Opportunity] {} closed ({}): {}ms, {} tick(s), peak {:.4}% (¥{:.2}), avg {:.4}%",
+        closed.key,
+        strategy,
+        closed.lifetime_ms(),
+        closed.ticks,
+        closed.peak_profit_pct,
+        closed.peak_profit_jpy,
+        closed.average_profit_pct,
+    );
+    if closed.confirmed {
+        info!("{}", message);
+    } else {
+        debug!("{} - not persisted", message);
+    }
+}
+
+/// 目標ポジションの変化をログに出す
+fn log_position_target(strategy: &str, target: &PositionTarget) {
+    let e = &target.estimate;
+    if target.quantity.is_zero() {
+        info!(
+            "📤 [キャリー解消] {:?} ({}): Perp {} / 現物 {} {:?} ({})",
+            target.asset, strategy, target.perp_exchange, target.hedge_exchange, target.hedge_instrument, target.reason
+        );
+        return;
+    }
+    info!("================================================================================");
+    info!("📥 [キャリー目標] {:?} ({})", target.asset, strategy);
+    info!("  ショート: {} Perp {:.6} @ {} {:?}", e.perp.exchange, target.quantity, e.perp.price, e.perp.currency);
+    info!("  ロング: {} {:?} {:.6} @ {} {:?}", e.hedge.exchange, e.hedge.instrument, target.quantity, e.hedge.price, e.hedge.currency);
+    info!("  想定元本: ¥{:.0} / FR年率: {:.2}%", e.notional_jpy, e.funding_apr_pct);
+    info!("  FR収入: ¥{:.2} ベーシス: ¥{:.2} 往復手数料: ¥{:.2} 為替リスク: ¥{:.2}", e.funding_income_jpy, e.basis_jpy, e.fees_jpy, e.fx_risk_jpy);
+    info!("  期待利益: ¥{:.2} ({:.4}%) - {}", e.net_edge_jpy, e.net_edge_pct, target.reason);
+    info!("================================================================================");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asset::{AssetConfig, AssetRegistry};
+    use crate::clock::VirtualClock;
+    use crate::collector::gmo::GmoCollector;
+    use crate::collector::Collector;
+    use crate::executor::{ExecutionError, ExecutionReport, LegFill, OrderStatus, Side};
+    use crate::replay::{gmo_fixture, run_replay, FeedReplayer, ReplaySpeed};
+    use crate::store::Exchange;
+    use crate::strategy::{Asset, InstrumentType};
+    use rust_decimal::Decimal;
+    use std::sync::Arc;
+
+    /// 買い足だけ約定して売り足が失敗する執行器
+    struct SellLegFails;
+
+    impl Executor for SellLegFails {
+        async fn execute(&mut self, opportunity: &ArbitrageOpportunity) -> Result<ExecutionReport, ExecutionError> {
+            let long = LegFill {
+                exchange: opportunity.long_exchange,
+                instrument: opportunity.long_instrument,
+                side: Side::Buy,
+                currency: opportunity.long_currency,
+                order_id: "long".to_string(),
+                requested_quantity: opportunity.quantity,
+                filled_quantity: opportunity.quantity,
+                average_price: opportunity.long_limit_price,
+                fee: Decimal::ZERO,
+                fee_jpy: Decimal::ZERO,
+                status: OrderStatus::Filled,
+            };
+            Err(ExecutionError::LegFailed { failed: Side::Sell, reason: "rejected".to_string(), other_leg: Ok(Box::new(long)) })
+        }
+    }
+
+    #[tokio::test]
+    async fn filled_leg_of_a_failed_execution_is_recorded_in_positions() {
+        let config = Config::default();
+        let registry = Arc::new(AssetRegistry::new(AssetConfig::defaults()));
+        let clock = Arc::new(VirtualClock::default());
+        let store = MarketStore::new().with_clock(clock.clone());
+        let collectors: Vec<Box<dyn Collector>> = vec![Box::new(GmoCollector::new(registry.clone()))];
+        let mut replayer = FeedReplayer::new(store.clone(), clock, collectors);
+        let mut engine = StrategyEngine::new(&store, &config, &registry, SellLegFails);
+
+        let frames = gmo_fixture(1_700_000_000_000_000).into_iter();
+        run_replay(frames, &mut replayer, &mut engine, &config.intervals, ReplaySpeed::AsFastAsPossible).await;
+
+        // fixture の機会は GMO 現物の買い -> GMO レバレッジの売りで、買い足だけが残る
+        let btc = Asset::new("BTC");
+        let positions = engine.positions();
+        assert_eq!(positions.get(Exchange::Gmo, InstrumentType::Spot, btc), Decimal::new(1, 2));
+        assert_eq!(positions.get(Exchange::Gmo, InstrumentType::Margin, btc), Decimal::ZERO);
+    }
+}
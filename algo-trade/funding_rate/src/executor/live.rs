@@ -0,0 +1,347 @@
+use super::*;
+use log::{info, warn};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::time::{sleep, Instant};
+
+#[derive(Debug, Clone)]
+pub struct ExecutorConfig {
+    /// 1回の裁定で建てる最大想定元本 (JPY)
+    pub max_notional_jpy: Decimal,
+    /// 発注数量の小数点以下桁数
+    pub quantity_dp: u32,
+    /// 約定状況を問い合わせる間隔
+    pub poll_interval: Duration,
+    /// この時間内に約定しなかった残りは取り消す
+    pub fill_timeout: Duration,
+}
+
+impl Default for ExecutorConfig {
+    fn default() -> Self {
+        Self {
+            max_notional_jpy: Decimal::from(100_000),
+            quantity_dp: 4,
+            poll_interval: Duration::from_millis(200),
+            fill_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// OrderGateway を介して買い足・売り足を同時に発注する執行器
+pub struct TwoLegExecutor<G: OrderGateway> {
+    gateway: G,
+    config: ExecutorConfig,
+    sequence: AtomicU64,
+}
+
+impl<G: OrderGateway> TwoLegExecutor<G> {
+    pub fn new(gateway: G, config: ExecutorConfig) -> Self {
+        Self {
+            gateway,
+            config,
+            sequence: AtomicU64::new(0),
+        }
+    }
+
+    /// 戦略が求めた数量と想定元本の上限から発注数量を決める
+    fn order_quantity(&self, opportunity: &ArbitrageOpportunity) -> Decimal {
+        order_quantity(&self.config, opportunity)
+    }
+
+    fn next_client_id(&self, side: Side) -> String {
+        let seq = self.sequence.fetch_add(1, Ordering::Relaxed);
+        format!("arb-{}-{:?}", seq, side).to_lowercase()
+    }
+
+    /// 注文が終端状態になるまで (またはタイムアウトまで) 待つ
+    async fn wait_for_fill(&self, exchange: Exchange, order_id: &str) -> Result<OrderState, ExecutionError> {
+        let deadline = Instant::now() + self.config.fill_timeout;
+        loop {
+            let state = self.gateway.order_state(exchange, order_id).await?;
+            if state.status.is_terminal() {
+                return Ok(state);
+            }
+            if Instant::now() >= deadline {
+                warn!("[Executor] {} order {} not filled within {:?}, cancelling", exchange, order_id, self.config.fill_timeout);
+                self.gateway.cancel_order(exchange, order_id).await?;
+                return self.gateway.order_state(exchange, order_id).await;
+            }
+            sleep(self.config.poll_interval).await;
+        }
+    }
+
+    fn leg_fill(request: &OrderRequest, currency: Currency, state: OrderState, jpy_rate: Decimal) -> LegFill {
+        LegFill {
+            exchange: request.exchange,
+            instrument: request.instrument,
+            side: request.side,
+            currency,
+            order_id: state.order_id,
+            requested_quantity: request.quantity,
+            filled_quantity: state.filled_quantity,
+            average_price: state.average_price,
+            fee: state.fee,
+            fee_jpy: to_jpy(state.fee, currency, jpy_rate),
+            status: state.status,
+        }
+    }
+
+    /// 発注済みの足の約定を待って LegFill にまとめる
+    async fn settle_leg(
+        &self,
+        request: &OrderRequest,
+        currency: Currency,
+        order_id: &str,
+        jpy_rate: Decimal,
+    ) -> Result<LegFill, ExecutionError> {
+        let state = self.wait_for_fill(request.exchange, order_id).await?;
+        Ok(Self::leg_fill(request, currency, state, jpy_rate))
+    }
+}
+
+impl<G: OrderGateway> Executor for TwoLegExecutor<G> {
+    fn is_simulated(&self) -> bool {
+        self.gateway.is_simulated()
+    }
+
+    async fn execute(&mut self, opportunity: &ArbitrageOpportunity) -> Result<ExecutionReport, ExecutionError> {
+        let quantity = self.order_quantity(opportunity);
+        if quantity <= Decimal::ZERO {
+            return Err(ExecutionError::InvalidOrder(format!(
+                "quantity rounds to zero (max notional ¥{}, price ¥{})",
+                self.config.max_notional_jpy, opportunity.long_price_jpy
+            )));
+        }
+        // 各足は機会検出時と同じ側のレート (買いは Ask、売りは Bid) で JPY に換算する
+        let (long_rate, short_rate) = (opportunity.long_fx_rate, opportunity.short_fx_rate);
+
+        let long_req = OrderRequest {
+            client_order_id: self.next_client_id(Side::Buy),
+            exchange: opportunity.long_exchange,
+            asset: opportunity.asset,
+            instrument: opportunity.long_instrument,
+            side: Side::Buy,
+            quantity,
+            // 板を複数レベル消費する前提で、最後に消費するレベルの価格を指値にする
+            limit_price: Some(opportunity.long_limit_price),
+        };
+        let short_req = OrderRequest {
+            client_order_id: self.next_client_id(Side::Sell),
+            exchange: opportunity.short_exchange,
+            asset: opportunity.asset,
+            instrument: opportunity.short_instrument,
+            side: Side::Sell,
+            quantity,
+            limit_price: Some(opportunity.short_limit_price),
+        };
+
+        info!(
+            "[Executor] {:?}: BUY {} {:?} {} @ {} / SELL {} {:?} {} @ {}",
+            opportunity.asset,
+            long_req.exchange, long_req.instrument, quantity, opportunity.long_limit_price,
+            short_req.exchange, short_req.instrument, quantity, opportunity.short_limit_price,
+        );
+
+        // 両足を同時に発注
+        let (long_placed, short_placed) = tokio::join!(
+            self.gateway.place_order(&long_req),
+            self.gateway.place_order(&short_req),
+        );
+
+        // 片足だけ通った場合は、通った方の約定を確定させて呼び出し元に返す
+        let (long_id, short_id) = match (long_placed, short_placed) {
+            (Ok(l), Ok(s)) => (l, s),
+            (Err(e), Ok(s)) => {
+                let other = self.settle_leg(&short_req, opportunity.short_currency, &s, short_rate).await;
+                return Err(ExecutionError::LegFailed { failed: Side::Buy, reason: e.to_string(), other_leg: other.map(Box::new).map_err(Box::new) });
+            }
+            (Ok(l), Err(e)) => {
+                let other = self.settle_leg(&long_req, opportunity.long_currency, &l, long_rate).await;
+                return Err(ExecutionError::LegFailed { failed: Side::Sell, reason: e.to_string(), other_leg: other.map(Box::new).map_err(Box::new) });
+            }
+            (Err(long), Err(short)) => {
+                return Err(ExecutionError::BothLegsFailed { long: Box::new(long), short: Box::new(short) });
+            }
+        };
+
+        let (long_fill, short_fill) = tokio::join!(
+            self.settle_leg(&long_req, opportunity.long_currency, &long_id, long_rate),
+            self.settle_leg(&short_req, opportunity.short_currency, &short_id, short_rate),
+        );
+        // 片足の約定を確認できなかった場合も、確認できた足は呼び出し元に返す
+        let (long, short) = match (long_fill, short_fill) {
+            (Ok(long), Ok(short)) => (long, short),
+            (Err(e), Ok(short)) => {
+                return Err(ExecutionError::LegFailed { failed: Side::Buy, reason: e.to_string(), other_leg: Ok(Box::new(short)) });
+            }
+            (Ok(long), Err(e)) => {
+                return Err(ExecutionError::LegFailed { failed: Side::Sell, reason: e.to_string(), other_leg: Ok(Box::new(long)) });
+            }
+            (Err(long), Err(short)) => {
+                return Err(ExecutionError::BothLegsFailed { long: Box::new(long), short: Box::new(short) });
+            }
+        };
+
+        let matched_quantity = long.filled_quantity.min(short.filled_quantity);
+        let long_cost_jpy = to_jpy(matched_quantity * long.average_price, long.currency, long_rate);
+        let short_revenue_jpy = to_jpy(matched_quantity * short.average_price, short.currency, short_rate);
+        let total_fee_jpy = long.fee_jpy + short.fee_jpy;
+
+        let report = ExecutionReport {
+            asset: opportunity.asset,
+            matched_quantity,
+            realized_profit_jpy: short_revenue_jpy - long_cost_jpy - total_fee_jpy,
+            estimated_profit_jpy: opportunity.estimated_profit_for(matched_quantity),
+            total_fee_jpy,
+            usd_jpy_rate: opportunity.usd_jpy_rate,
+            long,
+            short,
+        };
+
+        if report.unhedged_quantity() != Decimal::ZERO {
+            warn!("[Executor] {:?}: legs filled unevenly, unhedged quantity {}", report.asset, report.unhedged_quantity());
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategy::FeeSchedule;
+
+    /// 指定した取引所の注文だけ拒否する (unsettled の取引所は約定の確認に失敗する) 模擬取引所
+    struct Rejecting {
+        inner: LocalExchange,
+        reject: Vec<Exchange>,
+        unsettled: Vec<Exchange>,
+        error: fn(String) -> ExecutionError,
+    }
+
+    impl OrderGateway for Rejecting {
+        async fn place_order(&self, request: &OrderRequest) -> Result<String, ExecutionError> {
+            if self.reject.contains(&request.exchange) {
+                return Err((self.error)(format!("{} rejected", request.exchange)));
+            }
+            self.inner.place_order(request).await
+        }
+
+        async fn order_state(&self, exchange: Exchange, order_id: &str) -> Result<OrderState, ExecutionError> {
+            if self.unsettled.contains(&exchange) {
+                return Err(ExecutionError::Gateway(format!("{} order {} timed out", exchange, order_id)));
+            }
+            self.inner.order_state(exchange, order_id).await
+        }
+
+        async fn cancel_order(&self, exchange: Exchange, order_id: &str) -> Result<(), ExecutionError> {
+            self.inner.cancel_order(exchange, order_id).await
+        }
+    }
+
+    fn executor(reject: Vec<Exchange>, error: fn(String) -> ExecutionError) -> TwoLegExecutor<Rejecting> {
+        unsettled_executor(reject, vec![], error)
+    }
+
+    fn unsettled_executor(reject: Vec<Exchange>, unsettled: Vec<Exchange>, error: fn(String) -> ExecutionError) -> TwoLegExecutor<Rejecting> {
+        let gateway = Rejecting { inner: LocalExchange::with_fees(FeeSchedule::default()), reject, unsettled, error };
+        TwoLegExecutor::new(gateway, ExecutorConfig::default())
+    }
+
+    fn opportunity() -> ArbitrageOpportunity {
+        ArbitrageOpportunity::sample(
+            Asset::new("BTC"),
+            (Exchange::Bitbank, Decimal::from(10_000)),
+            (Exchange::Gmo, Decimal::from(10_100)),
+            Decimal::ONE,
+        )
+    }
+
+    #[tokio::test]
+    async fn both_legs_fill() {
+        let report = executor(vec![], ExecutionError::Gateway).execute(&opportunity()).await.unwrap();
+
+        assert_eq!(report.matched_quantity, Decimal::ONE);
+        assert_eq!(report.unhedged_quantity(), Decimal::ZERO);
+        assert_eq!((report.long.exchange, report.long.side), (Exchange::Bitbank, Side::Buy));
+        assert_eq!((report.short.exchange, report.short.side), (Exchange::Gmo, Side::Sell));
+        // Taker 手数料: Bitbank 0.12%、GMO 0.05%
+        let fees = Decimal::from(10_000) * Decimal::new(12, 4) + Decimal::from(10_100) * Decimal::new(5, 4);
+        assert_eq!(report.total_fee_jpy, fees);
+        assert_eq!(report.realized_profit_jpy, Decimal::from(100) - fees);
+        assert_eq!(report.estimated_profit_jpy, Decimal::from(100));
+    }
+
+    #[tokio::test]
+    async fn one_leg_fails_reports_the_filled_leg_to_unwind() {
+        let err = executor(vec![Exchange::Gmo], ExecutionError::Gateway).execute(&opportunity()).await.unwrap_err();
+
+        let ExecutionError::LegFailed { failed, reason, other_leg } = &err else {
+            panic!("expected LegFailed, got {}", err);
+        };
+        assert_eq!(*failed, Side::Sell);
+        assert!(reason.contains("Gmo rejected"), "{}", reason);
+        // 通った買い足の約定が残るので、呼び出し側で解消できる
+        let other = other_leg.as_ref().expect("filled leg");
+        assert_eq!((other.exchange, other.side, other.filled_quantity), (Exchange::Bitbank, Side::Buy, Decimal::ONE));
+        assert_eq!(other.status, OrderStatus::Filled);
+        assert_eq!(err.filled_leg().map(LegFill::signed_quantity), Some(Decimal::ONE));
+        assert!(!err.is_pre_trade());
+    }
+
+    #[tokio::test]
+    async fn unsettled_leg_after_placement_reports_the_filled_leg() {
+        let err = unsettled_executor(vec![], vec![Exchange::Bitbank], ExecutionError::Gateway)
+            .execute(&opportunity())
+            .await
+            .unwrap_err();
+
+        let ExecutionError::LegFailed { failed, reason, .. } = &err else {
+            panic!("expected LegFailed, got {}", err);
+        };
+        assert_eq!(*failed, Side::Buy);
+        assert!(reason.contains("timed out"), "{}", reason);
+        // 売り足は約定しているので、ヘッジされていない売り建玉として返る
+        let other = err.filled_leg().expect("filled leg");
+        assert_eq!((other.exchange, other.signed_quantity()), (Exchange::Gmo, -Decimal::ONE));
+    }
+
+    #[tokio::test]
+    async fn unsettled_other_leg_is_reported_as_unknown() {
+        let err = unsettled_executor(vec![Exchange::Gmo], vec![Exchange::Bitbank], ExecutionError::Gateway)
+            .execute(&opportunity())
+            .await
+            .unwrap_err();
+
+        let ExecutionError::LegFailed { failed, other_leg, .. } = &err else {
+            panic!("expected LegFailed, got {}", err);
+        };
+        assert_eq!(*failed, Side::Sell);
+        // 買い足の約定は確認できていないので、「約定なし」ではなく不明として残る
+        let unknown = other_leg.as_ref().unwrap_err();
+        assert!(unknown.to_string().contains("timed out"), "{}", unknown);
+        assert!(err.filled_leg().is_none());
+        assert!(err.to_string().contains("state unknown"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn both_legs_fail_keeps_both_errors() {
+        let err = executor(vec![Exchange::Bitbank, Exchange::Gmo], ExecutionError::Gateway)
+            .execute(&opportunity())
+            .await
+            .unwrap_err();
+
+        let ExecutionError::BothLegsFailed { long, short } = &err else {
+            panic!("expected BothLegsFailed, got {}", err);
+        };
+        assert!(long.to_string().contains("Bitbank rejected"), "{}", long);
+        assert!(short.to_string().contains("Gmo rejected"), "{}", short);
+        assert!(!err.is_pre_trade());
+
+        // どちらも発注前の検証で落ちたなら、別の経路で執行し直せる
+        let err = executor(vec![Exchange::Bitbank, Exchange::Gmo], ExecutionError::InvalidOrder)
+            .execute(&opportunity())
+            .await
+            .unwrap_err();
+        assert!(err.is_pre_trade(), "{}", err);
+    }
+}
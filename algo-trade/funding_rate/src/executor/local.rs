@@ -0,0 +1,77 @@
+use super::*;
+use crate::strategy::FeeSchedule;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// ローカルの模擬取引所。
+/// 指値で即時全量約定させ、Taker手数料を差し引く。実際の発注を伴わない動作確認用
+pub struct LocalExchange {
+    orders: Mutex<HashMap<String, OrderState>>,
+    next_id: AtomicU64,
+    fees: FeeSchedule,
+}
+
+impl LocalExchange {
+    pub fn new() -> Self {
+        Self::with_fees(FeeSchedule::default())
+    }
+
+    pub fn with_fees(fees: FeeSchedule) -> Self {
+        Self {
+            orders: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+            fees,
+        }
+    }
+}
+
+impl Default for LocalExchange {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OrderGateway for LocalExchange {
+    fn is_simulated(&self) -> bool {
+        true
+    }
+
+    async fn place_order(&self, request: &OrderRequest) -> Result<String, ExecutionError> {
+        let price = request
+            .limit_price
+            .ok_or_else(|| ExecutionError::InvalidOrder("local exchange requires a limit price".to_string()))?;
+
+        let order_id = format!("local-{}", self.next_id.fetch_add(1, Ordering::Relaxed));
+        let state = OrderState {
+            order_id: order_id.clone(),
+            status: OrderStatus::Filled,
+            filled_quantity: request.quantity,
+            average_price: price,
+            fee: request.quantity * price * self.fees.taker(request.exchange, request.instrument),
+        };
+        self.orders.lock().unwrap().insert(order_id.clone(), state);
+        Ok(order_id)
+    }
+
+    async fn order_state(&self, _exchange: Exchange, order_id: &str) -> Result<OrderState, ExecutionError> {
+        self.orders
+            .lock()
+            .unwrap()
+            .get(order_id)
+            .cloned()
+            .ok_or_else(|| ExecutionError::Gateway(format!("unknown order {}", order_id)))
+    }
+
+    async fn cancel_order(&self, _exchange: Exchange, order_id: &str) -> Result<(), ExecutionError> {
+        let mut orders = self.orders.lock().unwrap();
+        match orders.get_mut(order_id) {
+            Some(state) if !state.status.is_terminal() => {
+                state.status = OrderStatus::Cancelled;
+                Ok(())
+            }
+            Some(_) => Ok(()),
+            None => Err(ExecutionError::Gateway(format!("unknown order {}", order_id))),
+        }
+    }
+}
@@ -0,0 +1,222 @@
+pub mod live;
+pub mod local;
+pub mod paper;
+
+pub use live::*;
+pub use local::*;
+pub use paper::*;
+
+use crate::store::Exchange;
+use crate::strategy::{ArbitrageOpportunity, Asset, Currency, InstrumentType};
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::Serialize;
+use std::fmt;
+use std::future::Future;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+/// 取引所へ送る注文 (裁定の片足)
+#[derive(Debug, Clone)]
+pub struct OrderRequest {
+    pub client_order_id: String,
+    pub exchange: Exchange,
+    pub asset: Asset,
+    pub instrument: InstrumentType,
+    pub side: Side,
+    pub quantity: Decimal,
+    /// 指値 (IOC)。None の場合は成行
+    pub limit_price: Option<Decimal>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum OrderStatus {
+    Open,
+    PartiallyFilled,
+    Filled,
+    Cancelled,
+    Rejected(String),
+}
+
+impl OrderStatus {
+    /// これ以上約定が進まない状態か
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, OrderStatus::Filled | OrderStatus::Cancelled | OrderStatus::Rejected(_))
+    }
+}
+
+/// 取引所から見た注文の状態
+#[derive(Debug, Clone)]
+pub struct OrderState {
+    pub order_id: String,
+    pub status: OrderStatus,
+    pub filled_quantity: Decimal,
+    /// 約定平均価格 (建値通貨)
+    pub average_price: Decimal,
+    /// 支払手数料 (建値通貨)
+    pub fee: Decimal,
+}
+
+/// 片足の約定結果
+#[derive(Debug, Clone, Serialize)]
+pub struct LegFill {
+    pub exchange: Exchange,
+    pub instrument: InstrumentType,
+    pub side: Side,
+    pub currency: Currency,
+    pub order_id: String,
+    pub requested_quantity: Decimal,
+    pub filled_quantity: Decimal,
+    pub average_price: Decimal,
+    pub fee: Decimal,
+    pub fee_jpy: Decimal,
+    pub status: OrderStatus,
+}
+
+impl LegFill {
+    /// 約定代金 (建値通貨)
+    pub fn notional(&self) -> Decimal {
+        self.filled_quantity * self.average_price
+    }
+
+    /// 建玉の増減 (買いがプラス)
+    pub fn signed_quantity(&self) -> Decimal {
+        match self.side {
+            Side::Buy => self.filled_quantity,
+            Side::Sell => -self.filled_quantity,
+        }
+    }
+}
+
+/// 裁定取引1回分の執行結果
+#[derive(Debug, Clone)]
+pub struct ExecutionReport {
+    pub asset: Asset,
+    pub long: LegFill,
+    pub short: LegFill,
+    /// 両足で約定した数量 (小さい方)
+    pub matched_quantity: Decimal,
+    /// 実現損益 (手数料控除後, JPY)
+    pub realized_profit_jpy: Decimal,
+    /// 発注時点の推定損益 (matched_quantity 換算, JPY)
+    pub estimated_profit_jpy: Decimal,
+    pub total_fee_jpy: Decimal,
+    pub usd_jpy_rate: Decimal,
+}
+
+impl ExecutionReport {
+    /// 両足の約定数量が揃っていない (ヘッジ外れ) 場合の差分
+    pub fn unhedged_quantity(&self) -> Decimal {
+        self.long.filled_quantity - self.short.filled_quantity
+    }
+}
+
+#[derive(Debug)]
+pub enum ExecutionError {
+    /// 発注前の検証エラー (数量ゼロ、未対応の取引所など)
+    InvalidOrder(String),
+    /// ゲートウェイとの通信エラー
+    Gateway(String),
+    /// 約定価格の参照先となる気配が無い
+    NoQuote(String),
+    /// 仮想残高の不足 (ペーパートレード)
+    InsufficientBalance(String),
+    /// 片足の発注または約定の確認に失敗した。もう片方の足の状態を保持する
+    LegFailed {
+        failed: Side,
+        reason: String,
+        /// もう片方の足の約定。Err は約定を確認できず、建玉が不明なことを表す
+        other_leg: Result<Box<LegFill>, Box<ExecutionError>>,
+    },
+    /// 両足とも発注または約定の確認に失敗した。それぞれの原因を保持する
+    BothLegsFailed {
+        long: Box<ExecutionError>,
+        short: Box<ExecutionError>,
+    },
+}
+
+impl ExecutionError {
+    /// 片足だけ失敗したときに、約定を確認できたもう片方の足 (ヘッジされていない建玉)
+    pub fn filled_leg(&self) -> Option<&LegFill> {
+        match self {
+            ExecutionError::LegFailed { other_leg: Ok(leg), .. } => Some(leg),
+            _ => None,
+        }
+    }
+
+    /// どの足も発注する前に失敗したか (残高・板の厚み・数量の不足など)。
+    /// 建玉は変わっていないので、別の経路で執行し直せる
+    pub fn is_pre_trade(&self) -> bool {
+        match self {
+            ExecutionError::InvalidOrder(_) | ExecutionError::NoQuote(_) | ExecutionError::InsufficientBalance(_) => true,
+            ExecutionError::BothLegsFailed { long, short } => long.is_pre_trade() && short.is_pre_trade(),
+            ExecutionError::Gateway(_) | ExecutionError::LegFailed { .. } => false,
+        }
+    }
+}
+
+impl fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecutionError::InvalidOrder(msg) => write!(f, "invalid order: {}", msg),
+            ExecutionError::Gateway(msg) => write!(f, "gateway error: {}", msg),
+            ExecutionError::NoQuote(msg) => write!(f, "no quote: {}", msg),
+            ExecutionError::InsufficientBalance(msg) => write!(f, "insufficient balance: {}", msg),
+            ExecutionError::LegFailed { failed, reason, other_leg } => {
+                write!(f, "{:?} leg failed: {}", failed, reason)?;
+                match other_leg {
+                    Ok(leg) => write!(f, " (other leg {:?} filled {} on {})", leg.side, leg.filled_quantity, leg.exchange),
+                    Err(e) => write!(f, " (other leg state unknown: {})", e),
+                }
+            }
+            ExecutionError::BothLegsFailed { long, short } => write!(f, "both legs failed: Buy: {} / Sell: {}", long, short),
+        }
+    }
+}
+
+impl std::error::Error for ExecutionError {}
+
+/// 取引所への発注口。実取引所のクライアントやローカルの模擬取引所が実装する
+pub trait OrderGateway: Send + Sync {
+    /// 実際の取引所に発注しない模擬の発注口か
+    fn is_simulated(&self) -> bool {
+        false
+    }
+
+    fn place_order(&self, request: &OrderRequest) -> impl Future<Output = Result<String, ExecutionError>> + Send;
+
+    fn order_state(&self, exchange: Exchange, order_id: &str) -> impl Future<Output = Result<OrderState, ExecutionError>> + Send;
+
+    fn cancel_order(&self, exchange: Exchange, order_id: &str) -> impl Future<Output = Result<(), ExecutionError>> + Send;
+}
+
+/// 裁定機会を受け取って両足を執行する
+pub trait Executor: Send {
+    /// 約定が模擬 (実際の取引所に発注しない) か。執行結果のログに表示する
+    fn is_simulated(&self) -> bool {
+        false
+    }
+
+    fn execute(&mut self, opportunity: &ArbitrageOpportunity) -> impl Future<Output = Result<ExecutionReport, ExecutionError>> + Send;
+}
+
+/// 建値通貨の金額をJPYに換算 (jpy_rate は建値通貨1単位あたりのJPY。USDC なら USDC_USD × USD_JPY)
+pub fn to_jpy(amount: Decimal, currency: Currency, jpy_rate: Decimal) -> Decimal {
+    match currency {
+        Currency::JPY => amount,
+        Currency::USD | Currency::USDC | Currency::USDT => amount * jpy_rate,
+    }
+}
+
+/// 戦略が求めた数量を想定元本の上限で抑え、発注単位に丸める
+pub fn order_quantity(config: &ExecutorConfig, opportunity: &ArbitrageOpportunity) -> Decimal {
+    if opportunity.long_price_jpy <= Decimal::ZERO {
+        return Decimal::ZERO;
+    }
+    (config.max_notional_jpy / opportunity.long_price_jpy)
+        .min(opportunity.quantity)
+        .round_dp_with_strategy(config.quantity_dp, RoundingStrategy::ToZero)
+}
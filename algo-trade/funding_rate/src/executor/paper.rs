@@ -0,0 +1,591 @@
+use super::*;
+use crate::store::{BookSide, MarketStore};
+use crate::strategy::{symbol_for, FeeSchedule};
+use log::{info, warn};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// ペーパートレードの口座の設定 (設定ファイルの [paper])
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PaperAccountConfig {
+    /// 取引所・通貨ごとの初期残高。書いた場合は既定の一覧を置き換える
+    pub balances: Vec<PaperBalance>,
+    /// 取引所ごとの現物の初期保有数量。現物の売りは保有数量までしか約定させない
+    pub inventory: Vec<PaperInventory>,
+    /// Perp・レバレッジ取引の証拠金率の逆数 (建玉の想定元本 / leverage を証拠金として拘束する)
+    pub leverage: Decimal,
+    /// --paper の取引ジャーナル (JSON Lines) の出力先
+    pub journal_path: String,
+    /// --replay の取引ジャーナルの出力先
+    pub replay_journal_path: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PaperBalance {
+    pub exchange: Exchange,
+    pub currency: Currency,
+    pub amount: Decimal,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PaperInventory {
+    pub exchange: Exchange,
+    pub asset: Asset,
+    pub quantity: Decimal,
+}
+
+impl Default for PaperAccountConfig {
+    fn default() -> Self {
+        let balance = |exchange, currency, amount| PaperBalance { exchange, currency, amount: Decimal::from(amount) };
+        Self {
+            balances: vec![
+                balance(Exchange::Gmo, Currency::JPY, 1_000_000),
+                balance(Exchange::Bitbank, Currency::JPY, 1_000_000),
+                balance(Exchange::Hyperliquid, Currency::USDC, 10_000),
+            ],
+            inventory: Vec::new(),
+            leverage: Decimal::from(2),
+            journal_path: "paper_journal.jsonl".to_string(),
+            replay_journal_path: "replay_journal.jsonl".to_string(),
+        }
+    }
+}
+
+/// ペーパートレードの初期残高と取引ジャーナルの設定
+#[derive(Debug, Clone)]
+pub struct PaperConfig {
+    pub executor: ExecutorConfig,
+    /// 取引所・通貨ごとの初期残高
+    pub initial_balances: Vec<(Exchange, Currency, Decimal)>,
+    /// 取引所ごとの現物の初期保有数量。現物の売りは保有数量までしか約定させない
+    pub initial_inventory: Vec<(Exchange, Asset, Decimal)>,
+    /// Perp・レバレッジ取引の証拠金率の逆数 (建玉の想定元本 / leverage を証拠金として拘束する)
+    pub leverage: Decimal,
+    /// 取引ジャーナル (JSON Lines) の出力先
+    pub journal_path: String,
+    pub fees: FeeSchedule,
+}
+
+impl PaperConfig {
+    /// [paper] の口座の設定から作る。ジャーナルは --paper と --replay で出力先が異なるので別に渡す
+    pub fn new(account: &PaperAccountConfig, executor: ExecutorConfig, fees: FeeSchedule, journal_path: &str) -> Self {
+        Self {
+            executor,
+            initial_balances: account.balances.iter().map(|b| (b.exchange, b.currency, b.amount)).collect(),
+            initial_inventory: account.inventory.iter().map(|i| (i.exchange, i.asset, i.quantity)).collect(),
+            leverage: account.leverage,
+            journal_path: journal_path.to_string(),
+            fees,
+        }
+    }
+}
+
+impl Default for PaperConfig {
+    fn default() -> Self {
+        let account = PaperAccountConfig::default();
+        Self::new(&account, ExecutorConfig::default(), FeeSchedule::default(), &account.journal_path)
+    }
+}
+
+/// 推定損益と実現損益の累計
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PaperSummary {
+    pub trades: u64,
+    pub realized_profit_jpy: Decimal,
+    pub estimated_profit_jpy: Decimal,
+    pub total_fee_jpy: Decimal,
+}
+
+impl PaperSummary {
+    /// 推定損益のうち実際に取れた割合 (推定がゼロ以下なら None)
+    pub fn capture_ratio(&self) -> Option<Decimal> {
+        if self.estimated_profit_jpy > Decimal::ZERO {
+            Some(self.realized_profit_jpy / self.estimated_profit_jpy)
+        } else {
+            None
+        }
+    }
+}
+
+/// ジャーナル1行分
+#[derive(Debug, Serialize)]
+struct JournalEntry<'a> {
+    timestamp_ms: u64,
+    asset: Asset,
+    quantity: Decimal,
+    long: &'a LegFill,
+    short: &'a LegFill,
+    /// 機会検出時の価格 (約定価格とのずれ = 検出から約定までの価格変化)
+    quoted_long_price: Decimal,
+    quoted_short_price: Decimal,
+    usd_jpy_rate: Decimal,
+    realized_profit_jpy: Decimal,
+    estimated_profit_jpy: Decimal,
+    summary: &'a PaperSummary,
+    balances: Vec<(Exchange, Currency, Decimal)>,
+}
+
+/// 現在の板から片足の約定価格を取得 (買いはAsk、売りはBidを数量分消費したVWAP)。
+/// 板がなければ気配値で全量約定したものとする
+pub fn fill_price(
+    store: &MarketStore,
+    exchange: Exchange,
+    asset: Asset,
+    instrument: InstrumentType,
+    side: Side,
+    quantity: Decimal,
+) -> Result<Decimal, ExecutionError> {
+    let symbol = symbol_for(exchange, &asset, instrument);
+    let book_side = match side {
+        Side::Buy => BookSide::Ask,
+        Side::Sell => BookSide::Bid,
+    };
+    if let Ok(book) = store.get_fresh_order_book(exchange, &symbol) {
+        return book.vwap_for_size(book_side, quantity).ok_or_else(|| {
+            ExecutionError::NoQuote(format!("{} {} book too thin for {} {:?}", exchange, symbol, quantity, side))
+        });
+    }
+    let data = store
+        .get_fresh_market_data(exchange, &symbol)
+        .map_err(|e| ExecutionError::NoQuote(format!("{} {}: {}", exchange, symbol, e)))?;
+    let price = match side {
+        Side::Buy => data.ask,
+        Side::Sell => data.bid,
+    };
+    if price <= Decimal::ZERO {
+        return Err(ExecutionError::NoQuote(format!("{} {} has no {:?} side", exchange, symbol, side)));
+    }
+    Ok(price)
+}
+
+/// Perp・レバレッジ取引の建玉の平均建値と建値通貨
+#[derive(Debug, Clone, Copy)]
+struct MarginEntry {
+    price: Decimal,
+    currency: Currency,
+}
+
+/// 約定させる片足の注文
+#[derive(Debug, Clone, Copy)]
+struct PaperOrder {
+    exchange: Exchange,
+    instrument: InstrumentType,
+    currency: Currency,
+    side: Side,
+    quantity: Decimal,
+    price: Decimal,
+}
+
+impl PaperOrder {
+    /// 建玉の増減 (買いがプラス)
+    fn signed_quantity(&self) -> Decimal {
+        match self.side {
+            Side::Buy => self.quantity,
+            Side::Sell => -self.quantity,
+        }
+    }
+}
+
+/// MarketStore の最新気配で約定させる模擬執行器。
+/// 買いは現在の Ask、売りは現在の Bid で全量約定したものとし、取引所のTaker手数料を差し引く。
+/// 現物は代金を残高から受け払いし、保有数量を超える売りは拒否する。
+/// Perp・レバレッジ取引は建玉として持ち、残高からは手数料と決済時の損益だけを受け払いする
+/// (建玉の想定元本 / leverage を証拠金として拘束する)
+pub struct PaperExecutor {
+    store: MarketStore,
+    config: ExecutorConfig,
+    fees: FeeSchedule,
+    leverage: Decimal,
+    balances: HashMap<(Exchange, Currency), Decimal>,
+    /// 取引所・商品ごとの保有数量 (売り越しは負)
+    positions: HashMap<(Exchange, InstrumentType, Asset), Decimal>,
+    /// Perp・レバレッジ取引の建玉の平均建値
+    margin_entries: HashMap<(Exchange, InstrumentType, Asset), MarginEntry>,
+    journal: BufWriter<File>,
+    summary: PaperSummary,
+    next_order_id: u64,
+}
+
+impl PaperExecutor {
+    pub fn new(store: MarketStore, config: PaperConfig) -> std::io::Result<Self> {
+        let journal = open_journal(&config.journal_path)?;
+        info!("[Paper] Writing trade journal to {}", config.journal_path);
+        Ok(Self {
+            store,
+            config: config.executor,
+            fees: config.fees,
+            leverage: config.leverage,
+            balances: config
+                .initial_balances
+                .into_iter()
+                .map(|(exchange, currency, amount)| ((exchange, currency), amount))
+                .collect(),
+            positions: config
+                .initial_inventory
+                .into_iter()
+                .map(|(exchange, asset, quantity)| ((exchange, InstrumentType::Spot, asset), quantity))
+                .collect(),
+            margin_entries: HashMap::new(),
+            journal,
+            summary: PaperSummary::default(),
+            next_order_id: 1,
+        })
+    }
+
+    pub fn summary(&self) -> &PaperSummary {
+        &self.summary
+    }
+
+    pub fn balance(&self, exchange: Exchange, currency: Currency) -> Decimal {
+        self.balances.get(&(exchange, currency)).copied().unwrap_or(Decimal::ZERO)
+    }
+
+    pub fn position(&self, exchange: Exchange, instrument: InstrumentType, asset: Asset) -> Decimal {
+        self.positions.get(&(exchange, instrument, asset)).copied().unwrap_or(Decimal::ZERO)
+    }
+
+    /// 取引所・通貨ごとの、Perp・レバレッジ取引の建玉が拘束している証拠金
+    pub fn used_margin(&self, exchange: Exchange, currency: Currency) -> Decimal {
+        self.margin_entries
+            .iter()
+            .filter(|((e, _, _), entry)| *e == exchange && entry.currency == currency)
+            .map(|(key, entry)| self.positions.get(key).copied().unwrap_or_default().abs() * entry.price / self.leverage)
+            .sum()
+    }
+
+    /// 両足を約定させられるかを確かめる。現物の売りは保有数量と比べ、それ以外 (現物の買いの代金、
+    /// Perp・レバレッジ取引の手数料と証拠金) は取引所・通貨ごとに合算して、拘束されていない残高と比べる。
+    /// 同じ取引所の足 (GMO 現物の買いと GMO レバレッジの売りなど) が別々には足りても合わせて足りない場合を拒否する
+    fn check_legs(&self, asset: Asset, orders: &[PaperOrder]) -> Result<(), ExecutionError> {
+        let mut required: Vec<((Exchange, Currency), Decimal)> = Vec::new();
+        for order in orders {
+            let (exchange, instrument) = (order.exchange, order.instrument);
+            let notional = order.quantity * order.price;
+            let fee = notional * self.fees.taker(exchange, instrument);
+            let held = self.position(exchange, instrument, asset);
+            let amount = match (instrument, order.side) {
+                (InstrumentType::Spot, Side::Buy) => notional + fee,
+                (InstrumentType::Spot, Side::Sell) => {
+                    if held < order.quantity {
+                        return Err(ExecutionError::InsufficientBalance(format!(
+                            "{} {:?} spot inventory: need {}, have {}",
+                            exchange, asset, order.quantity, held
+                        )));
+                    }
+                    continue;
+                }
+                (InstrumentType::Perp | InstrumentType::Margin, _) => {
+                    // 建玉を減らす約定は証拠金を増やさない
+                    let additional_margin =
+                        if (held + order.signed_quantity()).abs() > held.abs() { notional / self.leverage } else { Decimal::ZERO };
+                    fee + additional_margin
+                }
+            };
+            let key = (exchange, order.currency);
+            match required.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, total)) => *total += amount,
+                None => required.push((key, amount)),
+            }
+        }
+        for ((exchange, currency), required) in required {
+            let available = self.balance(exchange, currency) - self.used_margin(exchange, currency);
+            if available < required {
+                return Err(ExecutionError::InsufficientBalance(format!(
+                    "{} {:?}: need {}, have {}",
+                    exchange, currency, required, available
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn simulate_leg(&mut self, asset: Asset, order: PaperOrder, jpy_rate: Decimal) -> LegFill {
+        let PaperOrder { exchange, instrument, currency, side, quantity, price } = order;
+        let notional = quantity * price;
+        let fee = notional * self.fees.taker(exchange, instrument);
+
+        let key = (exchange, instrument, asset);
+        let signed = order.signed_quantity();
+        let held = self.position(exchange, instrument, asset);
+        let cash = self.balances.entry((exchange, currency)).or_insert(Decimal::ZERO);
+        match instrument {
+            InstrumentType::Spot => *cash -= signed * price + fee,
+            InstrumentType::Perp | InstrumentType::Margin => {
+                *cash -= fee;
+                let entry = self.margin_entries.get(&key).map(|e| e.price).unwrap_or_default();
+                let remaining = held + signed;
+                let entry = if held.is_zero() || held.is_sign_positive() == signed.is_sign_positive() {
+                    // 建て増し: 平均建値を更新する
+                    (held.abs() * entry + quantity * price) / remaining.abs()
+                } else {
+                    // 決済: 減らした分の損益を受け払いし、ドテンした分は今回の価格で建てる
+                    let closed = quantity.min(held.abs());
+                    let pnl = closed * (price - entry);
+                    *cash += if held.is_sign_positive() { pnl } else { -pnl };
+                    if remaining.is_zero() || remaining.is_sign_positive() == held.is_sign_positive() { entry } else { price }
+                };
+                if remaining.is_zero() {
+                    self.margin_entries.remove(&key);
+                } else {
+                    self.margin_entries.insert(key, MarginEntry { price: entry, currency });
+                }
+            }
+        }
+        *self.positions.entry(key).or_insert(Decimal::ZERO) = held + signed;
+
+        let order_id = format!("paper-{}", self.next_order_id);
+        self.next_order_id += 1;
+
+        LegFill {
+            exchange,
+            instrument,
+            side,
+            currency,
+            order_id,
+            requested_quantity: quantity,
+            filled_quantity: quantity,
+            average_price: price,
+            fee,
+            fee_jpy: to_jpy(fee, currency, jpy_rate),
+            status: OrderStatus::Filled,
+        }
+    }
+
+    fn write_journal(&mut self, opportunity: &ArbitrageOpportunity, report: &ExecutionReport) {
+        let mut balances: Vec<(Exchange, Currency, Decimal)> = self
+            .balances
+            .iter()
+            .map(|(&(exchange, currency), &amount)| (exchange, currency, amount))
+            .collect();
+        balances.sort_by_key(|(exchange, currency, _)| (exchange.to_string(), *currency as u8));
+
+        let entry = JournalEntry {
+            timestamp_ms: self.store.now_ms(),
+            asset: report.asset,
+            quantity: report.matched_quantity,
+            long: &report.long,
+            short: &report.short,
+            quoted_long_price: opportunity.long_price_raw,
+            quoted_short_price: opportunity.short_price_raw,
+            usd_jpy_rate: report.usd_jpy_rate,
+            realized_profit_jpy: report.realized_profit_jpy,
+            estimated_profit_jpy: report.estimated_profit_jpy,
+            summary: &self.summary,
+            balances,
+        };
+
+        let result = serde_json::to_writer(&mut self.journal, &entry)
+            .map_err(std::io::Error::from)
+            .and_then(|_| self.journal.write_all(b"\n"))
+            .and_then(|_| self.journal.flush());
+        if let Err(e) = result {
+            warn!("[Paper] Failed to write journal: {}", e);
+        }
+    }
+}
+
+impl Executor for PaperExecutor {
+    fn is_simulated(&self) -> bool {
+        true
+    }
+
+    async fn execute(&mut self, opportunity: &ArbitrageOpportunity) -> Result<ExecutionReport, ExecutionError> {
+        // 各足は機会検出時と同じ側のレート (買いは Ask、売りは Bid) で JPY に換算する
+        let (long_rate, short_rate) = (opportunity.long_fx_rate, opportunity.short_fx_rate);
+        let asset = opportunity.asset;
+
+        let quantity = order_quantity(&self.config, opportunity);
+        if quantity <= Decimal::ZERO {
+            return Err(ExecutionError::InvalidOrder(format!(
+                "quantity rounds to zero (max notional ¥{}, price ¥{})",
+                self.config.max_notional_jpy, opportunity.long_price_jpy
+            )));
+        }
+
+        // 約定価格は機会検出時の価格ではなく、執行時点の板を使う
+        let long_price = fill_price(&self.store, opportunity.long_exchange, asset, opportunity.long_instrument, Side::Buy, quantity)?;
+        let short_price = fill_price(&self.store, opportunity.short_exchange, asset, opportunity.short_instrument, Side::Sell, quantity)?;
+
+        let long_order = PaperOrder {
+            exchange: opportunity.long_exchange,
+            instrument: opportunity.long_instrument,
+            currency: opportunity.long_currency,
+            side: Side::Buy,
+            quantity,
+            price: long_price,
+        };
+        let short_order = PaperOrder {
+            exchange: opportunity.short_exchange,
+            instrument: opportunity.short_instrument,
+            currency: opportunity.short_currency,
+            side: Side::Sell,
+            quantity,
+            price: short_price,
+        };
+        // 買い足の資金・売り足の在庫 (現物) または証拠金のチェック
+        self.check_legs(asset, &[long_order, short_order])?;
+
+        let long = self.simulate_leg(asset, long_order, long_rate);
+        let short = self.simulate_leg(asset, short_order, short_rate);
+
+        let long_cost_jpy = to_jpy(long.notional(), long.currency, long_rate);
+        let short_revenue_jpy = to_jpy(short.notional(), short.currency, short_rate);
+        let total_fee_jpy = long.fee_jpy + short.fee_jpy;
+
+        let report = ExecutionReport {
+            asset,
+            matched_quantity: quantity,
+            realized_profit_jpy: short_revenue_jpy - long_cost_jpy - total_fee_jpy,
+            estimated_profit_jpy: opportunity.estimated_profit_for(quantity),
+            total_fee_jpy,
+            usd_jpy_rate: opportunity.usd_jpy_rate,
+            long,
+            short,
+        };
+
+        self.summary.trades += 1;
+        self.summary.realized_profit_jpy += report.realized_profit_jpy;
+        self.summary.estimated_profit_jpy += report.estimated_profit_jpy;
+        self.summary.total_fee_jpy += report.total_fee_jpy;
+        self.write_journal(opportunity, &report);
+
+        Ok(report)
+    }
+}
+
+fn open_journal(path: &str) -> std::io::Result<BufWriter<File>> {
+    if let Some(parent) = Path::new(path).parent()
+        && !parent.as_os_str().is_empty()
+    {
+        std::fs::create_dir_all(parent)?;
+    }
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    Ok(BufWriter::new(file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    const BTC: &str = "BTC";
+
+    /// Bitbank 現物 ¥10,000 / GMO 現物・レバレッジ ¥10,100 の気配を持つ手数料なしの模擬執行器
+    fn executor(dir: &TempDir, initial_inventory: Vec<(Exchange, Asset, Decimal)>) -> PaperExecutor {
+        let store = MarketStore::new();
+        let (bid, ask) = (Decimal::from(10_000), Decimal::from(10_000));
+        store.update_market_data(Exchange::Bitbank, BTC, bid, ask, bid, None);
+        let (bid, ask) = (Decimal::from(10_100), Decimal::from(10_100));
+        store.update_market_data(Exchange::Gmo, "BTC_SPOT", bid, ask, bid, None);
+        store.update_market_data(Exchange::Gmo, BTC, bid, ask, bid, None);
+
+        let mut fees = FeeSchedule::default();
+        fees.set_taker(Exchange::Bitbank, Decimal::ZERO);
+        fees.set_taker(Exchange::Gmo, Decimal::ZERO);
+        let journal_path = dir.file("journal.jsonl");
+        let config = PaperConfig { fees, journal_path, initial_inventory, ..PaperConfig::default() };
+        PaperExecutor::new(store, config).unwrap()
+    }
+
+    fn opportunity(short_instrument: InstrumentType) -> ArbitrageOpportunity {
+        let mut opportunity = ArbitrageOpportunity::sample(
+            Asset::new(BTC),
+            (Exchange::Bitbank, Decimal::from(10_000)),
+            (Exchange::Gmo, Decimal::from(10_100)),
+            Decimal::ONE,
+        );
+        opportunity.short_instrument = short_instrument;
+        opportunity
+    }
+
+    #[tokio::test]
+    async fn spot_sell_without_inventory_is_rejected() {
+        let dir = TempDir::new("paper");
+        let mut executor = executor(&dir, Vec::new());
+        let err = executor.execute(&opportunity(InstrumentType::Spot)).await.unwrap_err();
+        assert!(matches!(err, ExecutionError::InsufficientBalance(_)), "{}", err);
+        assert!(err.is_pre_trade());
+        // 拒否された機会では買い足も約定させない
+        assert_eq!(executor.balance(Exchange::Bitbank, Currency::JPY), Decimal::from(1_000_000));
+        assert_eq!(executor.position(Exchange::Gmo, InstrumentType::Spot, Asset::new(BTC)), Decimal::ZERO);
+    }
+
+    #[tokio::test]
+    async fn spot_sell_consumes_inventory() {
+        let dir = TempDir::new("paper");
+        let mut executor = executor(&dir, vec![(Exchange::Gmo, Asset::new(BTC), Decimal::ONE)]);
+        executor.execute(&opportunity(InstrumentType::Spot)).await.unwrap();
+        assert_eq!(executor.position(Exchange::Gmo, InstrumentType::Spot, Asset::new(BTC)), Decimal::ZERO);
+        assert_eq!(executor.balance(Exchange::Gmo, Currency::JPY), Decimal::from(1_010_100));
+
+        // 在庫を売り切った後は同じ機会を約定させない
+        let err = executor.execute(&opportunity(InstrumentType::Spot)).await.unwrap_err();
+        assert!(matches!(err, ExecutionError::InsufficientBalance(_)), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn margin_short_is_booked_as_position_not_cash() {
+        let dir = TempDir::new("paper");
+        let mut executor = executor(&dir, Vec::new());
+        executor.execute(&opportunity(InstrumentType::Margin)).await.unwrap();
+
+        let asset = Asset::new(BTC);
+        assert_eq!(executor.position(Exchange::Gmo, InstrumentType::Margin, asset), -Decimal::ONE);
+        // 売り代金は残高に入らず、想定元本 / leverage が証拠金として拘束される
+        assert_eq!(executor.balance(Exchange::Gmo, Currency::JPY), Decimal::from(1_000_000));
+        assert_eq!(executor.used_margin(Exchange::Gmo, Currency::JPY), Decimal::from(5_050));
+        assert_eq!(executor.balance(Exchange::Bitbank, Currency::JPY), Decimal::from(990_000));
+    }
+
+    #[tokio::test]
+    async fn same_exchange_legs_are_checked_together() {
+        let dir = TempDir::new("paper");
+        let mut executor = executor(&dir, Vec::new());
+        // GMO 現物の買い (¥10,100) とレバレッジの売りの証拠金 (¥5,050) は、別々なら足りるが合わせると足りない
+        executor.balances.insert((Exchange::Gmo, Currency::JPY), Decimal::from(12_000));
+        let mut opportunity = opportunity(InstrumentType::Margin);
+        opportunity.long_exchange = Exchange::Gmo;
+        let err = executor.execute(&opportunity).await.unwrap_err();
+        assert!(matches!(err, ExecutionError::InsufficientBalance(_)), "{}", err);
+        assert_eq!(executor.balance(Exchange::Gmo, Currency::JPY), Decimal::from(12_000));
+
+        executor.balances.insert((Exchange::Gmo, Currency::JPY), Decimal::from(15_150));
+        executor.execute(&opportunity).await.unwrap();
+        assert_eq!(executor.position(Exchange::Gmo, InstrumentType::Spot, Asset::new(BTC)), Decimal::ONE);
+        assert_eq!(executor.position(Exchange::Gmo, InstrumentType::Margin, Asset::new(BTC)), -Decimal::ONE);
+    }
+
+    #[test]
+    fn closing_margin_position_realizes_pnl() {
+        let dir = TempDir::new("paper");
+        let mut executor = executor(&dir, Vec::new());
+        let asset = Asset::new(BTC);
+        let (gmo, margin, jpy) = (Exchange::Gmo, InstrumentType::Margin, Currency::JPY);
+        let order = |side, quantity, price| PaperOrder {
+            exchange: gmo,
+            instrument: margin,
+            currency: jpy,
+            side,
+            quantity: Decimal::from(quantity),
+            price: Decimal::from(price),
+        };
+        executor.simulate_leg(asset, order(Side::Sell, 2, 10_100), Decimal::ONE);
+        executor.simulate_leg(asset, order(Side::Buy, 1, 9_900), Decimal::ONE);
+        // 1 枚の決済で (10,100 - 9,900) の利益、残り 1 枚は建値 10,100 のまま
+        assert_eq!(executor.balance(gmo, jpy), Decimal::from(1_000_200));
+        assert_eq!(executor.position(gmo, margin, asset), -Decimal::ONE);
+        assert_eq!(executor.used_margin(gmo, jpy), Decimal::from(5_050));
+
+        // ドテンした分は今回の価格で建てる
+        executor.simulate_leg(asset, order(Side::Buy, 2, 10_000), Decimal::ONE);
+        assert_eq!(executor.balance(gmo, jpy), Decimal::from(1_000_300));
+        assert_eq!(executor.position(gmo, margin, asset), Decimal::ONE);
+        assert_eq!(executor.used_margin(gmo, jpy), Decimal::from(5_000));
+    }
+}
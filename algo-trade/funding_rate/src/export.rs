@@ -0,0 +1,838 @@
+// 収集データの列指向 (Parquet) 書き出し
+//
+// 気配・FR・検出した裁定機会を、種類・日付 (UTC)・取引所ごとの Parquet ファイルに書く。
+//
+//   <dir>/quotes/date=2026-01-02/Gmo.parquet
+//   <dir>/funding/date=2026-01-02/Hyperliquid.parquet
+//   <dir>/opportunities/date=2026-01-02/opportunities.parquet
+//
+// 裁定機会は2つの取引所にまたがるので日付ごとに1ファイルにする (取引所は long_venue / short_venue の列)。
+// 列の構成は quotes_schema / funding_schema / opportunities_schema で固定し、価格・数量・率は
+// 小数点以下 12 桁の Decimal128 (Decimal を丸めて、浮動小数の誤差なしに)、時刻は UTC の Timestamp(ms) で書く。
+// Decimal128 に収まらない値は null にしてログに出す。
+//
+// Parquet はファイルを閉じる (フッターを書く) まで読めない。日付が変わったら前日までのファイルを閉じ、
+// 残りは finish で閉じる。同じ日付・取引所のファイルが既にある場合 (再起動・遅れて届いた行) は
+// 連番を付けた別のファイルに書く。書き込みは専用スレッドで行う。
+use crate::asset::AssetRegistry;
+use crate::store::{Exchange, MarketStore, StoreUpdate, UpdateKind};
+use crate::strategy::registry::VENUE_INSTRUMENTS;
+use crate::strategy::{symbol_for, ArbitrageOpportunity, Asset, Currency, InstrumentType};
+use arrow_array::{ArrayRef, Decimal128Array, RecordBatch, StringArray, TimestampMillisecondArray, UInt32Array};
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef, TimeUnit};
+use log::{debug, error, info, warn};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::{Arc, OnceLock};
+use std::thread::{self, JoinHandle};
+use tokio::sync::broadcast::error::RecvError;
+
+/// 書き込みスレッドへのキューの長さ
+const QUEUE_CAPACITY: usize = 65_536;
+/// ファイルごとに溜めてから RecordBatch にまとめる行数
+const BATCH_ROWS: usize = 8_192;
+const MS_PER_DAY: u64 = 86_400_000;
+/// 価格・数量・率の列の Decimal128 の桁数と小数点以下の桁数
+const DECIMAL_PRECISION: u8 = 38;
+const DECIMAL_SCALE: u32 = 12;
+
+/// Parquet への書き出し設定 (設定ファイルの [export])
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExportConfig {
+    /// ライブ実行中に書き出す (--export による変換はこの設定に関係なく動く)
+    pub enabled: bool,
+    /// 書き出し先のディレクトリ
+    pub dir: String,
+    /// 1つの row group の最大行数
+    pub row_group_size: usize,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: "export".to_string(),
+            row_group_size: 100_000,
+        }
+    }
+}
+
+fn timestamp_type() -> DataType {
+    DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into()))
+}
+
+fn decimal_type() -> DataType {
+    DataType::Decimal128(DECIMAL_PRECISION, DECIMAL_SCALE as i8)
+}
+
+/// 気配の列。ts は受信時刻、exchange_ts は取引所側の時刻 (フィードが提供する場合のみ)。
+/// asset・instrument・currency は戦略の対象外のシンボル (為替など) では null。
+/// bid_size・ask_size は板を受信している場合、funding_rate は Perp の場合のみ。
+/// 価格・数量・率の列は Decimal128 に収まらない値が null になる
+pub fn quotes_schema() -> SchemaRef {
+    static SCHEMA: OnceLock<SchemaRef> = OnceLock::new();
+    SCHEMA
+        .get_or_init(|| {
+            Arc::new(Schema::new(vec![
+                Field::new("ts", timestamp_type(), false),
+                Field::new("exchange_ts", timestamp_type(), true),
+                Field::new("venue", DataType::Utf8, false),
+                Field::new("symbol", DataType::Utf8, false),
+                Field::new("asset", DataType::Utf8, true),
+                Field::new("instrument", DataType::Utf8, true),
+                Field::new("currency", DataType::Utf8, true),
+                Field::new("bid", decimal_type(), true),
+                Field::new("ask", decimal_type(), true),
+                Field::new("last", decimal_type(), true),
+                Field::new("bid_size", decimal_type(), true),
+                Field::new("ask_size", decimal_type(), true),
+                Field::new("funding_rate", decimal_type(), true),
+            ]))
+        })
+        .clone()
+}
+
+/// FRの列。FRが変わったときだけ1行書く (率はストアの値そのまま)
+pub fn funding_schema() -> SchemaRef {
+    static SCHEMA: OnceLock<SchemaRef> = OnceLock::new();
+    SCHEMA
+        .get_or_init(|| {
+            Arc::new(Schema::new(vec![
+                Field::new("ts", timestamp_type(), false),
+                Field::new("venue", DataType::Utf8, false),
+                Field::new("symbol", DataType::Utf8, false),
+                Field::new("asset", DataType::Utf8, true),
+                Field::new("instrument", DataType::Utf8, true),
+                Field::new("currency", DataType::Utf8, true),
+                Field::new("funding_rate", decimal_type(), true),
+            ]))
+        })
+        .clone()
+}
+
+/// 裁定機会の列。持続の条件を満たして執行に回した機会を、代替経路も含めて1経路1行で書く
+/// (rank は 0 が最良の経路)。価格は板を消費した平均 (建値通貨)、損益は JPY
+pub fn opportunities_schema() -> SchemaRef {
+    static SCHEMA: OnceLock<SchemaRef> = OnceLock::new();
+    SCHEMA
+        .get_or_init(|| {
+            Arc::new(Schema::new(vec![
+                Field::new("ts", timestamp_type(), false),
+                Field::new("asset", DataType::Utf8, false),
+                Field::new("rank", DataType::UInt32, false),
+                Field::new("long_venue", DataType::Utf8, false),
+                Field::new("long_instrument", DataType::Utf8, false),
+                Field::new("long_currency", DataType::Utf8, false),
+                Field::new("long_price", decimal_type(), true),
+                Field::new("long_limit_price", decimal_type(), true),
+                Field::new("short_venue", DataType::Utf8, false),
+                Field::new("short_instrument", DataType::Utf8, false),
+                Field::new("short_currency", DataType::Utf8, false),
+                Field::new("short_price", decimal_type(), true),
+                Field::new("short_limit_price", decimal_type(), true),
+                Field::new("quantity", decimal_type(), true),
+                Field::new("notional_jpy", decimal_type(), true),
+                Field::new("usd_jpy", decimal_type(), true),
+                Field::new("instant_spread_pct", decimal_type(), true),
+                Field::new("carry_annualized_pct", decimal_type(), true),
+                Field::new("fr_impact_jpy", decimal_type(), true),
+                Field::new("estimated_profit_jpy", decimal_type(), true),
+                Field::new("estimated_profit_pct", decimal_type(), true),
+            ]))
+        })
+        .clone()
+}
+
+/// MarketStore のキーがどの商品か
+#[derive(Debug, Clone, Copy)]
+struct Market {
+    asset: Asset,
+    instrument: InstrumentType,
+    currency: Currency,
+}
+
+struct QuoteRow {
+    ts_ms: u64,
+    exchange_ts_ms: Option<u64>,
+    venue: Exchange,
+    symbol: String,
+    market: Option<Market>,
+    bid: Decimal,
+    ask: Decimal,
+    last: Decimal,
+    bid_size: Option<Decimal>,
+    ask_size: Option<Decimal>,
+    funding_rate: Option<Decimal>,
+}
+
+struct FundingRow {
+    ts_ms: u64,
+    venue: Exchange,
+    symbol: String,
+    market: Option<Market>,
+    rate: Decimal,
+}
+
+struct OpportunityRow {
+    ts_ms: u64,
+    asset: Asset,
+    rank: u32,
+    long_venue: Exchange,
+    long_instrument: InstrumentType,
+    long_currency: Currency,
+    long_price: Decimal,
+    long_limit_price: Decimal,
+    short_venue: Exchange,
+    short_instrument: InstrumentType,
+    short_currency: Currency,
+    short_price: Decimal,
+    short_limit_price: Decimal,
+    quantity: Decimal,
+    notional_jpy: Decimal,
+    usd_jpy: Decimal,
+    instant_spread_pct: Decimal,
+    carry_annualized_pct: Decimal,
+    fr_impact_jpy: Decimal,
+    estimated_profit_jpy: Decimal,
+    estimated_profit_pct: Decimal,
+}
+
+enum ExportMessage {
+    Quote(QuoteRow),
+    Funding(FundingRow),
+    Opportunity(OpportunityRow),
+    Finish,
+}
+
+/// 書き出した行数とファイル
+#[derive(Debug, Clone, Default)]
+pub struct ExportSummary {
+    pub quotes: u64,
+    pub funding: u64,
+    pub opportunities: u64,
+    /// キューが溢れて、または通知を取りこぼして書けなかった行数
+    pub dropped: u64,
+    pub files: Vec<PathBuf>,
+}
+
+impl fmt::Display for ExportSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} quote(s), {} funding rate(s), {} opportunit(ies) in {} file(s), {} dropped",
+            self.quotes,
+            self.funding,
+            self.opportunities,
+            self.files.len(),
+            self.dropped
+        )
+    }
+}
+
+/// Parquet への書き出し。sink で作った口から行を受け取り、finish でファイルを閉じる
+pub struct Exporter {
+    tx: SyncSender<ExportMessage>,
+    thread: JoinHandle<ExportSummary>,
+    markets: Arc<HashMap<(Exchange, String), Market>>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl Exporter {
+    /// 書き出し先を作って書き込みスレッドを起動する
+    pub fn start(config: &ExportConfig, registry: &AssetRegistry) -> io::Result<Self> {
+        fs::create_dir_all(&config.dir)?;
+        let mut markets = HashMap::new();
+        for asset in registry.assets() {
+            for &(exchange, instrument, currency) in VENUE_INSTRUMENTS {
+                if registry.is_listed(asset, exchange, instrument) {
+                    markets.insert((exchange, symbol_for(exchange, &asset, instrument)), Market { asset, instrument, currency });
+                }
+            }
+        }
+
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_max_row_group_size(config.row_group_size)
+            .build();
+        let writer = ExportThread {
+            quotes: TableWriter::new(Path::new(&config.dir), properties.clone()),
+            funding: TableWriter::new(Path::new(&config.dir), properties.clone()),
+            opportunities: TableWriter::new(Path::new(&config.dir), properties),
+            latest_day: 0,
+            summary: ExportSummary::default(),
+        };
+        let (tx, rx) = mpsc::sync_channel(QUEUE_CAPACITY);
+        let thread = thread::Builder::new().name("export".to_string()).spawn(move || writer.run(rx))?;
+        Ok(Self { tx, thread, markets: Arc::new(markets), dropped: Arc::new(AtomicU64::new(0)) })
+    }
+
+    /// ライブ用の口。キューが溢れたら行を捨てて数える (フィードや戦略を止めない)
+    pub fn sink(&self) -> ExportSink {
+        self.new_sink(false)
+    }
+
+    /// 変換用の口。キューが空くまで待つので行を捨てない
+    pub fn lossless_sink(&self) -> ExportSink {
+        self.new_sink(true)
+    }
+
+    fn new_sink(&self, blocking: bool) -> ExportSink {
+        ExportSink {
+            tx: self.tx.clone(),
+            markets: self.markets.clone(),
+            blocking,
+            dropped: self.dropped.clone(),
+            last_quote: HashMap::new(),
+            last_funding: HashMap::new(),
+        }
+    }
+
+    /// MarketStore の更新通知を購読して気配・FRを書き出すタスクを起動する
+    pub fn spawn_live(&self, store: &MarketStore) {
+        let mut sink = self.sink();
+        let mut updates = store.subscribe();
+        let store = store.clone();
+        tokio::spawn(async move {
+            loop {
+                match updates.recv().await {
+                    Ok(update) => sink.on_update(&update, &store),
+                    Err(RecvError::Lagged(skipped)) => {
+                        sink.dropped.fetch_add(skipped, Ordering::Relaxed);
+                    }
+                    Err(RecvError::Closed) => return,
+                }
+            }
+        });
+    }
+
+    /// 残りの行を書いてすべてのファイルを閉じる。これより後に口に渡した行は捨てられる
+    pub fn finish(self) -> ExportSummary {
+        let _ = self.tx.send(ExportMessage::Finish);
+        let mut summary = self.thread.join().unwrap_or_else(|_| {
+            error!("[Export] Writer thread panicked");
+            ExportSummary::default()
+        });
+        summary.dropped = self.dropped.load(Ordering::Relaxed);
+        summary
+    }
+}
+
+/// 書き出しへの口。更新通知と裁定機会を行にして書き込みスレッドに送る
+pub struct ExportSink {
+    tx: SyncSender<ExportMessage>,
+    markets: Arc<HashMap<(Exchange, String), Market>>,
+    blocking: bool,
+    dropped: Arc<AtomicU64>,
+    last_quote: HashMap<(Exchange, String), (u64, Decimal, Decimal)>,
+    last_funding: HashMap<(Exchange, String), Decimal>,
+}
+
+impl ExportSink {
+    /// 更新通知を受けてストアの最新値を行にする。
+    /// 板の更新は最良気配が変わったときだけ、FRは値が変わったときだけ書く
+    pub fn on_update(&mut self, update: &StoreUpdate, store: &MarketStore) {
+        let Some(data) = store.get_symbol_data(update.exchange, &update.symbol) else {
+            return;
+        };
+        let key = (update.exchange, update.symbol.clone());
+        let market = self.markets.get(&key).copied();
+        let message = match update.kind {
+            UpdateKind::Quote | UpdateKind::Book => {
+                let previous = self.last_quote.get(&key);
+                let unchanged = match update.kind {
+                    UpdateKind::Book => previous.is_some_and(|&(_, bid, ask)| bid == data.bid && ask == data.ask),
+                    _ => previous.is_some_and(|&(ts_ms, _, _)| ts_ms == data.timestamp_ms),
+                };
+                if unchanged {
+                    return;
+                }
+                self.last_quote.insert(key, (data.timestamp_ms, data.bid, data.ask));
+                let (best_bid, best_ask) = store.best_levels(update.exchange, &update.symbol);
+                let perp = market.is_some_and(|m| m.instrument == InstrumentType::Perp);
+                ExportMessage::Quote(QuoteRow {
+                    ts_ms: data.timestamp_ms,
+                    exchange_ts_ms: data.exchange_timestamp_ms,
+                    venue: update.exchange,
+                    symbol: update.symbol.clone(),
+                    market,
+                    bid: data.bid,
+                    ask: data.ask,
+                    last: data.last_price,
+                    // 板の最良気配と気配 (ticker) がずれている場合は数量を付けない
+                    bid_size: best_bid.filter(|l| l.price == data.bid).map(|l| l.size),
+                    ask_size: best_ask.filter(|l| l.price == data.ask).map(|l| l.size),
+                    funding_rate: (perp && data.funding_timestamp_ms > 0).then_some(data.funding_rate),
+                })
+            }
+            UpdateKind::Funding => {
+                if self.last_funding.get(&key) == Some(&data.funding_rate) {
+                    return;
+                }
+                self.last_funding.insert(key, data.funding_rate);
+                ExportMessage::Funding(FundingRow {
+                    ts_ms: data.funding_timestamp_ms,
+                    venue: update.exchange,
+                    symbol: update.symbol.clone(),
+                    market,
+                    rate: data.funding_rate,
+                })
+            }
+        };
+        self.send(message);
+    }
+
+    /// 執行に回した裁定機会を、ストアの時計の現在時刻で書く。rank は代替経路の順位 (0 が最良)
+    pub fn on_opportunity(&mut self, opp: &ArbitrageOpportunity, rank: usize, store: &MarketStore) {
+        self.send(ExportMessage::Opportunity(OpportunityRow {
+            ts_ms: store.now_ms(),
+            asset: opp.asset,
+            rank: rank as u32,
+            long_venue: opp.long_exchange,
+            long_instrument: opp.long_instrument,
+            long_currency: opp.long_currency,
+            long_price: opp.long_price_raw,
+            long_limit_price: opp.long_limit_price,
+            short_venue: opp.short_exchange,
+            short_instrument: opp.short_instrument,
+            short_currency: opp.short_currency,
+            short_price: opp.short_price_raw,
+            short_limit_price: opp.short_limit_price,
+            quantity: opp.quantity,
+            notional_jpy: opp.notional_jpy,
+            usd_jpy: opp.usd_jpy_rate,
+            instant_spread_pct: opp.instant_spread_pct,
+            carry_annualized_pct: opp.carry_annualized_pct,
+            fr_impact_jpy: opp.fr_impact_jpy,
+            estimated_profit_jpy: opp.estimated_profit_jpy,
+            estimated_profit_pct: opp.estimated_profit_pct,
+        }));
+    }
+
+    fn send(&self, message: ExportMessage) {
+        let sent = if self.blocking { self.tx.send(message).is_ok() } else { self.tx.try_send(message).is_ok() };
+        if !sent {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// 1種類の行の書き出し方
+trait Table: Sized {
+    /// 書き出し先のサブディレクトリ
+    const DIR: &'static str;
+    fn schema() -> SchemaRef;
+    fn ts_ms(&self) -> u64;
+    /// 日付のディレクトリの中のファイル名 (拡張子なし)
+    fn file_name(&self) -> String;
+    fn to_batch(rows: &[Self]) -> Result<RecordBatch, ArrowError>;
+}
+
+fn timestamps(values: impl Iterator<Item = Option<u64>>) -> ArrayRef {
+    Arc::new(TimestampMillisecondArray::from_iter(values.map(|ts| ts.map(|ms| ms as i64))).with_timezone("UTC"))
+}
+
+/// DECIMAL_SCALE 桁に丸めた Decimal128 の仮数。桁が溢れて表せない値は None
+fn to_decimal128(value: Decimal) -> Option<i128> {
+    let mut rounded = value.round_dp(DECIMAL_SCALE);
+    rounded.rescale(DECIMAL_SCALE);
+    (rounded.scale() == DECIMAL_SCALE).then(|| rounded.mantissa())
+}
+
+fn decimals(values: impl Iterator<Item = Option<Decimal>>) -> Result<ArrayRef, ArrowError> {
+    let array = values
+        .map(|v| {
+            v.and_then(|d| {
+                let mantissa = to_decimal128(d);
+                if mantissa.is_none() {
+                    warn!("[Export] {} does not fit in {}; writing null", d, decimal_type());
+                }
+                mantissa
+            })
+        })
+        .collect::<Decimal128Array>()
+        .with_precision_and_scale(DECIMAL_PRECISION, DECIMAL_SCALE as i8)?;
+    Ok(Arc::new(array))
+}
+
+fn strings(values: impl Iterator<Item = Option<String>>) -> ArrayRef {
+    Arc::new(values.collect::<StringArray>())
+}
+
+impl Table for QuoteRow {
+    const DIR: &'static str = "quotes";
+
+    fn schema() -> SchemaRef {
+        quotes_schema()
+    }
+
+    fn ts_ms(&self) -> u64 {
+        self.ts_ms
+    }
+
+    fn file_name(&self) -> String {
+        self.venue.to_string()
+    }
+
+    fn to_batch(rows: &[Self]) -> Result<RecordBatch, ArrowError> {
+        RecordBatch::try_new(
+            Self::schema(),
+            vec![
+                timestamps(rows.iter().map(|r| Some(r.ts_ms))),
+                timestamps(rows.iter().map(|r| r.exchange_ts_ms)),
+                strings(rows.iter().map(|r| Some(r.venue.to_string()))),
+                strings(rows.iter().map(|r| Some(r.symbol.clone()))),
+                strings(rows.iter().map(|r| r.market.map(|m| m.asset.to_string()))),
+                strings(rows.iter().map(|r| r.market.map(|m| format!("{:?}", m.instrument)))),
+                strings(rows.iter().map(|r| r.market.map(|m| format!("{:?}", m.currency)))),
+                decimals(rows.iter().map(|r| Some(r.bid)))?,
+                decimals(rows.iter().map(|r| Some(r.ask)))?,
+                decimals(rows.iter().map(|r| Some(r.last)))?,
+                decimals(rows.iter().map(|r| r.bid_size))?,
+                decimals(rows.iter().map(|r| r.ask_size))?,
+                decimals(rows.iter().map(|r| r.funding_rate))?,
+            ],
+        )
+    }
+}
+
+impl Table for FundingRow {
+    const DIR: &'static str = "funding";
+
+    fn schema() -> SchemaRef {
+        funding_schema()
+    }
+
+    fn ts_ms(&self) -> u64 {
+        self.ts_ms
+    }
+
+    fn file_name(&self) -> String {
+        self.venue.to_string()
+    }
+
+    fn to_batch(rows: &[Self]) -> Result<RecordBatch, ArrowError> {
+        RecordBatch::try_new(
+            Self::schema(),
+            vec![
+                timestamps(rows.iter().map(|r| Some(r.ts_ms))),
+                strings(rows.iter().map(|r| Some(r.venue.to_string()))),
+                strings(rows.iter().map(|r| Some(r.symbol.clone()))),
+                strings(rows.iter().map(|r| r.market.map(|m| m.asset.to_string()))),
+                strings(rows.iter().map(|r| r.market.map(|m| format!("{:?}", m.instrument)))),
+                strings(rows.iter().map(|r| r.market.map(|m| format!("{:?}", m.currency)))),
+                decimals(rows.iter().map(|r| Some(r.rate)))?,
+            ],
+        )
+    }
+}
+
+impl Table for OpportunityRow {
+    const DIR: &'static str = "opportunities";
+
+    fn schema() -> SchemaRef {
+        opportunities_schema()
+    }
+
+    fn ts_ms(&self) -> u64 {
+        self.ts_ms
+    }
+
+    fn file_name(&self) -> String {
+        "opportunities".to_string()
+    }
+
+    fn to_batch(rows: &[Self]) -> Result<RecordBatch, ArrowError> {
+        let decimal = |f: fn(&Self) -> Decimal| decimals(rows.iter().map(move |r| Some(f(r))));
+        RecordBatch::try_new(
+            Self::schema(),
+            vec![
+                timestamps(rows.iter().map(|r| Some(r.ts_ms))),
+                strings(rows.iter().map(|r| Some(r.asset.to_string()))),
+                Arc::new(rows.iter().map(|r| r.rank).collect::<UInt32Array>()),
+                strings(rows.iter().map(|r| Some(r.long_venue.to_string()))),
+                strings(rows.iter().map(|r| Some(format!("{:?}", r.long_instrument)))),
+                strings(rows.iter().map(|r| Some(format!("{:?}", r.long_currency)))),
+                decimal(|r| r.long_price)?,
+                decimal(|r| r.long_limit_price)?,
+                strings(rows.iter().map(|r| Some(r.short_venue.to_string()))),
+                strings(rows.iter().map(|r| Some(format!("{:?}", r.short_instrument)))),
+                strings(rows.iter().map(|r| Some(format!("{:?}", r.short_currency)))),
+                decimal(|r| r.short_price)?,
+                decimal(|r| r.short_limit_price)?,
+                decimal(|r| r.quantity)?,
+                decimal(|r| r.notional_jpy)?,
+                decimal(|r| r.usd_jpy)?,
+                decimal(|r| r.instant_spread_pct)?,
+                decimal(|r| r.carry_annualized_pct)?,
+                decimal(|r| r.fr_impact_jpy)?,
+                decimal(|r| r.estimated_profit_jpy)?,
+                decimal(|r| r.estimated_profit_pct)?,
+            ],
+        )
+    }
+}
+
+/// 書き込み中のファイル
+struct OpenFile<T> {
+    path: PathBuf,
+    writer: ArrowWriter<File>,
+    pending: Vec<T>,
+    rows: u64,
+}
+
+impl<T: Table> OpenFile<T> {
+    fn write_pending(&mut self) -> Result<(), ParquetError> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let batch = T::to_batch(&self.pending)?;
+        self.pending.clear();
+        self.writer.write(&batch)
+    }
+
+    fn close(mut self) -> Result<(PathBuf, u64), ParquetError> {
+        self.write_pending()?;
+        self.writer.close()?;
+        Ok((self.path, self.rows))
+    }
+}
+
+/// 1種類の行を日付・ファイル名ごとのファイルに振り分ける
+struct TableWriter<T> {
+    dir: PathBuf,
+    properties: WriterProperties,
+    /// (1970-01-01 からの日数, ファイル名) -> ファイル
+    files: BTreeMap<(u64, String), OpenFile<T>>,
+}
+
+impl<T: Table> TableWriter<T> {
+    fn new(dir: &Path, properties: WriterProperties) -> Self {
+        Self { dir: dir.join(T::DIR), properties, files: BTreeMap::new() }
+    }
+
+    fn push(&mut self, row: T) -> Result<(), ParquetError> {
+        let key = (row.ts_ms() / MS_PER_DAY, row.file_name());
+        let file = match self.files.entry(key) {
+            std::collections::btree_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::btree_map::Entry::Vacant(entry) => {
+                let (day, name) = entry.key();
+                let path = new_file_path(&self.dir.join(format!("date={}", utc_date(*day))), name)?;
+                let writer = ArrowWriter::try_new(File::create(&path)?, T::schema(), Some(self.properties.clone()))?;
+                debug!("[Export] Opened {}", path.display());
+                entry.insert(OpenFile { path, writer, pending: Vec::new(), rows: 0 })
+            }
+        };
+        file.pending.push(row);
+        file.rows += 1;
+        if file.pending.len() >= BATCH_ROWS {
+            file.write_pending()?;
+        }
+        Ok(())
+    }
+
+    /// before_day より前の日付のファイルを閉じる
+    fn close_before(&mut self, before_day: u64, summary: &mut ExportSummary) {
+        let keep = self.files.split_off(&(before_day, String::new()));
+        let closed = std::mem::replace(&mut self.files, keep);
+        for file in closed.into_values() {
+            close_file(file, summary);
+        }
+    }
+}
+
+fn close_file<T: Table>(file: OpenFile<T>, summary: &mut ExportSummary) {
+    let path = file.path.clone();
+    match file.close() {
+        Ok((path, rows)) => {
+            info!("[Export] Wrote {} row(s) to {}", rows, path.display());
+            summary.files.push(path);
+        }
+        Err(e) => error!("[Export] Failed to close {}: {}", path.display(), e),
+    }
+}
+
+/// dir/name.parquet。既にあれば dir/name-1.parquet, dir/name-2.parquet, ...
+fn new_file_path(dir: &Path, name: &str) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let mut path = dir.join(format!("{}.parquet", name));
+    let mut n = 0;
+    while path.exists() {
+        n += 1;
+        path = dir.join(format!("{}-{}.parquet", name, n));
+    }
+    Ok(path)
+}
+
+/// 1970-01-01 からの日数を YYYY-MM-DD にする (proleptic Gregorian)
+fn utc_date(days: u64) -> String {
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+struct ExportThread {
+    quotes: TableWriter<QuoteRow>,
+    funding: TableWriter<FundingRow>,
+    opportunities: TableWriter<OpportunityRow>,
+    /// これまでに受け取った行の最新の日付
+    latest_day: u64,
+    summary: ExportSummary,
+}
+
+impl ExportThread {
+    fn run(mut self, rx: mpsc::Receiver<ExportMessage>) -> ExportSummary {
+        // Finish を受け取るか、すべての送信側が無くなったら閉じる
+        while let Ok(message) = rx.recv() {
+            let result = match message {
+                ExportMessage::Quote(row) => {
+                    self.roll_over(row.ts_ms);
+                    self.summary.quotes += 1;
+                    self.quotes.push(row)
+                }
+                ExportMessage::Funding(row) => {
+                    self.roll_over(row.ts_ms);
+                    self.summary.funding += 1;
+                    self.funding.push(row)
+                }
+                ExportMessage::Opportunity(row) => {
+                    self.roll_over(row.ts_ms);
+                    self.summary.opportunities += 1;
+                    self.opportunities.push(row)
+                }
+                ExportMessage::Finish => break,
+            };
+            if let Err(e) = result {
+                error!("[Export] Failed to write row: {}", e);
+            }
+        }
+        self.close_before(u64::MAX);
+        self.summary
+    }
+
+    /// 日付が変わったら前日までのファイルを閉じて読めるようにする
+    fn roll_over(&mut self, ts_ms: u64) {
+        let day = ts_ms / MS_PER_DAY;
+        if day > self.latest_day {
+            self.latest_day = day;
+            self.close_before(day);
+        }
+    }
+
+    fn close_before(&mut self, day: u64) {
+        self.quotes.close_before(day, &mut self.summary);
+        self.funding.close_before(day, &mut self.summary);
+        self.opportunities.close_before(day, &mut self.summary);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asset::AssetConfig;
+    use crate::clock::VirtualClock;
+    use crate::collector::gmo::GmoCollector;
+    use crate::collector::Collector;
+    use crate::config::Config;
+    use crate::engine::StrategyEngine;
+    use crate::executor::{PaperConfig, PaperExecutor};
+    use crate::replay::{gmo_fixture, run_replay, FeedReplayer, ReplaySpeed};
+    use arrow_array::Array;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use std::str::FromStr;
+
+    /// テストの行数は BATCH_ROWS より少ないので、1ファイルは1つの RecordBatch で読める
+    fn read(path: &Path) -> RecordBatch {
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap()).unwrap().build().unwrap();
+        let mut batches: Vec<RecordBatch> = reader.map(Result::unwrap).collect();
+        assert_eq!(batches.len(), 1);
+        batches.remove(0)
+    }
+
+    fn column(batch: &RecordBatch, name: &str) -> Vec<Option<Decimal>> {
+        let array = batch.column_by_name(name).unwrap().as_any().downcast_ref::<Decimal128Array>().unwrap();
+        (0..array.len())
+            .map(|i| array.is_valid(i).then(|| Decimal::from_i128_with_scale(array.value(i), DECIMAL_SCALE)))
+            .collect()
+    }
+
+    fn strings_of(batch: &RecordBatch, name: &str) -> Vec<String> {
+        let array = batch.column_by_name(name).unwrap().as_any().downcast_ref::<StringArray>().unwrap();
+        (0..array.len()).map(|i| array.value(i).to_string()).collect()
+    }
+
+    #[test]
+    fn decimals_are_exact_and_overflow_is_null() {
+        let values = [Decimal::from_str("0.000012345678").unwrap(), Decimal::from_str("10000000.1").unwrap(), Decimal::MAX];
+        let array = decimals(values.iter().copied().map(Some)).unwrap();
+        let array = array.as_any().downcast_ref::<Decimal128Array>().unwrap();
+        assert_eq!(array.data_type(), &decimal_type());
+        assert_eq!(array.value(0), 12_345_678);
+        assert_eq!(array.value(1), 10_000_000_100_000_000_000);
+        assert!(array.is_null(2));
+    }
+
+    /// --export と同じく記録をリプレイしながら書き出し、読み戻した値が Decimal と一致するか
+    #[tokio::test]
+    async fn replay_export_writes_exact_decimals() {
+        let dir = std::env::temp_dir().join(format!("export_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut config = Config::default();
+        config.export.dir = dir.to_string_lossy().into_owned();
+        let registry = Arc::new(AssetRegistry::new(AssetConfig::defaults()));
+        let exporter = Exporter::start(&config.export, &registry).unwrap();
+
+        let clock = Arc::new(VirtualClock::default());
+        let store = MarketStore::new().with_clock(clock.clone());
+        let journal_path = dir.join("journal.jsonl").to_string_lossy().into_owned();
+        let executor = PaperExecutor::new(store.clone(), PaperConfig { journal_path, ..PaperConfig::default() }).unwrap();
+        let collectors: Vec<Box<dyn Collector>> = vec![Box::new(GmoCollector::new(registry.clone()))];
+        let mut sink = exporter.lossless_sink();
+        let mut replayer = FeedReplayer::new(store.clone(), clock, collectors).with_update_tap(move |update, store| sink.on_update(update, store));
+        let mut sink = exporter.lossless_sink();
+        let opportunity_store = store.clone();
+        let mut engine = StrategyEngine::new(&store, &config, &registry, executor)
+            .with_on_opportunity(move |opp, rank| sink.on_opportunity(opp, rank, &opportunity_store));
+        let start_us = 1_700_000_000_000_000;
+        run_replay(gmo_fixture(start_us).into_iter(), &mut replayer, &mut engine, &config.intervals, ReplaySpeed::AsFastAsPossible).await;
+        drop(engine);
+        drop(replayer);
+
+        let summary = exporter.finish();
+        assert_eq!((summary.opportunities, summary.dropped), (1, 0));
+        assert!(summary.quotes > 0);
+
+        let quotes = read(&dir.join("quotes/date=2023-11-14/Gmo.parquet"));
+        assert_eq!(quotes.schema(), quotes_schema());
+        let symbols = strings_of(&quotes, "symbol");
+        let bids = column(&quotes, "bid");
+        let asks = column(&quotes, "ask");
+        let usd_jpy = symbols.iter().position(|s| s == "USD_JPY").unwrap();
+        assert_eq!(bids[usd_jpy], Some(Decimal::from_str("150.00").unwrap()));
+        assert_eq!(asks[usd_jpy], Some(Decimal::from_str("150.01").unwrap()));
+
+        let opportunities = read(&dir.join("opportunities/date=2023-11-14/opportunities.parquet"));
+        assert_eq!(column(&opportunities, "quantity"), vec![Some(Decimal::from_str("0.01").unwrap())]);
+        assert_eq!(column(&opportunities, "long_price"), vec![Some(Decimal::from(10_000_000))]);
+        assert_eq!(column(&opportunities, "short_price"), vec![Some(Decimal::from(10_150_000))]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,391 @@
+use crate::store::{Exchange, MarketStore};
+use crate::strategy::{Currency, FxConversion, FxRate, FxRates};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// 為替レートの参照元 (設定ファイルの [[fx.sources]])
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FxSource {
+    pub exchange: Exchange,
+    /// MarketStore 上のシンボル名 (例: "USD_JPY")
+    pub symbol: String,
+    /// weighted で集計する場合の重み
+    #[serde(default = "default_weight")]
+    pub weight: Decimal,
+}
+
+fn default_weight() -> Decimal {
+    Decimal::ONE
+}
+
+/// 複数の参照元のレートをまとめる方法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FxMethod {
+    /// Bid・Ask それぞれの中央値
+    Median,
+    /// Bid・Ask それぞれの重み付き平均
+    Weighted,
+}
+
+/// 為替レートの集計設定 (設定ファイルの [fx])。
+/// sources は USD/JPY の参照元、stablecoins はステーブルコインごとの USD 建てレートの参照元
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FxConfig {
+    pub sources: Vec<FxSource>,
+    pub method: FxMethod,
+    /// 参照元の仲値の乖離 ((最大 - 最小) / 集計後の仲値, %) がこれを超えたら取引しない
+    pub max_divergence_pct: Decimal,
+    /// 集計に必要な参照元の数 (鮮度チェックを通ったもの)
+    pub min_sources: usize,
+    pub stablecoins: Vec<StablecoinConfig>,
+}
+
+/// ステーブルコインの USD 建てレートの参照元 (設定ファイルの [[fx.stablecoins]])。
+/// 集計方法・乖離の上限・必要な参照元の数は USD/JPY と共通
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StablecoinConfig {
+    pub currency: Currency,
+    /// USD 建てのレート (例: Kraken の "USDC_USD")
+    pub sources: Vec<FxSource>,
+    /// 1 USD からの乖離 (%) がこれを超えたら、その通貨建ての市場を取引しない
+    pub max_depeg_pct: Decimal,
+}
+
+impl StablecoinConfig {
+    fn kraken(currency: Currency, symbol: &str) -> Self {
+        Self {
+            currency,
+            sources: vec![FxSource { exchange: Exchange::Kraken, symbol: symbol.to_string(), weight: Decimal::ONE }],
+            max_depeg_pct: Decimal::from_str("0.5").unwrap(),
+        }
+    }
+}
+
+impl Default for FxConfig {
+    fn default() -> Self {
+        Self {
+            sources: vec![
+                FxSource { exchange: Exchange::Kraken, symbol: "USD_JPY".to_string(), weight: Decimal::ONE },
+                FxSource { exchange: Exchange::Gmo, symbol: "USD_JPY".to_string(), weight: Decimal::ONE },
+            ],
+            method: FxMethod::Median,
+            max_divergence_pct: Decimal::from_str("0.1").unwrap(),
+            min_sources: 1,
+            stablecoins: vec![
+                StablecoinConfig::kraken(Currency::USDC, "USDC_USD"),
+                StablecoinConfig::kraken(Currency::USDT, "USDT_USD"),
+            ],
+        }
+    }
+}
+
+/// 1つの参照元から取れたレート
+#[derive(Debug, Clone)]
+pub struct FxSample {
+    pub exchange: Exchange,
+    pub bid: Decimal,
+    pub ask: Decimal,
+    pub weight: Decimal,
+    pub age_ms: u64,
+}
+
+impl FxSample {
+    pub fn mid(&self) -> Decimal {
+        (self.bid + self.ask) / Decimal::from(2)
+    }
+}
+
+/// 集計した為替レート
+#[derive(Debug, Clone)]
+pub struct FxQuote {
+    pub rate: FxRate,
+    pub samples: Vec<FxSample>,
+    /// 参照元の仲値の乖離 (%)
+    pub divergence_pct: Decimal,
+    /// divergence_pct が max_divergence_pct を超えている
+    pub diverged: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FxError {
+    /// 鮮度チェックを通った参照元が min_sources に満たない
+    NotEnoughSources { available: usize, required: usize },
+    /// 参照元の仲値が max_divergence_pct を超えて乖離している
+    Diverged { divergence_pct: Decimal },
+    /// ステーブルコインが 1 USD から max_depeg_pct を超えて乖離している
+    Depegged { price: Decimal, max_depeg_pct: Decimal },
+}
+
+impl fmt::Display for FxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FxError::NotEnoughSources { available, required } => {
+                write!(f, "not enough fresh FX sources ({} of {} required)", available, required)
+            }
+            FxError::Diverged { divergence_pct } => write!(f, "sources diverge by {:.4}%", divergence_pct),
+            FxError::Depegged { price, max_depeg_pct } => {
+                write!(f, "depegged: {} USD (max {}% from par)", price, max_depeg_pct)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FxError {}
+
+/// 複数の取引所の USD/JPY とステーブルコインのレートを集計するオラクル。
+/// 参照元ごとに MarketStore の鮮度チェックを通した Bid/Ask だけを使う
+#[derive(Debug, Clone)]
+pub struct FxOracle {
+    config: FxConfig,
+}
+
+impl FxOracle {
+    /// enabled(exchange) が false の取引所は参照元から外す
+    pub fn new(config: &FxConfig, enabled: impl Fn(Exchange) -> bool) -> Self {
+        let mut config = config.clone();
+        config.sources.retain(|s| enabled(s.exchange));
+        for stablecoin in &mut config.stablecoins {
+            stablecoin.sources.retain(|s| enabled(s.exchange));
+        }
+        Self { config }
+    }
+
+    /// 有効な参照元すべて (USD/JPY とステーブルコイン)
+    pub fn sources(&self) -> impl Iterator<Item = &FxSource> {
+        self.config.sources.iter().chain(self.config.stablecoins.iter().flat_map(|s| s.sources.iter()))
+    }
+
+    /// USD/JPY の集計値
+    pub fn quote(&self, store: &MarketStore) -> Result<FxQuote, FxError> {
+        self.aggregate(store, &self.config.sources)
+    }
+
+    /// ステーブルコインの USD 建ての集計値。乖離・デペッグしている場合はエラー
+    pub fn stablecoin_quote(&self, store: &MarketStore, stablecoin: &StablecoinConfig) -> Result<FxQuote, FxError> {
+        let quote = self.aggregate(store, &stablecoin.sources)?;
+        if quote.diverged {
+            return Err(FxError::Diverged { divergence_pct: quote.divergence_pct });
+        }
+        let price = quote.rate.mid();
+        if (price - Decimal::ONE).abs() * Decimal::from(100) > stablecoin.max_depeg_pct {
+            return Err(FxError::Depegged { price, max_depeg_pct: stablecoin.max_depeg_pct });
+        }
+        Ok(quote)
+    }
+
+    /// 通貨ごとの JPY 換算レート (USD は USD_JPY、ステーブルコインは XXX_USD -> USD_JPY)。
+    /// レートが取れなかったステーブルコインは含めず、理由を一緒に返す
+    pub fn rates(&self, store: &MarketStore, usd_jpy: &FxQuote) -> (FxRates, Vec<(Currency, FxError)>) {
+        let mut rates = FxRates::default();
+        let usd = FxConversion::jpy().then(Currency::USD, "USD_JPY", usd_jpy.rate);
+        let mut unavailable = Vec::new();
+        for stablecoin in &self.config.stablecoins {
+            match self.stablecoin_quote(store, stablecoin) {
+                Ok(quote) => {
+                    let pair = format!("{:?}_USD", stablecoin.currency);
+                    rates.insert(usd.then(stablecoin.currency, &pair, quote.rate));
+                }
+                Err(e) => unavailable.push((stablecoin.currency, e)),
+            }
+        }
+        rates.insert(usd);
+        (rates, unavailable)
+    }
+
+    /// 参照元ごとの集計
+    fn aggregate(&self, store: &MarketStore, sources: &[FxSource]) -> Result<FxQuote, FxError> {
+        let now_ms = store.now_ms();
+        let samples: Vec<FxSample> = sources
+            .iter()
+            .filter_map(|source| {
+                let data = store.get_fresh_market_data(source.exchange, &source.symbol).ok()?;
+                // 片側しかない・逆転している気配は使わない
+                if data.bid <= Decimal::ZERO || data.ask < data.bid {
+                    return None;
+                }
+                Some(FxSample {
+                    exchange: source.exchange,
+                    bid: data.bid,
+                    ask: data.ask,
+                    weight: source.weight,
+                    age_ms: data.age_ms(now_ms),
+                })
+            })
+            .collect();
+
+        let required = self.config.min_sources.max(1);
+        if samples.len() < required {
+            return Err(FxError::NotEnoughSources { available: samples.len(), required });
+        }
+
+        let rate = match self.config.method {
+            FxMethod::Median => FxRate {
+                bid: median(samples.iter().map(|s| s.bid).collect()),
+                ask: median(samples.iter().map(|s| s.ask).collect()),
+            },
+            FxMethod::Weighted => FxRate {
+                bid: weighted_mean(samples.iter().map(|s| (s.bid, s.weight))),
+                ask: weighted_mean(samples.iter().map(|s| (s.ask, s.weight))),
+            },
+        };
+
+        let (min_mid, max_mid) = samples
+            .iter()
+            .map(FxSample::mid)
+            .fold((Decimal::MAX, Decimal::MIN), |(lo, hi), m| (lo.min(m), hi.max(m)));
+        let divergence_pct = (max_mid - min_mid) / rate.mid() * Decimal::from(100);
+
+        Ok(FxQuote {
+            rate,
+            diverged: divergence_pct > self.config.max_divergence_pct,
+            divergence_pct,
+            samples,
+        })
+    }
+}
+
+fn median(mut values: Vec<Decimal>) -> Decimal {
+    values.sort();
+    let n = values.len();
+    if n % 2 == 1 {
+        values[n / 2]
+    } else {
+        (values[n / 2 - 1] + values[n / 2]) / Decimal::from(2)
+    }
+}
+
+/// 重みの合計が 0 の場合は単純平均
+fn weighted_mean(values: impl Iterator<Item = (Decimal, Decimal)>) -> Decimal {
+    let values: Vec<(Decimal, Decimal)> = values.collect();
+    let total_weight: Decimal = values.iter().map(|(_, w)| *w).sum();
+    if total_weight.is_zero() {
+        let sum: Decimal = values.iter().map(|(v, _)| *v).sum();
+        return sum / Decimal::from(values.len());
+    }
+    values.iter().map(|(v, w)| v * w).sum::<Decimal>() / total_weight
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::VirtualClock;
+    use std::sync::Arc;
+
+    const START_US: u64 = 1_700_000_000_000_000;
+
+    fn d(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    fn source(exchange: Exchange, symbol: &str, weight: &str) -> FxSource {
+        FxSource { exchange, symbol: symbol.to_string(), weight: d(weight) }
+    }
+
+    /// Kraken・GMO・bitbank の USD/JPY と Kraken の USDC/USD を参照するオラクル
+    fn oracle(method: FxMethod, min_sources: usize) -> FxOracle {
+        let config = FxConfig {
+            sources: vec![
+                source(Exchange::Kraken, "USD_JPY", "3"),
+                source(Exchange::Gmo, "USD_JPY", "1"),
+                source(Exchange::Bitbank, "USD_JPY", "1"),
+            ],
+            method,
+            max_divergence_pct: d("0.1"),
+            min_sources,
+            stablecoins: vec![StablecoinConfig::kraken(Currency::USDC, "USDC_USD")],
+        };
+        FxOracle::new(&config, |_| true)
+    }
+
+    fn store() -> (MarketStore, Arc<VirtualClock>) {
+        let clock = Arc::new(VirtualClock::new(START_US));
+        (MarketStore::new().with_clock(clock.clone()), clock)
+    }
+
+    fn set(store: &MarketStore, exchange: Exchange, symbol: &str, bid: &str, ask: &str) {
+        store.update_market_data(exchange, symbol, d(bid), d(ask), d(bid), None);
+    }
+
+    #[test]
+    fn median_takes_bid_and_ask_separately() {
+        let (store, _) = store();
+        set(&store, Exchange::Kraken, "USD_JPY", "150.00", "150.02");
+        set(&store, Exchange::Gmo, "USD_JPY", "150.01", "150.05");
+        set(&store, Exchange::Bitbank, "USD_JPY", "149.99", "150.03");
+
+        let quote = oracle(FxMethod::Median, 1).quote(&store).unwrap();
+        // Bid の中央値と Ask の中央値は別の参照元のものになりうる
+        assert_eq!(quote.rate, FxRate { bid: d("150.00"), ask: d("150.03") });
+        assert_eq!(quote.samples.len(), 3);
+        assert!(!quote.diverged, "{}", quote.divergence_pct);
+    }
+
+    #[test]
+    fn weighted_mean_uses_source_weights() {
+        let (store, _) = store();
+        set(&store, Exchange::Kraken, "USD_JPY", "150.00", "150.02");
+        set(&store, Exchange::Gmo, "USD_JPY", "150.04", "150.06");
+
+        // Kraken の重み 3、GMO の重み 1
+        let quote = oracle(FxMethod::Weighted, 1).quote(&store).unwrap();
+        assert_eq!(quote.rate, FxRate { bid: d("150.01"), ask: d("150.03") });
+    }
+
+    #[test]
+    fn divergent_source_flags_the_quote() {
+        let (store, _) = store();
+        set(&store, Exchange::Kraken, "USD_JPY", "150.00", "150.02");
+        set(&store, Exchange::Gmo, "USD_JPY", "150.01", "150.03");
+        set(&store, Exchange::Bitbank, "USD_JPY", "151.00", "151.02");
+
+        let quote = oracle(FxMethod::Median, 1).quote(&store).unwrap();
+        // 中央値は外れた参照元に引っ張られないが、乖離は最大と最小の仲値の差で測る
+        assert_eq!(quote.rate, FxRate { bid: d("150.01"), ask: d("150.03") });
+        assert!(quote.diverged);
+        assert!(quote.divergence_pct > d("0.6"), "{}", quote.divergence_pct);
+    }
+
+    #[test]
+    fn stale_and_one_sided_sources_are_left_out() {
+        let (store, clock) = store();
+        set(&store, Exchange::Gmo, "USD_JPY", "151.00", "151.02");
+        // GMO の気配の鮮度の上限は 10 秒
+        clock.advance(START_US + 11_000_000);
+        set(&store, Exchange::Kraken, "USD_JPY", "150.00", "150.02");
+        set(&store, Exchange::Bitbank, "USD_JPY", "150.05", "0");
+
+        let quote = oracle(FxMethod::Median, 1).quote(&store).unwrap();
+        assert_eq!(quote.rate, FxRate { bid: d("150.00"), ask: d("150.02") });
+        assert_eq!(quote.samples.iter().map(|s| s.exchange).collect::<Vec<_>>(), [Exchange::Kraken]);
+
+        let err = oracle(FxMethod::Median, 2).quote(&store).unwrap_err();
+        assert_eq!(err, FxError::NotEnoughSources { available: 1, required: 2 });
+    }
+
+    #[test]
+    fn depegged_stablecoin_is_left_out_of_rates() {
+        let (store, _) = store();
+        set(&store, Exchange::Kraken, "USD_JPY", "150.00", "150.02");
+        let oracle = oracle(FxMethod::Median, 1);
+        let usd_jpy = oracle.quote(&store).unwrap();
+
+        set(&store, Exchange::Kraken, "USDC_USD", "0.9998", "1.0002");
+        let (rates, unavailable) = oracle.rates(&store, &usd_jpy);
+        assert!(unavailable.is_empty());
+        // USDC は USDC_USD -> USD_JPY の順に、不利な側同士を掛けて換算する
+        let usdc = rates.get(Currency::USDC).unwrap();
+        assert_eq!(usdc.rate, FxRate { bid: d("149.97"), ask: d("150.050004") });
+
+        set(&store, Exchange::Kraken, "USDC_USD", "0.98", "0.98");
+        let (rates, unavailable) = oracle.rates(&store, &usd_jpy);
+        assert!(rates.get(Currency::USDC).is_none());
+        assert!(rates.get(Currency::USD).is_some());
+        assert_eq!(unavailable, [(Currency::USDC, FxError::Depegged { price: d("0.98"), max_depeg_pct: d("0.5") })]);
+    }
+}
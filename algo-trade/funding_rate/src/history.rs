@@ -0,0 +1,567 @@
+// 気配・FRの履歴の保存
+//
+// MarketStore の更新通知を購読し、気配 (bid/ask/last) と FR の変化を SQLite に書き込む。
+// 通知の受け取りは別タスク、書き込みは専用スレッドで行い、一定件数または一定時間ごとに1トランザクションで
+// まとめて挿入する。キューが溢れた場合はフィードを止めずに行を捨てて数える。
+//
+// 読み出し (HistoryDb) は書き込み中でも使える (WAL)。バックテストの入力にもなる。
+use crate::asset::AssetRegistry;
+use crate::store::{Exchange, MarketStore, StoreUpdate, UpdateKind};
+use crate::strategy::registry::VENUE_INSTRUMENTS;
+use crate::strategy::{symbol_for, Asset, Currency, InstrumentType};
+use log::{debug, error, warn};
+use rusqlite::{params, Connection, OpenFlags, Row};
+use rust_decimal::Decimal;
+use serde::de::IntoDeserializer;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, SyncSender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+
+/// 書き込みスレッドへのキューの長さ
+const QUEUE_CAPACITY: usize = 65_536;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS quotes (
+        ts_ms INTEGER NOT NULL,
+        exchange TEXT NOT NULL,
+        symbol TEXT NOT NULL,
+        bid TEXT NOT NULL,
+        ask TEXT NOT NULL,
+        last TEXT NOT NULL,
+        exchange_ts_ms INTEGER
+    );
+    CREATE INDEX IF NOT EXISTS quotes_symbol_ts ON quotes (symbol, ts_ms);
+    CREATE INDEX IF NOT EXISTS quotes_ts ON quotes (ts_ms);
+    CREATE TABLE IF NOT EXISTS funding (
+        ts_ms INTEGER NOT NULL,
+        exchange TEXT NOT NULL,
+        symbol TEXT NOT NULL,
+        rate TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS funding_symbol_ts ON funding (symbol, ts_ms);
+    CREATE INDEX IF NOT EXISTS funding_ts ON funding (ts_ms);
+";
+
+/// 履歴の保存設定 (設定ファイルの [history])
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HistoryConfig {
+    pub enabled: bool,
+    /// SQLite のファイル
+    pub path: String,
+    /// 1トランザクションで挿入する最大の行数
+    pub batch_size: usize,
+    /// 溜まった行を書き込む間隔 (ms)。異常終了時はこの間の分が失われる
+    pub flush_ms: u64,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: "history.sqlite".to_string(),
+            batch_size: 1_000,
+            flush_ms: 1_000,
+        }
+    }
+}
+
+/// 気配1件。ts_ms はストアに反映した時刻 (UNIX ms)
+#[derive(Debug, Clone)]
+pub struct QuoteRecord {
+    pub ts_ms: u64,
+    pub exchange: Exchange,
+    /// MarketStore のキー (例: "BTC", "BTC_SPOT", "USD_JPY")
+    pub symbol: String,
+    pub bid: Decimal,
+    pub ask: Decimal,
+    pub last: Decimal,
+    /// 取引所側のタイムスタンプ (UNIX ms)。フィードが提供する場合のみ
+    pub exchange_ts_ms: Option<u64>,
+}
+
+/// FR 1件 (1時間あたり、または取引所の支払い間隔あたりの率。ストアの値そのまま)
+#[derive(Debug, Clone)]
+pub struct FundingRecord {
+    pub ts_ms: u64,
+    pub exchange: Exchange,
+    pub symbol: String,
+    pub rate: Decimal,
+}
+
+/// 通貨を指定した問い合わせの結果 (どの商品の気配か付き)
+#[derive(Debug, Clone)]
+pub struct AssetQuote {
+    pub asset: Asset,
+    pub instrument: InstrumentType,
+    pub currency: Currency,
+    pub quote: QuoteRecord,
+}
+
+enum HistoryRow {
+    Quote(QuoteRecord),
+    Funding(FundingRecord),
+}
+
+/// MarketStore の更新を履歴に書き込む。start した時点から記録する
+pub struct HistoryWriter {
+    dropped: Arc<AtomicU64>,
+}
+
+impl HistoryWriter {
+    /// データベースを開いて表を作り、通知の受け取りタスクと書き込みスレッドを起動する
+    pub fn start(config: &HistoryConfig, store: &MarketStore) -> io::Result<Self> {
+        let conn = open_for_writing(&config.path).map_err(io::Error::other)?;
+
+        let dropped = Arc::new(AtomicU64::new(0));
+        let (tx, rx) = mpsc::sync_channel(QUEUE_CAPACITY);
+        let writer = RowWriter {
+            conn,
+            batch_size: config.batch_size,
+            flush_interval: Duration::from_millis(config.flush_ms),
+            batch: Vec::new(),
+            dropped: dropped.clone(),
+            reported_dropped: 0,
+        };
+        thread::Builder::new().name("history".to_string()).spawn(move || writer.run(rx))?;
+
+        let updates = store.subscribe();
+        tokio::spawn(forward_updates(store.clone(), updates, tx, dropped.clone()));
+        Ok(Self { dropped })
+    }
+
+    /// キューが溢れて、または通知を取りこぼして書けなかった行数
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+fn open_for_writing(path: &str) -> rusqlite::Result<Connection> {
+    let conn = Connection::open(path)?;
+    // 書き込み中も HistoryDb から読めるようにする
+    conn.pragma_update(None, "journal_mode", "WAL")?;
+    conn.pragma_update(None, "synchronous", "NORMAL")?;
+    conn.execute_batch(SCHEMA)?;
+    Ok(conn)
+}
+
+/// 更新通知を受けてストアの最新値を行にする。
+/// 板の更新は最良気配が変わったときだけ、同じ気配 (受信時刻が同じもの) は1回だけ書く
+async fn forward_updates(
+    store: MarketStore,
+    mut updates: tokio::sync::broadcast::Receiver<StoreUpdate>,
+    tx: SyncSender<HistoryRow>,
+    dropped: Arc<AtomicU64>,
+) {
+    let mut last_quote: HashMap<(Exchange, String), (u64, Decimal, Decimal)> = HashMap::new();
+    let mut last_funding: HashMap<(Exchange, String), Decimal> = HashMap::new();
+    loop {
+        let update = match updates.recv().await {
+            Ok(update) => update,
+            Err(RecvError::Lagged(skipped)) => {
+                dropped.fetch_add(skipped, Ordering::Relaxed);
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+        let Some(data) = store.get_symbol_data(update.exchange, &update.symbol) else {
+            continue;
+        };
+        let key = (update.exchange, update.symbol);
+        let row = match update.kind {
+            UpdateKind::Quote | UpdateKind::Book => {
+                let previous = last_quote.get(&key);
+                let unchanged = match update.kind {
+                    UpdateKind::Book => previous.is_some_and(|&(_, bid, ask)| bid == data.bid && ask == data.ask),
+                    _ => previous.is_some_and(|&(ts_ms, _, _)| ts_ms == data.timestamp_ms),
+                };
+                if unchanged {
+                    continue;
+                }
+                last_quote.insert(key.clone(), (data.timestamp_ms, data.bid, data.ask));
+                HistoryRow::Quote(QuoteRecord {
+                    ts_ms: data.timestamp_ms,
+                    exchange: key.0,
+                    symbol: key.1,
+                    bid: data.bid,
+                    ask: data.ask,
+                    last: data.last_price,
+                    exchange_ts_ms: data.exchange_timestamp_ms,
+                })
+            }
+            // FRは気配と一緒に毎回届く取引所があるので、値が変わったときだけ書く
+            UpdateKind::Funding => {
+                if last_funding.get(&key) == Some(&data.funding_rate) {
+                    continue;
+                }
+                last_funding.insert(key.clone(), data.funding_rate);
+                HistoryRow::Funding(FundingRecord {
+                    ts_ms: data.funding_timestamp_ms,
+                    exchange: key.0,
+                    symbol: key.1,
+                    rate: data.funding_rate,
+                })
+            }
+        };
+        if tx.try_send(row).is_err() {
+            dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+struct RowWriter {
+    conn: Connection,
+    batch_size: usize,
+    flush_interval: Duration,
+    batch: Vec<HistoryRow>,
+    dropped: Arc<AtomicU64>,
+    reported_dropped: u64,
+}
+
+impl RowWriter {
+    fn run(mut self, rx: mpsc::Receiver<HistoryRow>) {
+        let mut last_flush = Instant::now();
+        loop {
+            match rx.recv_timeout(self.flush_interval) {
+                Ok(row) => self.batch.push(row),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.flush();
+                    return;
+                }
+            }
+            if self.batch.len() >= self.batch_size || last_flush.elapsed() >= self.flush_interval {
+                self.flush();
+                last_flush = Instant::now();
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        let dropped = self.dropped.load(Ordering::Relaxed);
+        if dropped > self.reported_dropped {
+            warn!("[History] Dropped {} row(s) (queue full or lagging)", dropped - self.reported_dropped);
+            self.reported_dropped = dropped;
+        }
+        if self.batch.is_empty() {
+            return;
+        }
+        let rows = std::mem::take(&mut self.batch);
+        match self.insert(&rows) {
+            Ok(()) => debug!("[History] Wrote {} row(s)", rows.len()),
+            // 書けなかった分は捨てる (再試行でキューを詰まらせない)
+            Err(e) => error!("[History] Failed to write {} row(s): {}", rows.len(), e),
+        }
+    }
+
+    fn insert(&mut self, rows: &[HistoryRow]) -> rusqlite::Result<()> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut quote = tx.prepare_cached(
+                "INSERT INTO quotes (ts_ms, exchange, symbol, bid, ask, last, exchange_ts_ms) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )?;
+            let mut funding = tx.prepare_cached("INSERT INTO funding (ts_ms, exchange, symbol, rate) VALUES (?1, ?2, ?3, ?4)")?;
+            for row in rows {
+                match row {
+                    HistoryRow::Quote(q) => quote.execute(params![
+                        q.ts_ms as i64,
+                        q.exchange.to_string(),
+                        q.symbol,
+                        q.bid.to_string(),
+                        q.ask.to_string(),
+                        q.last.to_string(),
+                        q.exchange_ts_ms.map(|ts| ts as i64),
+                    ])?,
+                    HistoryRow::Funding(f) => {
+                        funding.execute(params![f.ts_ms as i64, f.exchange.to_string(), f.symbol, f.rate.to_string()])?
+                    }
+                };
+            }
+        }
+        tx.commit()
+    }
+}
+
+/// 保存した履歴の読み出し
+pub struct HistoryDb {
+    conn: Connection,
+}
+
+impl HistoryDb {
+    /// 読み取り専用で開く
+    pub fn open(path: impl AsRef<Path>) -> rusqlite::Result<Self> {
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)?;
+        Ok(Self { conn })
+    }
+
+    /// 1銘柄の [from_ms, to_ms] の気配 (時刻順)
+    pub fn quotes(&self, exchange: Exchange, symbol: &str, from_ms: u64, to_ms: u64) -> rusqlite::Result<Vec<QuoteRecord>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT ts_ms, exchange, symbol, bid, ask, last, exchange_ts_ms FROM quotes
+             WHERE symbol = ?1 AND exchange = ?2 AND ts_ms BETWEEN ?3 AND ?4 ORDER BY ts_ms, rowid",
+        )?;
+        let rows = stmt.query_map(params![symbol, exchange.to_string(), clamp(from_ms), clamp(to_ms)], quote_from_row)?;
+        rows.collect()
+    }
+
+    /// 全銘柄の [from_ms, to_ms] の気配 (時刻順)
+    pub fn quotes_between(&self, from_ms: u64, to_ms: u64) -> rusqlite::Result<Vec<QuoteRecord>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT ts_ms, exchange, symbol, bid, ask, last, exchange_ts_ms FROM quotes
+             WHERE ts_ms BETWEEN ?1 AND ?2 ORDER BY ts_ms, rowid",
+        )?;
+        let rows = stmt.query_map(params![clamp(from_ms), clamp(to_ms)], quote_from_row)?;
+        rows.collect()
+    }
+
+    /// 1通貨の、すべての取引所・商品の [from_ms, to_ms] の気配 (時刻順)
+    pub fn quotes_for_asset(&self, registry: &AssetRegistry, asset: Asset, from_ms: u64, to_ms: u64) -> rusqlite::Result<Vec<AssetQuote>> {
+        let mut quotes = Vec::new();
+        for &(exchange, instrument, currency) in VENUE_INSTRUMENTS {
+            if !registry.is_listed(asset, exchange, instrument) {
+                continue;
+            }
+            let symbol = symbol_for(exchange, &asset, instrument);
+            for quote in self.quotes(exchange, &symbol, from_ms, to_ms)? {
+                quotes.push(AssetQuote { asset, instrument, currency, quote });
+            }
+        }
+        quotes.sort_by_key(|q| q.quote.ts_ms);
+        Ok(quotes)
+    }
+
+    /// 1銘柄の [from_ms, to_ms] の FR (時刻順)
+    pub fn funding(&self, exchange: Exchange, symbol: &str, from_ms: u64, to_ms: u64) -> rusqlite::Result<Vec<FundingRecord>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT ts_ms, exchange, symbol, rate FROM funding
+             WHERE symbol = ?1 AND exchange = ?2 AND ts_ms BETWEEN ?3 AND ?4 ORDER BY ts_ms, rowid",
+        )?;
+        let rows = stmt.query_map(params![symbol, exchange.to_string(), clamp(from_ms), clamp(to_ms)], funding_from_row)?;
+        rows.collect()
+    }
+
+    /// 全銘柄の [from_ms, to_ms] の FR (時刻順)
+    pub fn funding_between(&self, from_ms: u64, to_ms: u64) -> rusqlite::Result<Vec<FundingRecord>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT ts_ms, exchange, symbol, rate FROM funding WHERE ts_ms BETWEEN ?1 AND ?2 ORDER BY ts_ms, rowid",
+        )?;
+        let rows = stmt.query_map(params![clamp(from_ms), clamp(to_ms)], funding_from_row)?;
+        rows.collect()
+    }
+}
+
+/// SQLite の INTEGER は i64 なので、範囲の指定 (u64::MAX など) を丸める
+fn clamp(ms: u64) -> i64 {
+    ms.min(i64::MAX as u64) as i64
+}
+
+fn exchange_column(row: &Row, index: usize) -> rusqlite::Result<Exchange> {
+    let text: String = row.get(index)?;
+    Exchange::deserialize(text.as_str().into_deserializer())
+        .map_err(|e: serde::de::value::Error| rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, Box::new(e)))
+}
+
+fn decimal_column(row: &Row, index: usize) -> rusqlite::Result<Decimal> {
+    let text: String = row.get(index)?;
+    Decimal::from_str(&text).map_err(|e| rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, Box::new(e)))
+}
+
+fn quote_from_row(row: &Row) -> rusqlite::Result<QuoteRecord> {
+    Ok(QuoteRecord {
+        ts_ms: row.get::<_, i64>(0)? as u64,
+        exchange: exchange_column(row, 1)?,
+        symbol: row.get(2)?,
+        bid: decimal_column(row, 3)?,
+        ask: decimal_column(row, 4)?,
+        last: decimal_column(row, 5)?,
+        exchange_ts_ms: row.get::<_, Option<i64>>(6)?.map(|ts| ts as u64),
+    })
+}
+
+fn funding_from_row(row: &Row) -> rusqlite::Result<FundingRecord> {
+    Ok(FundingRecord {
+        ts_ms: row.get::<_, i64>(0)? as u64,
+        exchange: exchange_column(row, 1)?,
+        symbol: row.get(2)?,
+        rate: decimal_column(row, 3)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asset::AssetConfig;
+    use crate::clock::VirtualClock;
+    use crate::store::PriceLevel;
+    use crate::testing::TempDir;
+
+    const START_MS: u64 = 1_700_000_000_000;
+
+    fn d(value: i64) -> Decimal {
+        Decimal::from(value)
+    }
+
+    fn level(price: i64) -> PriceLevel {
+        PriceLevel { price: d(price), size: Decimal::ONE }
+    }
+
+    fn store() -> (MarketStore, Arc<VirtualClock>) {
+        let clock = Arc::new(VirtualClock::new(START_MS * 1_000));
+        (MarketStore::new().with_clock(clock.clone()), clock)
+    }
+
+    fn describe(row: &HistoryRow) -> String {
+        match row {
+            HistoryRow::Quote(q) => format!("+{} quote {} {} {}/{}", q.ts_ms - START_MS, q.exchange, q.symbol, q.bid, q.ask),
+            HistoryRow::Funding(f) => format!("+{} funding {} {} {}", f.ts_ms - START_MS, f.exchange, f.symbol, f.rate),
+        }
+    }
+
+    /// forward_updates が通知を処理し終えるのを待って、書き出した行を取り出す
+    async fn drain(rows: &mpsc::Receiver<HistoryRow>) -> Vec<String> {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        rows.try_iter().map(|row| describe(&row)).collect()
+    }
+
+    #[tokio::test]
+    async fn forward_updates_skips_unchanged_quotes_books_and_funding() {
+        let (store, clock) = store();
+        let (tx, rx) = mpsc::sync_channel(100);
+        let dropped = Arc::new(AtomicU64::new(0));
+        tokio::spawn(forward_updates(store.clone(), store.subscribe(), tx, dropped.clone()));
+
+        store.update_market_data(Exchange::Bitbank, "BTC", d(100), d(101), d(100), None);
+        assert_eq!(drain(&rx).await, ["+0 quote Bitbank BTC 100/101"]);
+        // 同じ受信時刻の気配は1回だけ書く。時刻が進めば同じ値でも書く
+        store.update_market_data(Exchange::Bitbank, "BTC", d(100), d(101), d(100), None);
+        assert!(drain(&rx).await.is_empty());
+        clock.advance((START_MS + 5) * 1_000);
+        store.update_market_data(Exchange::Bitbank, "BTC", d(100), d(101), d(100), None);
+        assert_eq!(drain(&rx).await, ["+5 quote Bitbank BTC 100/101"]);
+
+        // 板は最良気配が変わったときだけ書く
+        store.update_order_book(Exchange::Gmo, "BTC", &[level(99), level(98)], &[level(102)], None, None);
+        assert_eq!(drain(&rx).await, ["+5 quote Gmo BTC 99/102"]);
+        clock.advance((START_MS + 10) * 1_000);
+        store.update_order_book(Exchange::Gmo, "BTC", &[level(99)], &[level(102), level(103)], None, None);
+        assert!(drain(&rx).await.is_empty());
+        store.update_order_book(Exchange::Gmo, "BTC", &[level(100)], &[level(102)], None, None);
+        assert_eq!(drain(&rx).await, ["+10 quote Gmo BTC 100/102"]);
+
+        // FR は値が変わったときだけ書く
+        let rate = Decimal::new(1, 4);
+        store.update_funding_rate(Exchange::Hyperliquid, "BTC", rate);
+        store.update_funding_rate(Exchange::Hyperliquid, "BTC", rate);
+        assert_eq!(drain(&rx).await, ["+10 funding Hyperliquid BTC 0.0001"]);
+        store.update_funding_rate(Exchange::Hyperliquid, "BTC", rate * d(2));
+        assert_eq!(drain(&rx).await, ["+10 funding Hyperliquid BTC 0.0002"]);
+        assert_eq!(dropped.load(Ordering::Relaxed), 0);
+    }
+
+    fn quote_row(ts_offset_ms: u64, exchange: Exchange, symbol: &str, bid: i64) -> HistoryRow {
+        HistoryRow::Quote(QuoteRecord {
+            ts_ms: START_MS + ts_offset_ms,
+            exchange,
+            symbol: symbol.to_string(),
+            bid: d(bid),
+            ask: d(bid + 1),
+            last: d(bid),
+            exchange_ts_ms: Some(START_MS + ts_offset_ms - 1),
+        })
+    }
+
+    #[test]
+    fn row_writer_inserts_full_batches_and_flushes_the_rest_on_shutdown() {
+        let dir = TempDir::new("history");
+        let path = dir.file("history.sqlite");
+        let writer = RowWriter {
+            conn: open_for_writing(&path).unwrap(),
+            batch_size: 2,
+            flush_interval: Duration::from_secs(60),
+            batch: Vec::new(),
+            dropped: Arc::new(AtomicU64::new(0)),
+            reported_dropped: 0,
+        };
+        let (tx, rx) = mpsc::sync_channel(10);
+        let handle = thread::spawn(move || writer.run(rx));
+        let db = HistoryDb::open(&path).unwrap();
+        let count = || db.quotes_between(0, u64::MAX).unwrap().len();
+
+        for i in 0..3 {
+            tx.send(quote_row(i, Exchange::Bitbank, "BTC", 100 + i as i64)).unwrap();
+        }
+        // batch_size 行たまった分だけすぐに書き、残りは flush_interval まで待つ
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while count() < 2 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(count(), 2);
+
+        drop(tx);
+        handle.join().unwrap();
+        let quotes = db.quotes_between(0, u64::MAX).unwrap();
+        assert_eq!(quotes.iter().map(|q| q.bid).collect::<Vec<_>>(), [d(100), d(101), d(102)]);
+        assert_eq!(quotes[2].exchange_ts_ms, Some(START_MS + 1));
+    }
+
+    #[tokio::test]
+    async fn store_updates_round_trip_through_the_database() {
+        let dir = TempDir::new("history");
+        let config = HistoryConfig { enabled: true, path: dir.file("history.sqlite"), batch_size: 1_000, flush_ms: 10 };
+        let (store, clock) = store();
+        let _writer = HistoryWriter::start(&config, &store).unwrap();
+
+        let btc = Asset::new("BTC");
+        let rate = Decimal::new(1, 4);
+        store.update_market_data(Exchange::Bitbank, "BTC", d(100), d(101), d(100), Some(START_MS - 3));
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        clock.advance((START_MS + 10) * 1_000);
+        store.update_market_data(Exchange::Gmo, &symbol_for(Exchange::Gmo, &btc, InstrumentType::Spot), d(102), d(103), d(102), None);
+        store.update_funding_rate(Exchange::Hyperliquid, "BTC", rate);
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        clock.advance((START_MS + 20) * 1_000);
+        // 通貨の一覧に無い銘柄は quotes_for_asset に出ない
+        store.update_market_data(Exchange::Kraken, "USD_JPY", d(150), d(151), d(150), None);
+
+        let db = HistoryDb::open(&config.path).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while db.quotes_between(0, u64::MAX).unwrap().len() < 3 && Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let registry = AssetRegistry::new(AssetConfig::defaults());
+        let quotes = db.quotes_for_asset(&registry, btc, 0, u64::MAX).unwrap();
+        let summary: Vec<_> = quotes.iter().map(|q| (q.quote.ts_ms - START_MS, q.quote.exchange, q.instrument, q.currency)).collect();
+        assert_eq!(summary, [(0, Exchange::Bitbank, InstrumentType::Spot, Currency::JPY), (10, Exchange::Gmo, InstrumentType::Spot, Currency::JPY)]);
+        assert_eq!(quotes[0].quote.exchange_ts_ms, Some(START_MS - 3));
+        assert_eq!((quotes[1].quote.bid, quotes[1].quote.ask, quotes[1].quote.last), (d(102), d(103), d(102)));
+
+        // 範囲は両端を含む
+        assert_eq!(db.quotes_for_asset(&registry, btc, START_MS + 1, START_MS + 10).unwrap().len(), 1);
+        assert_eq!(db.quotes(Exchange::Kraken, "USD_JPY", START_MS + 20, START_MS + 20).unwrap().len(), 1);
+        assert!(db.quotes(Exchange::Gmo, "USD_JPY", 0, u64::MAX).unwrap().is_empty());
+
+        let funding = db.funding_between(0, u64::MAX).unwrap();
+        assert_eq!(funding.len(), 1);
+        assert_eq!((funding[0].ts_ms, funding[0].exchange, funding[0].rate), (START_MS + 10, Exchange::Hyperliquid, rate));
+        assert!(db.funding_between(START_MS + 11, u64::MAX).unwrap().is_empty());
+        assert_eq!(db.funding(Exchange::Hyperliquid, "BTC", START_MS, START_MS + 10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn clamp_keeps_ranges_within_sqlite_integers() {
+        assert_eq!(clamp(0), 0);
+        assert_eq!(clamp(START_MS), START_MS as i64);
+        assert_eq!(clamp(u64::MAX), i64::MAX);
+        assert_eq!(clamp(i64::MAX as u64 + 1), i64::MAX);
+    }
+}
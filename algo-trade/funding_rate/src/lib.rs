@@ -0,0 +1,17 @@
+pub mod asset;
+pub mod backtest;
+pub mod clock;
+pub mod collector;
+pub mod config;
+pub mod engine;
+pub mod executor;
+pub mod export;
+pub mod fx;
+pub mod history;
+pub mod recorder;
+pub mod replay;
+pub mod store;
+pub mod strategy;
+
+#[cfg(test)]
+mod testing;
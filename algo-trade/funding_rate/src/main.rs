@@ -1,24 +1,28 @@
-mod collector;
-mod store;
-mod strategy;
-
-use crate::store::{Exchange, MarketStore};
-use crate::strategy::{find_best_arbitrage, Asset, Currency, InstrumentType, MarketData};
-use log::{info, warn};
+use funding_rate::asset::AssetRegistry;
+use funding_rate::backtest::{load_ticks, Backtester};
+use funding_rate::clock::VirtualClock;
+use funding_rate::collector::bitbank::BitbankCollector;
+use funding_rate::collector::gmo::GmoCollector;
+use funding_rate::collector::hyperliquid::HyperliquidCollector;
+use funding_rate::collector::kraken::KrakenCollector;
+use funding_rate::collector::{Collector, Supervisor};
+use funding_rate::config::{Config, DEFAULT_CONFIG_PATH};
+use funding_rate::engine::StrategyEngine;
+use funding_rate::executor::{Executor, ExecutorConfig, LocalExchange, PaperConfig, PaperExecutor, TwoLegExecutor};
+use funding_rate::export::Exporter;
+use funding_rate::fx::FxOracle;
+use funding_rate::history::HistoryWriter;
+use funding_rate::recorder::{Recorder, RecordingIndex};
+use funding_rate::replay::{recorded_frames, run_replay, FeedReplayer, ReplaySpeed};
+use funding_rate::store::{Exchange, MarketStore, StoreUpdate};
+use funding_rate::strategy::{ArbitrageOpportunity, AssetIndex, PendingEvaluations};
+use log::{debug, error, info, warn};
 use rust_decimal::Decimal;
-use rust_decimal::prelude::*;
-use std::time::Duration;
-use tokio::time::sleep;
-
-// 取引対象の定義
-const TARGET_ASSETS: &[Asset] = &[Asset::BTC, Asset::ETH, Asset::SOL, Asset::HYPE];
-
-fn symbol_for(exchange: Exchange, asset: &Asset, instrument: InstrumentType) -> String {
-    match (exchange, instrument) {
-        (Exchange::Hyperliquid, InstrumentType::Spot) => format!("{}_SPOT", asset.as_symbol()),
-        _ => asset.as_symbol().to_string(),
-    }
-}
+use std::future::Future;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{sleep, timeout_at, Instant};
 
 #[tokio::main]
 async fn main() {
@@ -28,131 +32,361 @@ async fn main() {
 
     info!("Initializing Arbitrage Bot System...");
 
+    // 設定ファイルの読み込み (--config <path>、既定は config.toml)
+    let args: Vec<String> = std::env::args().collect();
+    let config_path = arg_value(&args, "--config").unwrap_or(DEFAULT_CONFIG_PATH);
+    let config = match Config::load(config_path) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("[Config] {}", e);
+            std::process::exit(1);
+        }
+    };
+    let registry = Arc::new(config.registry());
+    info!("[Config] Assets: {:?}", registry.assets().collect::<Vec<_>>());
+
     let store = MarketStore::new();
+    for (exchange, venue) in config.exchanges.iter() {
+        store.set_max_age(exchange, venue.max_quote_age());
+        store.set_max_funding_age(exchange, venue.max_funding_age());
+    }
 
-    // 各Collectorの起動
-    let s_hl = store.clone();
-    tokio::spawn(async move {
-        let symbols = vec!["BTC", "ETH", "SOL", "HYPE"].into_iter().map(String::from).collect();
-        collector::hyperliquid::start_collection(symbols, s_hl).await;
-    });
+    // 各Collectorの起動 (有効な取引所のみ)
+    let collectors = build_collectors(&config, &registry);
 
-    let s_bb = store.clone();
-    tokio::spawn(async move {
-        collector::bitbank::start_collection(s_bb).await;
-    });
+    // --replay <dir>: 記録したフィードをコレクターに流し、仮想時計で戦略を評価してペーパートレードする
+    if let Some(dir) = arg_value(&args, "--replay") {
+        replay(&args, dir, &config, &registry, store, collectors, None).await;
+        return;
+    }
 
-    let s_gmo = store.clone();
-    tokio::spawn(async move {
-        let symbols = vec!["BTC", "ETH", "SOL", "HYPE"].into_iter().map(String::from).collect();
-        collector::gmo::start_collection(symbols, s_gmo).await;
-    });
-    
-    let s_kraken = store.clone();
-    tokio::spawn(async move {
-        collector::kraken::start_collection(s_kraken).await;
-    });
+    // --export <dir>: 記録したフィードをリプレイして、気配・FR・裁定機会を export.dir に Parquet で書き出す
+    if let Some(dir) = arg_value(&args, "--export") {
+        let exporter = start_exporter(&config, &registry);
+        replay(&args, dir, &config, &registry, store, collectors, Some(&exporter)).await;
+        info!("[Export] Done: {}", exporter.finish());
+        return;
+    }
 
-    info!("Waiting for market data warmup (5s)...");
-    sleep(Duration::from_secs(5)).await;
+    // --backtest <dir|file.csv|file.sqlite>: 記録・CSV・履歴の気配で裁定の判定と執行を模擬し、成績を出力する
+    if let Some(path) = arg_value(&args, "--backtest") {
+        backtest(&args, path, &config, &registry, collectors);
+        return;
+    }
 
-    loop {
-        // 為替レートの取得 (USD_JPY)
-        let usd_jpy = match store.get_fx_rate("USD_JPY") {
-            Some(rate) => rate,
-            None => {
-                warn!("FX rate (USD_JPY) not available yet. Skipping cycle.");
-                sleep(Duration::from_millis(1000)).await;
-                continue;
+    // 気配・FRの履歴 (SQLite)。コレクターの起動前に購読を始める
+    let _history = if config.history.enabled {
+        match HistoryWriter::start(&config.history, &store) {
+            Ok(writer) => {
+                info!("[History] Writing quotes and funding rates to {}", config.history.path);
+                Some(writer)
             }
-        };
+            Err(e) => {
+                error!("[History] Failed to open {}: {}", config.history.path, e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+
+    // 気配・FR・裁定機会の Parquet への書き出し。ファイルは日付が変わるか終了時 (Ctrl-C) に閉じる
+    let exporter = config.export.enabled.then(|| {
+        let exporter = start_exporter(&config, &registry);
+        exporter.spawn_live(&store);
+        info!("[Export] Writing quotes, funding rates and opportunities to {}", config.export.dir);
+        exporter
+    });
 
-        for asset in TARGET_ASSETS {
-            let mut market_data_list = Vec::new();
-
-            // Hyperliquid (Perp)
-            if let Some(data) = store.get_market_data(Exchange::Hyperliquid, &symbol_for(Exchange::Hyperliquid, asset, InstrumentType::Perp)) {
-                market_data_list.push(MarketData {
-                    exchange: Exchange::Hyperliquid,
-                    asset: *asset,
-                    instrument: InstrumentType::Perp,
-                    currency: Currency::USD,
-                    ask: data.ask,
-                    bid: data.bid,
-                    funding_rate: data.funding_rate,
-                });
+    let mut supervisor = Supervisor::new(store.clone());
+    if config.recorder.enabled {
+        match Recorder::start(&config.recorder) {
+            Ok(recorder) => {
+                info!("[Recorder] Recording raw frames to {}", config.recorder.dir);
+                supervisor = supervisor.with_recorder(recorder);
             }
-            
-            // Hyperliquid (Spot)
-            if let Some(data) = store.get_market_data(Exchange::Hyperliquid, &symbol_for(Exchange::Hyperliquid, asset, InstrumentType::Spot)) {
-                market_data_list.push(MarketData {
-                    exchange: Exchange::Hyperliquid,
-                    asset: *asset,
-                    instrument: InstrumentType::Spot,
-                    currency: Currency::USD,
-                    ask: data.ask,
-                    bid: data.bid,
-                    funding_rate: Decimal::ZERO,
-                });
+            Err(e) => {
+                error!("[Recorder] Failed to open {}: {}", config.recorder.dir, e);
+                std::process::exit(1);
             }
+        }
+    }
+    for collector in collectors {
+        supervisor.spawn(collector);
+    }
+    supervisor.spawn_health_reporter(config.intervals.health_report());
 
-            // Bitbank (JPY, Spot)
-            if let Some(data) = store.get_market_data(Exchange::Bitbank, &symbol_for(Exchange::Bitbank, asset, InstrumentType::Spot)) {
-                market_data_list.push(MarketData {
-                    exchange: Exchange::Bitbank,
-                    asset: *asset,
-                    instrument: InstrumentType::Spot,
-                    currency: Currency::JPY,
-                    ask: data.ask,
-                    bid: data.bid,
-                    funding_rate: Decimal::ZERO,
-                });
-            }
+    info!("Waiting for market data warmup ({:?})...", config.intervals.warmup());
+    sleep(config.intervals.warmup()).await;
 
-            // GMO (JPY, Spot)
-            if let Some(data) = store.get_market_data(Exchange::Gmo, &symbol_for(Exchange::Gmo, asset, InstrumentType::Spot)) {
-                market_data_list.push(MarketData {
-                    exchange: Exchange::Gmo,
-                    asset: *asset,
-                    instrument: InstrumentType::Spot,
-                    currency: Currency::JPY,
-                    ask: data.ask,
-                    bid: data.bid,
-                    funding_rate: Decimal::ZERO,
-                });
-            }
+    // 執行器の選択
+    // --paper: MarketStore の気配で約定を模擬し、仮想残高と取引ジャーナルを記録
+    // 既定: ローカル模擬取引所に発注 (実際の取引所の発注口はまだ無いので、約定はすべて模擬)
+    let paper_mode = args.iter().any(|a| a == "--paper");
+    if paper_mode {
+        let Some(executor) = paper_executor(&store, &config, &config.paper.journal_path) else {
+            return;
+        };
+        info!("Running in paper trading mode");
+        let engine = StrategyEngine::new(&store, &config, &registry, executor)
+            .with_after_execution(log_paper_summary)
+            .with_on_opportunity(export_opportunities(exporter.as_ref(), &store));
+        until_ctrl_c(run_strategy_loop(&store, &config, engine)).await;
+    } else {
+        warn!("Running in simulated mode: orders are filled by an in-process local exchange and never reach a venue");
+        let executor = TwoLegExecutor::new(LocalExchange::with_fees(config.fee_schedule()), executor_config(&config));
+        let engine = StrategyEngine::new(&store, &config, &registry, executor).with_on_opportunity(export_opportunities(exporter.as_ref(), &store));
+        until_ctrl_c(run_strategy_loop(&store, &config, engine)).await;
+    }
+    if let Some(exporter) = exporter {
+        info!("[Export] Closed: {}", exporter.finish());
+    }
+}
+
+/// Ctrl-C を受けるまで future を実行する
+async fn until_ctrl_c(future: impl Future<Output = ()>) {
+    tokio::select! {
+        _ = future => {}
+        _ = tokio::signal::ctrl_c() => info!("Received Ctrl-C, shutting down"),
+    }
+}
+
+/// --name value 形式の引数の値
+fn arg_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter().position(|a| a == name).and_then(|i| args.get(i + 1)).map(String::as_str)
+}
 
-            // 戦略実行
-            if market_data_list.len() >= 2 {
-                if let Some(opp) = find_best_arbitrage(&market_data_list, asset.clone(), usd_jpy) {
-                    if opp.estimated_profit_pct > Decimal::from_f64(0.05).unwrap() { // 0.05%
-                        info!("================================================================================");
-                        info!("🚀 [裁定取引機会] {:?}", asset);
-                        info!("================================================================================");
-                        info!("📊 取引詳細:");
-                        info!("  買い: {:?} {:?} @ {} {:?}", opp.long_exchange, opp.long_instrument, opp.long_price_raw, opp.long_currency);
-                        info!("  売り: {:?} {:?} @ {} {:?}", opp.short_exchange, opp.short_instrument, opp.short_price_raw, opp.short_currency);
-                        info!("  為替レート: {} JPY/USD", opp.usd_jpy_rate);
-                        info!("");
-                        info!("💰 損益計算 (1単位あたり):");
-                        info!("  買値(JPY換算): ¥{:.2}", opp.long_price_jpy);
-                        info!("  売値(JPY換算): ¥{:.2}", opp.short_price_jpy);
-                        info!("  粗利益: ¥{:.2}", opp.base_profit_jpy);
-                        info!("");
-                        info!("📉 コスト:");
-                        info!("  買い手数料: ¥{:.2}", opp.long_fee_jpy);
-                        info!("  売り手数料: ¥{:.2}", opp.short_fee_jpy);
-                        info!("  スリッページ: ¥{:.2}", opp.slippage_cost_jpy);
-                        info!("  FR影響: ¥{:.2}", opp.fr_impact_jpy);
-                        info!("  合計コスト: ¥{:.2}", opp.long_fee_jpy + opp.short_fee_jpy + opp.slippage_cost_jpy);
-                        info!("");
-                        info!("✅ 純利益: ¥{:.2} ({:.4}%)", opp.estimated_profit_jpy, opp.estimated_profit_pct);
-                        info!("================================================================================");
-                        // TODO: ここで executor::execute(&opportunity).await;
-                    }
+/// --from <UNIX ms> と --to <UNIX ms> で指定した期間 (UNIX µs)。省略時は全期間
+fn time_range(args: &[String]) -> (u64, u64) {
+    let parse_us = |name: &str| match arg_value(args, name).map(str::parse::<u64>) {
+        Some(Ok(ms)) => Some(ms * 1_000),
+        Some(Err(e)) => {
+            error!("[Config] Invalid {}: {}", name, e);
+            std::process::exit(1);
+        }
+        None => None,
+    };
+    (parse_us("--from").unwrap_or(0), parse_us("--to").unwrap_or(u64::MAX))
+}
+
+/// 有効な取引所のコレクター
+fn build_collectors(config: &Config, registry: &Arc<AssetRegistry>) -> Vec<Box<dyn Collector>> {
+    let mut collectors: Vec<Box<dyn Collector>> = Vec::new();
+    if config.exchanges.hyperliquid.enabled {
+        collectors.push(Box::new(HyperliquidCollector::new(registry.clone())));
+    }
+    if config.exchanges.bitbank.enabled {
+        collectors.push(Box::new(BitbankCollector::new(registry.clone())));
+    }
+    if config.exchanges.gmo.enabled {
+        collectors.push(Box::new(GmoCollector::new(registry.clone())));
+    }
+    if config.exchanges.kraken.enabled {
+        // 為替の参照元のうち Kraken のものを購読する
+        let mut symbols: Vec<String> = FxOracle::new(&config.fx, |e| e == Exchange::Kraken)
+            .sources()
+            .map(|s| s.symbol.clone())
+            .collect();
+        symbols.sort();
+        symbols.dedup();
+        collectors.push(Box::new(KrakenCollector::new(symbols)));
+    }
+    collectors
+}
+
+fn executor_config(config: &Config) -> ExecutorConfig {
+    ExecutorConfig {
+        max_notional_jpy: config.strategies.arbitrage.max_notional_jpy,
+        ..ExecutorConfig::default()
+    }
+}
+
+fn paper_executor(store: &MarketStore, config: &Config, journal_path: &str) -> Option<PaperExecutor> {
+    let paper_config = PaperConfig::new(&config.paper, executor_config(config), config.fee_schedule(), journal_path);
+    match PaperExecutor::new(store.clone(), paper_config) {
+        Ok(executor) => Some(executor),
+        Err(e) => {
+            error!("[Paper] Failed to open trade journal: {}", e);
+            None
+        }
+    }
+}
+
+fn start_exporter(config: &Config, registry: &AssetRegistry) -> Exporter {
+    match Exporter::start(&config.export, registry) {
+        Ok(exporter) => exporter,
+        Err(e) => {
+            error!("[Export] Failed to open {}: {}", config.export.dir, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// 書き出しが有効なら裁定機会を書き出しに渡す処理
+fn export_opportunities(exporter: Option<&Exporter>, store: &MarketStore) -> impl FnMut(&ArbitrageOpportunity, usize) + Send + 'static {
+    let mut sink = exporter.map(Exporter::sink);
+    let store = store.clone();
+    move |opp, rank| {
+        if let Some(sink) = &mut sink {
+            sink.on_opportunity(opp, rank, &store);
+        }
+    }
+}
+
+fn log_paper_summary(executor: &PaperExecutor) {
+    let summary = executor.summary();
+    info!(
+        "📒 [ペーパー累計] 取引数: {} 実現損益: ¥{:.2} 推定損益: ¥{:.2} 捕捉率: {}",
+        summary.trades,
+        summary.realized_profit_jpy,
+        summary.estimated_profit_jpy,
+        summary.capture_ratio().map(|r| format!("{:.1}%", r * Decimal::from(100))).unwrap_or_else(|| "-".to_string()),
+    );
+}
+
+/// 記録したフィードのリプレイ ([--from <UNIX ms>] [--to <UNIX ms>] [--speed max|1x|10x])。
+/// 執行はペーパートレードで、取引ジャーナルは [paper] の replay_journal_path に出す。
+/// exporter を渡した場合は、フレームごとの気配・FRと執行に回した裁定機会を取りこぼさずに書き出す
+async fn replay(
+    args: &[String],
+    dir: &str,
+    config: &Config,
+    registry: &AssetRegistry,
+    store: MarketStore,
+    collectors: Vec<Box<dyn Collector>>,
+    exporter: Option<&Exporter>,
+) {
+    let (from_us, to_us) = time_range(args);
+    let speed = match arg_value(args, "--speed").map(str::parse::<ReplaySpeed>) {
+        Some(Ok(speed)) => speed,
+        Some(Err(e)) => {
+            error!("[Replay] {}", e);
+            std::process::exit(1);
+        }
+        None => ReplaySpeed::AsFastAsPossible,
+    };
+    let index = match RecordingIndex::load(dir) {
+        Ok(index) => index,
+        Err(e) => {
+            error!("[Replay] Failed to read recordings in {}: {}", dir, e);
+            std::process::exit(1);
+        }
+    };
+    info!("[Replay] Replaying {} file(s) from {} at {:?}", index.files_between(from_us, to_us).len(), dir, speed);
+
+    // 気配の鮮度や執行の時刻はフレームの受信時刻で進む仮想時計で測る
+    let clock = Arc::new(VirtualClock::default());
+    let store = store.with_clock(clock.clone());
+
+    let Some(executor) = paper_executor(&store, config, &config.paper.replay_journal_path) else {
+        return;
+    };
+    let mut replayer = FeedReplayer::new(store.clone(), clock, collectors);
+    let mut engine = StrategyEngine::new(&store, config, registry, executor).with_after_execution(log_paper_summary);
+    if let Some(exporter) = exporter {
+        let mut sink = exporter.lossless_sink();
+        replayer = replayer.with_update_tap(move |update, store| sink.on_update(update, store));
+        let mut sink = exporter.lossless_sink();
+        let store = store.clone();
+        engine = engine.with_on_opportunity(move |opp, rank| sink.on_opportunity(opp, rank, &store));
+    }
+    let stats = run_replay(recorded_frames(&index, from_us, to_us), &mut replayer, &mut engine, &config.intervals, speed).await;
+    info!("[Replay] Done: {}", stats);
+    log_paper_summary(engine.executor());
+}
+
+/// バックテスト ([--from <UNIX ms>] [--to <UNIX ms>])。設定は [backtest]、結果は backtest.output_dir に書き出す
+fn backtest(args: &[String], path: &str, config: &Config, registry: &AssetRegistry, collectors: Vec<Box<dyn Collector>>) {
+    let (from_us, to_us) = time_range(args);
+    let ticks = match load_ticks(Path::new(path), from_us, to_us) {
+        Ok(ticks) => ticks,
+        Err(e) => {
+            error!("[Backtest] Failed to read {}: {}", path, e);
+            std::process::exit(1);
+        }
+    };
+    info!("[Backtest] Running over {}", path);
+    let report = Backtester::new(config, registry, collectors).run(ticks);
+
+    let span_ms = report.span_ms();
+    info!("[Backtest] {} ticks over {}ms ({} - {})", report.ticks, span_ms, report.start_ms, report.end_ms);
+    for (n, variant) in report.variants.iter().enumerate() {
+        info!("================================================================================");
+        info!("📈 [バックテスト #{}] {}", n, variant.label());
+        info!("  全体: {}", variant.total);
+        for (asset, stats) in &variant.assets {
+            info!("  {}: {} 機会の継続 {}", asset, stats, percent_of(stats.time_in_market(span_ms)));
+        }
+        for (route, stats) in &variant.routes {
+            info!("  {}: {} 機会の継続 {}", route, stats, percent_of(stats.time_in_market(span_ms)));
+        }
+    }
+    match report.write_to(&config.backtest.output_dir) {
+        Ok(()) => info!("[Backtest] Wrote summary.csv and equity curves to {}", config.backtest.output_dir),
+        Err(e) => error!("[Backtest] Failed to write results to {}: {}", config.backtest.output_dir, e),
+    }
+}
+
+fn percent_of(ratio: Option<Decimal>) -> String {
+    ratio.map(|r| format!("{:.1}%", r * Decimal::from(100))).unwrap_or_else(|| "-".to_string())
+}
+
+/// 戦略のメインループ。気配の更新を受けて影響する通貨だけを評価し、機会が見つかるたびに執行する。
+/// 更新が strategy_loop の間無ければすべての通貨を評価し直す
+async fn run_strategy_loop<E: Executor>(store: &MarketStore, config: &Config, mut engine: StrategyEngine<'_, E>) {
+    let registry = engine.registry();
+    let mut updates = store.subscribe();
+    let mut pending = PendingEvaluations::default();
+    let mut next_full_evaluation = Instant::now();
+    let mut next_latency_report = Instant::now() + config.intervals.latency_report();
+    loop {
+        if pending.is_empty() {
+            // 最初の更新を待ち、続く更新を debounce の間まとめる
+            if let Ok(update) = timeout_at(next_full_evaluation, updates.recv()).await {
+                receive_update(update, &mut pending, engine.index(), registry);
+                let deadline = Instant::now() + config.intervals.debounce();
+                while let Ok(update) = timeout_at(deadline, updates.recv()).await {
+                    receive_update(update, &mut pending, engine.index(), registry);
                 }
             }
         }
-        sleep(Duration::from_millis(500)).await;
+        // 更新の多い通貨があっても、ほかの通貨も strategy_loop ごとに評価し直す
+        if Instant::now() >= next_full_evaluation {
+            pending.mark_all(registry);
+            next_full_evaluation = Instant::now() + config.intervals.strategy_loop();
+        }
+        if pending.is_empty() {
+            continue;
+        }
+
+        if Instant::now() >= next_latency_report {
+            if let Some(summary) = engine.take_latency_summary() {
+                info!("[Latency] update -> decision: {}", summary);
+            }
+            next_latency_report = Instant::now() + config.intervals.latency_report();
+        }
+
+        // 為替レートが取れない間は評価待ちのまま再試行する
+        if !engine.evaluate_pending(&mut pending).await {
+            sleep(config.intervals.fx_retry()).await;
+        }
     }
-}
\ No newline at end of file
+}
+
+/// 更新通知を評価待ちに加える。取りこぼした場合はすべての通貨を評価し直す
+fn receive_update(
+    update: Result<StoreUpdate, RecvError>,
+    pending: &mut PendingEvaluations,
+    index: &AssetIndex,
+    registry: &AssetRegistry,
+) {
+    match update {
+        Ok(update) => pending.mark_update(&update, index, registry),
+        Err(RecvError::Lagged(skipped)) => {
+            debug!("[Strategy] Missed {} store updates, re-evaluating all assets", skipped);
+            pending.mark_all(registry);
+        }
+        // MarketStore を保持している限り閉じられることはない
+        Err(RecvError::Closed) => pending.mark_all(registry),
+    }
+}
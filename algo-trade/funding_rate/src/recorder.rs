@@ -0,0 +1,520 @@
+// 生フレームの記録
+//
+// コレクターが送受信した WebSocket のテキストフレームを、受信時刻 (UNIX µs) と取引所を付けて
+// gzip 圧縮した JSON Lines に追記する。ファイルは一定サイズ・一定時間で切り替え、閉じたファイルは
+// index.jsonl に期間を記録する。合計サイズが上限を超えたら古いファイルから消す。
+//
+// 書き込みは専用スレッドで行い、キューが溢れた場合はフィードを止めずにフレームを捨てて数える。
+use crate::store::{current_timestamp_us, Exchange};
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, SyncSender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const INDEX_FILE: &str = "index.jsonl";
+const FILE_PREFIX: &str = "frames-";
+const FILE_SUFFIX: &str = ".jsonl.gz";
+/// 書き込みスレッドへのキューの長さ
+const QUEUE_CAPACITY: usize = 65_536;
+const MB: u64 = 1024 * 1024;
+
+/// 生フレームの記録設定 (設定ファイルの [recorder])
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RecorderConfig {
+    pub enabled: bool,
+    /// 記録先のディレクトリ
+    pub dir: String,
+    /// 1ファイルに書く非圧縮のサイズの上限 (MB)
+    pub rotate_mb: u64,
+    /// 1ファイルに書く期間の上限 (秒)
+    pub rotate_secs: u64,
+    /// 記録先の合計サイズ (圧縮後) の上限 (MB)。超えたら古いファイルから消す
+    pub max_disk_mb: u64,
+    /// 書き込み途中のファイルをディスクに反映する間隔 (ms)。異常終了時はこの間の分が失われる
+    pub flush_ms: u64,
+}
+
+impl Default for RecorderConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: "recordings".to_string(),
+            rotate_mb: 64,
+            rotate_secs: 3_600,
+            max_disk_mb: 2_048,
+            flush_ms: 1_000,
+        }
+    }
+}
+
+/// フレームの向き
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameDirection {
+    /// 受信したフレーム
+    In,
+    /// 送信したフレーム (購読メッセージ、Pong など)
+    Out,
+    /// 新しい接続の確立 (text は接続先 URL)
+    Connect,
+}
+
+/// 記録した1フレーム (ファイルの1行)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    /// 受信 (送信) 時刻 (UNIX µs)
+    pub ts_us: u64,
+    pub venue: Exchange,
+    pub dir: FrameDirection,
+    /// コレクターごとの接続の通番 (1 始まり)
+    pub conn: u64,
+    pub text: String,
+}
+
+/// index.jsonl の1行。閉じたファイル1つ分
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub file: String,
+    pub start_us: u64,
+    /// 最後のフレームの時刻。正常に閉じられていないファイルは None
+    pub end_us: Option<u64>,
+    pub frames: u64,
+    /// 圧縮後のサイズ
+    pub bytes: u64,
+    pub venues: Vec<Exchange>,
+}
+
+impl IndexEntry {
+    /// [start_us, end_us] と期間が重なるか (閉じられていないファイルは開始以降すべてと重なるとみなす)
+    pub fn overlaps(&self, start_us: u64, end_us: u64) -> bool {
+        self.start_us <= end_us && self.end_us.is_none_or(|end| end >= start_us)
+    }
+}
+
+/// 記録先のファイルの一覧 (開始時刻順)
+#[derive(Debug, Clone)]
+pub struct RecordingIndex {
+    dir: PathBuf,
+    entries: Vec<IndexEntry>,
+}
+
+impl RecordingIndex {
+    /// index.jsonl と、索引に無いファイル (書き込み中・異常終了したもの) を読む
+    pub fn load(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        let mut entries: Vec<IndexEntry> = Vec::new();
+        match File::open(dir.join(INDEX_FILE)) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    let line = line?;
+                    match serde_json::from_str::<IndexEntry>(&line) {
+                        Ok(entry) => entries.push(entry),
+                        Err(e) => warn!("[Recorder] Skipping bad index line: {}", e),
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        for dir_entry in fs::read_dir(&dir)? {
+            let dir_entry = dir_entry?;
+            let name = dir_entry.file_name().to_string_lossy().into_owned();
+            let Some(start_ms) = name.strip_prefix(FILE_PREFIX).and_then(|n| n.strip_suffix(FILE_SUFFIX)) else {
+                continue;
+            };
+            let Ok(start_ms) = start_ms.parse::<u64>() else {
+                continue;
+            };
+            if entries.iter().any(|e| e.file == name) {
+                continue;
+            }
+            entries.push(IndexEntry {
+                file: name,
+                start_us: start_ms * 1_000,
+                end_us: None,
+                frames: 0,
+                bytes: dir_entry.metadata()?.len(),
+                venues: Vec::new(),
+            });
+        }
+        entries.sort_by_key(|e| e.start_us);
+        Ok(Self { dir, entries })
+    }
+
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    /// 期間 [start_us, end_us] のフレームを含みうるファイル (開始時刻順)
+    pub fn files_between(&self, start_us: u64, end_us: u64) -> Vec<PathBuf> {
+        self.entries
+            .iter()
+            .filter(|e| e.overlaps(start_us, end_us))
+            .map(|e| self.dir.join(&e.file))
+            .collect()
+    }
+}
+
+/// 1ファイル分のフレームを順に読む。
+/// 異常終了で末尾が壊れたファイルは、読めたところまでを返して終わる
+pub fn read_frames(path: impl AsRef<Path>) -> io::Result<impl Iterator<Item = RecordedFrame>> {
+    let path = path.as_ref().to_path_buf();
+    let reader = BufReader::new(MultiGzDecoder::new(File::open(&path)?));
+    Ok(reader.lines().map_while(move |line| match line {
+        Ok(line) => match serde_json::from_str(&line) {
+            Ok(frame) => Some(frame),
+            Err(e) => {
+                warn!("[Recorder] {}: stopping at bad frame: {}", path.display(), e);
+                None
+            }
+        },
+        Err(e) => {
+            warn!("[Recorder] {}: stopping at truncated data: {}", path.display(), e);
+            None
+        }
+    }))
+}
+
+/// コレクターから使う記録口。clone して各コレクターに渡す
+#[derive(Clone)]
+pub struct Recorder {
+    tx: SyncSender<RecordedFrame>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl Recorder {
+    /// 記録先を作り、書き込みスレッドを起動する
+    pub fn start(config: &RecorderConfig) -> io::Result<Self> {
+        fs::create_dir_all(&config.dir)?;
+        let dropped = Arc::new(AtomicU64::new(0));
+        let writer = FrameWriter {
+            dir: PathBuf::from(&config.dir),
+            rotate_bytes: config.rotate_mb * MB,
+            rotate_after: Duration::from_secs(config.rotate_secs),
+            max_disk_bytes: config.max_disk_mb * MB,
+            flush_interval: Duration::from_millis(config.flush_ms),
+            current: None,
+            dropped: dropped.clone(),
+            reported_dropped: 0,
+        };
+        writer.prune(None)?;
+        let (tx, rx) = mpsc::sync_channel(QUEUE_CAPACITY);
+        thread::Builder::new().name("recorder".to_string()).spawn(move || writer.run(rx))?;
+        Ok(Self { tx, dropped })
+    }
+
+    pub fn record(&self, venue: Exchange, dir: FrameDirection, conn: u64, text: &str) {
+        let frame = RecordedFrame { ts_us: current_timestamp_us(), venue, dir, conn, text: text.to_string() };
+        if self.tx.try_send(frame).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// 書き込み中のファイル
+struct OpenFile {
+    entry: IndexEntry,
+    encoder: GzEncoder<BufWriter<File>>,
+    raw_bytes: u64,
+    opened_at: Instant,
+}
+
+struct FrameWriter {
+    dir: PathBuf,
+    rotate_bytes: u64,
+    rotate_after: Duration,
+    max_disk_bytes: u64,
+    flush_interval: Duration,
+    current: Option<OpenFile>,
+    dropped: Arc<AtomicU64>,
+    reported_dropped: u64,
+}
+
+impl FrameWriter {
+    fn run(mut self, rx: mpsc::Receiver<RecordedFrame>) {
+        let mut last_flush = Instant::now();
+        loop {
+            match rx.recv_timeout(self.flush_interval) {
+                Ok(frame) => {
+                    if let Err(e) = self.write(&frame) {
+                        error!("[Recorder] Failed to write frame: {}", e);
+                        // 壊れたファイルは閉じずに捨て、次のフレームで新しいファイルを開く
+                        self.current = None;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    if let Err(e) = self.close() {
+                        error!("[Recorder] Failed to close file: {}", e);
+                    }
+                    return;
+                }
+            }
+            if last_flush.elapsed() >= self.flush_interval {
+                self.flush();
+                last_flush = Instant::now();
+            }
+        }
+    }
+
+    fn write(&mut self, frame: &RecordedFrame) -> io::Result<()> {
+        if self.current.is_none() {
+            self.current = Some(self.open(frame.ts_us)?);
+        }
+        let file = self.current.as_mut().expect("file opened above");
+        let mut line = serde_json::to_vec(frame)?;
+        line.push(b'\n');
+        file.encoder.write_all(&line)?;
+        file.raw_bytes += line.len() as u64;
+        file.entry.frames += 1;
+        file.entry.end_us = Some(frame.ts_us);
+        if !file.entry.venues.contains(&frame.venue) {
+            file.entry.venues.push(frame.venue);
+        }
+        if file.raw_bytes >= self.rotate_bytes || file.opened_at.elapsed() >= self.rotate_after {
+            self.close()?;
+        }
+        Ok(())
+    }
+
+    fn open(&self, start_us: u64) -> io::Result<OpenFile> {
+        let name = format!("{}{:013}{}", FILE_PREFIX, start_us / 1_000, FILE_SUFFIX);
+        let file = OpenOptions::new().create_new(true).write(true).open(self.dir.join(&name))?;
+        Ok(OpenFile {
+            entry: IndexEntry { file: name, start_us, end_us: None, frames: 0, bytes: 0, venues: Vec::new() },
+            encoder: GzEncoder::new(BufWriter::new(file), Compression::default()),
+            raw_bytes: 0,
+            opened_at: Instant::now(),
+        })
+    }
+
+    /// 書き込み中のファイルを閉じて索引に加え、上限を超えた分を消す
+    fn close(&mut self) -> io::Result<()> {
+        let Some(file) = self.current.take() else {
+            return Ok(());
+        };
+        let mut entry = file.entry;
+        let mut inner = file.encoder.finish()?;
+        inner.flush()?;
+        entry.bytes = inner.get_ref().metadata()?.len();
+        info!(
+            "[Recorder] Closed {} ({} frames, {} KB)",
+            entry.file, entry.frames, entry.bytes / 1024
+        );
+
+        let mut index = OpenOptions::new().create(true).append(true).open(self.dir.join(INDEX_FILE))?;
+        writeln!(index, "{}", serde_json::to_string(&entry)?)?;
+        self.prune(None)
+    }
+
+    /// 途中まで書いた圧縮データをディスクに反映する (読む側は反映済みの分まで読める)
+    fn flush(&mut self) {
+        if let Some(file) = self.current.as_mut()
+            && let Err(e) = file.encoder.flush()
+        {
+            error!("[Recorder] Failed to flush {}: {}", file.entry.file, e);
+        }
+        let dropped = self.dropped.load(Ordering::Relaxed);
+        if dropped > self.reported_dropped {
+            warn!("[Recorder] Dropped {} frames (queue full)", dropped - self.reported_dropped);
+            self.reported_dropped = dropped;
+        }
+        if let Err(e) = self.prune(self.current.as_ref().map(|f| f.entry.file.as_str())) {
+            error!("[Recorder] Failed to enforce disk budget: {}", e);
+        }
+    }
+
+    /// 合計サイズが上限を超えていれば古いファイルから消し、索引を書き直す。
+    /// keep (書き込み中のファイル) は消さない
+    fn prune(&self, keep: Option<&str>) -> io::Result<()> {
+        let index = RecordingIndex::load(&self.dir)?;
+        let mut total: u64 = index.entries.iter().map(|e| e.bytes).sum();
+        if total <= self.max_disk_bytes {
+            return Ok(());
+        }
+        let mut remaining = Vec::new();
+        for entry in index.entries {
+            if total > self.max_disk_bytes && keep != Some(entry.file.as_str()) {
+                match fs::remove_file(self.dir.join(&entry.file)) {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e),
+                }
+                info!("[Recorder] Removed {} to stay within the disk budget", entry.file);
+                total -= entry.bytes;
+            } else {
+                remaining.push(entry);
+            }
+        }
+
+        // 閉じたファイルだけを索引に残す (一時ファイルに書いてから置き換える)
+        let tmp = self.dir.join(format!("{}.tmp", INDEX_FILE));
+        {
+            let mut out = BufWriter::new(File::create(&tmp)?);
+            for entry in remaining.iter().filter(|e| e.end_us.is_some()) {
+                writeln!(out, "{}", serde_json::to_string(entry)?)?;
+            }
+            out.flush()?;
+        }
+        fs::rename(tmp, self.dir.join(INDEX_FILE))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    const START_US: u64 = 1_700_000_000_000_000;
+
+    fn writer(dir: &TempDir, rotate_bytes: u64, rotate_after: Duration, max_disk_bytes: u64) -> FrameWriter {
+        FrameWriter {
+            dir: dir.path().to_path_buf(),
+            rotate_bytes,
+            rotate_after,
+            max_disk_bytes,
+            flush_interval: Duration::from_secs(1),
+            current: None,
+            dropped: Arc::new(AtomicU64::new(0)),
+            reported_dropped: 0,
+        }
+    }
+
+    /// i 番目のフレーム (1ms ずつ進む)
+    fn frame(i: u64) -> RecordedFrame {
+        RecordedFrame {
+            ts_us: START_US + i * 1_000,
+            venue: if i.is_multiple_of(2) { Exchange::Gmo } else { Exchange::Bitbank },
+            dir: FrameDirection::In,
+            conn: 1,
+            text: format!(r#"{{"channel":"ticker","seq":{}}}"#, i),
+        }
+    }
+
+    fn frame_len(i: u64) -> u64 {
+        serde_json::to_vec(&frame(i)).unwrap().len() as u64 + 1
+    }
+
+    fn index_lines(dir: &TempDir) -> Vec<IndexEntry> {
+        let text = fs::read_to_string(dir.path().join(INDEX_FILE)).unwrap();
+        text.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+    }
+
+    #[test]
+    fn rotates_by_size_and_indexes_closed_files() {
+        let dir = TempDir::new("recorder");
+        // 2フレーム目で上限に届く
+        let mut writer = writer(&dir, frame_len(0) + 1, Duration::from_secs(3_600), u64::MAX);
+        for i in 0..5 {
+            writer.write(&frame(i)).unwrap();
+        }
+        writer.close().unwrap();
+
+        let index = RecordingIndex::load(dir.path()).unwrap();
+        let entries = index.entries();
+        assert_eq!(entries.iter().map(|e| e.frames).collect::<Vec<_>>(), [2, 2, 1]);
+        assert_eq!(entries[0].start_us, START_US);
+        assert_eq!(entries[0].end_us, Some(START_US + 1_000));
+        assert_eq!(entries[0].venues, [Exchange::Gmo, Exchange::Bitbank]);
+        assert_eq!(entries[2].end_us, Some(START_US + 4_000));
+        assert_eq!(index_lines(&dir).len(), 3);
+
+        let frames: Vec<u64> = index
+            .files_between(START_US, START_US + 4_000)
+            .iter()
+            .flat_map(|path| read_frames(path).unwrap())
+            .map(|f| f.ts_us)
+            .collect();
+        assert_eq!(frames, (0..5).map(|i| frame(i).ts_us).collect::<Vec<_>>());
+        // 期間で絞り込む
+        assert_eq!(index.files_between(START_US + 2_000, START_US + 3_000).len(), 1);
+    }
+
+    #[test]
+    fn rotates_by_time() {
+        let dir = TempDir::new("recorder");
+        let mut writer = writer(&dir, u64::MAX, Duration::ZERO, u64::MAX);
+        for i in 0..3 {
+            writer.write(&frame(i)).unwrap();
+            // 期間の上限を過ぎたファイルは書いた直後に閉じる
+            assert!(writer.current.is_none());
+        }
+        let entries = index_lines(&dir);
+        assert_eq!(entries.iter().map(|e| e.frames).collect::<Vec<_>>(), [1, 1, 1]);
+    }
+
+    #[test]
+    fn prune_removes_oldest_files_and_rewrites_the_index() {
+        let dir = TempDir::new("recorder");
+        let mut writer = writer(&dir, u64::MAX, Duration::ZERO, u64::MAX);
+        for i in 0..4 {
+            writer.write(&frame(i)).unwrap();
+        }
+        let entries = index_lines(&dir);
+        assert_eq!(entries.len(), 4);
+
+        // 新しい2ファイル分だけ残せる上限にする
+        writer.max_disk_bytes = entries[2].bytes + entries[3].bytes;
+        writer.prune(None).unwrap();
+
+        let kept = index_lines(&dir);
+        assert_eq!(kept.iter().map(|e| e.file.as_str()).collect::<Vec<_>>(), [&entries[2].file, &entries[3].file]);
+        for entry in &entries[..2] {
+            assert!(!dir.path().join(&entry.file).exists(), "{} was not removed", entry.file);
+        }
+        let total: u64 = RecordingIndex::load(dir.path()).unwrap().entries().iter().map(|e| e.bytes).sum();
+        assert!(total <= writer.max_disk_bytes);
+
+        // 書き込み中のファイルは上限を超えても消さない
+        writer.max_disk_bytes = 1;
+        writer.rotate_after = Duration::from_secs(3_600);
+        writer.write(&frame(4)).unwrap();
+        writer.flush();
+        let current = writer.current.as_ref().unwrap().entry.file.clone();
+        assert!(dir.path().join(&current).exists());
+        assert!(index_lines(&dir).is_empty());
+    }
+
+    #[test]
+    fn load_picks_up_unindexed_files_and_reads_them_up_to_the_truncation() {
+        let dir = TempDir::new("recorder");
+        let mut writer = writer(&dir, u64::MAX, Duration::from_secs(3_600), u64::MAX);
+        writer.write(&frame(0)).unwrap();
+        writer.rotate_after = Duration::ZERO;
+        writer.write(&frame(1)).unwrap();
+
+        // 異常終了: 反映済みの2フレームの後は gzip の末尾が無いまま残る
+        writer.rotate_after = Duration::from_secs(3_600);
+        writer.write(&frame(2)).unwrap();
+        writer.write(&frame(3)).unwrap();
+        writer.current.as_mut().unwrap().encoder.flush().unwrap();
+        std::mem::forget(writer.current.take());
+
+        let index = RecordingIndex::load(dir.path()).unwrap();
+        let entries = index.entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].start_us, frame(2).ts_us);
+        assert_eq!(entries[1].end_us, None);
+        assert!(entries[1].overlaps(START_US + 1_000_000, START_US + 2_000_000));
+
+        let crashed: Vec<u64> = read_frames(dir.path().join(&entries[1].file)).unwrap().map(|f| f.ts_us).collect();
+        assert_eq!(crashed, [frame(2).ts_us, frame(3).ts_us]);
+
+        // 途中で切れた圧縮データは、読めたところまでで止まる
+        let closed = dir.path().join(&entries[0].file);
+        let bytes = fs::read(&closed).unwrap();
+        fs::write(&closed, &bytes[..bytes.len() / 2]).unwrap();
+        assert!(read_frames(&closed).unwrap().count() < 2);
+    }
+}
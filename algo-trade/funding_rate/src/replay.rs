@@ -0,0 +1,321 @@
+// 記録したフィードのリプレイ
+//
+// recorder が記録した生フレームを、記録時と同じ順にコレクターの handle_text に渡して MarketStore を再構築し、
+// 戦略の評価をストアの仮想時計 (フレームの受信時刻) で回す。ウォームアップ・debounce・一定間隔の見直し・為替の再試行は
+// ライブの戦略ループと同じスケジュールを仮想時刻で再現するので、同じ記録と設定からは同じシグナルが出る。
+use crate::clock::VirtualClock;
+use crate::collector::Collector;
+use crate::config::IntervalConfig;
+use crate::engine::StrategyEngine;
+use crate::executor::Executor;
+use crate::recorder::{read_frames, FrameDirection, RecordedFrame, RecordingIndex};
+use crate::store::{Exchange, MarketStore, StoreUpdate, VenueStatus};
+use crate::strategy::PendingEvaluations;
+use log::{info, warn};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::TryRecvError};
+use tokio::time::{sleep_until, Instant};
+
+/// リプレイの速度
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaySpeed {
+    /// 待たずに次のフレームを処理する
+    AsFastAsPossible,
+    /// 記録時の間隔をこの倍率で縮めて再生する (1.0 で実時間)
+    Realtime(f64),
+}
+
+impl FromStr for ReplaySpeed {
+    type Err = String;
+
+    /// "max"、"1x"、"10x"、"0.5" などを受け付ける
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("max") {
+            return Ok(Self::AsFastAsPossible);
+        }
+        match s.trim_end_matches(['x', 'X']).parse::<f64>() {
+            Ok(factor) if factor > 0.0 && factor.is_finite() => Ok(Self::Realtime(factor)),
+            _ => Err(format!("invalid replay speed '{}' (expected max, 1x, 10x, ...)", s)),
+        }
+    }
+}
+
+/// リプレイの集計
+#[derive(Debug, Clone, Default)]
+pub struct ReplayStats {
+    pub frames: u64,
+    /// コレクターが処理に失敗したフレーム
+    pub errors: u64,
+    /// 対応するコレクターが無い (無効な取引所の) フレーム
+    pub skipped: u64,
+    pub evaluations: u64,
+    pub first_us: Option<u64>,
+    pub last_us: Option<u64>,
+}
+
+impl fmt::Display for ReplayStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let span_ms = match (self.first_us, self.last_us) {
+            (Some(first), Some(last)) => (last - first) / 1_000,
+            _ => 0,
+        };
+        write!(
+            f,
+            "{} frames ({} errors, {} skipped) over {}ms of recorded time, {} evaluation cycle(s)",
+            self.frames, self.errors, self.skipped, span_ms, self.evaluations
+        )
+    }
+}
+
+/// フレームごとの更新通知を受け取る処理
+type UpdateTap = Box<dyn FnMut(&StoreUpdate, &MarketStore) + Send>;
+
+/// 記録したフレームをコレクターに渡して MarketStore に反映する
+pub struct FeedReplayer {
+    store: MarketStore,
+    clock: Arc<VirtualClock>,
+    collectors: HashMap<Exchange, Box<dyn Collector>>,
+    stats: ReplayStats,
+    tap: Option<(broadcast::Receiver<StoreUpdate>, UpdateTap)>,
+}
+
+impl FeedReplayer {
+    /// store は clock を時計にしたもの (MarketStore::with_clock) を渡す。clock はフレームの時刻で進める
+    pub fn new(store: MarketStore, clock: Arc<VirtualClock>, collectors: Vec<Box<dyn Collector>>) -> Self {
+        let collectors = collectors.into_iter().map(|c| (c.exchange(), c)).collect();
+        Self { store, clock, collectors, stats: ReplayStats::default(), tap: None }
+    }
+
+    pub fn clock(&self) -> &Arc<VirtualClock> {
+        &self.clock
+    }
+
+    /// フレームを処理するたびに、そのフレームによる更新通知をストアと一緒に渡す (書き出しなど)。
+    /// 通知を受けた時点のストアはそのフレームを反映した直後の状態になる
+    pub fn with_update_tap(mut self, tap: impl FnMut(&StoreUpdate, &MarketStore) + Send + 'static) -> Self {
+        self.tap = Some((self.store.subscribe(), Box::new(tap)));
+        self
+    }
+
+    /// 仮想時計をフレームの時刻まで進めてから処理する。送信したフレーム (返信も含む) は再生しない
+    pub fn apply(&mut self, frame: &RecordedFrame) {
+        self.clock.advance(frame.ts_us);
+        self.stats.frames += 1;
+        self.stats.first_us.get_or_insert(frame.ts_us);
+        self.stats.last_us = Some(frame.ts_us);
+
+        let Some(collector) = self.collectors.get_mut(&frame.venue) else {
+            self.stats.skipped += 1;
+            return;
+        };
+        match frame.dir {
+            FrameDirection::Connect => {
+                collector.on_connect();
+                self.store.set_venue_status(frame.venue, VenueStatus::Up);
+            }
+            FrameDirection::In => {
+                if let Err(e) = collector.handle_text(&frame.text, &self.store) {
+                    self.stats.errors += 1;
+                    warn!("[Replay] {} frame at {}us (conn {}) failed: {}", collector.name(), frame.ts_us, frame.conn, e);
+                }
+            }
+            FrameDirection::Out => {}
+        }
+
+        if let Some((updates, tap)) = &mut self.tap {
+            loop {
+                match updates.try_recv() {
+                    Ok(update) => tap(&update, &self.store),
+                    Err(TryRecvError::Lagged(skipped)) => warn!("[Replay] Update tap missed {} update(s)", skipped),
+                    Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+                }
+            }
+        }
+    }
+
+    pub fn stats(&self) -> &ReplayStats {
+        &self.stats
+    }
+}
+
+/// 期間 [from_us, to_us] のフレームを記録順に読む。開けないファイルは飛ばす
+pub fn recorded_frames(index: &RecordingIndex, from_us: u64, to_us: u64) -> impl Iterator<Item = RecordedFrame> + use<> {
+    index
+        .files_between(from_us, to_us)
+        .into_iter()
+        .filter_map(|path| match read_frames(path.clone()) {
+            Ok(frames) => Some(frames),
+            Err(e) => {
+                warn!("[Replay] Skipping {}: {}", path.display(), e);
+                None
+            }
+        })
+        .flatten()
+        .filter(move |frame| frame.ts_us >= from_us && frame.ts_us <= to_us)
+}
+
+/// フレームを順に再生し、ライブの戦略ループと同じスケジュールで仮想時刻に沿って戦略を評価する
+pub async fn run_replay<E: Executor>(
+    frames: impl Iterator<Item = RecordedFrame>,
+    replayer: &mut FeedReplayer,
+    engine: &mut StrategyEngine<'_, E>,
+    intervals: &IntervalConfig,
+    speed: ReplaySpeed,
+) -> ReplayStats {
+    let us = |d: Duration| d.as_micros() as u64;
+    let mut updates = replayer.store.subscribe();
+    let mut pending = PendingEvaluations::default();
+    let mut evaluations = 0;
+    // 仮想時刻 (UNIX µs)。ウォームアップの終わりに最初の全通貨の評価をする
+    let mut warmup_end = None;
+    let mut next_full_evaluation = u64::MAX;
+    let mut next_evaluation: Option<u64> = None;
+    let started = Instant::now();
+
+    for frame in frames {
+        let warmup_end = *warmup_end.get_or_insert_with(|| {
+            next_full_evaluation = frame.ts_us + us(intervals.warmup());
+            info!("[Replay] Warming up for {:?} of recorded time", intervals.warmup());
+            next_full_evaluation
+        });
+
+        // このフレームより前に予定されている評価を済ませる
+        loop {
+            let due = next_evaluation.unwrap_or(u64::MAX).min(next_full_evaluation);
+            if due > frame.ts_us {
+                break;
+            }
+            replayer.clock.advance(due);
+            if due >= next_full_evaluation {
+                pending.mark_all(engine.registry());
+                next_full_evaluation = due + us(intervals.strategy_loop());
+            }
+            next_evaluation = None;
+            if !pending.is_empty() {
+                evaluations += 1;
+                // 為替レートが取れない間は評価待ちのまま再試行する
+                if !engine.evaluate_pending(&mut pending).await {
+                    next_evaluation = Some(due + us(intervals.fx_retry()));
+                }
+            }
+        }
+
+        if let ReplaySpeed::Realtime(factor) = speed {
+            let offset_us = frame.ts_us.saturating_sub(replayer.stats.first_us.unwrap_or(frame.ts_us));
+            sleep_until(started + Duration::from_micros(offset_us).div_f64(factor)).await;
+        }
+        replayer.apply(&frame);
+
+        // 更新通知を評価待ちに加え、最初の更新から debounce の間に届いたものをまとめて評価する
+        loop {
+            match updates.try_recv() {
+                // ウォームアップ中の更新は捨てる (ライブでも戦略ループの開始前の通知は受け取らない)
+                Ok(_) | Err(TryRecvError::Lagged(_)) if frame.ts_us < warmup_end => {}
+                Ok(update) => pending.mark_update(&update, engine.index(), engine.registry()),
+                Err(TryRecvError::Lagged(_)) => pending.mark_all(engine.registry()),
+                Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+            }
+        }
+        if !pending.is_empty() && next_evaluation.is_none() {
+            next_evaluation = Some(frame.ts_us + us(intervals.debounce()));
+        }
+    }
+
+    // 最後の debounce 中の更新を評価する
+    if let Some(due) = next_evaluation
+        && !pending.is_empty()
+    {
+        replayer.clock.advance(due);
+        evaluations += 1;
+        engine.evaluate_pending(&mut pending).await;
+    }
+
+    ReplayStats { evaluations, ..replayer.stats.clone() }
+}
+
+#[cfg(test)]
+/// GMO の記録 12 秒分。USD_JPY と BTC の現物・レバレッジの板を 500ms ごとに受け、
+/// 6s から 9s の間だけ現物の Ask がレバレッジの Bid を下回る
+pub(crate) fn gmo_fixture(start_us: u64) -> Vec<RecordedFrame> {
+    let frame = |offset_ms: u64, dir: FrameDirection, text: String| RecordedFrame {
+        ts_us: start_us + offset_ms * 1_000,
+        venue: Exchange::Gmo,
+        dir,
+        conn: 1,
+        text,
+    };
+    let book = |symbol: &str, bid: u64, ask: u64| {
+        format!(
+            r#"{{"channel":"orderbooks","symbol":"{}","bids":[{{"price":"{}","size":"0.5"}}],"asks":[{{"price":"{}","size":"0.5"}}]}}"#,
+            symbol, bid, ask
+        )
+    };
+    let mut frames = vec![frame(0, FrameDirection::Connect, "wss://api.coin.z.com/ws/public/v1".to_string())];
+    for offset_ms in (0..12_000).step_by(500) {
+        let crossed = (6_000..9_000).contains(&offset_ms);
+        let margin_bid = if crossed { 10_150_000 } else { 9_990_000 };
+        frames.push(frame(offset_ms, FrameDirection::In, r#"{"channel":"ticker","symbol":"USD_JPY","bid":"150.00","ask":"150.01"}"#.to_string()));
+        frames.push(frame(offset_ms + 1, FrameDirection::In, book("BTC", 9_990_000, 10_000_000)));
+        frames.push(frame(offset_ms + 2, FrameDirection::In, book("BTC_JPY", margin_bid, margin_bid + 10_000)));
+        frames.push(frame(offset_ms + 3, FrameDirection::Out, "{}".to_string()));
+    }
+    frames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asset::{AssetConfig, AssetRegistry};
+    use crate::collector::gmo::GmoCollector;
+    use crate::config::Config;
+    use crate::executor::{PaperConfig, PaperExecutor};
+
+    /// gmo_fixture を新しいストアと仮想時計でリプレイし、執行に回した機会を (開始からの ms, 経路, 数量, 推定利益) で返す
+    async fn replay_signals(name: &str, start_us: u64) -> Vec<String> {
+        let config = Config::default();
+        let registry = Arc::new(AssetRegistry::new(AssetConfig::defaults()));
+        let clock = Arc::new(VirtualClock::default());
+        let store = MarketStore::new().with_clock(clock.clone());
+        let journal_path = std::env::temp_dir()
+            .join(format!("replay_{}_{}.jsonl", name, std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        let executor = PaperExecutor::new(store.clone(), PaperConfig { journal_path, ..PaperConfig::default() }).unwrap();
+        let collectors: Vec<Box<dyn Collector>> = vec![Box::new(GmoCollector::new(registry.clone()))];
+        let mut replayer = FeedReplayer::new(store.clone(), clock, collectors);
+
+        let mut signals = Vec::new();
+        {
+            let mut engine = StrategyEngine::new(&store, &config, &registry, executor).with_on_opportunity(|opp, rank| {
+                signals.push(format!(
+                    "{} #{} {} {} {}",
+                    store.now_ms() - start_us / 1_000,
+                    rank,
+                    opp.route(),
+                    opp.quantity,
+                    opp.estimated_profit_jpy
+                ))
+            });
+            let stats = run_replay(gmo_fixture(start_us).into_iter(), &mut replayer, &mut engine, &config.intervals, ReplaySpeed::AsFastAsPossible).await;
+            assert_eq!(stats.errors, 0);
+            assert_eq!(engine.executor().summary().trades, 1);
+        }
+        signals
+    }
+
+    #[tokio::test]
+    async fn replay_is_reproducible() {
+        let start_us = 1_700_000_000_000_000;
+        let first = replay_signals("first", start_us).await;
+        assert_eq!(first.len(), 1, "{:?}", first);
+        assert_eq!(replay_signals("second", start_us).await, first);
+
+        // 時計はストアごとなので、同じプロセスで前の期間をリプレイしても同じ結果になる
+        let day_us = 86_400 * 1_000_000;
+        assert_eq!(replay_signals("earlier", start_us - day_us).await, first);
+    }
+}
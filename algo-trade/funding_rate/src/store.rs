@@ -1,14 +1,30 @@
+use crate::clock::{Clock, SystemClock};
 use dashmap::DashMap;
+use std::collections::BTreeMap;
 use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use std::fmt;
+use tokio::sync::broadcast;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// 取引所ごとの気配の最大許容経過時間 (既定値)
+const DEFAULT_MAX_AGE_MS: u64 = 5_000;
+/// FRの最大許容経過時間の既定値 (ms)
+const DEFAULT_MAX_FUNDING_AGE_MS: u64 = 300_000;
+/// 更新通知のバッファ。受信側が追いつかない場合は古い通知から捨てられる (Lagged)
+const UPDATE_CHANNEL_CAPACITY: usize = 4_096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Exchange {
+    // 設定ファイルでは小文字 ([exchanges.*] のキーと同じ) でも書ける
+    #[serde(alias = "hyperliquid")]
     Hyperliquid,
+    #[serde(alias = "bitbank")]
     Bitbank,
+    #[serde(alias = "kraken")]
     Kraken,
+    #[serde(alias = "gmo")]
     Gmo,
 }
 
@@ -24,63 +40,666 @@ pub struct SymbolData {
     pub ask: Decimal,
     pub last_price: Decimal,
     pub funding_rate: Decimal,
-    pub timestamp: u64,
+    /// 気配を受信した時刻 (UNIX ms)
+    pub timestamp_ms: u64,
+    /// 取引所側のタイムスタンプ (UNIX ms)。フィードが提供する場合のみ
+    pub exchange_timestamp_ms: Option<u64>,
+    /// FRを受信した時刻 (UNIX ms)
+    pub funding_timestamp_ms: u64,
+}
+
+impl SymbolData {
+    /// 気配の受信からの経過時間 (ms)
+    pub fn age_ms(&self, now_ms: u64) -> u64 {
+        now_ms.saturating_sub(self.timestamp_ms)
+    }
+
+    /// 取引所のタイムスタンプから受信までの遅延 (ms)
+    pub fn exchange_latency_ms(&self) -> Option<u64> {
+        self.exchange_timestamp_ms.map(|ts| self.timestamp_ms.saturating_sub(ts))
+    }
+}
+
+/// 鮮度チェック付きの気配取得に失敗した理由
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QuoteError {
+    /// まだ一度も受信していない
+    Missing,
+    /// 受信から max_age_ms 以上経過している
+    Stale { age_ms: u64, max_age_ms: u64 },
+    /// 取引所がサーキットブレーカーで停止中
+    VenueDown(String),
+    /// 片側が空、または買いと売りが交差している
+    Invalid(String),
+}
+
+impl fmt::Display for QuoteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuoteError::Missing => write!(f, "no quote"),
+            QuoteError::Stale { age_ms, max_age_ms } => write!(f, "stale quote ({}ms old, max {}ms)", age_ms, max_age_ms),
+            QuoteError::VenueDown(reason) => write!(f, "venue down: {}", reason),
+            QuoteError::Invalid(reason) => write!(f, "invalid quote: {}", reason),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum BookSide {
+    Bid,
+    Ask,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PriceLevel {
+    pub price: Decimal,
+    pub size: Decimal,
+}
+
+/// 板情報 (L2)。価格ごとの数量を保持する
+#[derive(Debug, Clone, Default)]
+pub struct OrderBook {
+    /// 価格 -> 数量 (昇順。ベストは末尾)
+    pub bids: BTreeMap<Decimal, Decimal>,
+    /// 価格 -> 数量 (昇順。ベストは先頭)
+    pub asks: BTreeMap<Decimal, Decimal>,
+    /// 取引所が付与する通番 (ある場合)
+    pub sequence: Option<u64>,
+    /// 最後に更新を受信した時刻 (UNIX ms)
+    pub timestamp_ms: u64,
+    /// 取引所側のタイムスタンプ (UNIX ms)
+    pub exchange_timestamp_ms: Option<u64>,
+}
+
+impl OrderBook {
+    /// 板全体を置き換える。timestamp_ms は受信時刻 (UNIX ms)
+    pub fn apply_snapshot(&mut self, bids: &[PriceLevel], asks: &[PriceLevel], sequence: Option<u64>, timestamp_ms: u64, exchange_ts_ms: Option<u64>) {
+        self.bids = bids.iter().filter(|l| l.size > Decimal::ZERO).map(|l| (l.price, l.size)).collect();
+        self.asks = asks.iter().filter(|l| l.size > Decimal::ZERO).map(|l| (l.price, l.size)).collect();
+        self.sequence = sequence;
+        self.timestamp_ms = timestamp_ms;
+        self.exchange_timestamp_ms = exchange_ts_ms;
+    }
+
+    /// 差分を適用する。数量ゼロの価格は削除
+    pub fn apply_level(&mut self, side: BookSide, level: PriceLevel) {
+        let book = match side {
+            BookSide::Bid => &mut self.bids,
+            BookSide::Ask => &mut self.asks,
+        };
+        if level.size.is_zero() {
+            book.remove(&level.price);
+        } else {
+            book.insert(level.price, level.size);
+        }
+    }
+
+    pub fn best_bid(&self) -> Option<PriceLevel> {
+        self.bids.iter().next_back().map(|(&price, &size)| PriceLevel { price, size })
+    }
+
+    /// 最良の買いと売り。片側が空、または買いが売り以上 (交差) ならエラー
+    pub fn top_of_book(&self) -> Result<(PriceLevel, PriceLevel), QuoteError> {
+        match (self.best_bid(), self.best_ask()) {
+            (Some(bid), Some(ask)) if bid.price < ask.price => Ok((bid, ask)),
+            (Some(bid), Some(ask)) => {
+                Err(QuoteError::Invalid(format!("crossed book (bid {} >= ask {})", bid.price, ask.price)))
+            }
+            _ => Err(QuoteError::Invalid("one-sided book".to_string())),
+        }
+    }
+
+    pub fn best_ask(&self) -> Option<PriceLevel> {
+        self.asks.iter().next().map(|(&price, &size)| PriceLevel { price, size })
+    }
+
+    pub fn mid(&self) -> Option<Decimal> {
+        Some((self.best_bid()?.price + self.best_ask()?.price) / Decimal::from(2))
+    }
+
+    /// ベストから順に価格レベルを返す (Bid は降順、Ask は昇順)
+    pub fn levels(&self, side: BookSide) -> Box<dyn Iterator<Item = PriceLevel> + '_> {
+        match side {
+            BookSide::Bid => Box::new(self.bids.iter().rev().map(|(&price, &size)| PriceLevel { price, size })),
+            BookSide::Ask => Box::new(self.asks.iter().map(|(&price, &size)| PriceLevel { price, size })),
+        }
+    }
+
+    /// ベスト価格から bps 以内にある数量の合計
+    pub fn depth_within_bps(&self, side: BookSide, bps: Decimal) -> Decimal {
+        let Some(best) = self.levels(side).next() else {
+            return Decimal::ZERO;
+        };
+        let width = best.price * bps / Decimal::from(10_000);
+        self.levels(side)
+            .take_while(|l| match side {
+                BookSide::Bid => l.price >= best.price - width,
+                BookSide::Ask => l.price <= best.price + width,
+            })
+            .map(|l| l.size)
+            .sum()
+    }
+
+    /// size だけ成行で約定させたときの平均価格。
+    /// 買いは Ask 側、売りは Bid 側を消費する。板が足りなければ None
+    pub fn vwap_for_size(&self, side: BookSide, size: Decimal) -> Option<Decimal> {
+        if size <= Decimal::ZERO {
+            return None;
+        }
+        let mut remaining = size;
+        let mut notional = Decimal::ZERO;
+        for level in self.levels(side) {
+            let take = remaining.min(level.size);
+            notional += take * level.price;
+            remaining -= take;
+            if remaining.is_zero() {
+                return Some(notional / size);
+            }
+        }
+        None
+    }
+}
+
+/// 取引所の稼働状況 (コレクターのサーキットブレーカーが更新する)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VenueStatus {
+    Up,
+    Down(String),
+}
+
+/// 更新された内容
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateKind {
+    Quote,
+    Book,
+    Funding,
+}
+
+/// 板の差分を反映した結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffOutcome {
+    Applied,
+    /// スナップショットがまだない (通番の欠落で板を破棄した後も含む)
+    NoSnapshot,
+    /// 通番がスナップショット・反映済みの差分以前
+    Stale,
+    /// 通番が飛んだ。板を破棄したので、次のスナップショットまで差分は反映しない
+    Gap { expected: u64, received: u64 },
+}
+
+/// MarketStore の更新通知 (キーごと)
+#[derive(Debug, Clone)]
+pub struct StoreUpdate {
+    pub exchange: Exchange,
+    pub symbol: String,
+    pub kind: UpdateKind,
+    /// WebSocket のフレームを受信した時刻 (コレクターが receiving_at で付けたもの。付けていなければストアに反映した時刻)。
+    /// 判断までの遅延の計測に使うので、メッセージの解析にかかった時間も含む
+    pub received_at: Instant,
+    /// 取引所側のタイムスタンプ (UNIX ms)。フィードが提供する場合のみ
+    pub exchange_timestamp_ms: Option<u64>,
 }
 
 #[derive(Clone)]
 pub struct MarketStore {
     // キー: (取引所, シンボル名)
     pub data: Arc<DashMap<(Exchange, String), SymbolData>>,
+    pub venues: Arc<DashMap<Exchange, VenueStatus>>,
+    /// 板情報。キーは data と同じ (取引所, シンボル名)
+    pub books: Arc<DashMap<(Exchange, String), OrderBook>>,
+    /// 取引所ごとの気配の最大許容経過時間 (ms)
+    pub max_age_ms: Arc<DashMap<Exchange, u64>>,
+    /// 取引所ごとのFRの最大許容経過時間 (ms)
+    pub max_funding_age_ms: Arc<DashMap<Exchange, u64>>,
+    updates: broadcast::Sender<StoreUpdate>,
+    /// 受信時刻と鮮度の判定に使う時計
+    clock: Arc<dyn Clock>,
+    /// このハンドルからの更新通知に付ける受信時刻 (receiving_at)
+    received_at: Option<Instant>,
 }
 
 impl MarketStore {
     pub fn new() -> Self {
+        let max_age_ms = DashMap::new();
+        // Hyperliquid の板は常時配信、国内取引所とFXは変化時のみ配信されるため長めに取る
+        max_age_ms.insert(Exchange::Hyperliquid, 2_000);
+        max_age_ms.insert(Exchange::Gmo, 10_000);
+        max_age_ms.insert(Exchange::Bitbank, 10_000);
+        max_age_ms.insert(Exchange::Kraken, 60_000);
         Self {
             data: Arc::new(DashMap::new()),
+            venues: Arc::new(DashMap::new()),
+            books: Arc::new(DashMap::new()),
+            max_age_ms: Arc::new(max_age_ms),
+            max_funding_age_ms: Arc::new(DashMap::new()),
+            updates: broadcast::channel(UPDATE_CHANNEL_CAPACITY).0,
+            clock: Arc::new(SystemClock),
+            received_at: None,
         }
     }
 
-    pub fn update_market_data(&self, exchange: Exchange, symbol: &str, bid: Decimal, ask: Decimal, last: Decimal) {
-        let timestamp = current_timestamp();
+    /// 実時間の代わりに clock を使う (リプレイ・バックテスト用)。
+    /// clone したストアは時計も共有するので、clone する前に設定する
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// フレームの受信時刻を付けたハンドル。データは共有し、このハンドルからの更新通知の received_at を
+    /// received_at にする (コレクターがフレームを受け取った直後に作り、メッセージの処理に使う)
+    pub fn receiving_at(&self, received_at: Instant) -> Self {
+        Self { received_at: Some(received_at), ..self.clone() }
+    }
+
+    pub fn clock(&self) -> Arc<dyn Clock> {
+        self.clock.clone()
+    }
+
+    /// このストアの時計での現在時刻 (UNIX ms)
+    pub fn now_ms(&self) -> u64 {
+        self.clock.now_ms()
+    }
+
+    /// 気配・板・FRの更新通知を購読する (最終約定価格の更新は通知しない)
+    pub fn subscribe(&self) -> broadcast::Receiver<StoreUpdate> {
+        self.updates.subscribe()
+    }
+
+    fn notify(&self, exchange: Exchange, symbol: &str, kind: UpdateKind, exchange_ts_ms: Option<u64>) {
+        // 購読者がいない場合は送信に失敗するだけなので無視する
+        let _ = self.updates.send(StoreUpdate {
+            exchange,
+            symbol: symbol.to_string(),
+            kind,
+            received_at: self.received_at.unwrap_or_else(Instant::now),
+            exchange_timestamp_ms: exchange_ts_ms,
+        });
+    }
+
+    pub fn set_max_age(&self, exchange: Exchange, max_age: Duration) {
+        self.max_age_ms.insert(exchange, max_age.as_millis() as u64);
+    }
+
+    pub fn max_age(&self, exchange: Exchange) -> Duration {
+        Duration::from_millis(self.max_age_ms.get(&exchange).map(|v| *v).unwrap_or(DEFAULT_MAX_AGE_MS))
+    }
+
+    pub fn set_max_funding_age(&self, exchange: Exchange, max_age: Duration) {
+        self.max_funding_age_ms.insert(exchange, max_age.as_millis() as u64);
+    }
+
+    pub fn max_funding_age(&self, exchange: Exchange) -> Duration {
+        Duration::from_millis(self.max_funding_age_ms.get(&exchange).map(|v| *v).unwrap_or(DEFAULT_MAX_FUNDING_AGE_MS))
+    }
+
+    pub fn set_venue_status(&self, exchange: Exchange, status: VenueStatus) {
+        self.venues.insert(exchange, status);
+    }
+
+    /// 未登録の取引所は稼働中とみなす
+    pub fn venue_status(&self, exchange: Exchange) -> VenueStatus {
+        self.venues.get(&exchange).map(|s| s.clone()).unwrap_or(VenueStatus::Up)
+    }
+
+    pub fn is_venue_up(&self, exchange: Exchange) -> bool {
+        self.venue_status(exchange) == VenueStatus::Up
+    }
+
+    /// 気配を更新する。exchange_ts_ms はフィードに含まれる取引所側の時刻 (UNIX ms)。
+    /// 板を受信しているシンボルは板だけを最良気配の出どころとし、ここでは最終価格だけを更新する
+    pub fn update_market_data(&self, exchange: Exchange, symbol: &str, bid: Decimal, ask: Decimal, last: Decimal, exchange_ts_ms: Option<u64>) {
+        if self.books.contains_key(&(exchange, symbol.to_string())) {
+            self.update_last_trade(exchange, symbol, last);
+            return;
+        }
+        let timestamp_ms = self.now_ms();
         self.data
             .entry((exchange, symbol.to_string()))
             .and_modify(|d| {
                 d.bid = bid;
                 d.ask = ask;
                 d.last_price = last;
-                d.timestamp = timestamp;
+                d.timestamp_ms = timestamp_ms;
+                d.exchange_timestamp_ms = exchange_ts_ms;
+            })
+            .or_insert_with(|| SymbolData {
+                bid, ask, last_price: last, timestamp_ms, exchange_timestamp_ms: exchange_ts_ms, ..Default::default()
+            });
+        self.notify(exchange, symbol, UpdateKind::Quote, exchange_ts_ms);
+    }
+
+    /// 板のスナップショットを反映し、最良気配も更新する
+    pub fn update_order_book(&self, exchange: Exchange, symbol: &str, bids: &[PriceLevel], asks: &[PriceLevel], sequence: Option<u64>, exchange_ts_ms: Option<u64>) {
+        let key = (exchange, symbol.to_string());
+        let book = {
+            let mut book = self.books.entry(key).or_default();
+            book.apply_snapshot(bids, asks, sequence, self.now_ms(), exchange_ts_ms);
+            book.clone()
+        };
+        self.sync_top_of_book(exchange, symbol, &book);
+        self.notify(exchange, symbol, UpdateKind::Book, exchange_ts_ms);
+    }
+
+    /// 板の差分を反映する。
+    /// スナップショット未受信、または通番がスナップショット以前の差分は捨てる。
+    /// 通番が飛んだら板を破棄して気配を無効にし、次のスナップショット (update_order_book) を待つ
+    pub fn apply_order_book_diff(&self, exchange: Exchange, symbol: &str, levels: &[(BookSide, PriceLevel)], sequence: Option<u64>, exchange_ts_ms: Option<u64>) -> DiffOutcome {
+        let key = (exchange, symbol.to_string());
+        let book = {
+            let Some(mut book) = self.books.get_mut(&key) else {
+                return DiffOutcome::NoSnapshot;
+            };
+            if let (Some(seq), Some(current)) = (sequence, book.sequence) {
+                if seq <= current {
+                    return DiffOutcome::Stale;
+                }
+                if seq > current + 1 {
+                    drop(book);
+                    self.books.remove(&key);
+                    let empty = OrderBook { timestamp_ms: self.now_ms(), exchange_timestamp_ms: exchange_ts_ms, ..Default::default() };
+                    self.sync_top_of_book(exchange, symbol, &empty);
+                    self.notify(exchange, symbol, UpdateKind::Book, exchange_ts_ms);
+                    return DiffOutcome::Gap { expected: current + 1, received: seq };
+                }
+            }
+            for &(side, level) in levels {
+                book.apply_level(side, level);
+            }
+            if sequence.is_some() {
+                book.sequence = sequence;
+            }
+            book.timestamp_ms = self.now_ms();
+            book.exchange_timestamp_ms = exchange_ts_ms.or(book.exchange_timestamp_ms);
+            book.clone()
+        };
+        self.sync_top_of_book(exchange, symbol, &book);
+        self.notify(exchange, symbol, UpdateKind::Book, book.exchange_timestamp_ms);
+        DiffOutcome::Applied
+    }
+
+    /// 板の最良気配を SymbolData に反映 (last_price は ticker・約定があればそちらを優先)。
+    /// 片側が空・交差した板では前の気配を残さず bid/ask を 0 (無効) にする
+    fn sync_top_of_book(&self, exchange: Exchange, symbol: &str, book: &OrderBook) {
+        let (bid, ask) = match book.top_of_book() {
+            Ok((bid, ask)) => (bid.price, ask.price),
+            Err(_) => (Decimal::ZERO, Decimal::ZERO),
+        };
+        let mid = (bid + ask) / Decimal::from(2);
+        self.data
+            .entry((exchange, symbol.to_string()))
+            .and_modify(|d| {
+                d.bid = bid;
+                d.ask = ask;
+                if d.last_price.is_zero() {
+                    d.last_price = mid;
+                }
+                d.timestamp_ms = book.timestamp_ms;
+                d.exchange_timestamp_ms = book.exchange_timestamp_ms;
             })
             .or_insert_with(|| SymbolData {
-                bid, ask, last_price: last, timestamp, ..Default::default()
+                bid,
+                ask,
+                last_price: mid,
+                timestamp_ms: book.timestamp_ms,
+                exchange_timestamp_ms: book.exchange_timestamp_ms,
+                ..Default::default()
             });
     }
 
+    pub fn get_order_book(&self, exchange: Exchange, symbol: &str) -> Option<OrderBook> {
+        self.books.get(&(exchange, symbol.to_string())).map(|b| b.clone())
+    }
+
+    /// 板の最良の買い・売り (価格と数量)。板を受信していない側は None
+    pub fn best_levels(&self, exchange: Exchange, symbol: &str) -> (Option<PriceLevel>, Option<PriceLevel>) {
+        self.books.get(&(exchange, symbol.to_string())).map(|b| (b.best_bid(), b.best_ask())).unwrap_or((None, None))
+    }
+
+    /// 鮮度チェック付きの板取得 (判定基準は get_fresh_market_data と同じ)。
+    /// 片側が空・交差した板もエラーにする
+    pub fn get_fresh_order_book(&self, exchange: Exchange, symbol: &str) -> Result<OrderBook, QuoteError> {
+        let book = self.get_order_book(exchange, symbol).ok_or(QuoteError::Missing)?;
+        self.check_freshness(exchange, book.timestamp_ms, self.max_age(exchange))?;
+        book.top_of_book()?;
+        Ok(book)
+    }
+
+    /// 約定履歴から最終約定価格を更新する。気配の鮮度には影響させない
+    pub fn update_last_trade(&self, exchange: Exchange, symbol: &str, price: Decimal) {
+        self.data
+            .entry((exchange, symbol.to_string()))
+            .and_modify(|d| d.last_price = price)
+            .or_insert_with(|| SymbolData {
+                last_price: price, ..Default::default()
+            });
+    }
+
+    /// FRを更新する。気配の鮮度には影響させない
     pub fn update_funding_rate(&self, exchange: Exchange, symbol: &str, funding: Decimal) {
-        let timestamp = current_timestamp();
+        let funding_timestamp_ms = self.now_ms();
         self.data
             .entry((exchange, symbol.to_string()))
             .and_modify(|d| {
                 d.funding_rate = funding;
-                d.timestamp = timestamp;
+                d.funding_timestamp_ms = funding_timestamp_ms;
             })
             .or_insert_with(|| SymbolData {
-                funding_rate: funding, timestamp, ..Default::default()
+                funding_rate: funding, funding_timestamp_ms, ..Default::default()
             });
+        self.notify(exchange, symbol, UpdateKind::Funding, None);
     }
 
     pub fn get_symbol_data(&self, exchange: Exchange, symbol: &str) -> Option<SymbolData> {
         self.data.get(&(exchange, symbol.to_string())).map(|entry| entry.clone())
     }
 
-    pub fn get_fx_rate(&self, symbol: &str) -> Option<Decimal> {
-        self.get_symbol_data(Exchange::Kraken, symbol).map(|d| d.last_price)
-    }
-
     pub fn get_market_data(&self, exchange: Exchange, symbol: &str) -> Option<SymbolData> {
         self.get_symbol_data(exchange, symbol)
     }
+
+    /// 鮮度チェック付きの気配取得。
+    /// 未受信、取引所の停止中、max_age を超えて古い場合、片側が無い・買いが売りを上回る場合はエラーを返す
+    pub fn get_fresh_market_data(&self, exchange: Exchange, symbol: &str) -> Result<SymbolData, QuoteError> {
+        let data = self.get_symbol_data(exchange, symbol).ok_or(QuoteError::Missing)?;
+        // FRのみ受信していて気配が無い場合
+        if data.timestamp_ms == 0 {
+            return Err(QuoteError::Missing);
+        }
+        self.check_freshness(exchange, data.timestamp_ms, self.max_age(exchange))?;
+        if data.bid <= Decimal::ZERO || data.ask <= Decimal::ZERO {
+            return Err(QuoteError::Invalid("one-sided quote".to_string()));
+        }
+        // 為替・ステーブルコインは bid == ask の気配があるので、交差だけを弾く
+        if data.bid > data.ask {
+            return Err(QuoteError::Invalid(format!("crossed quote (bid {} > ask {})", data.bid, data.ask)));
+        }
+        Ok(data)
+    }
+
+    /// 鮮度チェック付きのFR取得。
+    /// 未受信、取引所の停止中、max_funding_age を超えて古い場合はエラーを返す
+    pub fn get_fresh_funding_rate(&self, exchange: Exchange, symbol: &str) -> Result<Decimal, QuoteError> {
+        let data = self.get_symbol_data(exchange, symbol).ok_or(QuoteError::Missing)?;
+        if data.funding_timestamp_ms == 0 {
+            return Err(QuoteError::Missing);
+        }
+        self.check_freshness(exchange, data.funding_timestamp_ms, self.max_funding_age(exchange))?;
+        Ok(data.funding_rate)
+    }
+
+    /// 取引所が稼働中で、timestamp_ms (受信時刻) から max_age 以内か
+    fn check_freshness(&self, exchange: Exchange, timestamp_ms: u64, max_age: Duration) -> Result<(), QuoteError> {
+        if let VenueStatus::Down(reason) = self.venue_status(exchange) {
+            return Err(QuoteError::VenueDown(reason));
+        }
+        let max_age_ms = max_age.as_millis() as u64;
+        let age_ms = self.now_ms().saturating_sub(timestamp_ms);
+        if age_ms > max_age_ms {
+            return Err(QuoteError::Stale { age_ms, max_age_ms });
+        }
+        Ok(())
+    }
 }
 
-fn current_timestamp() -> u64 {
-    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
-}
\ No newline at end of file
+impl Default for MarketStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 実時間の現在時刻 (UNIX ms)。
+/// 気配の鮮度など、リプレイで仮想時刻に従うべき判定には MarketStore::now_ms を使う
+pub fn current_timestamp_ms() -> u64 {
+    current_timestamp_us() / 1_000
+}
+
+/// 実時間の現在時刻 (UNIX µs)
+pub fn current_timestamp_us() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_micros() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::VirtualClock;
+
+    const BTC: &str = "BTC";
+
+    fn level(price: i64, size: i64) -> PriceLevel {
+        PriceLevel { price: Decimal::from(price), size: Decimal::from(size) }
+    }
+
+    /// 1,000,000 ms から始まる仮想時計を持つストア
+    fn store() -> (MarketStore, Arc<VirtualClock>) {
+        let clock = Arc::new(VirtualClock::new(1_000_000_000));
+        let store = MarketStore::new().with_clock(clock.clone());
+        (store, clock)
+    }
+
+    #[test]
+    fn diff_emptying_one_side_invalidates_quote() {
+        let (store, _) = store();
+        store.update_order_book(Exchange::Bitbank, BTC, &[level(100, 1)], &[level(101, 1)], Some(1), None);
+        assert_eq!(store.get_fresh_market_data(Exchange::Bitbank, BTC).unwrap().bid, Decimal::from(100));
+
+        let outcome = store.apply_order_book_diff(Exchange::Bitbank, BTC, &[(BookSide::Bid, level(100, 0))], Some(2), None);
+        assert_eq!(outcome, DiffOutcome::Applied);
+        assert!(matches!(store.get_fresh_market_data(Exchange::Bitbank, BTC), Err(QuoteError::Invalid(_))));
+        assert!(matches!(store.get_fresh_order_book(Exchange::Bitbank, BTC), Err(QuoteError::Invalid(_))));
+    }
+
+    /// bid 100x1, 99x2, 90x5 / ask 101x1, 102x2
+    fn ladder() -> OrderBook {
+        let mut book = OrderBook::default();
+        book.apply_snapshot(&[level(100, 1), level(99, 2), level(90, 5)], &[level(101, 1), level(102, 2)], None, 0, None);
+        book
+    }
+
+    #[test]
+    fn depth_within_bps_stops_at_the_band() {
+        let book = ladder();
+        // 100 bps: bid は 99 まで (境界を含む)、ask は 102.01 まで
+        assert_eq!(book.depth_within_bps(BookSide::Bid, Decimal::from(100)), Decimal::from(3));
+        assert_eq!(book.depth_within_bps(BookSide::Ask, Decimal::from(100)), Decimal::from(3));
+        // 50 bps: ask は 101.505 まで
+        assert_eq!(book.depth_within_bps(BookSide::Ask, Decimal::from(50)), Decimal::from(1));
+        assert_eq!(book.depth_within_bps(BookSide::Bid, Decimal::ZERO), Decimal::from(1));
+        assert_eq!(OrderBook::default().depth_within_bps(BookSide::Bid, Decimal::from(100)), Decimal::ZERO);
+    }
+
+    #[test]
+    fn vwap_consumes_levels_partially() {
+        let book = ladder();
+        assert_eq!(book.vwap_for_size(BookSide::Ask, Decimal::ONE), Some(Decimal::from(101)));
+        // 101x1 + 102x0.5
+        assert_eq!(book.vwap_for_size(BookSide::Ask, Decimal::new(15, 1)), Some(Decimal::from(152) / Decimal::new(15, 1)));
+        // 100x1 + 99x2 + 90x1
+        assert_eq!(book.vwap_for_size(BookSide::Bid, Decimal::from(4)), Some(Decimal::new(970, 1)));
+    }
+
+    #[test]
+    fn vwap_without_enough_depth_is_none() {
+        let book = ladder();
+        assert_eq!(book.vwap_for_size(BookSide::Ask, Decimal::from(3)), Some(Decimal::from(305) / Decimal::from(3)));
+        assert_eq!(book.vwap_for_size(BookSide::Ask, Decimal::new(301, 2)), None);
+        assert_eq!(book.vwap_for_size(BookSide::Bid, Decimal::from(9)), None);
+        assert_eq!(book.vwap_for_size(BookSide::Bid, Decimal::ZERO), None);
+    }
+
+    #[test]
+    fn sequence_gap_drops_the_book_until_the_next_snapshot() {
+        let (store, _) = store();
+        let bid = |price| [(BookSide::Bid, level(price, 1))];
+        assert_eq!(store.apply_order_book_diff(Exchange::Bitbank, BTC, &bid(99), Some(1), None), DiffOutcome::NoSnapshot);
+
+        store.update_order_book(Exchange::Bitbank, BTC, &[level(100, 1)], &[level(101, 1)], Some(10), None);
+        assert_eq!(store.apply_order_book_diff(Exchange::Bitbank, BTC, &bid(99), Some(10), None), DiffOutcome::Stale);
+        assert_eq!(store.apply_order_book_diff(Exchange::Bitbank, BTC, &bid(99), Some(11), None), DiffOutcome::Applied);
+
+        let gap = store.apply_order_book_diff(Exchange::Bitbank, BTC, &bid(98), Some(13), None);
+        assert_eq!(gap, DiffOutcome::Gap { expected: 12, received: 13 });
+        assert_eq!(store.get_fresh_order_book(Exchange::Bitbank, BTC).unwrap_err(), QuoteError::Missing);
+        assert!(matches!(store.get_fresh_market_data(Exchange::Bitbank, BTC), Err(QuoteError::Invalid(_))));
+        assert_eq!(store.apply_order_book_diff(Exchange::Bitbank, BTC, &bid(98), Some(14), None), DiffOutcome::NoSnapshot);
+
+        store.update_order_book(Exchange::Bitbank, BTC, &[level(100, 1)], &[level(101, 1)], Some(20), None);
+        assert_eq!(store.apply_order_book_diff(Exchange::Bitbank, BTC, &bid(100), Some(21), None), DiffOutcome::Applied);
+        assert_eq!(store.get_fresh_market_data(Exchange::Bitbank, BTC).unwrap().bid, Decimal::from(100));
+    }
+
+    #[test]
+    fn crossed_book_is_rejected() {
+        let (store, _) = store();
+        store.update_order_book(Exchange::Gmo, BTC, &[level(101, 1)], &[level(101, 1)], None, None);
+        assert!(matches!(store.get_fresh_order_book(Exchange::Gmo, BTC), Err(QuoteError::Invalid(_))));
+        assert!(matches!(store.get_fresh_market_data(Exchange::Gmo, BTC), Err(QuoteError::Invalid(_))));
+
+        store.update_order_book(Exchange::Gmo, BTC, &[level(100, 1)], &[level(101, 1)], None, None);
+        assert!(store.get_fresh_order_book(Exchange::Gmo, BTC).is_ok());
+        assert!(store.get_fresh_market_data(Exchange::Gmo, BTC).is_ok());
+    }
+
+    #[test]
+    fn ticker_does_not_override_book_quote() {
+        let (store, _) = store();
+        let (bid, ask) = (Decimal::from(90), Decimal::from(95));
+        store.update_market_data(Exchange::Bitbank, BTC, bid, ask, bid, None);
+        assert_eq!(store.get_fresh_market_data(Exchange::Bitbank, BTC).unwrap().bid, bid);
+
+        store.update_order_book(Exchange::Bitbank, BTC, &[level(100, 1)], &[level(101, 1)], Some(1), None);
+        let last = Decimal::from(99);
+        store.update_market_data(Exchange::Bitbank, BTC, bid, ask, last, None);
+        let data = store.get_fresh_market_data(Exchange::Bitbank, BTC).unwrap();
+        assert_eq!((data.bid, data.ask, data.last_price), (Decimal::from(100), Decimal::from(101), last));
+    }
+
+    #[test]
+    fn quote_and_book_go_stale_on_the_store_clock() {
+        let (store, clock) = store();
+        store.set_max_age(Exchange::Gmo, Duration::from_millis(1_000));
+        store.update_order_book(Exchange::Gmo, BTC, &[level(100, 1)], &[level(101, 1)], None, None);
+        clock.advance(clock.now_us() + 1_000_000);
+        assert!(store.get_fresh_order_book(Exchange::Gmo, BTC).is_ok());
+
+        clock.advance(clock.now_us() + 1_000);
+        let stale = QuoteError::Stale { age_ms: 1_001, max_age_ms: 1_000 };
+        assert_eq!(store.get_fresh_order_book(Exchange::Gmo, BTC).unwrap_err(), stale);
+        assert_eq!(store.get_fresh_market_data(Exchange::Gmo, BTC).unwrap_err(), stale);
+    }
+
+    #[test]
+    fn funding_rate_goes_stale_after_max_funding_age() {
+        let (store, clock) = store();
+        assert_eq!(store.get_fresh_funding_rate(Exchange::Hyperliquid, BTC), Err(QuoteError::Missing));
+
+        store.set_max_funding_age(Exchange::Hyperliquid, Duration::from_millis(60_000));
+        let rate = Decimal::new(125, 7);
+        store.update_funding_rate(Exchange::Hyperliquid, BTC, rate);
+        clock.advance(clock.now_us() + 60_000_000);
+        assert_eq!(store.get_fresh_funding_rate(Exchange::Hyperliquid, BTC), Ok(rate));
+
+        clock.advance(clock.now_us() + 1_000);
+        let stale = QuoteError::Stale { age_ms: 60_001, max_age_ms: 60_000 };
+        assert_eq!(store.get_fresh_funding_rate(Exchange::Hyperliquid, BTC), Err(stale));
+    }
+}
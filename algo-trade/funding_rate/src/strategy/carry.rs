@@ -0,0 +1,380 @@
+use super::registry::{MarketSnapshot, Positions, Signal, Strategy};
+use super::strategy::{Asset, Currency, FxRates, InstrumentType, MarketData};
+use crate::store::Exchange;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// キャッシュ・アンド・キャリー (Perp ショート + 現物ロング) のパラメータ (設定ファイルの [strategies.carry])
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CarryParams {
+    pub enabled: bool,
+    /// FRを受け取り続ける想定の保有期間 (時間)
+    pub horizon_hours: Decimal,
+    /// 建てるのに必要なFRの年率 (%)
+    pub min_funding_apr_pct: Decimal,
+    /// FRが min_funding_apr_pct 以上で続いている必要がある時間 (分)
+    pub persistence_minutes: u64,
+    /// 建てるのに必要な期待利益率 (%, 往復手数料・ベーシス・為替リスク控除後)
+    pub entry_edge_pct: Decimal,
+    /// 保有中、残りの期待利益率 (%) がこれを下回ったら解消する
+    pub exit_edge_pct: Decimal,
+    /// Perp と現物の建値通貨が異なる場合に見込む為替の変動 (%)
+    pub fx_risk_pct: Decimal,
+    /// 1銘柄あたりの現物の想定元本の上限 (JPY)
+    pub max_notional_jpy: Decimal,
+}
+
+impl Default for CarryParams {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            horizon_hours: Decimal::from(72),
+            min_funding_apr_pct: Decimal::from(10),
+            persistence_minutes: 60,
+            entry_edge_pct: Decimal::from_str("0.1").unwrap(),
+            exit_edge_pct: Decimal::ZERO,
+            fx_risk_pct: Decimal::from_str("0.3").unwrap(),
+            max_notional_jpy: Decimal::from(100_000),
+        }
+    }
+}
+
+/// ポジションの片足
+#[derive(Debug, Clone)]
+pub struct CarryLeg {
+    pub exchange: Exchange,
+    pub instrument: InstrumentType,
+    pub currency: Currency,
+    /// 建値通貨での気配 (Perp は売る側の Bid、現物は買う側の Ask)
+    pub price: Decimal,
+    /// 同じ気配の JPY 換算
+    pub price_jpy: Decimal,
+}
+
+/// Perp と現物の組み合わせ1つ分の損益の見込み (金額はすべて quantity 分の合計, JPY)
+#[derive(Debug, Clone)]
+pub struct CarryEstimate {
+    pub asset: Asset,
+    pub perp: CarryLeg,
+    pub hedge: CarryLeg,
+    pub quantity: Decimal,
+    pub notional_jpy: Decimal,
+    pub funding_apr_pct: Decimal,
+    /// horizon_hours 中に受け取るFR
+    pub funding_income_jpy: Decimal,
+    /// 建てる・解消するときの両足の手数料
+    pub fees_jpy: Decimal,
+    /// Perp と現物の価格差。解消時に 0 に収束する前提で損益に含める
+    pub basis_jpy: Decimal,
+    pub fx_risk_jpy: Decimal,
+    pub net_edge_jpy: Decimal,
+    pub net_edge_pct: Decimal,
+}
+
+/// 銘柄ごとの目標ポジション。quantity が 0 なら持たない (解消する)
+#[derive(Debug, Clone)]
+pub struct PositionTarget {
+    pub asset: Asset,
+    /// Perp を quantity だけショートし、現物を quantity だけロングする
+    pub quantity: Decimal,
+    pub perp_exchange: Exchange,
+    pub hedge_exchange: Exchange,
+    pub hedge_instrument: InstrumentType,
+    pub estimate: CarryEstimate,
+    pub reason: String,
+}
+
+/// Perp のFRが継続してプラスの銘柄を探し、現物でヘッジしてFRを受け取るポジションの目標を出す。
+/// 一度きりの機会ではなく、銘柄ごとに目標ポジションを保持し、建てる・解消するときだけ変化を返す。
+/// 目標はシグナルとして出すだけで発注はしない (エンジンはログに出す)。
+/// そのため保有中かどうかは実際の建玉 (Positions) ではなく、自分が出した目標で判断する
+#[derive(Debug, Default)]
+pub struct CashAndCarry {
+    params: CarryParams,
+    targets: HashMap<Asset, PositionTarget>,
+    /// FRが min_funding_apr_pct 以上になった時刻 (UNIX ms)。下回ったら消す
+    funding_since: HashMap<Asset, u64>,
+}
+
+impl CashAndCarry {
+    pub fn new(params: CarryParams) -> Self {
+        Self { params, ..Default::default() }
+    }
+
+    /// 保有中 (quantity > 0) の目標ポジション
+    pub fn targets(&self) -> impl Iterator<Item = &PositionTarget> {
+        self.targets.values()
+    }
+
+    /// 1銘柄分の気配から目標ポジションを見直す。目標が変わった場合だけ返す
+    pub fn evaluate(&mut self, asset: Asset, market_data: &[MarketData], fx: &FxRates, now_ms: u64) -> Option<PositionTarget> {
+        let perp = market_data
+            .iter()
+            .find(|d| d.asset == asset && d.instrument == InstrumentType::Perp && d.funding.is_some());
+
+        // FRの継続時間
+        if let Some(funding) = perp.and_then(|p| p.funding.as_ref()) {
+            if funding.annualized_pct() >= self.params.min_funding_apr_pct {
+                self.funding_since.entry(asset).or_insert(now_ms);
+            } else {
+                self.funding_since.remove(&asset);
+            }
+        }
+
+        // 保有中は FR が取れなくても (収入 0 として) 解消を判断する
+        if let Some(current) = self.targets.get(&asset).cloned() {
+            let perp = market_data.iter().find(|d| {
+                d.asset == asset && d.exchange == current.perp_exchange && d.instrument == InstrumentType::Perp
+            })?;
+            return self.review(current, perp, market_data, fx, now_ms);
+        }
+        let perp = perp?;
+
+        let persisted_ms = self.funding_since.get(&asset).map(|since| now_ms.saturating_sub(*since))?;
+        if persisted_ms < self.params.persistence_minutes * 60_000 {
+            return None;
+        }
+
+        // 現物の候補のうち期待利益が最大のもの
+        let best = market_data
+            .iter()
+            .filter(|d| d.asset == asset && d.instrument == InstrumentType::Spot)
+            .filter_map(|hedge| self.estimate_entry(perp, hedge, fx, now_ms))
+            .max_by(|a, b| a.net_edge_jpy.cmp(&b.net_edge_jpy))?;
+        if best.net_edge_pct < self.params.entry_edge_pct {
+            return None;
+        }
+
+        let target = PositionTarget {
+            asset,
+            quantity: best.quantity,
+            perp_exchange: best.perp.exchange,
+            hedge_exchange: best.hedge.exchange,
+            hedge_instrument: best.hedge.instrument,
+            reason: format!(
+                "funding {:.2}% APR for {} min, edge {:.4}% over {}h",
+                best.funding_apr_pct,
+                persisted_ms / 60_000,
+                best.net_edge_pct,
+                self.params.horizon_hours
+            ),
+            estimate: best,
+        };
+        self.targets.insert(asset, target.clone());
+        Some(target)
+    }
+
+    /// 保有中のポジションを続けるか判断する。
+    /// 建てたときの手数料は払い済みなので、残りのFR・ベーシスの収束・為替リスクと解消時の手数料だけで比べる。
+    /// FR が取れない (未受信・古い) 間は FR 収入を 0 とみなす
+    fn review(
+        &mut self,
+        current: PositionTarget,
+        perp: &MarketData,
+        market_data: &[MarketData],
+        fx: &FxRates,
+        now_ms: u64,
+    ) -> Option<PositionTarget> {
+        let hedge = market_data
+            .iter()
+            .find(|d| d.exchange == current.hedge_exchange && d.instrument == current.hedge_instrument)?;
+        // 解消は Perp を Ask で買い戻し、現物を Bid で売る
+        let perp_ask_jpy = perp.ask * fx.for_buy(perp.currency)?;
+        let hedge_bid_jpy = hedge.bid * fx.for_sell(hedge.currency)?;
+        if perp_ask_jpy <= Decimal::ZERO || hedge_bid_jpy <= Decimal::ZERO {
+            return None;
+        }
+
+        let quantity = current.quantity;
+        let notional_jpy = quantity * hedge_bid_jpy;
+        let funding_income = perp
+            .funding
+            .as_ref()
+            .map_or(Decimal::ZERO, |f| quantity * perp_ask_jpy * f.expected_over(now_ms, self.params.horizon_hours));
+        // 今解消すると失う (待てば収束で得られる) ベーシス
+        let basis = quantity * (perp_ask_jpy - hedge_bid_jpy);
+        let fx_risk = self.fx_risk(perp.currency, hedge.currency, notional_jpy);
+        let exit_fees = quantity * (perp_ask_jpy * perp.taker_fee + hedge_bid_jpy * hedge.taker_fee);
+        let hold_edge_pct = (funding_income + basis - fx_risk - exit_fees) / notional_jpy * Decimal::from(100);
+        if hold_edge_pct >= self.params.exit_edge_pct {
+            return None;
+        }
+
+        self.targets.remove(&current.asset);
+        let funding = match &perp.funding {
+            Some(f) => format!("funding {:.2}% APR", f.annualized_pct()),
+            None => "funding unavailable".to_string(),
+        };
+        Some(PositionTarget {
+            quantity: Decimal::ZERO,
+            reason: format!(
+                "{}, remaining edge {:.4}% below exit threshold {}%",
+                funding,
+                hold_edge_pct,
+                self.params.exit_edge_pct
+            ),
+            ..current
+        })
+    }
+
+    /// Perp を Bid で売り、現物を Ask で買って horizon_hours 保有し、同じ手数料で解消した場合の見込み
+    fn estimate_entry(&self, perp: &MarketData, hedge: &MarketData, fx: &FxRates, now_ms: u64) -> Option<CarryEstimate> {
+        let funding = perp.funding.as_ref()?;
+        let perp_bid_jpy = perp.bid * fx.for_sell(perp.currency)?;
+        let hedge_ask_jpy = hedge.ask * fx.for_buy(hedge.currency)?;
+        if perp_bid_jpy <= Decimal::ZERO || hedge_ask_jpy <= Decimal::ZERO {
+            return None;
+        }
+
+        let quantity = self.params.max_notional_jpy / hedge_ask_jpy;
+        let notional_jpy = quantity * hedge_ask_jpy;
+        let funding_income_jpy = quantity * perp_bid_jpy * funding.expected_over(now_ms, self.params.horizon_hours);
+        let fees_jpy = quantity * (perp_bid_jpy * perp.taker_fee + hedge_ask_jpy * hedge.taker_fee) * Decimal::from(2);
+        let basis_jpy = quantity * (perp_bid_jpy - hedge_ask_jpy);
+        let fx_risk_jpy = self.fx_risk(perp.currency, hedge.currency, notional_jpy);
+        let net_edge_jpy = funding_income_jpy + basis_jpy - fees_jpy - fx_risk_jpy;
+
+        Some(CarryEstimate {
+            asset: perp.asset,
+            perp: CarryLeg {
+                exchange: perp.exchange,
+                instrument: perp.instrument,
+                currency: perp.currency,
+                price: perp.bid,
+                price_jpy: perp_bid_jpy,
+            },
+            hedge: CarryLeg {
+                exchange: hedge.exchange,
+                instrument: hedge.instrument,
+                currency: hedge.currency,
+                price: hedge.ask,
+                price_jpy: hedge_ask_jpy,
+            },
+            quantity,
+            notional_jpy,
+            funding_apr_pct: funding.annualized_pct(),
+            funding_income_jpy,
+            fees_jpy,
+            basis_jpy,
+            fx_risk_jpy,
+            net_edge_jpy,
+            net_edge_pct: net_edge_jpy / notional_jpy * Decimal::from(100),
+        })
+    }
+
+    /// 建値通貨が異なると、Perp の証拠金と現物の間に為替のエクスポージャーが残る
+    fn fx_risk(&self, perp_currency: Currency, hedge_currency: Currency, notional_jpy: Decimal) -> Decimal {
+        if perp_currency == hedge_currency {
+            Decimal::ZERO
+        } else {
+            notional_jpy * self.params.fx_risk_pct / Decimal::from(100)
+        }
+    }
+}
+
+impl Strategy for CashAndCarry {
+    fn name(&self) -> &'static str {
+        "carry"
+    }
+
+    fn evaluate(&mut self, snapshot: &MarketSnapshot, _positions: &Positions) -> Vec<Signal> {
+        CashAndCarry::evaluate(self, snapshot.asset, &snapshot.markets, &snapshot.fx, snapshot.now_ms)
+            .map(|target| vec![Signal::Target(Box::new(target))])
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategy::FundingQuote;
+
+    const NOW_MS: u64 = 1_700_000_000_000;
+
+    /// JPY 建て、気配 ¥10,000、Taker手数料 0.05% の Hyperliquid Perp と GMO 現物
+    fn markets(funding_rate: Decimal) -> Vec<MarketData> {
+        let market = |exchange, instrument, funding| MarketData {
+            exchange,
+            asset: Asset::new("BTC"),
+            instrument,
+            currency: Currency::JPY,
+            ask: Decimal::from(10_000),
+            bid: Decimal::from(10_000),
+            funding,
+            taker_fee: Decimal::from_str("0.0005").unwrap(),
+            daily_rollover_fee: Decimal::ZERO,
+            book: None,
+        };
+        let funding = FundingQuote::for_exchange(Exchange::Hyperliquid, funding_rate, NOW_MS);
+        vec![
+            market(Exchange::Hyperliquid, InstrumentType::Perp, funding),
+            market(Exchange::Gmo, InstrumentType::Spot, None),
+        ]
+    }
+
+    fn strategy() -> CashAndCarry {
+        CashAndCarry::new(CarryParams {
+            enabled: true,
+            horizon_hours: Decimal::ONE,
+            min_funding_apr_pct: Decimal::ZERO,
+            persistence_minutes: 0,
+            entry_edge_pct: Decimal::from_str("0.1").unwrap(),
+            exit_edge_pct: Decimal::ZERO,
+            fx_risk_pct: Decimal::ZERO,
+            max_notional_jpy: Decimal::from(100_000),
+        })
+    }
+
+    #[test]
+    fn entry_nets_round_trip_fees() {
+        let asset = Asset::new("BTC");
+        let fx = FxRates::default();
+        // 10 BTC で FR 0.3% = ¥300、往復手数料 ¥200
+        let target = strategy().evaluate(asset, &markets(Decimal::from_str("0.003").unwrap()), &fx, NOW_MS).unwrap();
+        assert_eq!(target.quantity, Decimal::from(10));
+        assert_eq!(target.estimate.fees_jpy, Decimal::from(200));
+        assert_eq!(target.estimate.net_edge_jpy, Decimal::from(100));
+
+        // FR 0.25% では手数料控除後の利益率が entry_edge_pct に届かない
+        assert!(strategy().evaluate(asset, &markets(Decimal::from_str("0.0025").unwrap()), &fx, NOW_MS).is_none());
+    }
+
+    #[test]
+    fn review_nets_exit_fees() {
+        let asset = Asset::new("BTC");
+        let fx = FxRates::default();
+        let mut carry = strategy();
+        carry.evaluate(asset, &markets(Decimal::from_str("0.003").unwrap()), &fx, NOW_MS).unwrap();
+
+        // 残りのFR ¥150 - 解消の手数料 ¥100 > 0 なので持ち続ける
+        assert!(carry.evaluate(asset, &markets(Decimal::from_str("0.0015").unwrap()), &fx, NOW_MS).is_none());
+        assert_eq!(carry.targets().count(), 1);
+
+        // 残りのFR ¥80 は解消の手数料 ¥100 に届かないので解消する
+        let exit = carry.evaluate(asset, &markets(Decimal::from_str("0.0008").unwrap()), &fx, NOW_MS).unwrap();
+        assert_eq!(exit.quantity, Decimal::ZERO);
+        assert_eq!(carry.targets().count(), 0);
+    }
+
+    #[test]
+    fn missing_funding_counts_as_no_income_while_holding() {
+        let asset = Asset::new("BTC");
+        let fx = FxRates::default();
+        let mut carry = strategy();
+        carry.evaluate(asset, &markets(Decimal::from_str("0.003").unwrap()), &fx, NOW_MS).unwrap();
+
+        // FR が古くなって取れなくても、収入 0 では解消の手数料 ¥100 を賄えないので解消する
+        let mut stale = markets(Decimal::from_str("0.003").unwrap());
+        stale[0].funding = None;
+        let exit = carry.evaluate(asset, &stale, &fx, NOW_MS).unwrap();
+        assert_eq!(exit.quantity, Decimal::ZERO);
+        assert!(exit.reason.starts_with("funding unavailable"));
+        assert_eq!(carry.targets().count(), 0);
+
+        // 保有していなければ FR なしでは建てない
+        assert!(carry.evaluate(asset, &stale, &fx, NOW_MS).is_none());
+    }
+}
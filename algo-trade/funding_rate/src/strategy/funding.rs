@@ -0,0 +1,137 @@
+use crate::store::Exchange;
+use rust_decimal::Decimal;
+
+const HOUR_MS: u64 = 3_600_000;
+const HOURS_PER_YEAR: u64 = 24 * 365;
+
+/// 取引所ごとのFRの支払い間隔 (時間)。Perp を扱わない取引所は None
+pub fn funding_interval_hours(exchange: Exchange) -> Option<u64> {
+    match exchange {
+        // Hyperliquid は毎正時に1時間分を精算する
+        Exchange::Hyperliquid => Some(1),
+        Exchange::Bitbank | Exchange::Gmo | Exchange::Kraken => None,
+    }
+}
+
+/// Perp 1銘柄分のFR。
+/// rate は1回の支払いあたりの比率で、次の支払いで適用される見込みの値 (Hyperliquid の activeAssetCtx の funding は
+/// 現在の1時間の推定値で、そのまま次の正時に精算される)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FundingQuote {
+    pub rate: Decimal,
+    pub interval_hours: u64,
+    /// 次の支払い時刻 (UNIX ms)
+    pub next_funding_ms: u64,
+}
+
+impl FundingQuote {
+    /// 取引所の支払い間隔に合わせて作る。次の支払い時刻は間隔の区切り (UTC) とする
+    pub fn for_exchange(exchange: Exchange, rate: Decimal, now_ms: u64) -> Option<Self> {
+        let interval_hours = funding_interval_hours(exchange)?;
+        let interval_ms = interval_hours * HOUR_MS;
+        Some(Self {
+            rate,
+            interval_hours,
+            next_funding_ms: (now_ms / interval_ms + 1) * interval_ms,
+        })
+    }
+
+    /// 1時間あたりに換算した比率
+    pub fn hourly_rate(&self) -> Decimal {
+        self.rate / Decimal::from(self.interval_hours)
+    }
+
+    /// 年率 (%)
+    pub fn annualized_pct(&self) -> Decimal {
+        self.hourly_rate() * Decimal::from(HOURS_PER_YEAR) * Decimal::from(100)
+    }
+
+    /// now_ms から holding_hours 保有する間に来る支払いの回数
+    pub fn payments_within(&self, now_ms: u64, holding_hours: Decimal) -> u64 {
+        let horizon_ms = (holding_hours * Decimal::from(HOUR_MS)).trunc();
+        let horizon_ms = u64::try_from(horizon_ms).unwrap_or(u64::MAX);
+        let first = self.next_funding_ms.saturating_sub(now_ms);
+        if first > horizon_ms {
+            return 0;
+        }
+        1 + (horizon_ms - first) / (self.interval_hours * HOUR_MS)
+    }
+
+    /// holding_hours 保有した場合の受け取り (ショート側) の合計比率。ロング側は符号を反転して使う
+    pub fn expected_over(&self, now_ms: u64, holding_hours: Decimal) -> Decimal {
+        self.rate * Decimal::from(self.payments_within(now_ms, holding_hours))
+    }
+}
+
+/// 保有期間中にかかるレバレッジ手数料の日数。
+/// 反対売買までに少なくとも1回は持ち越す前提で、端数は切り上げる。
+/// holding_hours = 0 (保有期間を見込まない) でも、建玉は解消する処理がないまま持ち越されるので 1 日分とする
+pub fn rollover_days(holding_hours: Decimal) -> Decimal {
+    (holding_hours / Decimal::from(24)).ceil().max(Decimal::ONE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2023-11-14 10:30 UTC
+    const NOW_MS: u64 = 1_699_957_800_000;
+
+    fn hours(h: &str) -> Decimal {
+        h.parse().unwrap()
+    }
+
+    #[test]
+    fn hyperliquid_pays_every_hour_on_the_hour() {
+        let rate = Decimal::new(1, 4);
+        let quote = FundingQuote::for_exchange(Exchange::Hyperliquid, rate, NOW_MS).unwrap();
+        assert_eq!(quote.next_funding_ms, NOW_MS + HOUR_MS / 2);
+        assert_eq!(quote.hourly_rate(), rate);
+        assert!(FundingQuote::for_exchange(Exchange::Gmo, rate, NOW_MS).is_none());
+
+        // 最初の支払いは 30 分後。ちょうど届く保有期間はその支払いを含む
+        assert_eq!(quote.payments_within(NOW_MS, Decimal::ZERO), 0);
+        assert_eq!(quote.payments_within(NOW_MS, hours("0.49")), 0);
+        assert_eq!(quote.payments_within(NOW_MS, hours("0.5")), 1);
+        assert_eq!(quote.payments_within(NOW_MS, hours("2")), 2);
+        assert_eq!(quote.payments_within(NOW_MS, hours("8")), 8);
+        assert_eq!(quote.expected_over(NOW_MS, hours("2")), rate * Decimal::from(2));
+
+        // 正時ちょうどの支払いは済んだものとし、次は1時間後
+        let on_the_hour = NOW_MS + HOUR_MS / 2;
+        let quote = FundingQuote::for_exchange(Exchange::Hyperliquid, rate, on_the_hour).unwrap();
+        assert_eq!(quote.next_funding_ms, on_the_hour + HOUR_MS);
+        assert_eq!(quote.payments_within(on_the_hour, hours("0.99")), 0);
+        assert_eq!(quote.payments_within(on_the_hour, hours("1")), 1);
+    }
+
+    #[test]
+    fn eight_hour_interval_counts_partial_horizons() {
+        let rate = Decimal::new(8, 4);
+        // 次の支払いは 16:00 UTC (5.5 時間後)
+        let quote = FundingQuote { rate, interval_hours: 8, next_funding_ms: NOW_MS + 11 * HOUR_MS / 2 };
+        assert_eq!(quote.hourly_rate(), Decimal::new(1, 4));
+        assert_eq!(quote.annualized_pct(), Decimal::new(876, 1));
+
+        assert_eq!(quote.payments_within(NOW_MS, hours("5")), 0);
+        assert_eq!(quote.payments_within(NOW_MS, hours("5.5")), 1);
+        assert_eq!(quote.payments_within(NOW_MS, hours("13.4")), 1);
+        assert_eq!(quote.payments_within(NOW_MS, hours("13.5")), 2);
+        assert_eq!(quote.payments_within(NOW_MS, hours("24")), 3);
+        assert_eq!(quote.expected_over(NOW_MS, hours("24")), rate * Decimal::from(3));
+        // 同じ保有期間でも、1時間ごとの支払いより回数は少ない
+        let hourly = FundingQuote::for_exchange(Exchange::Hyperliquid, quote.hourly_rate(), NOW_MS).unwrap();
+        assert_eq!(hourly.payments_within(NOW_MS, hours("5")), 5);
+        assert_eq!(hourly.expected_over(NOW_MS, hours("5")), Decimal::new(5, 4));
+        assert_eq!(quote.expected_over(NOW_MS, hours("5")), Decimal::ZERO);
+    }
+
+    #[test]
+    fn rollover_days_round_up_with_a_one_day_minimum() {
+        assert_eq!(rollover_days(Decimal::ZERO), Decimal::ONE);
+        assert_eq!(rollover_days(hours("1")), Decimal::ONE);
+        assert_eq!(rollover_days(hours("24")), Decimal::ONE);
+        assert_eq!(rollover_days(hours("24.5")), Decimal::from(2));
+        assert_eq!(rollover_days(hours("72")), Decimal::from(3));
+    }
+}
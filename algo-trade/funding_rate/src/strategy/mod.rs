@@ -1,2 +1,12 @@
+pub mod carry;
+pub mod funding;
+pub mod registry;
+#[allow(clippy::module_inception)]
 pub mod strategy;
+pub mod tracker;
+pub mod trigger;
 pub use strategy::*;
+pub use funding::{funding_interval_hours, FundingQuote};
+pub use registry::{MarketSnapshot, Positions, Signal, Strategy, StrategyRegistry};
+pub use tracker::{ClosedOpportunity, OpportunityKey, OpportunityTracker};
+pub use trigger::{Affected, AssetIndex, LatencyStats, LatencySummary, PendingEvaluations};
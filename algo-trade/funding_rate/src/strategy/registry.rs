@@ -0,0 +1,305 @@
+use super::carry::{CarryParams, CashAndCarry, PositionTarget};
+use super::funding::FundingQuote;
+use super::tracker::ClosedOpportunity;
+use super::strategy::{symbol_for, ArbitrageOpportunity, ArbitrageParams, Asset, Currency, FeeSchedule, FxRates, InstrumentType, MarketData, SpreadArbitrage};
+use crate::asset::AssetRegistry;
+use crate::config::ExchangesConfig;
+use crate::store::{Exchange, MarketStore, QuoteError};
+use log::debug;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// 戦略に渡す (取引所, 商品, 建値通貨) の組
+pub const VENUE_INSTRUMENTS: &[(Exchange, InstrumentType, Currency)] = &[
+    (Exchange::Hyperliquid, InstrumentType::Perp, Currency::USDC),
+    (Exchange::Hyperliquid, InstrumentType::Spot, Currency::USDC),
+    (Exchange::Bitbank, InstrumentType::Spot, Currency::JPY),
+    (Exchange::Gmo, InstrumentType::Spot, Currency::JPY),
+    (Exchange::Gmo, InstrumentType::Margin, Currency::JPY),
+];
+
+/// 戦略ごとの設定 (設定ファイルの [strategies.*])。各戦略は enabled で個別に有効化する
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StrategiesConfig {
+    /// 取引所間の即時の価格差を取る裁定
+    pub arbitrage: ArbitrageParams,
+    /// キャッシュ・アンド・キャリー
+    pub carry: CarryParams,
+}
+
+/// 1通貨分の、戦略に渡す市場の状態
+#[derive(Debug, Clone)]
+pub struct MarketSnapshot {
+    pub asset: Asset,
+    /// 鮮度チェックを通った気配 (JPY に換算できない通貨建ては含まない)
+    pub markets: Vec<MarketData>,
+    pub fx: FxRates,
+    pub now_ms: u64,
+}
+
+impl MarketSnapshot {
+    /// MarketStore から有効な取引所・上場している商品の気配を集める
+    pub fn collect(
+        store: &MarketStore,
+        exchanges: &ExchangesConfig,
+        registry: &AssetRegistry,
+        fees: &FeeSchedule,
+        asset: Asset,
+        fx: &FxRates,
+        now_ms: u64,
+    ) -> Self {
+        let mut markets = Vec::new();
+        for &(exchange, instrument, currency) in VENUE_INSTRUMENTS {
+            // 無効な取引所・上場していない通貨は飛ばす
+            if !exchanges.get(exchange).enabled || !registry.is_listed(asset, exchange, instrument) {
+                continue;
+            }
+            if fx.get(currency).is_none() {
+                continue;
+            }
+            let symbol = symbol_for(exchange, &asset, instrument);
+            match store.get_fresh_market_data(exchange, &symbol) {
+                Ok(data) => markets.push(MarketData {
+                    exchange,
+                    asset,
+                    instrument,
+                    currency,
+                    ask: data.ask,
+                    bid: data.bid,
+                    // FRを受信していない・古い Perp はFRを見込まない
+                    funding: match instrument {
+                        InstrumentType::Perp => match store.get_fresh_funding_rate(exchange, &symbol) {
+                            Ok(rate) => FundingQuote::for_exchange(exchange, rate, now_ms),
+                            Err(e) => {
+                                debug!("[Strategy] Ignoring funding of {} {}: {}", exchange, symbol, e);
+                                None
+                            }
+                        },
+                        InstrumentType::Spot | InstrumentType::Margin => None,
+                    },
+                    taker_fee: fees.taker(exchange, instrument),
+                    daily_rollover_fee: fees.daily_rollover(exchange, instrument),
+                    book: store.get_fresh_order_book(exchange, &symbol).ok(),
+                }),
+                Err(QuoteError::Missing) => {}
+                // 古い気配・停止中の取引所の気配は戦略に渡さない
+                Err(e) => debug!("[Strategy] Skipping {} {}: {}", exchange, symbol, e),
+            }
+        }
+        Self { asset, markets, fx: fx.clone(), now_ms }
+    }
+}
+
+/// 取引所・商品ごとの建玉 (買いがプラス)
+#[derive(Debug, Clone, Default)]
+pub struct Positions {
+    quantities: HashMap<(Exchange, InstrumentType, Asset), Decimal>,
+}
+
+impl Positions {
+    pub fn get(&self, exchange: Exchange, instrument: InstrumentType, asset: Asset) -> Decimal {
+        self.quantities.get(&(exchange, instrument, asset)).copied().unwrap_or(Decimal::ZERO)
+    }
+
+    /// 約定を反映する (売りは quantity をマイナスで渡す)
+    pub fn record_fill(&mut self, exchange: Exchange, instrument: InstrumentType, asset: Asset, quantity: Decimal) {
+        *self.quantities.entry((exchange, instrument, asset)).or_insert(Decimal::ZERO) += quantity;
+    }
+
+    /// 建玉のある (0 でない) ものの一覧
+    pub fn iter(&self) -> impl Iterator<Item = (Exchange, InstrumentType, Asset, Decimal)> + '_ {
+        self.quantities
+            .iter()
+            .filter(|(_, q)| !q.is_zero())
+            .map(|(&(exchange, instrument, asset), &q)| (exchange, instrument, asset, q))
+    }
+}
+
+/// 戦略の出力
+#[derive(Debug, Clone)]
+pub enum Signal {
+    /// すぐに両足を執行する裁定の候補 (利益の大きい順)。
+    /// 先頭の経路が執行できなかった場合は次の経路を試す
+    Arbitrage(Vec<ArbitrageOpportunity>),
+    /// 目標ポジションの変更
+    Target(Box<PositionTarget>),
+    /// 観測していた裁定機会が消えた (生存期間の統計)
+    OpportunityClosed(ClosedOpportunity),
+}
+
+/// 戦略の共通インターフェース。
+/// 通貨ごとの MarketSnapshot と現在の建玉を受け取り、執行すべきシグナルを返す
+pub trait Strategy: Send {
+    /// ログに出す名前 (設定ファイルの [strategies.*] のキーと同じ)
+    fn name(&self) -> &'static str;
+
+    fn evaluate(&mut self, snapshot: &MarketSnapshot, positions: &Positions) -> Vec<Signal>;
+}
+
+/// 有効な戦略の一覧。同じ MarketSnapshot を登録順にすべての戦略に渡す
+#[derive(Default)]
+pub struct StrategyRegistry {
+    strategies: Vec<Box<dyn Strategy>>,
+}
+
+impl StrategyRegistry {
+    /// 設定で有効になっている戦略を登録する
+    pub fn from_config(config: &StrategiesConfig) -> Self {
+        let mut registry = Self::default();
+        if config.arbitrage.enabled {
+            registry.register(Box::new(SpreadArbitrage::new(config.arbitrage.clone())));
+        }
+        if config.carry.enabled {
+            registry.register(Box::new(CashAndCarry::new(config.carry.clone())));
+        }
+        registry
+    }
+
+    pub fn register(&mut self, strategy: Box<dyn Strategy>) {
+        self.strategies.push(strategy);
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.strategies.iter().map(|s| s.name()).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strategies.is_empty()
+    }
+
+    /// すべての戦略を評価し、(戦略名, シグナル) を返す
+    pub fn evaluate(&mut self, snapshot: &MarketSnapshot, positions: &Positions) -> Vec<(&'static str, Signal)> {
+        let mut signals = Vec::new();
+        for strategy in &mut self.strategies {
+            let name = strategy.name();
+            signals.extend(strategy.evaluate(snapshot, positions).into_iter().map(|s| (name, s)));
+        }
+        signals
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asset::AssetConfig;
+    use crate::clock::VirtualClock;
+    use crate::config::Config;
+    use crate::strategy::{FxConversion, FxRate};
+    use std::sync::Arc;
+
+    const START_US: u64 = 1_700_000_000_000_000;
+
+    /// 決まった数のシグナルを返す戦略
+    struct Fixed(&'static str, usize);
+
+    impl Strategy for Fixed {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn evaluate(&mut self, _snapshot: &MarketSnapshot, _positions: &Positions) -> Vec<Signal> {
+            (0..self.1).map(|_| Signal::Arbitrage(Vec::new())).collect()
+        }
+    }
+
+    /// USD/JPY 150、USDC/USD 1 の換算レート
+    fn fx() -> FxRates {
+        let mut fx = FxRates::default();
+        let rate = |r: i64| FxRate { bid: Decimal::from(r), ask: Decimal::from(r) };
+        let usd = FxConversion::jpy().then(Currency::USD, "USD_JPY", rate(150));
+        fx.insert(usd.then(Currency::USDC, "USDC_USD", rate(1)));
+        fx.insert(usd);
+        fx
+    }
+
+    #[test]
+    fn from_config_registers_enabled_strategies_in_order() {
+        let mut config = StrategiesConfig::default();
+        assert_eq!(StrategyRegistry::from_config(&config).names(), ["arbitrage"]);
+        config.carry.enabled = true;
+        assert_eq!(StrategyRegistry::from_config(&config).names(), ["arbitrage", "carry"]);
+        config.arbitrage.enabled = false;
+        assert_eq!(StrategyRegistry::from_config(&config).names(), ["carry"]);
+        config.carry.enabled = false;
+        assert!(StrategyRegistry::from_config(&config).is_empty());
+    }
+
+    #[test]
+    fn signals_are_returned_in_registration_order() {
+        let mut registry = StrategyRegistry::default();
+        registry.register(Box::new(Fixed("second", 1)));
+        registry.register(Box::new(Fixed("none", 0)));
+        registry.register(Box::new(Fixed("first", 2)));
+        let snapshot = MarketSnapshot { asset: Asset::new("BTC"), markets: Vec::new(), fx: fx(), now_ms: 0 };
+        let names: Vec<_> = registry.evaluate(&snapshot, &Positions::default()).into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["second", "first", "first"]);
+    }
+
+    #[test]
+    fn collect_skips_disabled_venues_unlisted_instruments_and_stale_quotes() {
+        let btc = Asset::new("BTC");
+        // GMO のレバレッジ取引には上場していない
+        let registry = AssetRegistry::new(vec![AssetConfig {
+            gmo_leverage: None,
+            ..AssetConfig::defaults().into_iter().find(|a| a.symbol == btc).unwrap()
+        }]);
+        let clock = Arc::new(VirtualClock::new(START_US));
+        let store = MarketStore::new().with_clock(clock.clone());
+        store.set_max_funding_age(Exchange::Hyperliquid, std::time::Duration::from_secs(1));
+        let quote = |exchange, symbol: &str, price: i64| {
+            let price = Decimal::from(price);
+            store.update_market_data(exchange, symbol, price, price, price, None);
+        };
+
+        // GMO の気配の鮮度の上限は 10 秒
+        quote(Exchange::Gmo, "BTC_SPOT", 10_000);
+        store.update_funding_rate(Exchange::Hyperliquid, "BTC", Decimal::new(1, 4));
+        clock.advance(START_US + 11_000_000);
+        quote(Exchange::Gmo, "BTC", 10_000);
+        quote(Exchange::Bitbank, "BTC", 10_010);
+        quote(Exchange::Hyperliquid, "BTC", 66);
+        quote(Exchange::Hyperliquid, "BTC_SPOT", 67);
+
+        let mut exchanges = Config::default().exchanges;
+        let fees = FeeSchedule::default();
+        let now_ms = store.now_ms();
+        let collect = |exchanges: &ExchangesConfig, fx: &FxRates| {
+            let snapshot = MarketSnapshot::collect(&store, exchanges, &registry, &fees, btc, fx, now_ms);
+            snapshot.markets.iter().map(|m| (m.exchange, m.instrument)).collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            collect(&exchanges, &fx()),
+            [(Exchange::Hyperliquid, InstrumentType::Perp), (Exchange::Hyperliquid, InstrumentType::Spot), (Exchange::Bitbank, InstrumentType::Spot)]
+        );
+        // 円に換算できない通貨建ての市場は渡さない
+        assert_eq!(collect(&exchanges, &FxRates::default()), [(Exchange::Bitbank, InstrumentType::Spot)]);
+        exchanges.hyperliquid.enabled = false;
+        assert_eq!(collect(&exchanges, &fx()), [(Exchange::Bitbank, InstrumentType::Spot)]);
+
+        // 古いFRは見込まない
+        exchanges.hyperliquid.enabled = true;
+        let snapshot = MarketSnapshot::collect(&store, &exchanges, &registry, &fees, btc, &fx(), now_ms);
+        assert!(snapshot.markets[0].funding.is_none());
+        store.update_funding_rate(Exchange::Hyperliquid, "BTC", Decimal::new(1, 4));
+        let snapshot = MarketSnapshot::collect(&store, &exchanges, &registry, &fees, btc, &fx(), now_ms);
+        assert_eq!(snapshot.markets[0].funding.map(|f| f.rate), Some(Decimal::new(1, 4)));
+    }
+
+    #[test]
+    fn positions_net_fills_and_list_open_ones() {
+        let btc = Asset::new("BTC");
+        let mut positions = Positions::default();
+        positions.record_fill(Exchange::Gmo, InstrumentType::Margin, btc, -Decimal::ONE);
+        positions.record_fill(Exchange::Bitbank, InstrumentType::Spot, btc, Decimal::ONE);
+        positions.record_fill(Exchange::Bitbank, InstrumentType::Spot, btc, -Decimal::ONE);
+        assert_eq!(positions.get(Exchange::Gmo, InstrumentType::Margin, btc), -Decimal::ONE);
+        assert_eq!(positions.get(Exchange::Bitbank, InstrumentType::Spot, btc), Decimal::ZERO);
+        assert_eq!(positions.get(Exchange::Gmo, InstrumentType::Spot, btc), Decimal::ZERO);
+        // 決済して 0 になった建玉は一覧に出さない
+        let open: Vec<_> = positions.iter().collect();
+        assert_eq!(open, [(Exchange::Gmo, InstrumentType::Margin, btc, -Decimal::ONE)]);
+    }
+}
@@ -1,44 +1,78 @@
-use crate::store::Exchange;
+pub use crate::asset::Asset;
+use super::funding::{rollover_days, FundingQuote};
+use super::registry::{MarketSnapshot, Positions, Signal, Strategy};
+use super::tracker::OpportunityTracker;
+use crate::store::{BookSide, Exchange, OrderBook, PriceLevel};
+use log::debug;
 use rust_decimal::Decimal;
 use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::str::FromStr;
 
-// 定数定義
-const SLIPPAGE: &str = "0.0001"; // スリッページ 0.01%
-
-fn slippage() -> Decimal {
-    Decimal::from_str(SLIPPAGE).unwrap()
-}
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub enum Asset {
-    BTC,
-    ETH,
-    SOL,
-    HYPE,
+/// 裁定判定のパラメータ (設定ファイルの [strategies.arbitrage])
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArbitrageParams {
+    pub enabled: bool,
+    /// この利益率 (%) を超えた機会だけを執行する
+    pub min_profit_pct: Decimal,
+    /// 1回の裁定で建てる買い想定元本の上限 (JPY)
+    pub max_notional_jpy: Decimal,
+    /// 板がない場合に見込むスリッページ (小数。0.0001 = 0.01%)
+    pub slippage: Decimal,
+    /// 想定する保有期間 (時間)。FRの受け払いとレバレッジ手数料はこの期間分を見込む。
+    /// 両足は約定後すぐに手仕舞う前提で、建玉を持ち続けて解消する処理はないので既定は 0 (FRを見込まない)
+    pub holding_hours: Decimal,
+    /// 同じ経路の機会がこの時間 (ms) 以上続き、
+    pub min_persistence_ms: u64,
+    /// かつ、この回数続けて観測されたら執行する (両方が 0 か 1 なら初回から執行する)。
+    /// 評価はイベント駆動で数十 ms 間隔でも走るため、回数だけでは一瞬の交差を除けない
+    pub min_persistence_ticks: u32,
 }
 
-impl Asset {
-    pub fn as_symbol(&self) -> &'static str {
-        match self {
-            Asset::BTC => "BTC",
-            Asset::ETH => "ETH",
-            Asset::SOL => "SOL",
-            Asset::HYPE => "HYPE",
+impl Default for ArbitrageParams {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_profit_pct: Decimal::from_str("0.05").unwrap(),
+            max_notional_jpy: Decimal::from(100_000),
+            slippage: Decimal::from_str("0.0001").unwrap(), // スリッページ 0.01%
+            holding_hours: Decimal::ZERO,
+            min_persistence_ms: 1_000,
+            min_persistence_ticks: 2,
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
 pub enum InstrumentType {
     Spot,
     Perp,
+    /// 国内取引所のレバレッジ取引 (GMOコインの BTC_JPY など)。
+    /// FRの代わりに、建玉を翌日に持ち越すたびにレバレッジ手数料がかかる
+    Margin,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// MarketStore 上のキー (シンボル名) を決める
+pub fn symbol_for(exchange: Exchange, asset: &Asset, instrument: InstrumentType) -> String {
+    match (exchange, instrument) {
+        (Exchange::Hyperliquid, InstrumentType::Spot) | (Exchange::Gmo, InstrumentType::Spot) => {
+            format!("{}_SPOT", asset.as_symbol())
+        }
+        _ => asset.as_symbol().to_string(),
+    }
+}
+
+/// 建値通貨。
+/// Hyperliquid は USDC 建てなので、USD と同一視せずに USDC/USD のレートを挟んで円に換算する
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Currency {
     JPY,
     USD,
+    USDC,
+    USDT,
 }
 
 #[derive(Debug, Clone)]
@@ -49,176 +83,746 @@ pub struct MarketData {
     pub currency: Currency, // 通貨単位 (JPY or USD)
     pub ask: Decimal,       // 買値
     pub bid: Decimal,       // 売値
-    pub funding_rate: Decimal, 
+    /// Perp のFR (Perp 以外・未受信は None)
+    pub funding: Option<FundingQuote>,
+    /// Taker手数料 (単位: 小数。例: 0.05% -> 0.0005)
+    /// ※アビトラは即時約定が必要なためTaker手数料を採用
+    pub taker_fee: Decimal,
+    /// 1日あたりのレバレッジ手数料 (建玉の想定元本に対する小数。Margin 以外は 0)
+    pub daily_rollover_fee: Decimal,
+    /// 板情報 (ない場合は ask/bid を気配値として使う)
+    pub book: Option<OrderBook>,
 }
 
-impl MarketData {
-    /// 取引所ごとのTaker手数料を取得 (単位: 小数。例: 0.05% -> 0.0005)
-    /// 提示された数値: HL(0.015, 0.045), GMO(-0.01, 0.05), Bitbank(-0.02, 0.12)
-    /// ※アビトラは即時約定が必要なためTaker手数料を採用
-    pub fn taker_fee(&self) -> Decimal {
-        match self.exchange {
-            // 0.0450% -> 0.00045
-            Exchange::Hyperliquid => Decimal::from_f64(0.00045).unwrap(),
-            // 0.05% -> 0.0005
-            Exchange::Gmo => Decimal::from_f64(0.0005).unwrap(),
-            // 0.12% -> 0.0012
-            Exchange::Bitbank => Decimal::from_f64(0.0012).unwrap(),
-            // Kraken is used for FX rate only, not for trading
-            Exchange::Kraken => Decimal::ZERO,
+/// 取引所ごとのTaker手数料とレバレッジ手数料 (設定ファイルで上書きされなかった取引所は既定値)
+#[derive(Debug, Clone, Default)]
+pub struct FeeSchedule {
+    taker: HashMap<Exchange, Decimal>,
+    margin_taker: HashMap<Exchange, Decimal>,
+    daily_rollover: HashMap<Exchange, Decimal>,
+}
+
+impl FeeSchedule {
+    pub fn set_taker(&mut self, exchange: Exchange, fee: Decimal) {
+        self.taker.insert(exchange, fee);
+    }
+
+    /// レバレッジ取引の手数料 (現物と別体系の取引所のみ設定する)
+    pub fn set_margin(&mut self, exchange: Exchange, taker_fee: Decimal, daily_rollover_fee: Decimal) {
+        self.margin_taker.insert(exchange, taker_fee);
+        self.daily_rollover.insert(exchange, daily_rollover_fee);
+    }
+
+    pub fn taker(&self, exchange: Exchange, instrument: InstrumentType) -> Decimal {
+        if instrument == InstrumentType::Margin
+            && let Some(&fee) = self.margin_taker.get(&exchange)
+        {
+            return fee;
         }
+        self.taker.get(&exchange).copied().unwrap_or_else(|| taker_fee_for(exchange))
+    }
+
+    /// 1日あたりのレバレッジ手数料 (Margin 以外は 0)
+    pub fn daily_rollover(&self, exchange: Exchange, instrument: InstrumentType) -> Decimal {
+        match instrument {
+            InstrumentType::Margin => self
+                .daily_rollover
+                .get(&exchange)
+                .copied()
+                .unwrap_or_else(|| daily_rollover_fee_for(exchange)),
+            InstrumentType::Spot | InstrumentType::Perp => Decimal::ZERO,
+        }
+    }
+}
+
+/// 取引所ごとのTaker手数料の既定値
+/// 提示された数値: HL(0.015, 0.045), GMO(-0.01, 0.05), Bitbank(-0.02, 0.12)
+pub fn taker_fee_for(exchange: Exchange) -> Decimal {
+    match exchange {
+        // 0.0450% -> 0.00045
+        Exchange::Hyperliquid => Decimal::from_f64(0.00045).unwrap(),
+        // 0.05% -> 0.0005
+        Exchange::Gmo => Decimal::from_f64(0.0005).unwrap(),
+        // 0.12% -> 0.0012
+        Exchange::Bitbank => Decimal::from_f64(0.0012).unwrap(),
+        // Kraken is used for FX rate only, not for trading
+        Exchange::Kraken => Decimal::ZERO,
+    }
+}
+
+/// レバレッジ取引の1日あたりの手数料の既定値
+pub fn daily_rollover_fee_for(exchange: Exchange) -> Decimal {
+    match exchange {
+        // GMOコイン 暗号資産FX: 建玉を持ち越すごとに 0.04%/日 (買い・売りとも)
+        Exchange::Gmo => Decimal::from_str("0.0004").unwrap(),
+        _ => Decimal::ZERO,
     }
 }
 
-#[derive(Debug)]
+/// 数量ごとの損益 (限界利益曲線の1点)。
+/// 両方の板の価格レベルの切れ目ごとに1点ずつ記録する
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfitPoint {
+    /// この点までの累計数量
+    pub quantity: Decimal,
+    /// この点までの買い想定元本 (JPY, 手数料除く)
+    pub notional_jpy: Decimal,
+    /// この区間で1単位追加したときの利益 (JPY)
+    pub marginal_profit_jpy: Decimal,
+    /// この点までの累計利益 (JPY)
+    pub cumulative_profit_jpy: Decimal,
+}
+
+/// 価格はすべて1単位あたり (約定数量の平均)、損益・コストは quantity 分の合計
+#[derive(Debug, Clone)]
 pub struct ArbitrageOpportunity {
     pub asset: Asset,
     pub long_exchange: Exchange,
     pub long_instrument: InstrumentType,
+    /// 手数料・スリッページ込みの平均買値 (JPY換算)
     pub long_price_jpy: Decimal,
+    /// 板を消費した平均買値 (VWAP, 建値通貨)
     pub long_price_raw: Decimal,
+    /// 最後に消費した Ask の価格 (指値に使う)
+    pub long_limit_price: Decimal,
     pub long_currency: Currency,
     pub long_fee_jpy: Decimal,
     pub short_exchange: Exchange,
     pub short_instrument: InstrumentType,
+    /// 手数料・スリッページ控除後の平均売値 (JPY換算)
     pub short_price_jpy: Decimal,
+    /// 板を消費した平均売値 (VWAP, 建値通貨)
     pub short_price_raw: Decimal,
+    /// 最後に消費した Bid の価格 (指値に使う)
+    pub short_limit_price: Decimal,
     pub short_currency: Currency,
     pub short_fee_jpy: Decimal,
+    /// 利益が最大になる数量
+    pub quantity: Decimal,
+    /// 買い想定元本 (JPY, 手数料除く)
+    pub notional_jpy: Decimal,
     pub base_profit_jpy: Decimal,
+    /// 保有期間中のFRの受け払い (受け取りがプラス)
     pub fr_impact_jpy: Decimal,
+    /// 保有期間中のレバレッジ手数料 (両足の合計)
+    pub rollover_cost_jpy: Decimal,
+    /// 見込んだ保有期間 (時間)
+    pub holding_hours: Decimal,
+    /// FRを除いた、即時に確定する損益率 (%)
+    pub instant_spread_pct: Decimal,
+    /// 両足のFRの差を年率にしたもの (%, 受け取りがプラス)
+    pub carry_annualized_pct: Decimal,
+    /// ベスト気配からの乖離 (板の消費) によるコスト
     pub slippage_cost_jpy: Decimal,
     pub estimated_profit_jpy: Decimal,
     pub estimated_profit_pct: Decimal,
+    /// USD/JPY の仲値 (取れていない場合は 0)
     pub usd_jpy_rate: Decimal,
+    /// 買い足・売り足の JPY 換算に使ったレート (JPY建ては 1)
+    pub long_fx_rate: Decimal,
+    pub short_fx_rate: Decimal,
+    /// 買い足・売り足の JPY 換算の経路 (JPY建ては空)
+    pub long_fx_chain: Vec<FxHop>,
+    pub short_fx_chain: Vec<FxHop>,
+    /// 数量ごとの限界利益
+    pub profit_curve: Vec<ProfitPoint>,
     pub details: String,
 }
 
-/// usd_jpy_rate: Krakenから取得したUSD/JPYレート
-pub fn find_best_arbitrage(
-    market_data_list: &[MarketData],
+impl ArbitrageOpportunity {
+    /// quantity 分の推定利益を、実際に約定した数量に按分する
+    pub fn estimated_profit_for(&self, quantity: Decimal) -> Decimal {
+        if self.quantity.is_zero() {
+            return Decimal::ZERO;
+        }
+        self.estimated_profit_jpy * quantity / self.quantity
+    }
+
+    /// 買い足 -> 売り足の経路 (例: "Bitbank Spot -> Hyperliquid Perp")
+    pub fn route(&self) -> String {
+        format!(
+            "{} {:?} -> {} {:?}",
+            self.long_exchange, self.long_instrument, self.short_exchange, self.short_instrument
+        )
+    }
+}
+
+#[cfg(test)]
+impl ArbitrageOpportunity {
+    /// テスト用の JPY 建て現物同士の機会 (手数料・FR なし、推定利益は価格差 × 数量)
+    pub(crate) fn sample(asset: Asset, long: (Exchange, Decimal), short: (Exchange, Decimal), quantity: Decimal) -> Self {
+        let (long_exchange, long_price) = long;
+        let (short_exchange, short_price) = short;
+        let profit = (short_price - long_price) * quantity;
+        Self {
+            asset,
+            long_exchange,
+            long_instrument: InstrumentType::Spot,
+            long_price_jpy: long_price,
+            long_price_raw: long_price,
+            long_limit_price: long_price,
+            long_currency: Currency::JPY,
+            long_fee_jpy: Decimal::ZERO,
+            short_exchange,
+            short_instrument: InstrumentType::Spot,
+            short_price_jpy: short_price,
+            short_price_raw: short_price,
+            short_limit_price: short_price,
+            short_currency: Currency::JPY,
+            short_fee_jpy: Decimal::ZERO,
+            quantity,
+            notional_jpy: long_price * quantity,
+            base_profit_jpy: profit,
+            fr_impact_jpy: Decimal::ZERO,
+            rollover_cost_jpy: Decimal::ZERO,
+            holding_hours: Decimal::ZERO,
+            instant_spread_pct: (short_price - long_price) / long_price * Decimal::from(100),
+            carry_annualized_pct: Decimal::ZERO,
+            slippage_cost_jpy: Decimal::ZERO,
+            estimated_profit_jpy: profit,
+            estimated_profit_pct: (short_price - long_price) / long_price * Decimal::from(100),
+            usd_jpy_rate: Decimal::ZERO,
+            long_fx_rate: Decimal::ONE,
+            short_fx_rate: Decimal::ONE,
+            long_fx_chain: Vec::new(),
+            short_fx_chain: Vec::new(),
+            profit_curve: Vec::new(),
+            details: String::new(),
+        }
+    }
+}
+
+/// 片側の板 (買いなら Ask、売りなら Bid) をベストから順に並べたもの。
+/// 板がなければ気配値を、想定元本 max_notional_jpy 分 (fx_rate で JPY 換算) の数量を持つ1レベルとして扱い、
+/// 固定のスリッページを見込む
+fn executable_levels(data: &MarketData, side: BookSide, slippage: Decimal, max_notional_jpy: Decimal, fx_rate: Decimal) -> (Vec<PriceLevel>, Decimal) {
+    if let Some(book) = &data.book {
+        let levels: Vec<PriceLevel> = book.levels(side).collect();
+        if !levels.is_empty() {
+            return (levels, Decimal::ZERO);
+        }
+    }
+    let price = match side {
+        BookSide::Ask => data.ask,
+        BookSide::Bid => data.bid,
+    };
+    let price_jpy = price * fx_rate;
+    let size = if price_jpy > Decimal::ZERO { max_notional_jpy / price_jpy } else { Decimal::ZERO };
+    (vec![PriceLevel { price, size }], slippage)
+}
+
+/// 為替レートの Bid/Ask (例: USD_JPY なら 1 USD あたりの JPY)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FxRate {
+    pub bid: Decimal,
+    pub ask: Decimal,
+}
+
+impl FxRate {
+    pub const ONE: FxRate = FxRate { bid: Decimal::ONE, ask: Decimal::ONE };
+
+    pub fn mid(&self) -> Decimal {
+        (self.bid + self.ask) / Decimal::from(2)
+    }
+
+    /// 2つのレートを掛け合わせる (例: USDC_USD × USD_JPY = USDC_JPY)。
+    /// どちらの側も不利な方同士を掛けるので、換算後の Bid/Ask も保守的になる
+    pub fn chain(&self, next: &FxRate) -> FxRate {
+        FxRate { bid: self.bid * next.bid, ask: self.ask * next.ask }
+    }
+}
+
+/// 換算経路の1段 (例: "USDC_USD" 0.9998/1.0001)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FxHop {
+    pub pair: String,
+    pub rate: FxRate,
+}
+
+/// ある通貨から JPY への換算レートと、その経路
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FxConversion {
+    pub currency: Currency,
+    /// 経路のレートを掛け合わせた JPY 換算レート
+    pub rate: FxRate,
+    /// JPY 建ては空
+    pub chain: Vec<FxHop>,
+}
+
+impl FxConversion {
+    pub fn jpy() -> Self {
+        Self { currency: Currency::JPY, rate: FxRate::ONE, chain: Vec::new() }
+    }
+
+    /// 経路を1段伸ばす (self の建値通貨 -> pair の建値通貨)
+    pub fn then(&self, currency: Currency, pair: &str, rate: FxRate) -> Self {
+        let mut chain = vec![FxHop { pair: pair.to_string(), rate }];
+        chain.extend(self.chain.iter().cloned());
+        Self { currency, rate: rate.chain(&self.rate), chain }
+    }
+
+    pub fn describe(&self) -> String {
+        describe_fx_chain(&self.chain)
+    }
+}
+
+/// 換算経路の表示 (例: "USDC_USD 0.9998/1.0001 -> USD_JPY 150.10/150.12")
+pub fn describe_fx_chain(chain: &[FxHop]) -> String {
+    if chain.is_empty() {
+        return "JPY".to_string();
+    }
+    chain
+        .iter()
+        .map(|hop| format!("{} {}/{}", hop.pair, hop.rate.bid, hop.rate.ask))
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+/// 通貨ごとの JPY 換算レート。
+/// レートが取れなかった (乖離・デペッグを含む) 通貨は含まれず、その通貨建ての市場は裁定の対象外になる
+#[derive(Debug, Clone)]
+pub struct FxRates {
+    conversions: HashMap<Currency, FxConversion>,
+}
+
+impl Default for FxRates {
+    fn default() -> Self {
+        let mut conversions = HashMap::new();
+        conversions.insert(Currency::JPY, FxConversion::jpy());
+        Self { conversions }
+    }
+}
+
+impl FxRates {
+    pub fn insert(&mut self, conversion: FxConversion) {
+        self.conversions.insert(conversion.currency, conversion);
+    }
+
+    pub fn get(&self, currency: Currency) -> Option<&FxConversion> {
+        self.conversions.get(&currency)
+    }
+
+    /// USD/JPY の仲値 (取れていない場合は None)
+    pub fn usd_jpy_mid(&self) -> Option<Decimal> {
+        self.get(Currency::USD).map(|c| c.rate.mid())
+    }
+
+    /// 買い足の JPY 換算レート。
+    /// 円を売って建値通貨を用意するので Ask (不利な側) で換算する
+    pub fn for_buy(&self, currency: Currency) -> Option<Decimal> {
+        self.get(currency).map(|c| c.rate.ask)
+    }
+
+    /// 売り足の JPY 換算レート。
+    /// 受け取った建値通貨を円に戻すので Bid で換算する
+    pub fn for_sell(&self, currency: Currency) -> Option<Decimal> {
+        self.get(currency).map(|c| c.rate.bid)
+    }
+}
+
+/// 買い板と売り板を同時に歩き、1単位あたりの利益がプラスの間だけ数量を積み上げる。
+/// Ask は上がり Bid は下がるので限界利益は単調に減少し、プラスの区間をすべて取った数量で合計利益が最大になる
+fn size_opportunity(
+    buy_side: &MarketData,
+    sell_side: &MarketData,
     target_asset: Asset,
-    usd_jpy_rate: Decimal,
+    fx: &FxRates,
+    params: &ArbitrageParams,
+    now_ms: u64,
 ) -> Option<ArbitrageOpportunity> {
-    
+    let buy_conversion = fx.get(buy_side.currency)?;
+    let sell_conversion = fx.get(sell_side.currency)?;
+    let buy_fx = buy_conversion.rate.ask;
+    let sell_fx = sell_conversion.rate.bid;
+    let (asks, buy_slippage) = executable_levels(buy_side, BookSide::Ask, params.slippage, params.max_notional_jpy, buy_fx);
+    let (bids, sell_slippage) = executable_levels(sell_side, BookSide::Bid, params.slippage, params.max_notional_jpy, sell_fx);
+    let best_ask = asks[0].price;
+    let best_bid = bids[0].price;
+    if best_ask <= Decimal::ZERO || best_bid <= Decimal::ZERO {
+        return None;
+    }
+
+    // FRは保有期間中に来る支払いの回数分を、買いコストに対する比率 (小数) として見込む
+    // Buy(Long)なら -FR, Sell(Short)なら +FR (FR>0の場合)
+    let mut fr_impact_ratio = Decimal::ZERO;
+    let mut carry_hourly = Decimal::ZERO;
+    if let Some(funding) = &buy_side.funding {
+        fr_impact_ratio -= funding.expected_over(now_ms, params.holding_hours);
+        carry_hourly -= funding.hourly_rate();
+    }
+    if let Some(funding) = &sell_side.funding {
+        fr_impact_ratio += funding.expected_over(now_ms, params.holding_hours);
+        carry_hourly += funding.hourly_rate();
+    }
+
+    // レバレッジ建玉は保有期間の日数分 (最低1日) の手数料を見込む
+    let days = rollover_days(params.holding_hours);
+    let buy_rollover = buy_side.daily_rollover_fee * days;
+    let sell_rollover = sell_side.daily_rollover_fee * days;
+
+    let buy_fee_multiplier = Decimal::ONE + buy_side.taker_fee + buy_slippage;
+    let sell_fee_multiplier = Decimal::ONE - sell_side.taker_fee - sell_slippage;
+
+    let mut quantity = Decimal::ZERO;
+    let mut buy_raw = Decimal::ZERO;
+    let mut sell_raw = Decimal::ZERO;
+    let mut buy_cost_jpy = Decimal::ZERO;
+    let mut sell_revenue_jpy = Decimal::ZERO;
+    let mut fr_profit_jpy = Decimal::ZERO;
+    let mut rollover_jpy = Decimal::ZERO;
+    let mut slippage_jpy = Decimal::ZERO;
+    let mut long_limit = best_ask;
+    let mut short_limit = best_bid;
+    let mut curve = Vec::new();
+
+    let (mut ai, mut bi) = (0, 0);
+    let mut ask_left = asks[0].size;
+    let mut bid_left = bids[0].size;
+    while ai < asks.len() && bi < bids.len() {
+        let ask = asks[ai].price;
+        let bid = bids[bi].price;
+
+        // 1単位あたり: 売上 - コスト + コスト * FR比率 - レバレッジ手数料
+        let unit_cost_jpy = ask * buy_fx * buy_fee_multiplier;
+        let unit_revenue_jpy = bid * sell_fx * sell_fee_multiplier;
+        let unit_rollover_jpy = ask * buy_fx * buy_rollover + bid * sell_fx * sell_rollover;
+        let marginal = unit_revenue_jpy - unit_cost_jpy + unit_cost_jpy * fr_impact_ratio - unit_rollover_jpy;
+        if marginal <= Decimal::ZERO {
+            break;
+        }
+
+        let notional_left = params.max_notional_jpy - buy_raw * buy_fx;
+        if notional_left <= Decimal::ZERO {
+            break;
+        }
+        let notional_cap = notional_left / (ask * buy_fx);
+        let step = ask_left.min(bid_left).min(notional_cap);
+        if step <= Decimal::ZERO {
+            break;
+        }
+
+        quantity += step;
+        buy_raw += ask * step;
+        sell_raw += bid * step;
+        buy_cost_jpy += unit_cost_jpy * step;
+        sell_revenue_jpy += unit_revenue_jpy * step;
+        fr_profit_jpy += unit_cost_jpy * fr_impact_ratio * step;
+        rollover_jpy += unit_rollover_jpy * step;
+        slippage_jpy += ((ask - best_ask) * buy_fx + ask * buy_fx * buy_slippage) * step
+            + ((best_bid - bid) * sell_fx + bid * sell_fx * sell_slippage) * step;
+        long_limit = ask;
+        short_limit = bid;
+        curve.push(ProfitPoint {
+            quantity,
+            notional_jpy: buy_raw * buy_fx,
+            marginal_profit_jpy: marginal,
+            cumulative_profit_jpy: sell_revenue_jpy - buy_cost_jpy + fr_profit_jpy - rollover_jpy,
+        });
+
+        if step >= notional_cap {
+            break;
+        }
+        ask_left -= step;
+        bid_left -= step;
+        if ask_left.is_zero() {
+            ai += 1;
+            ask_left = asks.get(ai).map(|l| l.size).unwrap_or_default();
+        }
+        if bid_left.is_zero() {
+            bi += 1;
+            bid_left = bids.get(bi).map(|l| l.size).unwrap_or_default();
+        }
+    }
+
+    if quantity.is_zero() {
+        return None;
+    }
+
+    let base_profit_jpy = sell_revenue_jpy - buy_cost_jpy;
+    let total_profit_jpy = base_profit_jpy + fr_profit_jpy - rollover_jpy;
+    let long_vwap = buy_raw / quantity;
+    let short_vwap = sell_raw / quantity;
+
+    let details = format!(
+        "Buy {:?} {:?} {}@{} {:?} | Sell {:?} {:?} {}@{} {:?} | FX: buy {} / sell {}",
+        buy_side.exchange, buy_side.instrument, quantity, long_vwap, buy_side.currency,
+        sell_side.exchange, sell_side.instrument, quantity, short_vwap, sell_side.currency,
+        buy_conversion.describe(), sell_conversion.describe()
+    );
+
+    Some(ArbitrageOpportunity {
+        asset: target_asset,
+        long_exchange: buy_side.exchange,
+        long_instrument: buy_side.instrument,
+        long_price_jpy: buy_cost_jpy / quantity,
+        long_price_raw: long_vwap,
+        long_limit_price: long_limit,
+        long_currency: buy_side.currency,
+        long_fee_jpy: buy_raw * buy_side.taker_fee * buy_fx,
+        short_exchange: sell_side.exchange,
+        short_instrument: sell_side.instrument,
+        short_price_jpy: sell_revenue_jpy / quantity,
+        short_price_raw: short_vwap,
+        short_limit_price: short_limit,
+        short_currency: sell_side.currency,
+        short_fee_jpy: sell_raw * sell_side.taker_fee * sell_fx,
+        quantity,
+        notional_jpy: buy_raw * buy_fx,
+        base_profit_jpy,
+        fr_impact_jpy: fr_profit_jpy,
+        rollover_cost_jpy: rollover_jpy,
+        holding_hours: params.holding_hours,
+        instant_spread_pct: base_profit_jpy / buy_cost_jpy * Decimal::from(100),
+        carry_annualized_pct: carry_hourly * Decimal::from(24 * 365) * Decimal::from(100),
+        slippage_cost_jpy: slippage_jpy,
+        estimated_profit_jpy: total_profit_jpy,
+        estimated_profit_pct: total_profit_jpy / buy_cost_jpy * Decimal::from(100),
+        usd_jpy_rate: fx.usd_jpy_mid().unwrap_or_default(),
+        long_fx_rate: buy_fx,
+        short_fx_rate: sell_fx,
+        long_fx_chain: buy_conversion.chain.clone(),
+        short_fx_chain: sell_conversion.chain.clone(),
+        profit_curve: curve,
+        details,
+    })
+}
+
+/// fx: FxOracle で集計した通貨ごとの JPY 換算レート (買い足は Ask、売り足は Bid で換算する)
+/// params.max_notional_jpy: 1回の裁定で建てる買い想定元本の上限
+/// now_ms: 評価時刻 (UNIX ms)。保有期間中のFRの支払い回数を数えるのに使う
+///
+/// 全ての (買い, 売り) の組について板を歩いて最適な数量を求め、合計利益 (JPY) がプラスの組を
+/// 利益の大きい順に並べて返す (先頭が最良。同じ利益なら評価順)
+pub fn rank_arbitrage(
+    market_data_list: &[MarketData],
+    target_asset: Asset,
+    fx: &FxRates,
+    params: &ArbitrageParams,
+    now_ms: u64,
+) -> Vec<ArbitrageOpportunity> {
     // 対象通貨のデータのみ抽出
     let relevant_data: Vec<&MarketData> = market_data_list
         .iter()
         .filter(|d| d.asset == target_asset)
         .collect();
 
-    let mut best_opportunity: Option<ArbitrageOpportunity> = None;
-    let mut max_profit_pct = Decimal::ZERO;
-
+    let mut opportunities = Vec::new();
     for buy_side in &relevant_data {
         for sell_side in &relevant_data {
-            
             // 同一取引所の同一商品はスキップ
             if buy_side.exchange == sell_side.exchange && buy_side.instrument == sell_side.instrument {
                 continue;
             }
-
-            // --- 1. Buy Side (Long) コスト計算 (JPY換算) ---
-            // Ask価格 * (1 + 手数料 + スリッページ)
-            let buy_price_raw = buy_side.ask;
-            let buy_fee_multiplier = Decimal::ONE + buy_side.taker_fee() + slippage();
-            
-            // 通貨変換
-            let buy_cost_jpy = match buy_side.currency {
-                Currency::JPY => buy_price_raw * buy_fee_multiplier,
-                Currency::USD => buy_price_raw * usd_jpy_rate * buy_fee_multiplier,
-            };
-
-            // --- 2. Sell Side (Short) 売上計算 (JPY換算) ---
-            // Bid価格 * (1 - 手数料 - スリッページ)
-            let sell_price_raw = sell_side.bid;
-            let sell_fee_multiplier = Decimal::ONE - sell_side.taker_fee() - slippage();
-
-            // 通貨変換
-            let sell_revenue_jpy = match sell_side.currency {
-                Currency::JPY => sell_price_raw * sell_fee_multiplier,
-                Currency::USD => sell_price_raw * usd_jpy_rate * sell_fee_multiplier,
-            };
-
-            // --- 3. FRインパクト (JPY換算) ---
-            // FRはスポット価格に対する比率として単純加算
-            // Buy(Long)なら -FR, Sell(Short)なら +FR (FR>0の場合)
-            let mut fr_impact_pct = Decimal::ZERO;
-            if let InstrumentType::Perp = buy_side.instrument {
-                fr_impact_pct -= buy_side.funding_rate;
-            }
-            if let InstrumentType::Perp = sell_side.instrument {
-                fr_impact_pct += sell_side.funding_rate;
+            if let Some(opportunity) = size_opportunity(buy_side, sell_side, target_asset, fx, params, now_ms)
+                && opportunity.estimated_profit_jpy > Decimal::ZERO
+            {
+                opportunities.push(opportunity);
             }
+        }
+    }
+    opportunities.sort_by_key(|o| std::cmp::Reverse(o.estimated_profit_jpy));
+    opportunities
+}
 
-            // 収益計算 (1単位あたり)
-            // (売上 - コスト) + (コスト * FR%) 
-            // ※FRは保有額(コスト相当)に対してかかると概算
-            let base_profit_jpy = sell_revenue_jpy - buy_cost_jpy;
-            let fr_profit_jpy = buy_cost_jpy * fr_impact_pct;
-            
-            let total_profit_jpy = base_profit_jpy + fr_profit_jpy;
-            let total_profit_pct = total_profit_jpy / buy_cost_jpy;
-
-            // --- 4. 判定 (プラスかつ最大利益) ---
-            if total_profit_pct > Decimal::ZERO && total_profit_pct > max_profit_pct {
-                max_profit_pct = total_profit_pct;
-                
-                // 手数料とスリッページのコスト計算
-                let buy_fee_cost = buy_price_raw * buy_side.taker_fee();
-                let buy_slippage_cost = buy_price_raw * slippage();
-                let sell_fee_cost = sell_price_raw * sell_side.taker_fee();
-                let sell_slippage_cost = sell_price_raw * slippage();
-                
-                let total_buy_fee_jpy = match buy_side.currency {
-                    Currency::JPY => buy_fee_cost,
-                    Currency::USD => buy_fee_cost * usd_jpy_rate,
-                };
-                
-                let total_sell_fee_jpy = match sell_side.currency {
-                    Currency::JPY => sell_fee_cost,
-                    Currency::USD => sell_fee_cost * usd_jpy_rate,
-                };
-                
-                let total_slippage_jpy = match buy_side.currency {
-                    Currency::JPY => buy_slippage_cost,
-                    Currency::USD => buy_slippage_cost * usd_jpy_rate,
-                } + match sell_side.currency {
-                    Currency::JPY => sell_slippage_cost,
-                    Currency::USD => sell_slippage_cost * usd_jpy_rate,
-                };
-
-                let details = format!(
-                    "Buy {:?} {:?}@{} {} | Sell {:?} {:?}@{} {} | Rate: {} JPY/USD",
-                    buy_side.exchange, buy_side.instrument, buy_side.ask, buy_side.currency as u8,
-                    sell_side.exchange, sell_side.instrument, sell_side.bid, sell_side.currency as u8,
-                    usd_jpy_rate
-                );
-
-                best_opportunity = Some(ArbitrageOpportunity {
-                    asset: target_asset,
-                    long_exchange: buy_side.exchange,
-                    long_instrument: buy_side.instrument,
-                    long_price_jpy: buy_cost_jpy,
-                    long_price_raw: buy_price_raw,
-                    long_currency: buy_side.currency,
-                    long_fee_jpy: total_buy_fee_jpy,
-                    short_exchange: sell_side.exchange,
-                    short_instrument: sell_side.instrument,
-                    short_price_jpy: sell_revenue_jpy,
-                    short_price_raw: sell_price_raw,
-                    short_currency: sell_side.currency,
-                    short_fee_jpy: total_sell_fee_jpy,
-                    base_profit_jpy: base_profit_jpy,
-                    fr_impact_jpy: fr_profit_jpy,
-                    slippage_cost_jpy: total_slippage_jpy,
-                    estimated_profit_jpy: total_profit_jpy,
-                    estimated_profit_pct: total_profit_pct * Decimal::from(100),
-                    usd_jpy_rate,
-                    details,
-                });
-            }
+/// rank_arbitrage の先頭 (合計利益が最大の組)
+pub fn find_best_arbitrage(
+    market_data_list: &[MarketData],
+    target_asset: Asset,
+    fx: &FxRates,
+    params: &ArbitrageParams,
+    now_ms: u64,
+) -> Option<ArbitrageOpportunity> {
+    rank_arbitrage(market_data_list, target_asset, fx, params, now_ms).into_iter().next()
+}
+
+/// 取引所間の即時の価格差を取る裁定 (rank_arbitrage を Strategy として使う)。
+/// 持続しなかった機会は OpportunityTracker で除く
+#[derive(Debug, Clone)]
+pub struct SpreadArbitrage {
+    params: ArbitrageParams,
+    tracker: OpportunityTracker,
+}
+
+impl SpreadArbitrage {
+    pub fn new(params: ArbitrageParams) -> Self {
+        let tracker = OpportunityTracker::new(params.min_persistence_ms, params.min_persistence_ticks);
+        Self { params, tracker }
+    }
+}
+
+impl Strategy for SpreadArbitrage {
+    fn name(&self) -> &'static str {
+        "arbitrage"
+    }
+
+    fn evaluate(&mut self, snapshot: &MarketSnapshot, _positions: &Positions) -> Vec<Signal> {
+        // 市場が1つ以下でも、観測中の機会を閉じるために空の結果を記録する
+        let ranked = if snapshot.markets.len() < 2 {
+            Vec::new()
+        } else {
+            rank_arbitrage(&snapshot.markets, snapshot.asset, &snapshot.fx, &self.params, snapshot.now_ms)
+        };
+        for (rank, opp) in ranked.iter().enumerate() {
+            debug!(
+                "[Strategy] {:?} route #{} {}: ¥{:.2} ({:.4}%)",
+                snapshot.asset, rank + 1, opp.route(), opp.estimated_profit_jpy, opp.estimated_profit_pct
+            );
+        }
+        let candidates: Vec<ArbitrageOpportunity> = ranked
+            .into_iter()
+            .filter(|opp| opp.estimated_profit_pct > self.params.min_profit_pct)
+            .collect();
+
+        let update = self.tracker.observe(snapshot.asset, candidates, snapshot.now_ms);
+        let mut signals: Vec<Signal> = update.closed.into_iter().map(Signal::OpportunityClosed).collect();
+        if !update.confirmed.is_empty() {
+            signals.push(Signal::Arbitrage(update.confirmed));
+        }
+        signals
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::VirtualClock;
+    use crate::fx::{FxConfig, FxOracle, FxSource, StablecoinConfig};
+    use crate::store::MarketStore;
+    use std::sync::Arc;
+
+    fn level(price: i64, size: &str) -> PriceLevel {
+        PriceLevel { price: Decimal::from(price), size: Decimal::from_str(size).unwrap() }
+    }
+
+    fn book(bids: &[PriceLevel], asks: &[PriceLevel]) -> OrderBook {
+        let mut book = OrderBook::default();
+        book.apply_snapshot(bids, asks, None, 0, None);
+        book
+    }
+
+    /// 手数料なしの JPY 建て現物
+    fn market(exchange: Exchange, bid: i64, ask: i64, book: Option<OrderBook>) -> MarketData {
+        MarketData {
+            exchange,
+            asset: Asset::new("BTC"),
+            instrument: InstrumentType::Spot,
+            currency: Currency::JPY,
+            ask: Decimal::from(ask),
+            bid: Decimal::from(bid),
+            funding: None,
+            taker_fee: Decimal::ZERO,
+            daily_rollover_fee: Decimal::ZERO,
+            book,
+        }
+    }
+
+    fn params(max_notional_jpy: i64, slippage: &str) -> ArbitrageParams {
+        ArbitrageParams {
+            max_notional_jpy: Decimal::from(max_notional_jpy),
+            slippage: Decimal::from_str(slippage).unwrap(),
+            ..ArbitrageParams::default()
         }
     }
-    best_opportunity
-}
\ No newline at end of file
+
+    fn size(buy: &MarketData, sell: &MarketData, params: &ArbitrageParams) -> Option<ArbitrageOpportunity> {
+        size_opportunity(buy, sell, Asset::new("BTC"), &FxRates::default(), params, 0)
+    }
+
+    #[test]
+    fn walks_both_books_while_marginal_profit_is_positive() {
+        let asks = [level(100, "1"), level(101, "1"), level(103, "5")];
+        let bids = [level(104, "1.5"), level(102, "1"), level(100, "5")];
+        let buy = market(Exchange::Bitbank, 99, 100, Some(book(&[level(99, "1")], &asks)));
+        let sell = market(Exchange::Gmo, 104, 105, Some(book(&bids, &[level(105, "1")])));
+
+        let opp = size(&buy, &sell, &params(1_000_000, "0.0001")).unwrap();
+        // 100x1 と 104x1 / 101x0.5 と 104x0.5 / 101x0.5 と 102x0.5 で止まる (103 と 102 は逆ざや)
+        assert_eq!(opp.quantity, Decimal::from(2));
+        assert_eq!(opp.estimated_profit_jpy, Decimal::from(6));
+        assert_eq!((opp.long_limit_price, opp.short_limit_price), (Decimal::from(101), Decimal::from(102)));
+        assert_eq!(opp.profit_curve.len(), 3);
+        // 板があればスリッページはベストからの乖離分だけ (101 - 100) x 1 + (104 - 102) x 0.5
+        assert_eq!(opp.slippage_cost_jpy, Decimal::from(2));
+    }
+
+    #[test]
+    fn stops_at_max_notional() {
+        let asks = [level(100, "1"), level(101, "1")];
+        let bids = [level(104, "5")];
+        let buy = market(Exchange::Bitbank, 99, 100, Some(book(&[level(99, "1")], &asks)));
+        let sell = market(Exchange::Gmo, 104, 105, Some(book(&bids, &[level(105, "1")])));
+
+        let opp = size(&buy, &sell, &params(150, "0")).unwrap();
+        assert!(opp.notional_jpy <= Decimal::from(150));
+        assert!(opp.notional_jpy > Decimal::from(149));
+        assert!(opp.quantity < Decimal::from(2));
+        assert_eq!(opp.profit_curve.len(), 2);
+    }
+
+    #[test]
+    fn leg_without_book_falls_back_to_quote_with_slippage() {
+        let asks = [level(100, "1"), level(101, "1")];
+        let buy = market(Exchange::Bitbank, 99, 100, Some(book(&[level(99, "1")], &asks)));
+        // 売り足は板がないので気配値 104 を使う
+        let sell = market(Exchange::Gmo, 104, 105, None);
+
+        let opp = size(&buy, &sell, &params(1_000, "0.0001")).unwrap();
+        // 買い板を食べ尽くしたところで止まる
+        assert_eq!(opp.quantity, Decimal::from(2));
+        assert_eq!(opp.short_limit_price, Decimal::from(104));
+        // 買い板の乖離 (101 - 100) x 1 と、気配値で売る分の固定スリッページ 104 x 0.0001 x 2
+        assert_eq!(opp.slippage_cost_jpy, Decimal::from_str("1.0208").unwrap());
+    }
+
+    /// USDC が 0.98 USD にデペッグしていれば、USDC 建ての売上は 0.98 × USD/JPY で換算され、
+    /// USDC = USD とみなした場合の見かけの利益 (100 × 150 - 14,600 = ¥400) は計上しない
+    #[test]
+    fn depegged_stablecoin_is_priced_into_the_fx_rate() {
+        let clock = Arc::new(VirtualClock::new(1_700_000_000_000_000));
+        let store = MarketStore::new().with_clock(clock);
+        store.update_market_data(Exchange::Kraken, "USD_JPY", Decimal::from(150), Decimal::from(150), Decimal::from(150), None);
+        let depeg = Decimal::from_str("0.98").unwrap();
+        store.update_market_data(Exchange::Kraken, "USDC_USD", depeg, depeg, depeg, None);
+        let config = FxConfig {
+            sources: vec![FxSource { exchange: Exchange::Kraken, symbol: "USD_JPY".to_string(), weight: Decimal::ONE }],
+            // デペッグしても取引を止めないよう上限を広げる
+            stablecoins: vec![StablecoinConfig {
+                currency: Currency::USDC,
+                sources: vec![FxSource { exchange: Exchange::Kraken, symbol: "USDC_USD".to_string(), weight: Decimal::ONE }],
+                max_depeg_pct: Decimal::from(5),
+            }],
+            ..FxConfig::default()
+        };
+        let oracle = FxOracle::new(&config, |_| true);
+        let (fx, unavailable) = oracle.rates(&store, &oracle.quote(&store).unwrap());
+        assert!(unavailable.is_empty());
+        assert_eq!(fx.get(Currency::USDC).unwrap().describe(), "USDC_USD 0.98/0.98 -> USD_JPY 150/150");
+
+        let buy = market(Exchange::Bitbank, 14_590, 14_600, Some(book(&[level(14_590, "1")], &[level(14_600, "1")])));
+        let sell = MarketData {
+            currency: Currency::USDC,
+            ..market(Exchange::Hyperliquid, 100, 101, Some(book(&[level(100, "1")], &[level(101, "1")])))
+        };
+        let opp = size_opportunity(&buy, &sell, Asset::new("BTC"), &fx, &params(1_000_000, "0"), 0).unwrap();
+        assert_eq!(opp.long_fx_rate, Decimal::ONE);
+        assert_eq!(opp.short_fx_rate, Decimal::from(147));
+        assert_eq!(opp.short_price_jpy, Decimal::from(14_700));
+        assert_eq!(opp.quantity, Decimal::ONE);
+        assert_eq!(opp.estimated_profit_jpy, Decimal::from(100));
+    }
+
+    #[test]
+    fn quotes_without_book_are_capped_by_max_notional() {
+        let buy = market(Exchange::Bitbank, 99, 100, None);
+        let sell = market(Exchange::Gmo, 104, 105, None);
+
+        let opp = size(&buy, &sell, &params(1_000, "0")).unwrap();
+        // 売り足も想定元本 ¥1,000 分 (1000 / 104) までしか見込まない
+        assert_eq!(opp.quantity, Decimal::from(1_000) / Decimal::from(104));
+        assert!(opp.notional_jpy <= Decimal::from(1_000));
+        assert_eq!(opp.profit_curve.len(), 1);
+
+        let no_quote = market(Exchange::Gmo, 0, 0, None);
+        assert!(size(&buy, &no_quote, &params(1_000, "0")).is_none());
+    }
+}
@@ -0,0 +1,210 @@
+use super::strategy::{ArbitrageOpportunity, Asset, InstrumentType};
+use crate::store::Exchange;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::fmt;
+
+/// 機会の識別子 (通貨, 買い足, 売り足)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OpportunityKey {
+    pub asset: Asset,
+    pub long: (Exchange, InstrumentType),
+    pub short: (Exchange, InstrumentType),
+}
+
+impl OpportunityKey {
+    pub fn of(opportunity: &ArbitrageOpportunity) -> Self {
+        Self {
+            asset: opportunity.asset,
+            long: (opportunity.long_exchange, opportunity.long_instrument),
+            short: (opportunity.short_exchange, opportunity.short_instrument),
+        }
+    }
+}
+
+impl fmt::Display for OpportunityKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} {} {:?} -> {} {:?}",
+            self.asset, self.long.0, self.long.1, self.short.0, self.short.1
+        )
+    }
+}
+
+/// 観測中の機会の統計
+#[derive(Debug, Clone)]
+struct Observation {
+    first_seen_ms: u64,
+    last_seen_ms: u64,
+    ticks: u32,
+    peak_profit_pct: Decimal,
+    total_profit_pct: Decimal,
+    peak_profit_jpy: Decimal,
+    /// 持続の条件を満たして一度でもシグナルを出したか
+    confirmed: bool,
+}
+
+/// 消えた機会の生存期間の統計
+#[derive(Debug, Clone)]
+pub struct ClosedOpportunity {
+    pub key: OpportunityKey,
+    pub first_seen_ms: u64,
+    pub last_seen_ms: u64,
+    /// 観測した評価の回数
+    pub ticks: u32,
+    /// 純利益率 (%) の最大値と平均
+    pub peak_profit_pct: Decimal,
+    pub average_profit_pct: Decimal,
+    pub peak_profit_jpy: Decimal,
+    /// 持続の条件を満たしてシグナルを出したか (false なら1ティックだけの見せかけの機会)
+    pub confirmed: bool,
+}
+
+impl ClosedOpportunity {
+    pub fn lifetime_ms(&self) -> u64 {
+        self.last_seen_ms - self.first_seen_ms
+    }
+}
+
+/// 1回の評価の結果
+#[derive(Debug, Default)]
+pub struct TrackerUpdate {
+    /// 今回の評価で持続の条件を初めて満たした機会 (渡された順)。
+    /// 同じ機会は消えて再び現れるまで二度は入らない
+    pub confirmed: Vec<ArbitrageOpportunity>,
+    /// 今回の評価で見えなくなった機会
+    pub closed: Vec<ClosedOpportunity>,
+}
+
+/// 裁定機会を (通貨, 買い足, 売り足) ごとに追跡し、一定時間かつ一定回数続いたものだけを通す。
+/// 古い気配や一瞬の気配の交差による1ティックだけの機会を執行しないためのフィルタ
+#[derive(Debug, Clone)]
+pub struct OpportunityTracker {
+    min_persistence_ms: u64,
+    min_persistence_ticks: u32,
+    open: HashMap<OpportunityKey, Observation>,
+}
+
+impl OpportunityTracker {
+    /// min_persistence_ms 以上続き、かつ min_persistence_ticks 回以上続けて観測された機会を、
+    /// 条件を満たした評価で一度だけ通す。
+    /// 評価はイベント駆動で短い間隔でも走るため、回数だけでは一瞬の交差を除けないので時間の条件は必ず課す
+    pub fn new(min_persistence_ms: u64, min_persistence_ticks: u32) -> Self {
+        Self { min_persistence_ms, min_persistence_ticks, open: HashMap::new() }
+    }
+
+    /// 1通貨分の評価結果を記録する。
+    /// 前回まで観測していて今回の opportunities に含まれない同じ通貨の機会は閉じる
+    pub fn observe(&mut self, asset: Asset, opportunities: Vec<ArbitrageOpportunity>, now_ms: u64) -> TrackerUpdate {
+        let mut update = TrackerUpdate::default();
+        let seen: Vec<OpportunityKey> = opportunities.iter().map(OpportunityKey::of).collect();
+
+        for (key, opportunity) in seen.iter().zip(opportunities) {
+            let profit_pct = opportunity.estimated_profit_pct;
+            let observation = self.open.entry(*key).or_insert(Observation {
+                first_seen_ms: now_ms,
+                last_seen_ms: now_ms,
+                ticks: 0,
+                peak_profit_pct: profit_pct,
+                total_profit_pct: Decimal::ZERO,
+                peak_profit_jpy: opportunity.estimated_profit_jpy,
+                confirmed: false,
+            });
+            observation.last_seen_ms = now_ms;
+            observation.ticks += 1;
+            observation.total_profit_pct += profit_pct;
+            observation.peak_profit_pct = observation.peak_profit_pct.max(profit_pct);
+            observation.peak_profit_jpy = observation.peak_profit_jpy.max(opportunity.estimated_profit_jpy);
+
+            let persisted_ms = now_ms.saturating_sub(observation.first_seen_ms);
+            if !observation.confirmed
+                && observation.ticks >= self.min_persistence_ticks
+                && persisted_ms >= self.min_persistence_ms
+            {
+                observation.confirmed = true;
+                update.confirmed.push(opportunity);
+            }
+        }
+
+        // HashMap の順序に依らないよう、見え始めた順に閉じる (リプレイで同じ順序になるように)
+        let mut gone: Vec<(u64, String, OpportunityKey)> = self
+            .open
+            .iter()
+            .filter(|(k, _)| k.asset == asset && !seen.contains(k))
+            .map(|(k, o)| (o.first_seen_ms, k.to_string(), *k))
+            .collect();
+        gone.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+        for (_, _, key) in gone {
+            if let Some(o) = self.open.remove(&key) {
+                update.closed.push(ClosedOpportunity {
+                    key,
+                    first_seen_ms: o.first_seen_ms,
+                    last_seen_ms: o.last_seen_ms,
+                    ticks: o.ticks,
+                    peak_profit_pct: o.peak_profit_pct,
+                    average_profit_pct: o.total_profit_pct / Decimal::from(o.ticks),
+                    peak_profit_jpy: o.peak_profit_jpy,
+                    confirmed: o.confirmed,
+                });
+            }
+        }
+        update
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::Exchange;
+
+    fn opportunity() -> ArbitrageOpportunity {
+        ArbitrageOpportunity::sample(
+            Asset::new("BTC"),
+            (Exchange::Bitbank, Decimal::from(10_000)),
+            (Exchange::Gmo, Decimal::from(10_100)),
+            Decimal::ONE,
+        )
+    }
+
+    #[test]
+    fn persisting_opportunity_is_confirmed_once() {
+        let mut tracker = OpportunityTracker::new(1_000, 2);
+        let asset = Asset::new("BTC");
+        let confirmed: Vec<usize> = [0, 500, 1_000, 1_500, 2_000]
+            .into_iter()
+            .map(|now_ms| tracker.observe(asset, vec![opportunity()], now_ms).confirmed.len())
+            .collect();
+        assert_eq!(confirmed, vec![0, 0, 1, 0, 0]);
+
+        // 消えてから再び現れたら、改めて持続の条件を満たしたときに通す
+        let update = tracker.observe(asset, Vec::new(), 2_500);
+        assert_eq!(update.closed.len(), 1);
+        assert!(update.closed[0].confirmed);
+        assert_eq!(update.closed[0].ticks, 5);
+        let confirmed: Vec<usize> = [3_000, 4_000]
+            .into_iter()
+            .map(|now_ms| tracker.observe(asset, vec![opportunity()], now_ms).confirmed.len())
+            .collect();
+        assert_eq!(confirmed, vec![0, 1]);
+    }
+
+    #[test]
+    fn quick_ticks_do_not_satisfy_persistence() {
+        // デバウンス間隔で続けて評価されても、時間の条件を満たすまでは通さない
+        let mut tracker = OpportunityTracker::new(1_000, 2);
+        let asset = Asset::new("BTC");
+        for now_ms in [0, 20, 40, 60] {
+            assert!(tracker.observe(asset, vec![opportunity()], now_ms).confirmed.is_empty());
+        }
+        let update = tracker.observe(asset, Vec::new(), 80);
+        assert!(!update.closed[0].confirmed);
+    }
+
+    #[test]
+    fn zero_thresholds_confirm_on_first_tick() {
+        let mut tracker = OpportunityTracker::new(0, 0);
+        let update = tracker.observe(Asset::new("BTC"), vec![opportunity()], 0);
+        assert_eq!(update.confirmed.len(), 1);
+    }
+}
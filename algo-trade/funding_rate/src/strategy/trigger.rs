@@ -0,0 +1,231 @@
+use super::registry::VENUE_INSTRUMENTS;
+use super::strategy::{symbol_for, Asset};
+use crate::asset::AssetRegistry;
+use crate::store::{Exchange, StoreUpdate};
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// 更新で評価し直す必要がある範囲
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Affected {
+    Asset(Asset),
+    /// 為替レートなど、すべての通貨の評価に使うもの
+    All,
+}
+
+/// MarketStore のキー (取引所, シンボル) から、更新で影響を受ける通貨を引く
+#[derive(Debug, Clone, Default)]
+pub struct AssetIndex {
+    keys: HashMap<(Exchange, String), Affected>,
+}
+
+impl AssetIndex {
+    /// fx_keys は為替レートの参照元のキー。どちらにも含まれないキーの更新は無視する
+    pub fn new(registry: &AssetRegistry, fx_keys: impl IntoIterator<Item = (Exchange, String)>) -> Self {
+        let mut keys = HashMap::new();
+        for key in fx_keys {
+            keys.insert(key, Affected::All);
+        }
+        for asset in registry.assets() {
+            for &(exchange, instrument, _) in VENUE_INSTRUMENTS {
+                if registry.is_listed(asset, exchange, instrument) {
+                    keys.insert((exchange, symbol_for(exchange, &asset, instrument)), Affected::Asset(asset));
+                }
+            }
+        }
+        Self { keys }
+    }
+
+    pub fn affected(&self, exchange: Exchange, symbol: &str) -> Option<Affected> {
+        self.keys.get(&(exchange, symbol.to_string())).copied()
+    }
+}
+
+/// 評価待ちの通貨。debounce の間に届いた更新を通貨ごとに1回の評価にまとめる。
+/// 値は最初に届いた更新のフレームの受信時刻 (一定間隔の見直しで追加した場合は None)
+#[derive(Debug, Default)]
+pub struct PendingEvaluations {
+    assets: HashMap<Asset, Option<Instant>>,
+}
+
+impl PendingEvaluations {
+    /// 更新通知を評価待ちに加える。対象外のキーの更新は無視する
+    pub fn mark_update(&mut self, update: &StoreUpdate, index: &AssetIndex, registry: &AssetRegistry) {
+        match index.affected(update.exchange, &update.symbol) {
+            Some(Affected::Asset(asset)) => self.mark(asset, Some(update.received_at)),
+            Some(Affected::All) => {
+                for asset in registry.assets() {
+                    self.mark(asset, Some(update.received_at));
+                }
+            }
+            None => {}
+        }
+    }
+
+    /// すべての通貨を評価待ちにする (一定間隔の見直し、通知の取りこぼし時)
+    pub fn mark_all(&mut self, registry: &AssetRegistry) {
+        for asset in registry.assets() {
+            self.mark(asset, None);
+        }
+    }
+
+    fn mark(&mut self, asset: Asset, received_at: Option<Instant>) {
+        let entry = self.assets.entry(asset).or_insert(received_at);
+        if entry.is_none() {
+            *entry = received_at;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.assets.is_empty()
+    }
+
+    /// 評価待ちなら取り出す。Some(None) は更新ではなく一定間隔の見直しによるもの
+    pub fn take(&mut self, asset: Asset) -> Option<Option<Instant>> {
+        self.assets.remove(&asset)
+    }
+}
+
+/// フレームの受信から判断 (戦略の評価が終わるまで) の遅延の集計
+#[derive(Debug, Default)]
+pub struct LatencyStats {
+    samples: Vec<Duration>,
+}
+
+impl LatencyStats {
+    pub fn record(&mut self, latency: Duration) {
+        self.samples.push(latency);
+    }
+
+    /// これまでの集計を返してリセットする。記録が無ければ None
+    pub fn take_summary(&mut self) -> Option<LatencySummary> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let mut samples = std::mem::take(&mut self.samples);
+        samples.sort();
+        let percentile = |p: usize| samples[(samples.len() - 1) * p / 100];
+        Some(LatencySummary {
+            count: samples.len(),
+            p50: percentile(50),
+            p99: percentile(99),
+            max: samples[samples.len() - 1],
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LatencySummary {
+    pub count: usize,
+    pub p50: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl fmt::Display for LatencySummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "p50 {:?}, p99 {:?}, max {:?} ({} decisions)", self.p50, self.p99, self.max, self.count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asset::AssetConfig;
+    use crate::store::{MarketStore, UpdateKind};
+    use rust_decimal::Decimal;
+
+    fn setup() -> (AssetRegistry, AssetIndex) {
+        let registry = AssetRegistry::new(AssetConfig::defaults());
+        let index = AssetIndex::new(&registry, [(Exchange::Kraken, "USD_JPY".to_string())]);
+        (registry, index)
+    }
+
+    fn update(exchange: Exchange, symbol: &str, received_at: Instant) -> StoreUpdate {
+        StoreUpdate { exchange, symbol: symbol.to_string(), kind: UpdateKind::Quote, received_at, exchange_timestamp_ms: None }
+    }
+
+    #[test]
+    fn index_maps_store_keys_to_assets() {
+        let (_, index) = setup();
+        let btc = Asset::new("BTC");
+        assert_eq!(index.affected(Exchange::Gmo, "BTC_SPOT"), Some(Affected::Asset(btc)));
+        assert_eq!(index.affected(Exchange::Gmo, "BTC"), Some(Affected::Asset(btc)));
+        assert_eq!(index.affected(Exchange::Hyperliquid, "HYPE_SPOT"), Some(Affected::Asset(Asset::new("HYPE"))));
+        assert_eq!(index.affected(Exchange::Kraken, "USD_JPY"), Some(Affected::All));
+        // HYPE は国内取引所に上場していない
+        assert_eq!(index.affected(Exchange::Bitbank, "HYPE"), None);
+        assert_eq!(index.affected(Exchange::Gmo, "USD_JPY"), None);
+    }
+
+    #[test]
+    fn fx_update_marks_every_asset() {
+        let (registry, index) = setup();
+        let mut pending = PendingEvaluations::default();
+        let received_at = Instant::now();
+        pending.mark_update(&update(Exchange::Gmo, "USD_JPY", received_at), &index, &registry);
+        assert!(pending.is_empty());
+
+        pending.mark_update(&update(Exchange::Kraken, "USD_JPY", received_at), &index, &registry);
+        for asset in registry.assets() {
+            assert_eq!(pending.take(asset), Some(Some(received_at)), "{}", asset);
+        }
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn pending_keeps_the_first_receive_time() {
+        let (registry, index) = setup();
+        let btc = Asset::new("BTC");
+        let first = Instant::now();
+        let later = first + Duration::from_millis(5);
+
+        let mut pending = PendingEvaluations::default();
+        pending.mark_all(&registry);
+        pending.mark_update(&update(Exchange::Bitbank, "BTC", first), &index, &registry);
+        pending.mark_update(&update(Exchange::Gmo, "BTC_SPOT", later), &index, &registry);
+        pending.mark_all(&registry);
+        // 同じ通貨の更新は1回の評価にまとめ、遅延は最初の更新から測る
+        assert_eq!(pending.take(btc), Some(Some(first)));
+        assert_eq!(pending.take(btc), None);
+        // 一定間隔の見直しだけで評価待ちになった通貨は遅延を測らない
+        assert_eq!(pending.take(Asset::new("ETH")), Some(None));
+    }
+
+    #[test]
+    fn updates_carry_the_frame_receive_time() {
+        let store = MarketStore::new();
+        let mut updates = store.subscribe();
+        let received_at = Instant::now();
+        std::thread::sleep(Duration::from_millis(2));
+
+        // コレクターは受信時刻を付けたハンドルでストアを更新する
+        store.receiving_at(received_at).update_market_data(Exchange::Bitbank, "BTC", Decimal::ONE, Decimal::TWO, Decimal::ONE, None);
+        assert_eq!(updates.try_recv().unwrap().received_at, received_at);
+        assert_eq!(store.get_symbol_data(Exchange::Bitbank, "BTC").unwrap().ask, Decimal::TWO);
+
+        store.update_market_data(Exchange::Bitbank, "BTC", Decimal::ONE, Decimal::TWO, Decimal::ONE, None);
+        assert!(updates.try_recv().unwrap().received_at > received_at);
+    }
+
+    #[test]
+    fn latency_percentiles_use_nearest_rank_below() {
+        let mut stats = LatencyStats::default();
+        assert!(stats.take_summary().is_none());
+        for ms in (1..=100).rev() {
+            stats.record(Duration::from_millis(ms));
+        }
+        let summary = stats.take_summary().unwrap();
+        assert_eq!(summary.count, 100);
+        assert_eq!(summary.p50, Duration::from_millis(50));
+        assert_eq!(summary.p99, Duration::from_millis(99));
+        assert_eq!(summary.max, Duration::from_millis(100));
+        // 集計したらリセットする
+        assert!(stats.take_summary().is_none());
+
+        stats.record(Duration::from_millis(7));
+        let summary = stats.take_summary().unwrap();
+        assert_eq!((summary.count, summary.p50, summary.p99, summary.max), (1, Duration::from_millis(7), Duration::from_millis(7), Duration::from_millis(7)));
+    }
+}